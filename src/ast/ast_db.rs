@@ -9,7 +9,7 @@ use sled::Db;
 use lazy_static::lazy_static;
 use regex::Regex;
 
-use crate::ast::ast_structs::{AstDB, AstDefinition, AstCounters, AstErrorStats};
+use crate::ast::ast_structs::{AstDB, AstDefinition, AstCounters, AstErrorStats, AstDocFingerprint};
 use crate::ast::ast_parse_anything::{parse_anything_and_add_file_path, filesystem_path_to_double_colon_path};
 use crate::fuzzy_search::fuzzy_search;
 
@@ -51,9 +51,13 @@ use crate::fuzzy_search::fuzzy_search;
 //             ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ file_global_path (means path up to the global scope of the file)
 //                                                ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ file filesystem path
 //
+// Per doc fingerprint, allows to skip unchanged files after restart if the database is permanent:
+//   doc-fingerprint|alt_testsuite::cpp_goat_library 👉 AstDocFingerprint { mtime_ns, size, text_md5 }
+//
 // Other keys:
 //   counters|defs: 42
 //   counters|usages: 100
+//   meta|version 👉 "0.10.12", permanent database only, mismatch wipes the database because parsers might have changed
 //
 //
 // Read tests below, the show what this index can do!
@@ -70,6 +74,7 @@ macro_rules! debug_print {
 }
 
 const CACHE_CAPACITY_BYTES: u64 = 256 * 1024 * 1024;  // 256M cache
+const META_VERSION_KEY: &[u8] = b"meta|version";
 
 pub async fn ast_index_init(ast_permanent: String, ast_max_files: usize, want_perf_report: bool) -> Arc<AMutex<AstDB>>
 {
@@ -80,17 +85,30 @@ pub async fn ast_index_init(ast_permanent: String, ast_max_files: usize, want_pe
         .mode(sled::Mode::HighThroughput)
         .flush_every_ms(Some(5000));
 
-    if ast_permanent.is_empty() {
-        config = config.temporary(true).create_new(true);
-    } else {
+    let is_permanent = !ast_permanent.is_empty();
+    if is_permanent {
         config = config.path(ast_permanent.clone());
+    } else {
+        config = config.temporary(true).create_new(true);
     }
 
     tracing::info!("starting AST db, ast_permanent={:?}", ast_permanent);
     let db: Arc<Db> = Arc::new(task::spawn_blocking(
         move || config.open().unwrap()
     ).await.unwrap());
-    db.clear().unwrap();
+    if is_permanent {
+        let want_version = crate::version::build_info::PKG_VERSION;
+        let have_version = db.get(META_VERSION_KEY).unwrap().map(|v| String::from_utf8_lossy(&v).to_string()).unwrap_or_default();
+        if have_version != want_version {
+            tracing::info!("AST db version {:?} != {:?}, starting from scratch", have_version, want_version);
+            db.clear().unwrap();
+            db.insert(META_VERSION_KEY, want_version.as_bytes()).unwrap();
+        } else {
+            tracing::info!("AST db reused, has {} records", db.len());
+        }
+    } else {
+        db.clear().unwrap();
+    }
     tracing::info!("/starting AST");
     let ast_index = AstDB {
         sleddb: db,
@@ -98,6 +116,7 @@ pub async fn ast_index_init(ast_permanent: String, ast_max_files: usize, want_pe
         batch_counter: 0,
         counters_increase: HashMap::new(),
         ast_max_files,
+        is_permanent,
     };
     Arc::new(AMutex::new(ast_index))
 }
@@ -166,6 +185,15 @@ pub async fn flush_sled_batch(
     ast_index_locked.sledbatch.clone()
 }
 
+pub async fn ast_db_flush(ast_index: Arc<AMutex<AstDB>>)
+{
+    flush_sled_batch(ast_index.clone(), 0).await;
+    let db = ast_index.lock().await.sleddb.clone();
+    if let Err(e) = db.flush_async().await {
+        tracing::error!("failed to flush AST db: {:?}", e);
+    }
+}
+
 pub async fn doc_add(
     ast_index: Arc<AMutex<AstDB>>,
     cpath: &String,
@@ -284,6 +312,8 @@ pub async fn doc_remove(ast_index: Arc<AMutex<AstDB>>, cpath: &String)
     }
     let doc_resolved_key = format!("doc-resolved|{}", file_global_path.join("::"));
     batch.remove(doc_resolved_key.as_bytes());
+    let doc_fingerprint_key = format!("doc-fingerprint|{}", file_global_path.join("::"));
+    batch.remove(doc_fingerprint_key.as_bytes());
     let doc_key = format!("doc-cpath|{}", file_global_path.join("::"));
     if db.get(doc_key.as_bytes()).unwrap().is_some() {
         _increase_counter(ast_index.clone(), "counters|docs", -1).await;
@@ -293,6 +323,47 @@ pub async fn doc_remove(ast_index: Arc<AMutex<AstDB>>, cpath: &String)
     _increase_counter(ast_index.clone(), "counters|usages", -deleted_usages).await;
 }
 
+pub fn doc_fingerprint_calculate(text: &str, mtime_ns: u64) -> AstDocFingerprint
+{
+    AstDocFingerprint {
+        mtime_ns,
+        size: text.len() as u64,
+        text_md5: format!("{:x}", md5::compute(text)),
+    }
+}
+
+pub async fn doc_fingerprint_get(ast_index: Arc<AMutex<AstDB>>, cpath: &String) -> Option<AstDocFingerprint>
+{
+    let file_global_path = filesystem_path_to_double_colon_path(cpath);
+    let doc_fingerprint_key = format!("doc-fingerprint|{}", file_global_path.join("::"));
+    let db = ast_index.lock().await.sleddb.clone();
+    match db.get(doc_fingerprint_key.as_bytes()) {
+        Ok(Some(value)) => serde_cbor::from_slice::<AstDocFingerprint>(&value).ok(),
+        _ => None,
+    }
+}
+
+pub async fn doc_fingerprint_set(ast_index: Arc<AMutex<AstDB>>, cpath: &String, fingerprint: &AstDocFingerprint)
+{
+    // Goes into the same batch as the definitions, so a crash can't leave a fingerprint without the data behind it
+    let file_global_path = filesystem_path_to_double_colon_path(cpath);
+    let doc_fingerprint_key = format!("doc-fingerprint|{}", file_global_path.join("::"));
+    let batch_arc = flush_sled_batch(ast_index.clone(), 1000).await;
+    let mut batch = batch_arc.lock().await;
+    batch.insert(doc_fingerprint_key.as_bytes(), serde_cbor::to_vec(fingerprint).unwrap());
+}
+
+pub async fn doc_cpaths_all(ast_index: Arc<AMutex<AstDB>>) -> Vec<String>
+{
+    let db = ast_index.lock().await.sleddb.clone();
+    let mut cpaths = Vec::new();
+    let mut iter = db.scan_prefix("doc-cpath|");
+    while let Some(Ok((_, value))) = iter.next() {
+        cpaths.push(String::from_utf8_lossy(&value).to_string());
+    }
+    cpaths
+}
+
 pub async fn doc_defs(ast_index: Arc<AMutex<AstDB>>, cpath: &String) -> Vec<Arc<AstDefinition>>
 {
     let to_search_prefix = filesystem_path_to_double_colon_path(cpath);
@@ -714,6 +785,42 @@ pub async fn definitions(ast_index: Arc<AMutex<AstDB>>, double_colon_path: &str)
     defs
 }
 
+pub async fn subclasses(ast_index: Arc<AMutex<AstDB>>, this_is_a_class: &str) -> Vec<Arc<AstDefinition>>
+{
+    // classes|cpp🔎Animal ⚡ alt_testsuite::cpp_goat_library::Goat 👉 "cpp🔎Goat"
+    let db = ast_index.lock().await.sleddb.clone();
+    let t_prefix = format!("classes|{} ⚡ ", this_is_a_class);
+    let mut defs = Vec::new();
+    let mut iter = db.scan_prefix(&t_prefix);
+    while let Some(Ok((key, _))) = iter.next() {
+        let key_string = String::from_utf8(key.to_vec()).unwrap();
+        let full_path = key_string.strip_prefix(&t_prefix).unwrap_or_default().trim();
+        let d_key = format!("d|{}", full_path);
+        if let Ok(Some(d_value)) = db.get(d_key.as_bytes()) {
+            match serde_cbor::from_slice::<AstDefinition>(&d_value) {
+                Ok(definition) => defs.push(Arc::new(definition)),
+                Err(e) => tracing::error!("failed to deserialize value for {}: {:?}", d_key, e),
+            }
+        }
+    }
+    defs
+}
+
+pub async fn superclasses(ast_index: Arc<AMutex<AstDB>>, definition: &AstDefinition) -> Vec<Arc<AstDefinition>>
+{
+    // this_class_derived_from has "cpp🔎Animal", look up Animal and keep only classes that are exactly cpp🔎Animal
+    let mut defs = Vec::new();
+    for from in &definition.this_class_derived_from {
+        let name = from.split_once("🔎").map(|(_, name)| name).unwrap_or(from.as_str());
+        for candidate in definitions(ast_index.clone(), name).await {
+            if candidate.this_is_a_class == *from {
+                defs.push(candidate);
+            }
+        }
+    }
+    defs
+}
+
 #[allow(dead_code)]
 pub async fn type_hierarchy(ast_index: Arc<AMutex<AstDB>>, language: String, subtree_of: String) -> String
 {
@@ -920,6 +1027,13 @@ mod tests {
             type_hierarchy(ast_index.clone(), language.to_string(), format!("{}🔎Animal", language)).await
         );
 
+        let animal_defs = definitions(ast_index.clone(), format!("{}_goat_library::Animal", language).as_str()).await;
+        let animal_def0 = animal_defs.first().unwrap();
+        let animal_subclasses = subclasses(ast_index.clone(), &animal_def0.this_is_a_class).await;
+        assert_eq!(animal_subclasses.iter().map(|d| d.name()).collect::<Vec<_>>(), vec!["Goat".to_string()]);
+        let goat_superclasses = superclasses(ast_index.clone(), &animal_subclasses[0]).await;
+        assert_eq!(goat_superclasses.iter().map(|d| d.path()).collect::<Vec<_>>(), vec![animal_def0.path()]);
+
         // Goat::Goat() is a C++ constructor
         let goat_def = definitions(ast_index.clone(), goat_location).await;
         let mut goat_def_str = String::new();
@@ -970,6 +1084,40 @@ mod tests {
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
     }
 
+    #[tokio::test]
+    async fn test_ast_db_permanent_survives_restart() {
+        init_tracing();
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db_path = tmp_dir.path().join("ast_permanent").to_string_lossy().to_string();
+        let library_file_path = "src/ast/alt_testsuite/py_goat_library.py".to_string();
+        let library_text = read_file(&library_file_path);
+        let fingerprint = doc_fingerprint_calculate(&library_text, 0);
+
+        {
+            let ast_index = ast_index_init(db_path.clone(), 10, false).await;
+            let mut errstats: AstErrorStats = AstErrorStats::default();
+            doc_add(ast_index.clone(), &library_file_path, &library_text, &mut errstats).await.unwrap();
+            doc_fingerprint_set(ast_index.clone(), &library_file_path, &fingerprint).await;
+            ast_db_flush(ast_index.clone()).await;
+            let db = ast_index.lock().await.sleddb.clone();
+            drop(ast_index);
+            assert!(Arc::strong_count(&db) == 1);
+            drop(db);
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+
+        let ast_index = ast_index_init(db_path.clone(), 10, false).await;
+        assert_eq!(doc_fingerprint_get(ast_index.clone(), &library_file_path).await, Some(fingerprint));
+        assert_eq!(doc_cpaths_all(ast_index.clone()).await, vec![library_file_path.clone()]);
+        assert!(!doc_defs(ast_index.clone(), &library_file_path).await.is_empty());
+        assert_eq!(fetch_counters(ast_index.clone()).await.counter_docs, 1);
+
+        doc_remove(ast_index.clone(), &library_file_path).await;
+        flush_sled_batch(ast_index.clone(), 0).await;
+        assert_eq!(doc_fingerprint_get(ast_index.clone(), &library_file_path).await, None);
+        assert_eq!(fetch_counters(ast_index.clone()).await.counter_docs, 0);
+    }
+
     #[tokio::test]
     async fn test_ast_db_cpp() {
         init_tracing();
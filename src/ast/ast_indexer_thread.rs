@@ -9,7 +9,7 @@ use crate::files_in_workspace::Document;
 use crate::global_context::GlobalContext;
 
 use crate::ast::ast_structs::{AstDB, AstStatus, AstCounters, AstErrorStats};
-use crate::ast::ast_db::{ast_index_init, fetch_counters, doc_add, doc_remove, flush_sled_batch, ConnectUsageContext, connect_usages, connect_usages_look_if_full_reset_needed};
+use crate::ast::ast_db::{ast_index_init, fetch_counters, doc_add, doc_remove, doc_cpaths_all, doc_fingerprint_calculate, doc_fingerprint_get, doc_fingerprint_set, flush_sled_batch, ConnectUsageContext, connect_usages, connect_usages_look_if_full_reset_needed};
 
 
 pub struct AstIndexService {
@@ -17,6 +17,7 @@ pub struct AstIndexService {
     pub ast_status: Arc<AMutex<AstStatus>>,
     pub ast_sleeping_point: Arc<ANotify>,
     pub ast_todo: IndexSet<String>,
+    pub ast_forget: IndexSet<String>,  // in ast_todo too, but to be removed from the index even if the file is still there
 }
 
 async fn ast_indexer_thread(
@@ -27,6 +28,7 @@ async fn ast_indexer_thread(
     let mut reported_connect_stats = true;
     let mut stats_parsed_cnt = 0;
     let mut stats_symbols_cnt = 0;
+    let mut stats_unchanged_cnt = 0;
     let mut stats_t0 = std::time::Instant::now();
     let mut stats_update_ts = std::time::Instant::now() - std::time::Duration::from_millis(1000);
     let mut stats_failure_reasons: IndexMap<String, usize> = IndexMap::new();
@@ -41,7 +43,10 @@ async fn ast_indexer_thread(
             ast_service_locked.ast_sleeping_point.clone(),
         )
     };
-    let ast_max_files = ast_index.lock().await.ast_max_files;  // cannot change
+    let (ast_max_files, is_permanent) = {
+        let ast_index_locked = ast_index.lock().await;
+        (ast_index_locked.ast_max_files, ast_index_locked.is_permanent)  // cannot change
+    };
 
     loop {
         let (cpath, left_todo_count) = {
@@ -72,12 +77,36 @@ async fn ast_indexer_thread(
                     break;
                 }
             };
-            let mut doc = Document { doc_path: cpath.clone().into(), doc_text: None };
+            if ast_service.lock().await.ast_forget.swap_remove(&cpath) {
+                doc_remove(ast_index.clone(), &cpath).await;
+                continue;
+            }
+            let mut doc = Document { doc_path: cpath.clone().into(), doc_text: None, doc_version: None };
 
-            doc_remove(ast_index.clone(), &cpath).await;
+            // Permanent database: files that didn't change since the last run keep their records
+            let stored_fingerprint = if is_permanent { doc_fingerprint_get(ast_index.clone(), &cpath).await } else { None };
+            let is_in_memory = gcx.read().await.documents_state.memory_document_map.contains_key(&doc.doc_path);
+            let disk_mtime_ns = if is_in_memory { 0 } else { file_mtime_ns(&doc.doc_path).await };
+            if let Some(stored) = &stored_fingerprint {
+                if !is_in_memory && disk_mtime_ns != 0 && stored.mtime_ns == disk_mtime_ns && Some(stored.size) == file_size(&doc.doc_path).await {
+                    stats_unchanged_cnt += 1;
+                    continue;
+                }
+            }
 
             match crate::files_in_workspace::get_file_text_from_memory_or_disk(gcx.clone(), &doc.doc_path).await {
                 Ok(file_text) => {
+                    let fingerprint = doc_fingerprint_calculate(&file_text, disk_mtime_ns);
+                    if let Some(stored) = &stored_fingerprint {
+                        if stored.text_md5 == fingerprint.text_md5 {
+                            if *stored != fingerprint {
+                                doc_fingerprint_set(ast_index.clone(), &cpath, &fingerprint).await;  // touched but not changed, remember new mtime
+                            }
+                            stats_unchanged_cnt += 1;
+                            continue;
+                        }
+                    }
+                    doc_remove(ast_index.clone(), &cpath).await;
                     doc.update_text(&file_text);
                     let mut error_message: Option<String> = None;
                     match doc.does_text_look_good() {
@@ -89,6 +118,9 @@ async fn ast_indexer_thread(
                                     if elapsed > 0.1 {
                                         tracing::info!("{}/{} doc_add {:.3?}s {}", stats_parsed_cnt, (stats_parsed_cnt+left_todo_count), elapsed, crate::nicer_logs::last_n_chars(&cpath, 40));
                                     }
+                                    if is_permanent {
+                                        doc_fingerprint_set(ast_index.clone(), &cpath, &fingerprint).await;
+                                    }
                                     stats_parsed_cnt += 1;
                                     stats_symbols_cnt += defs.len();
                                     *stats_success_languages.entry(language).or_insert(0) += 1;
@@ -107,6 +139,7 @@ async fn ast_indexer_thread(
                     }
                 }
                 Err(_e) => {
+                    doc_remove(ast_index.clone(), &cpath).await;
                     tracing::info!("deleting from index {} because cannot read it", crate::nicer_logs::last_n_chars(&cpath, 30));
                     *stats_failure_reasons.entry("cannot read file".to_string()).or_insert(0) += 1;
                 }
@@ -158,6 +191,9 @@ async fn ast_indexer_thread(
                     stats_t0.elapsed().as_secs_f64()
                 );
             }
+            if stats_unchanged_cnt > 0 {
+                info!("AST skipped {} files unchanged since the last run", stats_unchanged_cnt);
+            }
             let language_stats: String = if stats_success_languages.is_empty() {
                 "no files".to_string()
             } else {
@@ -186,6 +222,7 @@ async fn ast_indexer_thread(
             stats_failure_reasons.clear();
             stats_parsed_cnt = 0;
             stats_symbols_cnt = 0;
+            stats_unchanged_cnt = 0;
             reported_parse_stats = true;
             let counters: AstCounters = fetch_counters(ast_index.clone()).await;
             {
@@ -263,6 +300,20 @@ async fn ast_indexer_thread(
     }
 }
 
+async fn file_mtime_ns(path: &std::path::PathBuf) -> u64
+{
+    tokio::fs::metadata(path).await.ok()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+async fn file_size(path: &std::path::PathBuf) -> Option<u64>
+{
+    tokio::fs::metadata(path).await.ok().map(|m| m.len())
+}
+
 pub async fn ast_indexer_block_until_finished(ast_service: Arc<AMutex<AstIndexService>>, max_blocking_time_ms: usize, wake_up_indexer: bool) -> bool
 {
     let max_blocking_duration = tokio::time::Duration::from_millis(max_blocking_time_ms as u64);
@@ -303,6 +354,12 @@ pub async fn ast_indexer_block_until_finished(ast_service: Arc<AMutex<AstIndexSe
 pub async fn ast_service_init(ast_permanent: String, ast_max_files: usize) -> Arc<AMutex<AstIndexService>>
 {
     let ast_index = ast_index_init(ast_permanent, ast_max_files, false).await;
+    let mut ast_todo = IndexSet::new();
+    if ast_index.lock().await.is_permanent {
+        // Files deleted while we were not running are only known to the database, check them all, unchanged ones are quick
+        ast_todo.extend(doc_cpaths_all(ast_index.clone()).await);
+        info!("AST permanent database has {} files, will check them for changes", ast_todo.len());
+    }
     let ast_status = Arc::new(AMutex::new(AstStatus {
         astate_notify: Arc::new(ANotify::new()),
         astate: String::from("starting"),
@@ -317,7 +374,8 @@ pub async fn ast_service_init(ast_permanent: String, ast_max_files: usize) -> Ar
         ast_sleeping_point: Arc::new(ANotify::new()),
         ast_index,
         ast_status,
-        ast_todo: IndexSet::new(),
+        ast_todo,
+        ast_forget: IndexSet::new(),
     };
     Arc::new(AMutex::new(ast_service))
 }
@@ -345,6 +403,7 @@ pub async fn ast_indexer_enqueue_files(ast_service: Arc<AMutex<AstIndexService>>
         ast_status = ast_service_locked.ast_status.clone();
         for cpath in cpaths {
             ast_service_locked.ast_todo.insert(cpath.clone());
+            ast_service_locked.ast_forget.swap_remove(cpath);
         }
     }
     {
@@ -361,3 +420,14 @@ pub async fn ast_indexer_enqueue_files(ast_service: Arc<AMutex<AstIndexService>>
         ast_service_locked.ast_sleeping_point.notify_waiters();
     }
 }
+
+// For files that are still on disk but shouldn't be indexed anymore, like the ones that became ignored
+pub async fn ast_indexer_forget_files(ast_service: Arc<AMutex<AstIndexService>>, cpaths: &Vec<String>)
+{
+    let mut ast_service_locked = ast_service.lock().await;
+    for cpath in cpaths {
+        ast_service_locked.ast_todo.insert(cpath.clone());
+        ast_service_locked.ast_forget.insert(cpath.clone());
+    }
+    ast_service_locked.ast_sleeping_point.notify_waiters();
+}
@@ -57,12 +57,20 @@ impl AstDefinition {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct AstDocFingerprint {
+    pub mtime_ns: u64,    // zero if the text came from memory (file open in IDE)
+    pub size: u64,
+    pub text_md5: String,
+}
+
 pub struct AstDB {
     pub sleddb: Arc<sled::Db>,
     pub sledbatch: Arc<AMutex<sled::Batch>>,
     pub batch_counter: usize,
     pub counters_increase: HashMap<String, i32>,
     pub ast_max_files: usize,
+    pub is_permanent: bool,
 }
 
 #[derive(Serialize, Clone)]
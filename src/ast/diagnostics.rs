@@ -0,0 +1,172 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock as ARwLock;
+use tree_sitter::{Node, Parser};
+
+use crate::ast::linters::lint_problems;
+use crate::ast::treesitter::parsers::{get_language_id_by_filename, get_tree_sitter_language};
+use crate::files_in_workspace::{Document, get_file_text_from_memory_or_disk};
+use crate::global_context::GlobalContext;
+
+const TOO_MANY_DIAGNOSTICS: usize = 100;
+const ERROR_SNIPPET_MAX_CHARS: usize = 30;
+
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AstDiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AstDiagnostic {
+    // lines are 1-based and inclusive, columns are 0-based in UTF-16 code units like in LSP
+    pub line1: usize,
+    pub col1: usize,
+    pub line2: usize,
+    pub col2: usize,
+    pub severity: AstDiagnosticSeverity,
+    pub source: String,
+    pub message: String,
+}
+
+fn byte_to_line0_col16(rope: &Rope, byte_idx: usize) -> (usize, usize) {
+    let char_idx = rope.byte_to_char(byte_idx.min(rope.len_bytes()));
+    let line0 = rope.char_to_line(char_idx);
+    let line_start = rope.line_to_char(line0);
+    let col16 = rope.char_to_utf16_cu(char_idx) - rope.char_to_utf16_cu(line_start);
+    (line0, col16)
+}
+
+fn line_len16(rope: &Rope, line0: usize) -> usize {
+    if line0 >= rope.len_lines() {
+        return 0;
+    }
+    rope.line(line0).to_string().trim_end_matches(&['\r', '\n'][..]).encode_utf16().count()
+}
+
+fn collect_syntax_errors(node: &Node, code: &str, rope: &Rope, out: &mut Vec<AstDiagnostic>) {
+    if out.len() >= TOO_MANY_DIAGNOSTICS {
+        return;
+    }
+    if node.is_missing() || node.is_error() {
+        let message = if node.is_missing() {
+            format!("syntax error: missing {:?}", node.kind())
+        } else {
+            let snippet = code.get(node.start_byte()..node.end_byte()).unwrap_or("")
+                .lines().next().unwrap_or("").trim()
+                .chars().take(ERROR_SNIPPET_MAX_CHARS).collect::<String>();
+            if snippet.is_empty() {
+                "syntax error".to_string()
+            } else {
+                format!("syntax error: unexpected {:?}", snippet)
+            }
+        };
+        let (start_line0, start_col) = byte_to_line0_col16(rope, node.start_byte());
+        let (end_line0, end_col) = byte_to_line0_col16(rope, node.end_byte());
+        out.push(AstDiagnostic {
+            line1: start_line0 + 1,
+            col1: start_col,
+            line2: end_line0 + 1,
+            col2: end_col,
+            severity: AstDiagnosticSeverity::Error,
+            source: "syntax".to_string(),
+            message,
+        });
+        return;
+    }
+    if !node.has_error() {
+        return;
+    }
+    for i in 0..node.child_count() {
+        if let Some(child) = node.child(i) {
+            collect_syntax_errors(&child, code, rope, out);
+        }
+    }
+}
+
+pub fn syntax_errors(cpath: &PathBuf, code: &str) -> Vec<AstDiagnostic> {
+    let language = match get_language_id_by_filename(cpath).and_then(get_tree_sitter_language) {
+        Some(x) => x,
+        None => return vec![],
+    };
+    let mut parser = Parser::new();
+    if let Err(e) = parser.set_language(&language) {
+        tracing::error!("cannot set language for {}: {}", cpath.display(), e);
+        return vec![];
+    }
+    let tree = match parser.parse(code, None) {
+        Some(x) => x,
+        None => return vec![],
+    };
+    let rope = Rope::from_str(code);
+    let mut result = vec![];
+    collect_syntax_errors(&tree.root_node(), code, &rope, &mut result);
+    result
+}
+
+pub fn diagnostics_for_text(cpath: &PathBuf, code: &str) -> Vec<AstDiagnostic> {
+    if get_language_id_by_filename(cpath).is_none() {
+        return vec![];
+    }
+    let mut result = syntax_errors(cpath, code);
+    let rope = Rope::from_str(code);
+    let doc = Document { doc_path: cpath.clone(), doc_text: Some(rope.clone()), doc_version: None };
+    for problem in lint_problems(&doc) {
+        let line0 = problem.line1.saturating_sub(1);
+        result.push(AstDiagnostic {
+            line1: line0 + 1,
+            col1: 0,
+            line2: line0 + 1,
+            col2: if problem.line1 == 0 { 0 } else { line_len16(&rope, line0) },
+            severity: AstDiagnosticSeverity::Warning,
+            source: "linter".to_string(),
+            message: problem.message,
+        });
+    }
+    result.truncate(TOO_MANY_DIAGNOSTICS);
+    result
+}
+
+pub async fn diagnostics_for_file(gcx: Arc<ARwLock<GlobalContext>>, cpath: &PathBuf) -> Result<Vec<AstDiagnostic>, String> {
+    let code = get_file_text_from_memory_or_disk(gcx.clone(), cpath).await?;
+    Ok(diagnostics_for_text(cpath, &code))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syntax_errors() {
+        let rust_ok = "fn main() {\n    let x = 1;\n}\n";
+        assert!(diagnostics_for_text(&PathBuf::from("/tmp/a.rs"), rust_ok).is_empty());
+
+        let rust_missing = "fn main() {\n    let x = 1\n}\n";
+        let diags = diagnostics_for_text(&PathBuf::from("/tmp/a.rs"), rust_missing);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, AstDiagnosticSeverity::Error);
+        assert_eq!(diags[0].line1, 2);
+        assert!(diags[0].message.contains("missing"), "{}", diags[0].message);
+
+        let py_broken = "def f(:\n    return 1\n";
+        let diags = diagnostics_for_text(&PathBuf::from("/tmp/a.py"), py_broken);
+        assert!(!diags.is_empty());
+        assert!(diags.iter().all(|d| d.line1 == 1 && d.source == "syntax"), "{:?}", diags);
+
+        assert!(diagnostics_for_text(&PathBuf::from("/tmp/a.txt"), "def f(:").is_empty());
+    }
+
+    #[test]
+    fn test_linter_problems_become_warnings() {
+        let py_mixed = "def f():\n\treturn 1\n\ndef g():\n    return 2\n";
+        let diags = diagnostics_for_text(&PathBuf::from("/tmp/a.py"), py_mixed);
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert_eq!(diags[0].severity, AstDiagnosticSeverity::Warning);
+        assert_eq!(diags[0].source, "linter");
+    }
+}
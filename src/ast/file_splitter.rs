@@ -17,13 +17,15 @@ pub(crate) const LINES_OVERLAP: usize = 3;
 
 pub struct AstBasedFileSplitter {
     fallback_file_splitter: crate::vecdb::vdb_file_splitter::FileSplitter,
+    per_definition: bool,  // one split per definition made from its outline, instead of the full text in windows
 }
 
 impl AstBasedFileSplitter {
 
-    pub fn new(window_size: usize) -> Self {
+    pub fn new(window_size: usize, per_definition: bool) -> Self {
         Self {
             fallback_file_splitter: crate::vecdb::vdb_file_splitter::FileSplitter::new(window_size),
+            per_definition,
         }
     }
 
@@ -39,6 +41,10 @@ impl AstBasedFileSplitter {
         let doc_lines: Vec<String> = doc_text.split("\n").map(|x| x.to_string()).collect();
         let path = doc.doc_path.clone();
 
+        if let Some(splits) = crate::doc_splitter::doc_split(&path, &doc_text, tokenizer.clone(), tokens_limit) {
+            return Ok(splits);
+        }
+
         let (mut parser, language) = match get_ast_parser_by_filename(&path) {
             Ok(parser) => parser,
             Err(_e) => {
@@ -117,6 +123,16 @@ impl AstBasedFileSplitter {
             flush_accumulator(&mut unused_symbols_cluster_accumulator, &mut chunks);
 
             let formatter = make_formatter(&language);
+            if self.per_definition {
+                // fields are in the skeleton of their struct already
+                if symbol.symbol_type != SymbolType::ClassFieldDeclaration {
+                    let (summary, top_bottom_rows) = formatter.make_definition_summary(&symbol, &doc_text, &guid_to_children, &guid_to_info);
+                    let chunks_ = crate::ast::chunk_utils::get_chunks(&summary, &symbol.file_path,
+                                             &symbol.symbol_path, top_bottom_rows, tokenizer.clone(), tokens_limit, LINES_OVERLAP, true);
+                    chunks.extend(chunks_.into_iter().take(1));
+                }
+                continue;
+            }
             if symbol.symbol_type == SymbolType::StructDeclaration {
                 if let Some(children) = guid_to_children.get(&symbol.guid) {
                     if !children.is_empty() {
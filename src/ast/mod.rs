@@ -16,6 +16,7 @@ pub mod ast_indexer_thread;
 pub mod ast_db;
 
 pub mod linters;
+pub mod diagnostics;
 
 #[cfg(feature="vecdb")]
 pub mod file_splitter;
@@ -104,6 +104,7 @@ impl From<&str> for LanguageId {
             "java" => Self::Java,
             "javascript" => Self::JavaScript,
             // "json" => Self::Json,
+            "kotlin" => Self::Kotlin,
             "lua" => Self::Lua,
             // "markdown" => Self::Markdown,
             // "objective-c" => Self::ObjectiveC,
@@ -145,6 +146,12 @@ impl From<Language> for LanguageId {
             Self::TypeScript
         } else if value == tree_sitter_typescript::language_tsx() {
             Self::TypeScriptReact
+        } else if value == tree_sitter_go::language() {
+            Self::Go
+        } else if value == tree_sitter_c_sharp::language() {
+            Self::CSharp
+        } else if value == tree_sitter_kotlin::language() {
+            Self::Kotlin
         } else {
             Self::Unknown
         }
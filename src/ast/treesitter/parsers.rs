@@ -16,6 +16,9 @@ mod java;
 mod cpp;
 mod ts;
 mod js;
+pub(crate) mod go;
+mod csharp;
+mod kotlin;
 
 
 #[derive(Debug, PartialEq, Eq)]
@@ -65,6 +68,18 @@ pub(crate) fn get_ast_parser(language_id: LanguageId) -> Result<Box<dyn AstLangu
             let parser = ts::TSParser::new()?; //quick fix untill we have a dedicated parser for TypeScriptReact
             Ok(Box::new(parser))
         }
+        LanguageId::Go => {
+            let parser = go::GoParser::new()?;
+            Ok(Box::new(parser))
+        }
+        LanguageId::CSharp => {
+            let parser = csharp::CSharpParser::new()?;
+            Ok(Box::new(parser))
+        }
+        LanguageId::Kotlin => {
+            let parser = kotlin::KotlinParser::new()?;
+            Ok(Box::new(parser))
+        }
         other => Err(ParserError {
             message: "Unsupported language id: ".to_string() + &other.to_string()
         }),
@@ -72,6 +87,23 @@ pub(crate) fn get_ast_parser(language_id: LanguageId) -> Result<Box<dyn AstLangu
 }
 
 
+pub(crate) fn get_tree_sitter_language(language_id: LanguageId) -> Option<tree_sitter::Language> {
+    match language_id {
+        LanguageId::Rust => Some(tree_sitter_rust::language()),
+        LanguageId::Python => Some(tree_sitter_python::language()),
+        LanguageId::Java => Some(tree_sitter_java::language()),
+        LanguageId::Cpp => Some(tree_sitter_cpp::language()),
+        LanguageId::TypeScript => Some(tree_sitter_typescript::language_typescript()),
+        LanguageId::TypeScriptReact => Some(tree_sitter_typescript::language_tsx()),
+        LanguageId::JavaScript => Some(tree_sitter_javascript::language()),
+        LanguageId::Go => Some(tree_sitter_go::language()),
+        LanguageId::CSharp => Some(tree_sitter_c_sharp::language()),
+        LanguageId::Kotlin => Some(tree_sitter_kotlin::language()),
+        _ => None,
+    }
+}
+
+
 pub fn get_ast_parser_by_filename(filename: &PathBuf) -> Result<(Box<dyn AstLanguageParser + 'static>, LanguageId), ParserError> {
     let suffix = filename.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
     let maybe_language_id = get_language_id_by_filename(filename);
@@ -95,6 +127,9 @@ pub fn get_language_id_by_filename(filename: &PathBuf) -> Option<LanguageId> {
         "rs" => Some(LanguageId::Rust),
         "ts" => Some(LanguageId::TypeScript),
         "tsx" => Some(LanguageId::TypeScriptReact),
+        "go" => Some(LanguageId::Go),
+        "cs" => Some(LanguageId::CSharp),
+        "kt" | "kts" => Some(LanguageId::Kotlin),
         _ => None
     }
 }
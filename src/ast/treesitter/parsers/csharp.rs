@@ -0,0 +1,837 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::string::ToString;
+use std::sync::Arc;
+
+#[cfg(test)]
+use itertools::Itertools;
+
+use parking_lot::RwLock;
+use similar::DiffableStr;
+use tree_sitter::{Node, Parser, Range};
+use tree_sitter_c_sharp::language;
+use uuid::Uuid;
+
+use crate::ast::treesitter::ast_instance_structs::{AstSymbolFields, AstSymbolInstanceArc, ClassFieldDeclaration, CommentDefinition, FunctionArg, FunctionCall, FunctionDeclaration, ImportDeclaration, ImportType, StructDeclaration, TypeDef, VariableDefinition, VariableUsage};
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, ParserError};
+use crate::ast::treesitter::parsers::utils::{CandidateInfo, get_guid};
+
+pub(crate) struct CSharpParser {
+    pub parser: Parser,
+}
+
+static CSHARP_KEYWORDS: [&str; 77] = [
+    "abstract", "as", "base", "bool", "break", "byte", "case", "catch", "char", "checked",
+    "class", "const", "continue", "decimal", "default", "delegate", "do", "double", "else", "enum",
+    "event", "explicit", "extern", "false", "finally", "fixed", "float", "for", "foreach", "goto",
+    "if", "implicit", "in", "int", "interface", "internal", "is", "lock", "long", "namespace",
+    "new", "null", "object", "operator", "out", "override", "params", "private", "protected", "public",
+    "readonly", "ref", "return", "sbyte", "sealed", "short", "sizeof", "stackalloc", "static", "string",
+    "struct", "switch", "this", "throw", "true", "try", "typeof", "uint", "ulong", "unchecked",
+    "unsafe", "ushort", "using", "virtual", "void", "volatile", "while",
+];
+
+static SYSTEM_MODULES: [&str; 2] = [
+    "System", "Microsoft",
+];
+
+pub fn parse_type(parent: &Node, code: &str) -> Option<TypeDef> {
+    let kind = parent.kind();
+    let text = code.slice(parent.byte_range()).to_string();
+    match kind {
+        "identifier" => {
+            return Some(TypeDef {
+                name: Some(text),
+                inference_info: None,
+                inference_info_guid: None,
+                is_pod: false,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            });
+        }
+        "predefined_type" => {
+            return Some(TypeDef {
+                name: None,
+                inference_info: Some(text),
+                inference_info_guid: None,
+                is_pod: true,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            });
+        }
+        "generic_name" => {
+            let mut decl = TypeDef::default();
+            for i in 0..parent.named_child_count() {
+                let child = parent.named_child(i).unwrap();
+                match child.kind() {
+                    "identifier" => {
+                        decl.name = Some(code.slice(child.byte_range()).to_string());
+                    }
+                    "type_argument_list" => {
+                        for i in 0..child.named_child_count() {
+                            let child = child.named_child(i).unwrap();
+                            if let Some(t) = parse_type(&child, code) {
+                                decl.nested_types.push(t);
+                            }
+                        }
+                    }
+                    &_ => {}
+                }
+            }
+            return Some(decl);
+        }
+        "qualified_name" | "alias_qualified_name" => {
+            let mut decl = TypeDef::default();
+            if let Some(name) = parent.child_by_field_name("name") {
+                if let Some(dtype) = parse_type(&name, code) {
+                    decl = dtype;
+                }
+            }
+            for field in ["qualifier", "alias"] {
+                if let Some(qualifier) = parent.child_by_field_name(field) {
+                    decl.namespace = code.slice(qualifier.byte_range()).to_string();
+                }
+            }
+            return Some(decl);
+        }
+        "nullable_type" | "pointer_type" | "ref_type" | "scoped_type" => {
+            if let Some(type_node) = parent.child_by_field_name("type") {
+                return parse_type(&type_node, code);
+            }
+        }
+        "array_type" => {
+            let mut decl = TypeDef {
+                name: Some("[]".to_string()),
+                inference_info: None,
+                inference_info_guid: None,
+                is_pod: false,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            };
+            if let Some(rank) = parent.child_by_field_name("rank") {
+                decl.name = Some(code.slice(rank.byte_range()).to_string());
+            }
+            if let Some(type_node) = parent.child_by_field_name("type") {
+                if let Some(dtype) = parse_type(&type_node, code) {
+                    decl.nested_types.push(dtype);
+                }
+            }
+            return Some(decl);
+        }
+        "tuple_type" => {
+            let mut decl = TypeDef::default();
+            for i in 0..parent.named_child_count() {
+                let child = parent.named_child(i).unwrap();
+                if let Some(type_node) = child.child_by_field_name("type") {
+                    if let Some(dtype) = parse_type(&type_node, code) {
+                        decl.nested_types.push(dtype);
+                    }
+                }
+            }
+            return Some(decl);
+        }
+        &_ => {}
+    }
+    None
+}
+
+fn parse_function_arg(parent: &Node, code: &str) -> FunctionArg {
+    let mut arg = FunctionArg::default();
+    if let Some(name) = parent.child_by_field_name("name") {
+        arg.name = code.slice(name.byte_range()).to_string();
+    }
+    if let Some(type_node) = parent.child_by_field_name("type") {
+        arg.type_ = parse_type(&type_node, code);
+    }
+    arg
+}
+
+// braces usually go on their own line in C#, so the declaration ends where the node before the body ends
+fn declaration_range_before_body(parent: &Node, body: &Node) -> Range {
+    let end = body.prev_sibling().unwrap_or(body.clone());
+    let (end_byte, end_point) = if end.id() == body.id() {
+        (body.start_byte(), body.start_position())
+    } else {
+        (end.end_byte(), end.end_position())
+    };
+    Range {
+        start_byte: parent.start_byte(),
+        end_byte,
+        start_point: parent.start_position(),
+        end_point,
+    }
+}
+
+impl CSharpParser {
+    pub fn new() -> Result<CSharpParser, ParserError> {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&language())
+            .map_err(internal_error)?;
+        Ok(CSharpParser { parser })
+    }
+
+    pub fn parse_struct_declaration<'a>(
+        &mut self,
+        info: &CandidateInfo<'a>,
+        code: &str,
+        candidates: &mut VecDeque<CandidateInfo<'a>>,
+    ) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = StructDeclaration::default();
+
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.definition_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+
+        if let Some(name_node) = info.node.child_by_field_name("name") {
+            decl.ast_fields.name = code.slice(name_node.byte_range()).to_string();
+        }
+
+        for i in 0..info.node.named_child_count() {
+            let child = info.node.named_child(i).unwrap();
+            match child.kind() {
+                "base_list" => {
+                    symbols.extend(self.find_error_usages(&child, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+                    for i in 0..child.named_child_count() {
+                        let child = child.named_child(i).unwrap();
+                        let type_node = if child.kind() == "primary_constructor_base_type" {
+                            child.child_by_field_name("type")
+                        } else {
+                            Some(child)
+                        };
+                        if let Some(dtype) = type_node.and_then(|t| parse_type(&t, code)) {
+                            decl.inherited_types.push(dtype);
+                        }
+                    }
+                }
+                "type_parameter_list" => {
+                    for i in 0..child.named_child_count() {
+                        let child = child.named_child(i).unwrap();
+                        if let Some(name) = child.child_by_field_name("name") {
+                            decl.template_types.push(TypeDef {
+                                name: Some(code.slice(name.byte_range()).to_string()),
+                                ..Default::default()
+                            });
+                        }
+                    }
+                }
+                &_ => {}
+            }
+        }
+
+        if let Some(body) = info.node.child_by_field_name("body") {
+            decl.ast_fields.definition_range = body.range();
+            decl.ast_fields.declaration_range = declaration_range_before_body(&info.node, &body);
+            candidates.push_back(CandidateInfo {
+                ast_fields: decl.ast_fields.clone(),
+                node: body,
+                parent_guid: decl.ast_fields.guid.clone(),
+            })
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    // `int a = 1, b;` is a variable_declaration with a type and several declarators
+    fn parse_variable_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>, is_field: bool) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        let mut declaration: Option<Node> = None;
+        for i in 0..info.node.named_child_count() {
+            let child = info.node.named_child(i).unwrap();
+            if child.kind() == "variable_declaration" {
+                declaration = Some(child);
+            }
+        }
+        let Some(declaration) = declaration else {
+            return symbols;
+        };
+        let mut type_ = TypeDef::default();
+        if let Some(type_node) = declaration.child_by_field_name("type") {
+            if let Some(dtype) = parse_type(&type_node, code) {
+                type_ = dtype;
+            }
+        }
+
+        let declarators_n = (0..declaration.named_child_count())
+            .filter_map(|i| declaration.named_child(i))
+            .filter(|x| x.kind() == "variable_declarator")
+            .count();
+        for i in 0..declaration.named_child_count() {
+            let child = declaration.named_child(i).unwrap();
+            if child.kind() != "variable_declarator" {
+                continue;
+            }
+            // `int a, b;` declares several variables, each of them gets its own range
+            let full_range = if declarators_n > 1 { child.range() } else { info.node.range() };
+            symbols.extend(self.find_error_usages(&child, code, &info.ast_fields.file_path, &info.parent_guid));
+            let mut name = String::new();
+            let mut value: Option<Node> = None;
+            if let Some(name_node) = child.child_by_field_name("name") {
+                name = code.slice(name_node.byte_range()).to_string();
+                for j in 0..child.named_child_count() {
+                    let value_node = child.named_child(j).unwrap();
+                    if value_node.id() != name_node.id() {
+                        value = Some(value_node);
+                    }
+                }
+            }
+            let mut dtype = type_.clone();
+            if let Some(value) = value {
+                dtype.inference_info = Some(code.slice(value.byte_range()).to_string());
+                candidates.push_back(CandidateInfo {
+                    ast_fields: info.ast_fields.clone(),
+                    node: value,
+                    parent_guid: info.parent_guid.clone(),
+                });
+            }
+            if is_field {
+                let mut decl = ClassFieldDeclaration::default();
+                decl.ast_fields.language = info.ast_fields.language;
+                decl.ast_fields.full_range = full_range;
+                decl.ast_fields.declaration_range = info.node.range();
+                decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+                decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                decl.ast_fields.guid = get_guid();
+                decl.ast_fields.is_error = info.ast_fields.is_error;
+                decl.ast_fields.name = name;
+                decl.type_ = dtype;
+                symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+            } else {
+                let mut decl = VariableDefinition::default();
+                decl.ast_fields.language = info.ast_fields.language;
+                decl.ast_fields.full_range = full_range;
+                decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+                decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                decl.ast_fields.guid = get_guid();
+                decl.ast_fields.is_error = info.ast_fields.is_error;
+                decl.ast_fields.name = name;
+                decl.type_ = dtype;
+                symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+            }
+        }
+        symbols
+    }
+
+    fn parse_property_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let mut decl = ClassFieldDeclaration::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        if let Some(type_node) = info.node.child_by_field_name("type") {
+            if let Some(dtype) = parse_type(&type_node, code) {
+                decl.type_ = dtype;
+            }
+        }
+        if let Some(name) = info.node.child_by_field_name("name") {
+            decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+            if let Some(accessors) = info.node.child_by_field_name("accessors") {
+                let has_bodies = (0..accessors.named_child_count())
+                    .filter_map(|i| accessors.named_child(i))
+                    .any(|x| x.child_by_field_name("body").is_some());
+                // `{ get; set; }` is a part of the declaration, accessor bodies are not
+                if has_bodies {
+                    decl.ast_fields.declaration_range = Range {
+                        start_byte: decl.ast_fields.full_range.start_byte,
+                        end_byte: name.end_byte(),
+                        start_point: decl.ast_fields.full_range.start_point,
+                        end_point: name.end_position(),
+                    };
+                }
+                candidates.push_back(CandidateInfo {
+                    ast_fields: info.ast_fields.clone(),
+                    node: accessors,
+                    parent_guid: info.parent_guid.clone(),
+                });
+            }
+        }
+        if let Some(value) = info.node.child_by_field_name("value") {
+            decl.type_.inference_info = Some(code.slice(value.byte_range()).to_string());
+            candidates.push_back(CandidateInfo {
+                ast_fields: info.ast_fields.clone(),
+                node: value,
+                parent_guid: info.parent_guid.clone(),
+            });
+        }
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    fn parse_enum_field_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let mut decl = ClassFieldDeclaration::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        if let Some(name) = info.node.child_by_field_name("name") {
+            decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+        }
+        if let Some(value) = info.node.child_by_field_name("value") {
+            decl.type_.inference_info = Some(code.slice(value.byte_range()).to_string());
+            candidates.push_back(CandidateInfo {
+                ast_fields: info.ast_fields.clone(),
+                node: value,
+                parent_guid: info.parent_guid.clone(),
+            });
+        }
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    fn parse_usages_<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let kind = info.node.kind();
+        #[cfg(test)]
+        #[allow(unused)]
+            let text = code.slice(info.node.byte_range());
+        match kind {
+            "class_declaration" | "struct_declaration" | "interface_declaration" | "enum_declaration" | "record_declaration" => {
+                symbols.extend(self.parse_struct_declaration(info, code, candidates));
+            }
+            "namespace_declaration" | "file_scoped_namespace_declaration" => {
+                // the namespace name is not a usage, everything else is
+                let name = info.node.child_by_field_name("name");
+                for i in 0..info.node.named_child_count() {
+                    let child = info.node.named_child(i).unwrap();
+                    if name.map(|x| x.id() == child.id()).unwrap_or(false) {
+                        continue;
+                    }
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+            "local_declaration_statement" => {
+                symbols.extend(self.parse_variable_declaration(info, code, candidates, false));
+            }
+            "field_declaration" | "event_field_declaration" => {
+                symbols.extend(self.parse_variable_declaration(info, code, candidates, true));
+            }
+            "property_declaration" => {
+                symbols.extend(self.parse_property_declaration(info, code, candidates));
+            }
+            "enum_member_declaration" => {
+                symbols.extend(self.parse_enum_field_declaration(info, code, candidates));
+            }
+            "method_declaration" | "constructor_declaration" | "destructor_declaration" | "local_function_statement" => {
+                symbols.extend(self.parse_function_declaration(info, code, candidates));
+            }
+            "invocation_expression" | "object_creation_expression" => {
+                symbols.extend(self.parse_call_expression(info, code, candidates));
+            }
+            "identifier" => {
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = code.slice(info.node.byte_range()).to_string();
+                usage.ast_fields.language = info.ast_fields.language;
+                usage.ast_fields.full_range = info.node.range();
+                usage.ast_fields.file_path = info.ast_fields.file_path.clone();
+                usage.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.is_error = info.ast_fields.is_error;
+                if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+                    usage.ast_fields.guid = caller_guid;
+                }
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "member_access_expression" => {
+                let expression = info.node.child_by_field_name("expression").unwrap();
+                let name = info.node.child_by_field_name("name").unwrap();
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = parse_type(&name, code).and_then(|t| t.name)
+                    .unwrap_or(code.slice(name.byte_range()).to_string());
+                usage.ast_fields.language = info.ast_fields.language;
+                usage.ast_fields.full_range = info.node.range();
+                usage.ast_fields.file_path = info.ast_fields.file_path.clone();
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                usage.ast_fields.caller_guid = Some(get_guid());
+                usage.ast_fields.is_error = info.ast_fields.is_error;
+                if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+                    usage.ast_fields.guid = caller_guid;
+                }
+                candidates.push_back(CandidateInfo {
+                    ast_fields: usage.ast_fields.clone(),
+                    node: expression,
+                    parent_guid: info.parent_guid.clone(),
+                });
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "lambda_expression" | "anonymous_method_expression" => {
+                // parameters are declarations, only the body has usages
+                let body = info.node.child_by_field_name("body")
+                    .or_else(|| (0..info.node.named_child_count())
+                        .filter_map(|i| info.node.named_child(i))
+                        .find(|x| x.kind() == "block"));
+                if let Some(body) = body {
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: body,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+            "comment" => {
+                let mut def = CommentDefinition::default();
+                def.ast_fields.language = info.ast_fields.language;
+                def.ast_fields.full_range = info.node.range();
+                def.ast_fields.file_path = info.ast_fields.file_path.clone();
+                def.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                def.ast_fields.guid = get_guid();
+                def.ast_fields.is_error = info.ast_fields.is_error;
+                symbols.push(Arc::new(RwLock::new(Box::new(def))));
+            }
+            "using_directive" => {
+                let mut def = ImportDeclaration::default();
+                def.ast_fields.language = info.ast_fields.language;
+                def.ast_fields.full_range = info.node.range();
+                def.ast_fields.file_path = info.ast_fields.file_path.clone();
+                // `using Alias = Some.Namespace;` keeps the alias in the `name` field
+                let alias = info.node.child_by_field_name("name");
+                if let Some(alias) = alias {
+                    def.alias = Some(code.slice(alias.byte_range()).to_string());
+                }
+                for i in 0..info.node.named_child_count() {
+                    let child = info.node.named_child(i).unwrap();
+                    if alias.map(|x| x.id() == child.id()).unwrap_or(false) {
+                        continue;
+                    }
+                    if ["qualified_name", "identifier", "generic_name", "alias_qualified_name"].contains(&child.kind()) {
+                        let path = code.slice(child.byte_range()).to_string();
+                        def.path_components = path.split(".").map(|x| x.to_string()).collect();
+                        if let Some(first) = def.path_components.first() {
+                            if SYSTEM_MODULES.contains(&first.as_str()) {
+                                def.import_type = ImportType::System;
+                            }
+                        }
+                    }
+                }
+                def.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                def.ast_fields.guid = get_guid();
+                symbols.push(Arc::new(RwLock::new(Box::new(def))));
+            }
+            "ERROR" => {
+                let mut ast = info.ast_fields.clone();
+                ast.is_error = true;
+
+                for i in 0..info.node.child_count() {
+                    let child = info.node.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: ast.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+            "attribute_list" | "parameter_list" | "type_parameter_list" | "type_parameter_constraints_clause" | "base_list" => {}
+            _ => {
+                for i in 0..info.node.child_count() {
+                    let child = info.node.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    })
+                }
+            }
+        }
+        symbols
+    }
+
+    fn find_error_usages(&mut self, parent: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        for i in 0..parent.child_count() {
+            let child = parent.child(i).unwrap();
+            if child.kind() == "ERROR" {
+                symbols.extend(self.parse_error_usages(&child, code, path, parent_guid));
+            }
+        }
+        symbols
+    }
+
+    fn parse_error_usages(&mut self, parent: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        match parent.kind() {
+            "identifier" => {
+                let name = code.slice(parent.byte_range()).to_string();
+                if CSHARP_KEYWORDS.contains(&name.as_str()) {
+                    return symbols;
+                }
+
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = name;
+                usage.ast_fields.language = LanguageId::CSharp;
+                usage.ast_fields.full_range = parent.range();
+                usage.ast_fields.file_path = path.clone();
+                usage.ast_fields.parent_guid = Some(parent_guid.clone());
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.is_error = true;
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "member_access_expression" => {
+                let expression = parent.child_by_field_name("expression").unwrap();
+                let usages = self.parse_error_usages(&expression, code, path, parent_guid);
+                let name = parent.child_by_field_name("name").unwrap();
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = code.slice(name.byte_range()).to_string();
+                usage.ast_fields.language = LanguageId::CSharp;
+                usage.ast_fields.full_range = parent.range();
+                usage.ast_fields.file_path = path.clone();
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.parent_guid = Some(parent_guid.clone());
+                usage.ast_fields.is_error = true;
+                if let Some(last) = usages.last() {
+                    usage.ast_fields.caller_guid = last.read().fields().parent_guid.clone();
+                }
+                symbols.extend(usages);
+                if !CSHARP_KEYWORDS.contains(&usage.ast_fields.name.as_str()) {
+                    symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+                }
+            }
+            &_ => {
+                for i in 0..parent.child_count() {
+                    let child = parent.child(i).unwrap();
+                    symbols.extend(self.parse_error_usages(&child, code, path, parent_guid));
+                }
+            }
+        }
+
+        symbols
+    }
+
+    pub fn parse_function_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = FunctionDeclaration::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.definition_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        decl.ast_fields.guid = get_guid();
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+
+        if let Some(name_node) = info.node.child_by_field_name("name") {
+            decl.ast_fields.name = code.slice(name_node.byte_range()).to_string();
+        }
+
+        if let Some(parameters_node) = info.node.child_by_field_name("parameters") {
+            symbols.extend(self.find_error_usages(&parameters_node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+            decl.ast_fields.declaration_range = Range {
+                start_byte: decl.ast_fields.full_range.start_byte,
+                end_byte: parameters_node.end_byte(),
+                start_point: decl.ast_fields.full_range.start_point,
+                end_point: parameters_node.end_position(),
+            };
+
+            let mut function_args = vec![];
+            for idx in 0..parameters_node.named_child_count() {
+                let child = parameters_node.named_child(idx).unwrap();
+                if child.kind() == "parameter" {
+                    function_args.push(parse_function_arg(&child, code));
+                }
+            }
+            decl.args = function_args;
+        }
+        if let Some(type_parameters) = info.node.child_by_field_name("type_parameters") {
+            for i in 0..type_parameters.named_child_count() {
+                let child = type_parameters.named_child(i).unwrap();
+                if let Some(name) = child.child_by_field_name("name") {
+                    decl.template_types.push(TypeDef {
+                        name: Some(code.slice(name.byte_range()).to_string()),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        // methods have `returns`, local functions have `type`
+        for field in ["returns", "type"] {
+            if let Some(return_type) = info.node.child_by_field_name(field) {
+                decl.return_type = parse_type(&return_type, code);
+                symbols.extend(self.find_error_usages(&return_type, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+            }
+        }
+
+        if let Some(body_node) = info.node.child_by_field_name("body") {
+            decl.ast_fields.definition_range = body_node.range();
+            decl.ast_fields.declaration_range = declaration_range_before_body(&info.node, &body_node);
+            candidates.push_back(CandidateInfo {
+                ast_fields: decl.ast_fields.clone(),
+                node: body_node,
+                parent_guid: decl.ast_fields.guid.clone(),
+            });
+        } else {
+            decl.ast_fields.declaration_range = decl.ast_fields.full_range;
+        }
+
+        // `: base(x)` and `: this(x)` calls
+        for i in 0..info.node.named_child_count() {
+            let child = info.node.named_child(i).unwrap();
+            if child.kind() == "constructor_initializer" {
+                candidates.push_back(CandidateInfo {
+                    ast_fields: decl.ast_fields.clone(),
+                    node: child,
+                    parent_guid: decl.ast_fields.guid.clone(),
+                });
+            }
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    pub fn parse_call_expression<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = FunctionCall::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+            decl.ast_fields.guid = caller_guid;
+        }
+        decl.ast_fields.caller_guid = Some(get_guid());
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        if let Some(function) = info.node.child_by_field_name("function") {
+            match function.kind() {
+                "identifier" | "generic_name" => {
+                    decl.ast_fields.name = parse_type(&function, code).and_then(|t| t.name)
+                        .unwrap_or(code.slice(function.byte_range()).to_string());
+                }
+                "member_access_expression" => {
+                    if let Some(name) = function.child_by_field_name("name") {
+                        decl.ast_fields.name = parse_type(&name, code).and_then(|t| t.name)
+                            .unwrap_or(code.slice(name.byte_range()).to_string());
+                    }
+                    if let Some(expression) = function.child_by_field_name("expression") {
+                        candidates.push_back(CandidateInfo {
+                            ast_fields: decl.ast_fields.clone(),
+                            node: expression,
+                            parent_guid: info.parent_guid.clone(),
+                        });
+                    }
+                }
+                &_ => {
+                    let mut new_ast_fields = info.ast_fields.clone();
+                    new_ast_fields.caller_guid = None;
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: new_ast_fields,
+                        node: function,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+        }
+        if let Some(type_) = info.node.child_by_field_name("type") {
+            symbols.extend(self.find_error_usages(&type_, code, &info.ast_fields.file_path, &info.parent_guid));
+            if let Some(dtype) = parse_type(&type_, code) {
+                if let Some(name) = dtype.name {
+                    decl.ast_fields.name = name;
+                } else {
+                    decl.ast_fields.name = code.slice(type_.byte_range()).to_string();
+                }
+            } else {
+                decl.ast_fields.name = code.slice(type_.byte_range()).to_string();
+            }
+        }
+        for field in ["arguments", "initializer"] {
+            if let Some(arguments) = info.node.child_by_field_name(field) {
+                symbols.extend(self.find_error_usages(&arguments, code, &info.ast_fields.file_path, &info.parent_guid));
+                let mut new_ast_fields = info.ast_fields.clone();
+                new_ast_fields.caller_guid = None;
+                for i in 0..arguments.child_count() {
+                    let child = arguments.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: new_ast_fields.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    fn parse_(&mut self, parent: &Node, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut ast_fields = AstSymbolFields::default();
+        ast_fields.file_path = path.clone();
+        ast_fields.is_error = false;
+        ast_fields.language = LanguageId::CSharp;
+
+        let mut candidates = VecDeque::from(vec![CandidateInfo {
+            ast_fields,
+            node: parent.clone(),
+            parent_guid: get_guid(),
+        }]);
+        while let Some(candidate) = candidates.pop_front() {
+            let symbols_l = self.parse_usages_(&candidate, code, &mut candidates);
+            symbols.extend(symbols_l);
+        }
+        let guid_to_symbol_map = symbols.iter()
+            .map(|s| (s.clone().read().guid().clone(), s.clone())).collect::<HashMap<_, _>>();
+        for symbol in symbols.iter_mut() {
+            let guid = symbol.read().guid().clone();
+            if let Some(parent_guid) = symbol.read().parent_guid() {
+                if let Some(parent) = guid_to_symbol_map.get(parent_guid) {
+                    parent.write().fields_mut().childs_guid.push(guid);
+                }
+            }
+        }
+
+        #[cfg(test)]
+        for symbol in symbols.iter_mut() {
+            let mut sym = symbol.write();
+            sym.fields_mut().childs_guid = sym.fields_mut().childs_guid.iter()
+                .sorted_by_key(|x| {
+                    guid_to_symbol_map.get(*x).unwrap().read().full_range().start_byte
+                }).map(|x| x.clone()).collect();
+        }
+
+        symbols
+    }
+}
+
+impl AstLanguageParser for CSharpParser {
+    fn parse(&mut self, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
+        let tree = self.parser.parse(code, None).unwrap();
+        let symbols = self.parse_(&tree.root_node(), code, path);
+        symbols
+    }
+}
@@ -0,0 +1,948 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::string::ToString;
+use std::sync::Arc;
+
+#[cfg(test)]
+use itertools::Itertools;
+
+use parking_lot::RwLock;
+use similar::DiffableStr;
+use tree_sitter::{Node, Parser, Range};
+use tree_sitter_go::language;
+use uuid::Uuid;
+
+use crate::ast::treesitter::ast_instance_structs::{AstSymbolFields, AstSymbolInstanceArc, ClassFieldDeclaration, CommentDefinition, FunctionArg, FunctionCall, FunctionDeclaration, ImportDeclaration, ImportType, StructDeclaration, SymbolInformation, TypeAlias, TypeDef, VariableDefinition, VariableUsage};
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, ParserError};
+use crate::ast::treesitter::parsers::utils::{CandidateInfo, get_guid};
+use crate::ast::treesitter::skeletonizer::SkeletonFormatter;
+use crate::ast::treesitter::structs::SymbolType;
+
+pub(crate) struct GoParser {
+    pub parser: Parser,
+    // method guid -> receiver type name, methods get attached to their struct after the parse
+    method_receivers: HashMap<Uuid, String>,
+}
+
+static GO_KEYWORDS: [&str; 25] = [
+    "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "for",
+    "func", "go", "goto", "if", "import", "interface", "map", "package", "range", "return",
+    "select", "struct", "switch", "type", "var",
+];
+
+static GO_BUILTIN_TYPES: [&str; 22] = [
+    "any", "bool", "byte", "complex64", "complex128", "error", "float32", "float64", "int", "int8",
+    "int16", "int32", "int64", "rune", "string", "uint", "uint8", "uint16", "uint32", "uint64",
+    "uintptr", "comparable",
+];
+
+pub fn parse_type(parent: &Node, code: &str) -> Option<TypeDef> {
+    let kind = parent.kind();
+    let text = code.slice(parent.byte_range()).to_string();
+    match kind {
+        "type_identifier" => {
+            if GO_BUILTIN_TYPES.contains(&text.as_str()) {
+                return Some(TypeDef {
+                    name: None,
+                    inference_info: Some(text),
+                    inference_info_guid: None,
+                    is_pod: true,
+                    namespace: "".to_string(),
+                    guid: None,
+                    nested_types: vec![],
+                });
+            }
+            return Some(TypeDef {
+                name: Some(text),
+                inference_info: None,
+                inference_info_guid: None,
+                is_pod: false,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            });
+        }
+        "qualified_type" => {
+            let mut decl = TypeDef::default();
+            if let Some(name) = parent.child_by_field_name("name") {
+                decl.name = Some(code.slice(name.byte_range()).to_string());
+            }
+            if let Some(package) = parent.child_by_field_name("package") {
+                decl.namespace = code.slice(package.byte_range()).to_string();
+            }
+            return Some(decl);
+        }
+        "pointer_type" | "parenthesized_type" | "type_elem" | "negated_type" | "type_constraint" => {
+            for i in 0..parent.named_child_count() {
+                let child = parent.named_child(i).unwrap();
+                if let Some(dtype) = parse_type(&child, code) {
+                    return Some(dtype);
+                }
+            }
+        }
+        "generic_type" => {
+            let mut decl = TypeDef::default();
+            if let Some(type_node) = parent.child_by_field_name("type") {
+                if let Some(dtype) = parse_type(&type_node, code) {
+                    decl.name = dtype.name;
+                    decl.namespace = dtype.namespace;
+                }
+            }
+            if let Some(type_arguments) = parent.child_by_field_name("type_arguments") {
+                for i in 0..type_arguments.named_child_count() {
+                    let child = type_arguments.named_child(i).unwrap();
+                    if let Some(t) = parse_type(&child, code) {
+                        decl.nested_types.push(t);
+                    }
+                }
+            }
+            return Some(decl);
+        }
+        "slice_type" | "array_type" | "implicit_length_array_type" => {
+            let mut decl = TypeDef {
+                name: Some("[]".to_string()),
+                inference_info: None,
+                inference_info_guid: None,
+                is_pod: false,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            };
+            if let Some(element) = parent.child_by_field_name("element") {
+                if let Some(dtype) = parse_type(&element, code) {
+                    decl.nested_types.push(dtype);
+                }
+            }
+            return Some(decl);
+        }
+        "map_type" => {
+            let mut decl = TypeDef {
+                name: Some("map".to_string()),
+                inference_info: None,
+                inference_info_guid: None,
+                is_pod: false,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            };
+            for field in ["key", "value"] {
+                if let Some(node) = parent.child_by_field_name(field) {
+                    if let Some(dtype) = parse_type(&node, code) {
+                        decl.nested_types.push(dtype);
+                    }
+                }
+            }
+            return Some(decl);
+        }
+        "channel_type" => {
+            let mut decl = TypeDef {
+                name: Some("chan".to_string()),
+                inference_info: None,
+                inference_info_guid: None,
+                is_pod: false,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            };
+            if let Some(value) = parent.child_by_field_name("value") {
+                if let Some(dtype) = parse_type(&value, code) {
+                    decl.nested_types.push(dtype);
+                }
+            }
+            return Some(decl);
+        }
+        "function_type" | "struct_type" | "interface_type" => {
+            return Some(TypeDef {
+                name: None,
+                inference_info: Some(text),
+                inference_info_guid: None,
+                is_pod: false,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            });
+        }
+        &_ => {}
+    }
+    None
+}
+
+fn parse_function_args(parent: &Node, code: &str) -> Vec<FunctionArg> {
+    let mut args = vec![];
+    for i in 0..parent.named_child_count() {
+        let child = parent.named_child(i).unwrap();
+        match child.kind() {
+            "parameter_declaration" | "variadic_parameter_declaration" => {
+                let type_ = child.child_by_field_name("type").and_then(|t| parse_type(&t, code));
+                let mut cursor = child.walk();
+                let names = child.children_by_field_name("name", &mut cursor).collect::<Vec<_>>();
+                if names.is_empty() {
+                    args.push(FunctionArg { name: "".to_string(), type_: type_.clone() });
+                }
+                for name in names {
+                    args.push(FunctionArg {
+                        name: code.slice(name.byte_range()).to_string(),
+                        type_: type_.clone(),
+                    });
+                }
+            }
+            &_ => {}
+        }
+    }
+    args
+}
+
+impl GoParser {
+    pub fn new() -> Result<GoParser, ParserError> {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&language())
+            .map_err(internal_error)?;
+        Ok(GoParser { parser, method_receivers: Default::default() })
+    }
+
+    fn parse_type_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+        let specs = (0..info.node.named_child_count())
+            .map(|i| info.node.named_child(i).unwrap())
+            .filter(|x| ["type_spec", "type_alias"].contains(&x.kind()))
+            .collect::<Vec<_>>();
+        // `type Foo struct {...}` covers the keyword, `type ( ... )` groups get one symbol per spec
+        let use_outer_range = specs.len() == 1;
+        for spec in specs {
+            let full_range = if use_outer_range { info.node.range() } else { spec.range() };
+            let type_node = spec.child_by_field_name("type");
+            let is_struct = spec.kind() == "type_spec" && type_node
+                .map(|x| ["struct_type", "interface_type"].contains(&x.kind()))
+                .unwrap_or(false);
+            if is_struct {
+                symbols.extend(self.parse_struct_declaration(info, &spec, &type_node.unwrap(), full_range, code, candidates));
+                continue;
+            }
+            let mut type_alias = TypeAlias::default();
+            type_alias.ast_fields.language = info.ast_fields.language;
+            type_alias.ast_fields.full_range = full_range;
+            type_alias.ast_fields.declaration_range = full_range;
+            type_alias.ast_fields.definition_range = full_range;
+            type_alias.ast_fields.file_path = info.ast_fields.file_path.clone();
+            type_alias.ast_fields.parent_guid = Some(info.parent_guid.clone());
+            type_alias.ast_fields.guid = get_guid();
+            type_alias.ast_fields.is_error = info.ast_fields.is_error;
+            if let Some(name) = spec.child_by_field_name("name") {
+                type_alias.ast_fields.name = code.slice(name.byte_range()).to_string();
+            }
+            if let Some(type_node) = type_node {
+                if let Some(dtype) = parse_type(&type_node, code) {
+                    type_alias.types.push(dtype);
+                }
+            }
+            symbols.push(Arc::new(RwLock::new(Box::new(type_alias))));
+        }
+        symbols
+    }
+
+    fn parse_struct_declaration<'a>(
+        &mut self,
+        info: &CandidateInfo<'a>,
+        spec: &Node<'a>,
+        type_node: &Node<'a>,
+        full_range: Range,
+        code: &str,
+        candidates: &mut VecDeque<CandidateInfo<'a>>,
+    ) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = StructDeclaration::default();
+
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = full_range;
+        decl.ast_fields.declaration_range = full_range;
+        decl.ast_fields.definition_range = full_range;
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+
+        symbols.extend(self.find_error_usages(spec, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+
+        if let Some(name_node) = spec.child_by_field_name("name") {
+            decl.ast_fields.name = code.slice(name_node.byte_range()).to_string();
+        }
+        if let Some(type_parameters) = spec.child_by_field_name("type_parameters") {
+            for i in 0..type_parameters.named_child_count() {
+                let child = type_parameters.named_child(i).unwrap();
+                if let Some(name) = child.child_by_field_name("name") {
+                    decl.template_types.push(TypeDef {
+                        name: Some(code.slice(name.byte_range()).to_string()),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        // struct_type wraps a field_declaration_list, interface_type holds its elements directly
+        let mut body: Option<Node> = None;
+        let mut body_range: Option<Range> = None;
+        if type_node.kind() == "struct_type" {
+            for i in 0..type_node.named_child_count() {
+                let child = type_node.named_child(i).unwrap();
+                if child.kind() == "field_declaration_list" {
+                    body = Some(child);
+                    body_range = Some(child.range());
+                }
+            }
+        } else {
+            body = Some(type_node.clone());
+            for i in 0..type_node.child_count() {
+                let child = type_node.child(i).unwrap();
+                if child.kind() == "{" {
+                    body_range = Some(Range {
+                        start_byte: child.start_byte(),
+                        end_byte: type_node.end_byte(),
+                        start_point: child.start_position(),
+                        end_point: type_node.end_position(),
+                    });
+                    break;
+                }
+            }
+        }
+
+        if let Some(body) = body {
+            symbols.extend(self.find_error_usages(&body, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+            // embedded types are the closest thing to inheritance Go has
+            for i in 0..body.named_child_count() {
+                let child = body.named_child(i).unwrap();
+                let embedded = match child.kind() {
+                    "field_declaration" => child.child_by_field_name("name").is_none(),
+                    "type_elem" => true,
+                    _ => false,
+                };
+                if embedded {
+                    let type_node = if child.kind() == "field_declaration" { child.child_by_field_name("type") } else { Some(child) };
+                    if let Some(dtype) = type_node.and_then(|t| parse_type(&t, code)) {
+                        decl.inherited_types.push(dtype);
+                    }
+                }
+            }
+            if let Some(body_range) = body_range {
+                decl.ast_fields.definition_range = body_range;
+                decl.ast_fields.declaration_range = Range {
+                    start_byte: decl.ast_fields.full_range.start_byte,
+                    end_byte: body_range.start_byte,
+                    start_point: decl.ast_fields.full_range.start_point,
+                    end_point: body_range.start_point,
+                };
+            }
+            candidates.push_back(CandidateInfo {
+                ast_fields: decl.ast_fields.clone(),
+                node: body,
+                parent_guid: decl.ast_fields.guid.clone(),
+            });
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    fn parse_field_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, _candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        let mut dtype = TypeDef::default();
+        if let Some(type_node) = info.node.child_by_field_name("type") {
+            if let Some(type_) = parse_type(&type_node, code) {
+                dtype = type_;
+            }
+        }
+        let mut cursor = info.node.walk();
+        let names = info.node.children_by_field_name("name", &mut cursor).collect::<Vec<_>>();
+        for name in names.iter() {
+            let mut decl = ClassFieldDeclaration::default();
+            decl.ast_fields.language = info.ast_fields.language;
+            // `X, Y float64` declares several fields, each of them gets its own range
+            decl.ast_fields.full_range = if names.len() > 1 { name.range() } else { info.node.range() };
+            decl.ast_fields.declaration_range = info.node.range();
+            decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+            decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+            decl.ast_fields.guid = get_guid();
+            decl.ast_fields.is_error = info.ast_fields.is_error;
+            decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+            decl.type_ = dtype.clone();
+            symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        }
+        symbols
+    }
+
+    fn parse_variable_spec<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        let mut type_ = TypeDef::default();
+        if let Some(type_node) = info.node.child_by_field_name("type") {
+            if let Some(dtype) = parse_type(&type_node, code) {
+                type_ = dtype;
+            }
+        }
+        let values = info.node.child_by_field_name("value")
+            .map(|v| (0..v.named_child_count()).map(|i| v.named_child(i).unwrap()).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let mut cursor = info.node.walk();
+        let names = info.node.children_by_field_name("name", &mut cursor)
+            .filter(|x| x.kind() == "identifier")
+            .collect::<Vec<_>>();
+        for (idx, name) in names.iter().enumerate() {
+            let mut decl = VariableDefinition::default();
+            decl.ast_fields.language = info.ast_fields.language;
+            decl.ast_fields.full_range = if names.len() > 1 { name.range() } else { info.node.range() };
+            decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+            decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+            decl.ast_fields.guid = get_guid();
+            decl.ast_fields.is_error = info.ast_fields.is_error;
+            decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+            decl.type_ = type_.clone();
+            if let Some(value) = values.get(idx) {
+                decl.type_.inference_info = Some(code.slice(value.byte_range()).to_string());
+            }
+            symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        }
+        for value in values {
+            candidates.push_back(CandidateInfo {
+                ast_fields: info.ast_fields.clone(),
+                node: value,
+                parent_guid: info.parent_guid.clone(),
+            });
+        }
+        symbols
+    }
+
+    fn parse_short_var_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        let values = info.node.child_by_field_name("right")
+            .map(|v| (0..v.named_child_count()).map(|i| v.named_child(i).unwrap()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if let Some(left) = info.node.child_by_field_name("left") {
+            let names_n = left.named_child_count();
+            for idx in 0..names_n {
+                let name = left.named_child(idx).unwrap();
+                if name.kind() != "identifier" {
+                    continue;
+                }
+                let mut decl = VariableDefinition::default();
+                decl.ast_fields.language = info.ast_fields.language;
+                decl.ast_fields.full_range = if names_n > 1 { name.range() } else { info.node.range() };
+                decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+                decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                decl.ast_fields.guid = get_guid();
+                decl.ast_fields.is_error = info.ast_fields.is_error;
+                decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+                if let Some(value) = values.get(idx) {
+                    decl.type_.inference_info = Some(code.slice(value.byte_range()).to_string());
+                }
+                symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+            }
+        }
+        for value in values {
+            candidates.push_back(CandidateInfo {
+                ast_fields: info.ast_fields.clone(),
+                node: value,
+                parent_guid: info.parent_guid.clone(),
+            });
+        }
+        symbols
+    }
+
+    fn parse_import_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let mut specs: Vec<Node> = vec![];
+        for i in 0..info.node.named_child_count() {
+            let child = info.node.named_child(i).unwrap();
+            match child.kind() {
+                "import_spec" => specs.push(child),
+                "import_spec_list" => {
+                    for j in 0..child.named_child_count() {
+                        let spec = child.named_child(j).unwrap();
+                        if spec.kind() == "import_spec" {
+                            specs.push(spec);
+                        }
+                    }
+                }
+                &_ => {}
+            }
+        }
+        for spec in specs {
+            let mut def = ImportDeclaration::default();
+            def.ast_fields.language = info.ast_fields.language;
+            def.ast_fields.full_range = spec.range();
+            def.ast_fields.file_path = info.ast_fields.file_path.clone();
+            def.ast_fields.parent_guid = Some(info.parent_guid.clone());
+            def.ast_fields.guid = get_guid();
+            if let Some(path) = spec.child_by_field_name("path") {
+                let path = code.slice(path.byte_range()).to_string();
+                let path = path.trim_matches(|c| c == '"' || c == '`');
+                def.path_components = path.split("/").map(|x| x.to_string()).collect();
+                // standard library packages have no domain in the first component
+                if let Some(first) = def.path_components.first() {
+                    if !first.contains(".") {
+                        def.import_type = ImportType::System;
+                    }
+                }
+            }
+            if let Some(name) = spec.child_by_field_name("name") {
+                def.alias = Some(code.slice(name.byte_range()).to_string());
+            }
+            symbols.push(Arc::new(RwLock::new(Box::new(def))));
+        }
+        symbols
+    }
+
+    fn parse_usages_<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let kind = info.node.kind();
+        #[cfg(test)]
+        #[allow(unused)]
+            let text = code.slice(info.node.byte_range());
+        match kind {
+            "type_declaration" => {
+                symbols.extend(self.parse_type_declaration(info, code, candidates));
+            }
+            "function_declaration" | "method_declaration" | "method_elem" => {
+                symbols.extend(self.parse_function_declaration(info, code, candidates));
+            }
+            "field_declaration" => {
+                symbols.extend(self.parse_field_declaration(info, code, candidates));
+            }
+            "var_spec" | "const_spec" => {
+                symbols.extend(self.parse_variable_spec(info, code, candidates));
+            }
+            "short_var_declaration" => {
+                symbols.extend(self.parse_short_var_declaration(info, code, candidates));
+            }
+            "call_expression" | "composite_literal" => {
+                symbols.extend(self.parse_call_expression(info, code, candidates));
+            }
+            "identifier" => {
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = code.slice(info.node.byte_range()).to_string();
+                usage.ast_fields.language = info.ast_fields.language;
+                usage.ast_fields.full_range = info.node.range();
+                usage.ast_fields.file_path = info.ast_fields.file_path.clone();
+                usage.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.is_error = info.ast_fields.is_error;
+                if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+                    usage.ast_fields.guid = caller_guid;
+                }
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "selector_expression" => {
+                let operand = info.node.child_by_field_name("operand").unwrap();
+                let field = info.node.child_by_field_name("field").unwrap();
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = code.slice(field.byte_range()).to_string();
+                usage.ast_fields.language = info.ast_fields.language;
+                usage.ast_fields.full_range = info.node.range();
+                usage.ast_fields.file_path = info.ast_fields.file_path.clone();
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                usage.ast_fields.caller_guid = Some(get_guid());
+                usage.ast_fields.is_error = info.ast_fields.is_error;
+                if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+                    usage.ast_fields.guid = caller_guid;
+                }
+                candidates.push_back(CandidateInfo {
+                    ast_fields: usage.ast_fields.clone(),
+                    node: operand,
+                    parent_guid: info.parent_guid.clone(),
+                });
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "keyed_element" => {
+                // the key of `Point{X: 1}` is a field name, not a variable
+                if let Some(value) = info.node.named_child(1) {
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: value,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+            "func_literal" => {
+                if let Some(body) = info.node.child_by_field_name("body") {
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: body,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+            "comment" => {
+                let mut def = CommentDefinition::default();
+                def.ast_fields.language = info.ast_fields.language;
+                def.ast_fields.full_range = info.node.range();
+                def.ast_fields.file_path = info.ast_fields.file_path.clone();
+                def.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                def.ast_fields.guid = get_guid();
+                def.ast_fields.is_error = info.ast_fields.is_error;
+                symbols.push(Arc::new(RwLock::new(Box::new(def))));
+            }
+            "import_declaration" => {
+                symbols.extend(self.parse_import_declaration(info, code));
+            }
+            "ERROR" => {
+                let mut ast = info.ast_fields.clone();
+                ast.is_error = true;
+
+                for i in 0..info.node.child_count() {
+                    let child = info.node.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: ast.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+            "package_clause" | "parameter_list" | "type_elem" => {}
+            _ => {
+                for i in 0..info.node.child_count() {
+                    let child = info.node.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    })
+                }
+            }
+        }
+        symbols
+    }
+
+    fn find_error_usages(&mut self, parent: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        for i in 0..parent.child_count() {
+            let child = parent.child(i).unwrap();
+            if child.kind() == "ERROR" {
+                symbols.extend(self.parse_error_usages(&child, code, path, parent_guid));
+            }
+        }
+        symbols
+    }
+
+    fn parse_error_usages(&mut self, parent: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        match parent.kind() {
+            "identifier" => {
+                let name = code.slice(parent.byte_range()).to_string();
+                if GO_KEYWORDS.contains(&name.as_str()) {
+                    return symbols;
+                }
+
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = name;
+                usage.ast_fields.language = LanguageId::Go;
+                usage.ast_fields.full_range = parent.range();
+                usage.ast_fields.file_path = path.clone();
+                usage.ast_fields.parent_guid = Some(parent_guid.clone());
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.is_error = true;
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "selector_expression" => {
+                let operand = parent.child_by_field_name("operand").unwrap();
+                let usages = self.parse_error_usages(&operand, code, path, parent_guid);
+                let field = parent.child_by_field_name("field").unwrap();
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = code.slice(field.byte_range()).to_string();
+                usage.ast_fields.language = LanguageId::Go;
+                usage.ast_fields.full_range = parent.range();
+                usage.ast_fields.file_path = path.clone();
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.parent_guid = Some(parent_guid.clone());
+                usage.ast_fields.is_error = true;
+                if let Some(last) = usages.last() {
+                    usage.ast_fields.caller_guid = last.read().fields().parent_guid.clone();
+                }
+                symbols.extend(usages);
+                if !GO_KEYWORDS.contains(&usage.ast_fields.name.as_str()) {
+                    symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+                }
+            }
+            &_ => {
+                for i in 0..parent.child_count() {
+                    let child = parent.child(i).unwrap();
+                    symbols.extend(self.parse_error_usages(&child, code, path, parent_guid));
+                }
+            }
+        }
+
+        symbols
+    }
+
+    pub fn parse_function_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = FunctionDeclaration::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.definition_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        decl.ast_fields.guid = get_guid();
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+
+        if let Some(name_node) = info.node.child_by_field_name("name") {
+            decl.ast_fields.name = code.slice(name_node.byte_range()).to_string();
+        }
+
+        if let Some(receiver) = info.node.child_by_field_name("receiver") {
+            symbols.extend(self.find_error_usages(&receiver, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+            let receiver_type = parse_function_args(&receiver, code).into_iter()
+                .filter_map(|arg| arg.type_)
+                .filter_map(|t| t.name)
+                .next();
+            if let Some(receiver_type) = receiver_type {
+                self.method_receivers.insert(decl.ast_fields.guid.clone(), receiver_type);
+            }
+        }
+
+        if let Some(type_parameters) = info.node.child_by_field_name("type_parameters") {
+            for i in 0..type_parameters.named_child_count() {
+                let child = type_parameters.named_child(i).unwrap();
+                if let Some(name) = child.child_by_field_name("name") {
+                    decl.template_types.push(TypeDef {
+                        name: Some(code.slice(name.byte_range()).to_string()),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        let mut decl_end: Option<Node> = None;
+        if let Some(parameters_node) = info.node.child_by_field_name("parameters") {
+            symbols.extend(self.find_error_usages(&parameters_node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+            decl.args = parse_function_args(&parameters_node, code);
+            decl_end = Some(parameters_node);
+        }
+        if let Some(result) = info.node.child_by_field_name("result") {
+            symbols.extend(self.find_error_usages(&result, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+            if result.kind() == "parameter_list" {
+                // multiple return values are stored as nested types of an unnamed tuple
+                let mut dtype = TypeDef::default();
+                for arg in parse_function_args(&result, code) {
+                    if let Some(t) = arg.type_ {
+                        dtype.nested_types.push(t);
+                    }
+                }
+                decl.return_type = Some(dtype);
+            } else {
+                decl.return_type = parse_type(&result, code);
+            }
+            decl_end = Some(result);
+        }
+        if let Some(decl_end) = decl_end {
+            decl.ast_fields.declaration_range = Range {
+                start_byte: decl.ast_fields.full_range.start_byte,
+                end_byte: decl_end.end_byte(),
+                start_point: decl.ast_fields.full_range.start_point,
+                end_point: decl_end.end_position(),
+            };
+        }
+
+        if let Some(body_node) = info.node.child_by_field_name("body") {
+            decl.ast_fields.definition_range = body_node.range();
+            candidates.push_back(CandidateInfo {
+                ast_fields: decl.ast_fields.clone(),
+                node: body_node,
+                parent_guid: decl.ast_fields.guid.clone(),
+            });
+        } else {
+            decl.ast_fields.declaration_range = decl.ast_fields.full_range;
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    pub fn parse_call_expression<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = FunctionCall::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+            decl.ast_fields.guid = caller_guid;
+        }
+        decl.ast_fields.caller_guid = Some(get_guid());
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        if let Some(function) = info.node.child_by_field_name("function") {
+            match function.kind() {
+                "identifier" => {
+                    decl.ast_fields.name = code.slice(function.byte_range()).to_string();
+                }
+                "selector_expression" => {
+                    if let Some(field) = function.child_by_field_name("field") {
+                        decl.ast_fields.name = code.slice(field.byte_range()).to_string();
+                    }
+                    if let Some(operand) = function.child_by_field_name("operand") {
+                        candidates.push_back(CandidateInfo {
+                            ast_fields: decl.ast_fields.clone(),
+                            node: operand,
+                            parent_guid: info.parent_guid.clone(),
+                        });
+                    }
+                }
+                &_ => {
+                    let mut new_ast_fields = info.ast_fields.clone();
+                    new_ast_fields.caller_guid = None;
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: new_ast_fields,
+                        node: function,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+        }
+        // composite literals `Point{X: 1}` construct a type, like `new` in other languages
+        if let Some(type_) = info.node.child_by_field_name("type")
+            .filter(|x| ["type_identifier", "qualified_type", "generic_type"].contains(&x.kind())) {
+            if let Some(dtype) = parse_type(&type_, code) {
+                if let Some(name) = dtype.name {
+                    decl.ast_fields.name = name;
+                }
+            }
+        }
+        for field in ["arguments", "body"] {
+            if let Some(arguments) = info.node.child_by_field_name(field) {
+                symbols.extend(self.find_error_usages(&arguments, code, &info.ast_fields.file_path, &info.parent_guid));
+                let mut new_ast_fields = info.ast_fields.clone();
+                new_ast_fields.caller_guid = None;
+                for i in 0..arguments.child_count() {
+                    let child = arguments.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: new_ast_fields.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+        }
+
+        if decl.ast_fields.name.is_empty() {
+            // `[]int{1, 2}` or `func() {...}()`, nothing to link to
+            return symbols;
+        }
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    fn parse_(&mut self, parent: &Node, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut ast_fields = AstSymbolFields::default();
+        ast_fields.file_path = path.clone();
+        ast_fields.is_error = false;
+        ast_fields.language = LanguageId::Go;
+
+        self.method_receivers.clear();
+        let root_guid = get_guid();
+        let mut candidates = VecDeque::from(vec![CandidateInfo {
+            ast_fields,
+            node: parent.clone(),
+            parent_guid: root_guid.clone(),
+        }]);
+        while let Some(candidate) = candidates.pop_front() {
+            let symbols_l = self.parse_usages_(&candidate, code, &mut candidates);
+            symbols.extend(symbols_l);
+        }
+
+        // methods are declared outside of the struct body, move them inside if the struct lives in this file
+        let struct_guids = symbols.iter()
+            .filter(|s| s.read().as_any().downcast_ref::<StructDeclaration>().is_some())
+            .filter(|s| s.read().parent_guid().as_ref() == Some(&root_guid))
+            .map(|s| (s.read().name().to_string(), s.read().guid().clone()))
+            .collect::<HashMap<_, _>>();
+        for symbol in symbols.iter_mut() {
+            let guid = symbol.read().guid().clone();
+            if let Some(receiver_type) = self.method_receivers.get(&guid) {
+                if let Some(struct_guid) = struct_guids.get(receiver_type) {
+                    symbol.write().fields_mut().parent_guid = Some(struct_guid.clone());
+                }
+            }
+        }
+
+        let guid_to_symbol_map = symbols.iter()
+            .map(|s| (s.clone().read().guid().clone(), s.clone())).collect::<HashMap<_, _>>();
+        for symbol in symbols.iter_mut() {
+            let guid = symbol.read().guid().clone();
+            if let Some(parent_guid) = symbol.read().parent_guid() {
+                if let Some(parent) = guid_to_symbol_map.get(parent_guid) {
+                    parent.write().fields_mut().childs_guid.push(guid);
+                }
+            }
+        }
+
+        #[cfg(test)]
+        for symbol in symbols.iter_mut() {
+            let mut sym = symbol.write();
+            sym.fields_mut().childs_guid = sym.fields_mut().childs_guid.iter()
+                .sorted_by_key(|x| {
+                    guid_to_symbol_map.get(*x).unwrap().read().full_range().start_byte
+                }).map(|x| x.clone()).collect();
+        }
+
+        symbols
+    }
+}
+
+impl AstLanguageParser for GoParser {
+    fn parse(&mut self, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
+        let tree = self.parser.parse(code, None).unwrap();
+        let symbols = self.parse_(&tree.root_node(), code, path);
+        symbols
+    }
+}
+
+pub struct GoSkeletonFormatter;
+
+// Methods with a receiver are declared after the type, fields and interface methods have nothing after them
+impl SkeletonFormatter for GoSkeletonFormatter {
+    fn make_skeleton(&self,
+                     symbol: &SymbolInformation,
+                     text: &String,
+                     guid_to_children: &HashMap<Uuid, Vec<Uuid>>,
+                     guid_to_info: &HashMap<Uuid, &SymbolInformation>) -> String {
+        let declaration_lines = |s: &SymbolInformation| s.get_declaration_content(text).unwrap()
+            .split("\n")
+            .map(|x| x.trim_start().trim_end().to_string())
+            .collect::<Vec<_>>();
+        let mut res_line = declaration_lines(symbol);
+        let mut methods: Vec<String> = vec![];
+        res_line.last_mut().unwrap().push_str(" {");
+        for child in guid_to_children.get(&symbol.guid).unwrap() {
+            let child_symbol = guid_to_info.get(&child).unwrap();
+            let mut content = declaration_lines(child_symbol);
+            match child_symbol.symbol_type {
+                SymbolType::FunctionDeclaration if content.first().map_or(false, |x| x.starts_with("func")) => {
+                    content.last_mut().unwrap().push_str(" { ... }");
+                    methods.extend(content);
+                }
+                SymbolType::FunctionDeclaration | SymbolType::ClassFieldDeclaration => {
+                    res_line.extend(content.iter().map(|x| format!("  {}", x)));
+                }
+                _ => {}
+            }
+        }
+        res_line.push("}".to_string());
+        res_line.extend(methods);
+        res_line.join("\n")
+    }
+}
@@ -0,0 +1,856 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::string::ToString;
+use std::sync::Arc;
+
+#[cfg(test)]
+use itertools::Itertools;
+
+use parking_lot::RwLock;
+use similar::DiffableStr;
+use tree_sitter::{Node, Parser, Range};
+use tree_sitter_kotlin::language;
+use uuid::Uuid;
+
+use crate::ast::treesitter::ast_instance_structs::{AstSymbolFields, AstSymbolInstanceArc, ClassFieldDeclaration, CommentDefinition, FunctionArg, FunctionCall, FunctionDeclaration, ImportDeclaration, ImportType, StructDeclaration, TypeAlias, TypeDef, VariableDefinition, VariableUsage};
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, ParserError};
+use crate::ast::treesitter::parsers::utils::{CandidateInfo, get_guid};
+
+pub(crate) struct KotlinParser {
+    pub parser: Parser,
+}
+
+static KOTLIN_KEYWORDS: [&str; 28] = [
+    "as", "break", "class", "continue", "do", "else", "false", "for", "fun", "if",
+    "in", "interface", "is", "null", "object", "package", "return", "super", "this", "throw",
+    "true", "try", "typealias", "typeof", "val", "var", "when", "while",
+];
+
+static KOTLIN_BUILTIN_TYPES: [&str; 12] = [
+    "Any", "Boolean", "Byte", "Char", "Double", "Float", "Int", "Long", "Nothing", "Short",
+    "String", "Unit",
+];
+
+static SYSTEM_MODULES: [&str; 5] = [
+    "kotlin", "kotlinx", "java", "javax", "android",
+];
+
+// the grammar has no field names, so children are looked up by kind
+fn child_of_kind<'a>(parent: &Node<'a>, kinds: &[&str]) -> Option<Node<'a>> {
+    (0..parent.named_child_count())
+        .filter_map(|i| parent.named_child(i))
+        .find(|x| kinds.contains(&x.kind()))
+}
+
+static TYPE_KINDS: [&str; 5] = [
+    "user_type", "nullable_type", "function_type", "parenthesized_type", "non_nullable_type",
+];
+
+pub fn parse_type(parent: &Node, code: &str) -> Option<TypeDef> {
+    let kind = parent.kind();
+    let text = code.slice(parent.byte_range()).to_string();
+    match kind {
+        "type_identifier" => {
+            let is_pod = KOTLIN_BUILTIN_TYPES.contains(&text.as_str());
+            return Some(TypeDef {
+                name: if is_pod { None } else { Some(text.clone()) },
+                inference_info: if is_pod { Some(text) } else { None },
+                inference_info_guid: None,
+                is_pod,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            });
+        }
+        "user_type" => {
+            // `a.b.C<T>` is a flat list of identifiers and type arguments
+            let mut decl = TypeDef::default();
+            let mut path: Vec<String> = vec![];
+            for i in 0..parent.named_child_count() {
+                let child = parent.named_child(i).unwrap();
+                match child.kind() {
+                    "type_identifier" => {
+                        path.push(code.slice(child.byte_range()).to_string());
+                    }
+                    "type_arguments" => {
+                        decl.nested_types.clear();
+                        for i in 0..child.named_child_count() {
+                            let child = child.named_child(i).unwrap();
+                            if let Some(t) = child_of_kind(&child, &TYPE_KINDS).and_then(|t| parse_type(&t, code)) {
+                                decl.nested_types.push(t);
+                            }
+                        }
+                    }
+                    &_ => {}
+                }
+            }
+            if let Some(name) = path.pop() {
+                if path.is_empty() && decl.nested_types.is_empty() && KOTLIN_BUILTIN_TYPES.contains(&name.as_str()) {
+                    decl.inference_info = Some(name);
+                    decl.is_pod = true;
+                } else {
+                    decl.name = Some(name);
+                }
+            }
+            decl.namespace = path.join(".");
+            return Some(decl);
+        }
+        "nullable_type" | "parenthesized_type" | "non_nullable_type" => {
+            if let Some(type_node) = child_of_kind(parent, &TYPE_KINDS) {
+                return parse_type(&type_node, code);
+            }
+        }
+        "function_type" => {
+            let mut decl = TypeDef {
+                name: None,
+                inference_info: Some(text),
+                inference_info_guid: None,
+                is_pod: false,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            };
+            for i in 0..parent.named_child_count() {
+                let child = parent.named_child(i).unwrap();
+                if child.kind() == "function_type_parameters" {
+                    for i in 0..child.named_child_count() {
+                        let child = child.named_child(i).unwrap();
+                        if let Some(dtype) = parse_type(&child, code) {
+                            decl.nested_types.push(dtype);
+                        }
+                    }
+                } else if let Some(dtype) = parse_type(&child, code) {
+                    decl.nested_types.push(dtype);
+                }
+            }
+            return Some(decl);
+        }
+        &_ => {}
+    }
+    None
+}
+
+fn parse_function_arg(parent: &Node, code: &str) -> FunctionArg {
+    let mut arg = FunctionArg::default();
+    if let Some(name) = child_of_kind(parent, &["simple_identifier"]) {
+        arg.name = code.slice(name.byte_range()).to_string();
+    }
+    if let Some(type_node) = child_of_kind(parent, &TYPE_KINDS) {
+        arg.type_ = parse_type(&type_node, code);
+    }
+    arg
+}
+
+fn parse_template_types(parent: &Node, code: &str) -> Vec<TypeDef> {
+    let mut types = vec![];
+    if let Some(type_parameters) = child_of_kind(parent, &["type_parameters"]) {
+        for i in 0..type_parameters.named_child_count() {
+            let child = type_parameters.named_child(i).unwrap();
+            if let Some(name) = child_of_kind(&child, &["type_identifier"]) {
+                types.push(TypeDef {
+                    name: Some(code.slice(name.byte_range()).to_string()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    types
+}
+
+impl KotlinParser {
+    pub fn new() -> Result<KotlinParser, ParserError> {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&language())
+            .map_err(internal_error)?;
+        Ok(KotlinParser { parser })
+    }
+
+    pub fn parse_struct_declaration<'a>(
+        &mut self,
+        info: &CandidateInfo<'a>,
+        code: &str,
+        candidates: &mut VecDeque<CandidateInfo<'a>>,
+    ) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = StructDeclaration::default();
+
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.definition_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+
+        if let Some(name_node) = child_of_kind(&info.node, &["type_identifier"]) {
+            decl.ast_fields.name = code.slice(name_node.byte_range()).to_string();
+        } else if info.node.kind() == "companion_object" {
+            decl.ast_fields.name = "Companion".to_string();
+        }
+        decl.template_types = parse_template_types(&info.node, code);
+
+        for i in 0..info.node.named_child_count() {
+            let child = info.node.named_child(i).unwrap();
+            match child.kind() {
+                "delegation_specifier" => {
+                    symbols.extend(self.find_error_usages(&child, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+                    let Some(type_node) = child_of_kind(&child, &["user_type", "constructor_invocation", "explicit_delegation"]) else {
+                        continue;
+                    };
+                    // `: Base(x)` calls the base constructor, `: Iface by x` delegates to x
+                    let (type_node, args) = match type_node.kind() {
+                        "constructor_invocation" => (child_of_kind(&type_node, &["user_type"]), child_of_kind(&type_node, &["value_arguments"])),
+                        "explicit_delegation" => (child_of_kind(&type_node, &["user_type"]), type_node.named_child(type_node.named_child_count().saturating_sub(1))),
+                        _ => (Some(type_node), None),
+                    };
+                    if let Some(dtype) = type_node.and_then(|t| parse_type(&t, code)) {
+                        decl.inherited_types.push(dtype);
+                    }
+                    if let Some(args) = args {
+                        candidates.push_back(CandidateInfo {
+                            ast_fields: decl.ast_fields.clone(),
+                            node: args,
+                            parent_guid: decl.ast_fields.guid.clone(),
+                        });
+                    }
+                }
+                "primary_constructor" => {
+                    symbols.extend(self.parse_primary_constructor(&child, &decl.ast_fields, code, candidates));
+                }
+                &_ => {}
+            }
+        }
+
+        if let Some(body) = child_of_kind(&info.node, &["class_body", "enum_class_body"]) {
+            decl.ast_fields.definition_range = body.range();
+            decl.ast_fields.declaration_range = Range {
+                start_byte: decl.ast_fields.full_range.start_byte,
+                end_byte: decl.ast_fields.definition_range.start_byte,
+                start_point: decl.ast_fields.full_range.start_point,
+                end_point: decl.ast_fields.definition_range.start_point,
+            };
+            candidates.push_back(CandidateInfo {
+                ast_fields: decl.ast_fields.clone(),
+                node: body,
+                parent_guid: decl.ast_fields.guid.clone(),
+            })
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    // `class A(val x: Int, y: Int)`: only `val` and `var` parameters become fields
+    fn parse_primary_constructor<'a>(&mut self, parent: &Node<'a>, struct_fields: &AstSymbolFields, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let struct_guid = struct_fields.guid.clone();
+        symbols.extend(self.find_error_usages(parent, code, &struct_fields.file_path, &struct_guid));
+        for i in 0..parent.named_child_count() {
+            let child = parent.named_child(i).unwrap();
+            if child.kind() != "class_parameter" {
+                continue;
+            }
+            let mut value: Option<Node> = None;
+            if let Some(type_node) = child_of_kind(&child, &TYPE_KINDS) {
+                value = type_node.next_named_sibling();
+            }
+            if let Some(value) = value {
+                candidates.push_back(CandidateInfo {
+                    ast_fields: struct_fields.clone(),
+                    node: value,
+                    parent_guid: struct_guid.clone(),
+                });
+            }
+            if child_of_kind(&child, &["binding_pattern_kind"]).is_none() {
+                continue;
+            }
+            let arg = parse_function_arg(&child, code);
+            let mut decl = ClassFieldDeclaration::default();
+            decl.ast_fields.language = struct_fields.language;
+            decl.ast_fields.full_range = child.range();
+            decl.ast_fields.declaration_range = child.range();
+            decl.ast_fields.file_path = struct_fields.file_path.clone();
+            decl.ast_fields.parent_guid = Some(struct_guid.clone());
+            decl.ast_fields.guid = get_guid();
+            decl.ast_fields.is_error = struct_fields.is_error;
+            decl.ast_fields.name = arg.name;
+            if let Some(dtype) = arg.type_ {
+                decl.type_ = dtype;
+            }
+            if let Some(value) = value {
+                decl.type_.inference_info = Some(code.slice(value.byte_range()).to_string());
+            }
+            symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        }
+        symbols
+    }
+
+    fn parse_property_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        let is_field = info.node.parent()
+            .map(|x| ["class_body", "enum_class_body"].contains(&x.kind()))
+            .unwrap_or(false);
+        let mut declarations: Vec<Node> = vec![];
+        let mut value: Option<Node> = None;
+        for i in 0..info.node.named_child_count() {
+            let child = info.node.named_child(i).unwrap();
+            match child.kind() {
+                "variable_declaration" => {
+                    declarations.push(child);
+                }
+                "multi_variable_declaration" => {
+                    for i in 0..child.named_child_count() {
+                        let child = child.named_child(i).unwrap();
+                        if child.kind() == "variable_declaration" {
+                            declarations.push(child);
+                        }
+                    }
+                }
+                "modifiers" | "binding_pattern_kind" | "type_parameters" | "type_constraints" | "user_type" => {}
+                "getter" | "setter" | "property_delegate" => {
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+                &_ => {
+                    value = Some(child);
+                }
+            }
+        }
+        if let Some(value) = value {
+            candidates.push_back(CandidateInfo {
+                ast_fields: info.ast_fields.clone(),
+                node: value,
+                parent_guid: info.parent_guid.clone(),
+            });
+        }
+
+        for declaration in declarations {
+            let arg = parse_function_arg(&declaration, code);
+            let mut dtype = arg.type_.unwrap_or_default();
+            if let Some(value) = value {
+                dtype.inference_info = Some(code.slice(value.byte_range()).to_string());
+            }
+            if is_field {
+                let mut decl = ClassFieldDeclaration::default();
+                decl.ast_fields.language = info.ast_fields.language;
+                decl.ast_fields.full_range = info.node.range();
+                decl.ast_fields.declaration_range = info.node.range();
+                decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+                decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                decl.ast_fields.guid = get_guid();
+                decl.ast_fields.is_error = info.ast_fields.is_error;
+                decl.ast_fields.name = arg.name;
+                decl.type_ = dtype;
+                symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+            } else {
+                let mut decl = VariableDefinition::default();
+                decl.ast_fields.language = info.ast_fields.language;
+                decl.ast_fields.full_range = info.node.range();
+                decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+                decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                decl.ast_fields.guid = get_guid();
+                decl.ast_fields.is_error = info.ast_fields.is_error;
+                decl.ast_fields.name = arg.name;
+                decl.type_ = dtype;
+                symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+            }
+        }
+        symbols
+    }
+
+    fn parse_enum_entry<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let mut decl = ClassFieldDeclaration::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        if let Some(name) = child_of_kind(&info.node, &["simple_identifier"]) {
+            decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+        }
+        for i in 0..info.node.named_child_count() {
+            let child = info.node.named_child(i).unwrap();
+            if child.kind() == "value_arguments" {
+                decl.type_.inference_info = Some(code.slice(child.byte_range()).to_string());
+            }
+            if ["value_arguments", "class_body"].contains(&child.kind()) {
+                candidates.push_back(CandidateInfo {
+                    ast_fields: info.ast_fields.clone(),
+                    node: child,
+                    parent_guid: info.parent_guid.clone(),
+                });
+            }
+        }
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    fn parse_type_alias<'a>(&mut self, info: &CandidateInfo<'a>, code: &str) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let mut type_alias = TypeAlias::default();
+        type_alias.ast_fields.language = info.ast_fields.language;
+        type_alias.ast_fields.full_range = info.node.range();
+        type_alias.ast_fields.declaration_range = info.node.range();
+        type_alias.ast_fields.definition_range = info.node.range();
+        type_alias.ast_fields.file_path = info.ast_fields.file_path.clone();
+        type_alias.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        type_alias.ast_fields.guid = get_guid();
+        type_alias.ast_fields.is_error = info.ast_fields.is_error;
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        if let Some(name) = child_of_kind(&info.node, &["type_identifier"]) {
+            type_alias.ast_fields.name = code.slice(name.byte_range()).to_string();
+        }
+        if let Some(dtype) = child_of_kind(&info.node, &TYPE_KINDS).and_then(|t| parse_type(&t, code)) {
+            type_alias.types.push(dtype);
+        }
+        symbols.push(Arc::new(RwLock::new(Box::new(type_alias))));
+        symbols
+    }
+
+    fn parse_usages_<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let kind = info.node.kind();
+        #[cfg(test)]
+        #[allow(unused)]
+            let text = code.slice(info.node.byte_range());
+        match kind {
+            "class_declaration" | "object_declaration" | "companion_object" => {
+                symbols.extend(self.parse_struct_declaration(info, code, candidates));
+            }
+            "property_declaration" => {
+                symbols.extend(self.parse_property_declaration(info, code, candidates));
+            }
+            "enum_entry" => {
+                symbols.extend(self.parse_enum_entry(info, code, candidates));
+            }
+            "type_alias" => {
+                symbols.extend(self.parse_type_alias(info, code));
+            }
+            "function_declaration" | "secondary_constructor" | "anonymous_initializer" => {
+                symbols.extend(self.parse_function_declaration(info, code, candidates));
+            }
+            "call_expression" => {
+                symbols.extend(self.parse_call_expression(info, code, candidates));
+            }
+            "simple_identifier" => {
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = code.slice(info.node.byte_range()).to_string();
+                usage.ast_fields.language = info.ast_fields.language;
+                usage.ast_fields.full_range = info.node.range();
+                usage.ast_fields.file_path = info.ast_fields.file_path.clone();
+                usage.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.is_error = info.ast_fields.is_error;
+                if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+                    usage.ast_fields.guid = caller_guid;
+                }
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "navigation_expression" => {
+                let expression = info.node.named_child(0).unwrap();
+                let name = child_of_kind(&info.node, &["navigation_suffix"])
+                    .and_then(|x| child_of_kind(&x, &["simple_identifier"]));
+                // `Foo::class` has nothing to point at, only the receiver is a usage
+                let Some(name) = name else {
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: expression,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                    return symbols;
+                };
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = code.slice(name.byte_range()).to_string();
+                usage.ast_fields.language = info.ast_fields.language;
+                usage.ast_fields.full_range = info.node.range();
+                usage.ast_fields.file_path = info.ast_fields.file_path.clone();
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                usage.ast_fields.caller_guid = Some(get_guid());
+                usage.ast_fields.is_error = info.ast_fields.is_error;
+                if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+                    usage.ast_fields.guid = caller_guid;
+                }
+                candidates.push_back(CandidateInfo {
+                    ast_fields: usage.ast_fields.clone(),
+                    node: expression,
+                    parent_guid: info.parent_guid.clone(),
+                });
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "line_comment" | "multiline_comment" => {
+                let mut def = CommentDefinition::default();
+                def.ast_fields.language = info.ast_fields.language;
+                def.ast_fields.full_range = info.node.range();
+                def.ast_fields.file_path = info.ast_fields.file_path.clone();
+                def.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                def.ast_fields.guid = get_guid();
+                def.ast_fields.is_error = info.ast_fields.is_error;
+                symbols.push(Arc::new(RwLock::new(Box::new(def))));
+            }
+            "import_header" => {
+                let mut def = ImportDeclaration::default();
+                def.ast_fields.language = info.ast_fields.language;
+                def.ast_fields.full_range = info.node.range();
+                def.ast_fields.file_path = info.ast_fields.file_path.clone();
+                if let Some(identifier) = child_of_kind(&info.node, &["identifier"]) {
+                    def.path_components = (0..identifier.named_child_count())
+                        .filter_map(|i| identifier.named_child(i))
+                        .map(|x| code.slice(x.byte_range()).to_string())
+                        .collect();
+                    if let Some(first) = def.path_components.first() {
+                        if SYSTEM_MODULES.contains(&first.as_str()) {
+                            def.import_type = ImportType::System;
+                        }
+                    }
+                }
+                if let Some(alias) = child_of_kind(&info.node, &["import_alias"])
+                    .and_then(|x| child_of_kind(&x, &["type_identifier"])) {
+                    def.alias = Some(code.slice(alias.byte_range()).to_string());
+                }
+                def.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                def.ast_fields.guid = get_guid();
+                symbols.push(Arc::new(RwLock::new(Box::new(def))));
+                // comments which follow the imports end up inside the import list
+                for i in 0..info.node.named_child_count() {
+                    let child = info.node.named_child(i).unwrap();
+                    if ["line_comment", "multiline_comment"].contains(&child.kind()) {
+                        candidates.push_back(CandidateInfo {
+                            ast_fields: info.ast_fields.clone(),
+                            node: child,
+                            parent_guid: info.parent_guid.clone(),
+                        });
+                    }
+                }
+            }
+            "ERROR" => {
+                let mut ast = info.ast_fields.clone();
+                ast.is_error = true;
+
+                for i in 0..info.node.child_count() {
+                    let child = info.node.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: ast.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+            "package_header" | "modifiers" | "annotation" | "type_parameters" | "type_constraints" | "type_arguments"
+            | "function_value_parameters" | "lambda_parameters" | "user_type" | "nullable_type" | "function_type" => {}
+            _ => {
+                for i in 0..info.node.child_count() {
+                    let child = info.node.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    })
+                }
+            }
+        }
+        symbols
+    }
+
+    fn find_error_usages(&mut self, parent: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        for i in 0..parent.child_count() {
+            let child = parent.child(i).unwrap();
+            if child.kind() == "ERROR" {
+                symbols.extend(self.parse_error_usages(&child, code, path, parent_guid));
+            }
+        }
+        symbols
+    }
+
+    fn parse_error_usages(&mut self, parent: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        match parent.kind() {
+            "simple_identifier" => {
+                let name = code.slice(parent.byte_range()).to_string();
+                if KOTLIN_KEYWORDS.contains(&name.as_str()) {
+                    return symbols;
+                }
+
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = name;
+                usage.ast_fields.language = LanguageId::Kotlin;
+                usage.ast_fields.full_range = parent.range();
+                usage.ast_fields.file_path = path.clone();
+                usage.ast_fields.parent_guid = Some(parent_guid.clone());
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.is_error = true;
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "navigation_expression" => {
+                let expression = parent.named_child(0).unwrap();
+                let usages = self.parse_error_usages(&expression, code, path, parent_guid);
+                let name = child_of_kind(parent, &["navigation_suffix"])
+                    .and_then(|x| child_of_kind(&x, &["simple_identifier"]));
+                symbols.extend(usages.clone());
+                if let Some(name) = name {
+                    let mut usage = VariableUsage::default();
+                    usage.ast_fields.name = code.slice(name.byte_range()).to_string();
+                    usage.ast_fields.language = LanguageId::Kotlin;
+                    usage.ast_fields.full_range = parent.range();
+                    usage.ast_fields.file_path = path.clone();
+                    usage.ast_fields.guid = get_guid();
+                    usage.ast_fields.parent_guid = Some(parent_guid.clone());
+                    usage.ast_fields.is_error = true;
+                    if let Some(last) = usages.last() {
+                        usage.ast_fields.caller_guid = last.read().fields().parent_guid.clone();
+                    }
+                    if !KOTLIN_KEYWORDS.contains(&usage.ast_fields.name.as_str()) {
+                        symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+                    }
+                }
+            }
+            &_ => {
+                for i in 0..parent.child_count() {
+                    let child = parent.child(i).unwrap();
+                    symbols.extend(self.parse_error_usages(&child, code, path, parent_guid));
+                }
+            }
+        }
+
+        symbols
+    }
+
+    pub fn parse_function_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = FunctionDeclaration::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.definition_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        decl.ast_fields.guid = get_guid();
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+
+        match info.node.kind() {
+            "secondary_constructor" => decl.ast_fields.name = "constructor".to_string(),
+            "anonymous_initializer" => decl.ast_fields.name = "init".to_string(),
+            _ => {
+                if let Some(name_node) = child_of_kind(&info.node, &["simple_identifier"]) {
+                    decl.ast_fields.name = code.slice(name_node.byte_range()).to_string();
+                }
+            }
+        }
+        decl.template_types = parse_template_types(&info.node, code);
+
+        let mut body: Option<Node> = None;
+        let mut after_parameters = false;
+        for i in 0..info.node.named_child_count() {
+            let child = info.node.named_child(i).unwrap();
+            match child.kind() {
+                "function_value_parameters" => {
+                    after_parameters = true;
+                    symbols.extend(self.find_error_usages(&child, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+                    decl.ast_fields.declaration_range = Range {
+                        start_byte: decl.ast_fields.full_range.start_byte,
+                        end_byte: child.end_byte(),
+                        start_point: decl.ast_fields.full_range.start_point,
+                        end_point: child.end_position(),
+                    };
+                    for idx in 0..child.named_child_count() {
+                        let param = child.named_child(idx).unwrap();
+                        match param.kind() {
+                            "parameter" => decl.args.push(parse_function_arg(&param, code)),
+                            "parameter_modifiers" => {}
+                            // default values follow their parameter
+                            &_ => {
+                                candidates.push_back(CandidateInfo {
+                                    ast_fields: decl.ast_fields.clone(),
+                                    node: param,
+                                    parent_guid: decl.ast_fields.guid.clone(),
+                                });
+                            }
+                        }
+                    }
+                }
+                // the type before the name is an extension receiver, the one after the parameters is the return type
+                "user_type" | "nullable_type" | "function_type" | "parenthesized_type" if after_parameters => {
+                    decl.return_type = parse_type(&child, code);
+                }
+                "function_body" | "block" | "statements" => {
+                    body = Some(child);
+                }
+                "constructor_delegation_call" => {
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: decl.ast_fields.clone(),
+                        node: child,
+                        parent_guid: decl.ast_fields.guid.clone(),
+                    });
+                }
+                &_ => {}
+            }
+        }
+
+        if let Some(body_node) = body {
+            decl.ast_fields.definition_range = body_node.range();
+            decl.ast_fields.declaration_range = Range {
+                start_byte: decl.ast_fields.full_range.start_byte,
+                end_byte: decl.ast_fields.definition_range.start_byte,
+                start_point: decl.ast_fields.full_range.start_point,
+                end_point: decl.ast_fields.definition_range.start_point,
+            };
+            candidates.push_back(CandidateInfo {
+                ast_fields: decl.ast_fields.clone(),
+                node: body_node,
+                parent_guid: decl.ast_fields.guid.clone(),
+            });
+        } else {
+            decl.ast_fields.declaration_range = decl.ast_fields.full_range;
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    pub fn parse_call_expression<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = FunctionCall::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+            decl.ast_fields.guid = caller_guid;
+        }
+        decl.ast_fields.caller_guid = Some(get_guid());
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        if let Some(function) = info.node.named_child(0) {
+            match function.kind() {
+                "simple_identifier" => {
+                    decl.ast_fields.name = code.slice(function.byte_range()).to_string();
+                }
+                "navigation_expression" => {
+                    let name = child_of_kind(&function, &["navigation_suffix"])
+                        .and_then(|x| child_of_kind(&x, &["simple_identifier"]));
+                    if let Some(name) = name {
+                        decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+                    }
+                    if let Some(expression) = function.named_child(0) {
+                        candidates.push_back(CandidateInfo {
+                            ast_fields: decl.ast_fields.clone(),
+                            node: expression,
+                            parent_guid: info.parent_guid.clone(),
+                        });
+                    }
+                }
+                &_ => {
+                    let mut new_ast_fields = info.ast_fields.clone();
+                    new_ast_fields.caller_guid = None;
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: new_ast_fields,
+                        node: function,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+        }
+        if let Some(call_suffix) = child_of_kind(&info.node, &["call_suffix"]) {
+            symbols.extend(self.find_error_usages(&call_suffix, code, &info.ast_fields.file_path, &info.parent_guid));
+            let mut new_ast_fields = info.ast_fields.clone();
+            new_ast_fields.caller_guid = None;
+            for i in 0..call_suffix.named_child_count() {
+                let child = call_suffix.named_child(i).unwrap();
+                match child.kind() {
+                    "value_arguments" => {
+                        for i in 0..child.child_count() {
+                            let child = child.child(i).unwrap();
+                            candidates.push_back(CandidateInfo {
+                                ast_fields: new_ast_fields.clone(),
+                                node: child,
+                                parent_guid: info.parent_guid.clone(),
+                            });
+                        }
+                    }
+                    "annotated_lambda" => {
+                        candidates.push_back(CandidateInfo {
+                            ast_fields: new_ast_fields.clone(),
+                            node: child,
+                            parent_guid: info.parent_guid.clone(),
+                        });
+                    }
+                    &_ => {}
+                }
+            }
+        }
+
+        if !decl.ast_fields.name.is_empty() {
+            symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        }
+        symbols
+    }
+
+    fn parse_(&mut self, parent: &Node, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut ast_fields = AstSymbolFields::default();
+        ast_fields.file_path = path.clone();
+        ast_fields.is_error = false;
+        ast_fields.language = LanguageId::Kotlin;
+
+        let mut candidates = VecDeque::from(vec![CandidateInfo {
+            ast_fields,
+            node: parent.clone(),
+            parent_guid: get_guid(),
+        }]);
+        while let Some(candidate) = candidates.pop_front() {
+            let symbols_l = self.parse_usages_(&candidate, code, &mut candidates);
+            symbols.extend(symbols_l);
+        }
+        let guid_to_symbol_map = symbols.iter()
+            .map(|s| (s.clone().read().guid().clone(), s.clone())).collect::<HashMap<_, _>>();
+        for symbol in symbols.iter_mut() {
+            let guid = symbol.read().guid().clone();
+            if let Some(parent_guid) = symbol.read().parent_guid() {
+                if let Some(parent) = guid_to_symbol_map.get(parent_guid) {
+                    parent.write().fields_mut().childs_guid.push(guid);
+                }
+            }
+        }
+
+        #[cfg(test)]
+        for symbol in symbols.iter_mut() {
+            let mut sym = symbol.write();
+            sym.fields_mut().childs_guid = sym.fields_mut().childs_guid.iter()
+                .sorted_by_key(|x| {
+                    guid_to_symbol_map.get(*x).unwrap().read().full_range().start_byte
+                }).map(|x| x.clone()).collect();
+        }
+
+        symbols
+    }
+}
+
+impl AstLanguageParser for KotlinParser {
+    fn parse(&mut self, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
+        let tree = self.parser.parse(code, None).unwrap();
+        let symbols = self.parse_(&tree.root_node(), code, path);
+        symbols
+    }
+}
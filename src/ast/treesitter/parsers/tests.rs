@@ -22,6 +22,9 @@ mod java;
 mod cpp;
 mod ts;
 mod js;
+mod go;
+mod csharp;
+mod kotlin;
 
 pub(crate) fn print(symbols: &Vec<AstSymbolInstanceArc>, code: &str) {
     let guid_to_symbol_map = symbols.iter()
@@ -217,6 +220,7 @@ pub(crate) fn base_skeletonizer_test(lang: &LanguageId,
     let doc = Document {
         doc_path: file.clone(),
         doc_text: Some(Rope::from_str(code)),
+        doc_version: None,
     };
     let guid_to_children: HashMap<Uuid, Vec<Uuid>> = symbols.iter().map(|s| (s.read().guid().clone(), s.read().childs_guid().clone())).collect();
     let ast_markup: FileASTMarkup = crate::ast::lowlevel_file_markup(&doc, &symbols_struct).unwrap();
@@ -253,6 +257,7 @@ pub(crate) fn base_declaration_formatter_test(lang: &LanguageId,
     let doc = Document {
         doc_path: file.clone(),
         doc_text: Some(Rope::from_str(code)),
+        doc_version: None,
     };
     let guid_to_children: HashMap<Uuid, Vec<Uuid>> = symbols.iter().map(|s| (s.read().guid().clone(), s.read().childs_guid().clone())).collect();
     let ast_markup: FileASTMarkup = crate::ast::lowlevel_file_markup(&doc, &symbols_struct).unwrap();
@@ -0,0 +1,69 @@
+using System;
+using System.Collections.Generic;
+using Json = Newtonsoft.Json;
+
+namespace Zoo
+{
+    // an animal living in the zoo
+    public interface IAnimal
+    {
+        string Name { get; }
+        void MakeSound();
+    }
+
+    public enum Size
+    {
+        Small,
+        Large = 10,
+    }
+
+    public abstract class Animal : IAnimal
+    {
+        protected int age = 0, weight;
+        public string Name { get; set; }
+        public Size Size { get { return age > 5 ? Size.Large : Size.Small; } }
+
+        public Animal(string name)
+        {
+            Name = name;
+        }
+
+        public abstract void MakeSound();
+    }
+
+    public class Dog<T> : Animal, IComparable<Dog<T>> where T : class
+    {
+        private readonly List<T> toys = new List<T>();
+
+        public Dog(string name) : base(name) { }
+
+        public override void MakeSound()
+        {
+            Console.WriteLine(Name + " says woof");
+            toys.Clear();
+        }
+
+        public int CompareTo(Dog<T> other) => age.CompareTo(other.age);
+    }
+
+    public struct Cage
+    {
+        public int Number;
+    }
+
+    public record Keeper(string Name, int Age);
+
+    class Program
+    {
+        static void Main(string[] args)
+        {
+            var dog = new Dog<string>("Rex");
+            dog.MakeSound();
+            var animals = new List<IAnimal> { dog };
+            animals.ForEach(a => Console.WriteLine(a.Name));
+            int Add(int x, int y) { return x + y; }
+            Console.WriteLine(Add(1, 2));
+            var text = Json.JsonConvert.SerializeObject(dog);
+        }
+    }
+}
\ No newline at end of file
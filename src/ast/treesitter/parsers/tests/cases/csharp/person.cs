@@ -0,0 +1,49 @@
+using System;
+
+namespace People;
+
+/// <summary>
+/// Represents a simple model of a Person.
+/// </summary>
+public class Person
+{
+    // Fields
+    private string name;
+    private int age;
+
+    /// <summary>
+    /// Constructs a new Person with the specified name and age.
+    /// </summary>
+    public Person(string name, int age)
+    {
+        this.name = name;
+        this.age = age;
+    }
+
+    public string Name { get; set; }
+
+    /// <summary>
+    /// Gets the age of the person.
+    /// </summary>
+    public int GetAge()
+    {
+        return age;
+    }
+
+    // Sets the age of the person.
+    public void SetAge(int age)
+    {
+        this.age = age;
+    }
+
+    public override string ToString()
+    {
+        return $"Person{{name='{name}', age={age}}}";
+    }
+
+    public static void Main(string[] args)
+    {
+        Person person = new Person("John Doe", 30);
+        Console.WriteLine(person);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,72 @@
+package main
+
+import (
+	"fmt"
+	"strings"
+	log "github.com/sirupsen/logrus"
+)
+
+import "math"
+
+// Shape is anything with an area
+type Shape interface {
+	Area() float64
+	Perimeter() float64
+}
+
+type Named interface {
+	Shape
+	Name() string
+}
+
+type Point struct {
+	X, Y float64
+}
+
+type Circle struct {
+	Point
+	Radius float64
+	tags   []string
+}
+
+type Celsius float64
+
+type (
+	Handler func(string) error
+	Pair[K comparable, V any] struct {
+		Key   K
+		Value V
+	}
+)
+
+var defaultRadius = 1.0
+const pi, e = 3.14, 2.71
+
+func (c *Circle) Area() float64 {
+	return math.Pi * c.Radius * c.Radius
+}
+
+func (c Circle) Perimeter() float64 {
+	return 2 * pi * c.Radius
+}
+
+func NewCircle(x, y float64, radius float64) *Circle {
+	return &Circle{Point: Point{X: x, Y: y}, Radius: radius}
+}
+
+func describe(s Shape) (string, error) {
+	area := s.Area()
+	parts := []string{"shape", fmt.Sprintf("%.2f", area)}
+	return strings.Join(parts, " "), nil
+}
+
+func main() {
+	c := NewCircle(0, 0, defaultRadius)
+	text, err := describe(c)
+	if err != nil {
+		log.Fatal(err)
+	}
+	fmt.Println(text, c.Point.X)
+	apply := func(h Handler) { h(text) }
+	apply(func(s string) error { return nil })
+}
\ No newline at end of file
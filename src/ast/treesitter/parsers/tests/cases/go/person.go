@@ -0,0 +1,35 @@
+package people
+
+import "fmt"
+
+// Person represents a simple model of a person.
+type Person struct {
+	// Name of the person
+	Name string
+	Age  int
+}
+
+// Greeter can say hello.
+type Greeter interface {
+	Greet() string
+}
+
+// NewPerson constructs a new Person with the specified name and age.
+func NewPerson(name string, age int) *Person {
+	return &Person{Name: name, Age: age}
+}
+
+// GetName returns the name of the person.
+func (p *Person) GetName() string {
+	return p.Name
+}
+
+// SetAge sets the age of the person.
+func (p *Person) SetAge(age int) {
+	p.Age = age
+}
+
+// Greet returns a greeting.
+func (p Person) Greet() string {
+	return fmt.Sprintf("Hello, %s", p.Name)
+}
\ No newline at end of file
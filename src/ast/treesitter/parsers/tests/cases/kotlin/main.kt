@@ -0,0 +1,66 @@
+package zoo
+
+import kotlin.math.sqrt
+import java.util.*
+import com.example.json.Parser as JsonParser
+
+// an animal living in the zoo
+interface Animal {
+    val name: String
+    fun makeSound(): String
+}
+
+enum class Size(val weight: Int) {
+    SMALL(1),
+    LARGE(10);
+
+    fun isHeavy() = weight > 5
+}
+
+abstract class Pet(override val name: String, private var age: Int = 0) : Animal {
+    abstract override fun makeSound(): String
+}
+
+class Dog<T : Any>(name: String) : Pet(name), Comparable<Dog<T>> {
+    private val toys: MutableList<T> = mutableListOf()
+    var size = Size.SMALL
+
+    constructor() : this("Rex")
+
+    override fun makeSound(): String {
+        println(name + " says woof")
+        toys.clear()
+        return "woof"
+    }
+
+    override fun compareTo(other: Dog<T>): Int = name.compareTo(other.name)
+
+    companion object {
+        const val KIND = "dog"
+        fun create(): Dog<String> = Dog("Rex")
+    }
+}
+
+data class Point(val x: Double, val y: Double)
+
+object Registry {
+    val pets = ArrayList<Pet>()
+}
+
+typealias Pets = List<Pet>
+
+val origin = Point(0.0, 0.0)
+
+fun distance(a: Point, b: Point): Double {
+    val dx = a.x - b.x
+    val dy = a.y - b.y
+    return sqrt(dx * dx + dy * dy)
+}
+
+fun main(args: Array<String>) {
+    val dog = Dog.create()
+    dog.makeSound()
+    Registry.pets.add(dog)
+    val names = Registry.pets.map { it.name }
+    println(distance(origin, Point(3.0, 4.0)))
+}
\ No newline at end of file
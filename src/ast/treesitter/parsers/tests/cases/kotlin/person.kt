@@ -0,0 +1,35 @@
+package people
+
+import java.time.LocalDate
+
+/**
+ * A person with a name and an age
+ */
+open class Person(val name: String, var age: Int) : Comparable<Person> {
+    // how many times the birthday was celebrated
+    private var birthdays: Int = 0
+
+    fun greet(other: Person): String {
+        return "Hello, ${other.name}, I am $name"
+    }
+
+    fun celebrate() {
+        age += 1
+        birthdays += 1
+    }
+
+    override fun compareTo(other: Person): Int = age - other.age
+}
+
+class Employee(name: String, age: Int, val company: String) : Person(name, age) {
+    fun describe(): String = "$name works at $company"
+}
+
+enum class Gender {
+    MALE,
+    FEMALE
+}
+
+fun birthYear(person: Person): Int {
+    return LocalDate.now().year - person.age
+}
\ No newline at end of file
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::canonicalize;
+    use std::path::PathBuf;
+
+    use crate::ast::treesitter::language_id::LanguageId;
+    use crate::ast::treesitter::parsers::AstLanguageParser;
+    use crate::ast::treesitter::parsers::csharp::CSharpParser;
+    use crate::ast::treesitter::parsers::tests::{base_declaration_formatter_test, base_parser_test, base_skeletonizer_test};
+
+    const MAIN_CSHARP_CODE: &str = include_str!("cases/csharp/main.cs");
+    const MAIN_CSHARP_SYMBOLS: &str = include_str!("cases/csharp/main.cs.json");
+
+    const PERSON_CSHARP_CODE: &str = include_str!("cases/csharp/person.cs");
+    const PERSON_CSHARP_SKELETON: &str = include_str!("cases/csharp/person.cs.skeleton");
+    const PERSON_CSHARP_DECLS: &str = include_str!("cases/csharp/person.cs.decl_json");
+
+    #[test]
+    fn parser_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(CSharpParser::new().expect("CSharpParser::new"));
+        let path = PathBuf::from("file:///main.cs");
+        base_parser_test(&mut parser, &path, MAIN_CSHARP_CODE, MAIN_CSHARP_SYMBOLS);
+    }
+
+    #[test]
+    fn skeletonizer_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(CSharpParser::new().expect("CSharpParser::new"));
+        let file = canonicalize(PathBuf::from(file!())).unwrap().parent().unwrap().join("cases/csharp/person.cs");
+        assert!(file.exists());
+
+        base_skeletonizer_test(&LanguageId::CSharp, &mut parser, &file, PERSON_CSHARP_CODE, PERSON_CSHARP_SKELETON);
+    }
+
+    #[test]
+    fn declaration_formatter_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(CSharpParser::new().expect("CSharpParser::new"));
+        let file = canonicalize(PathBuf::from(file!())).unwrap().parent().unwrap().join("cases/csharp/person.cs");
+        assert!(file.exists());
+        base_declaration_formatter_test(&LanguageId::CSharp, &mut parser, &file, PERSON_CSHARP_CODE, PERSON_CSHARP_DECLS);
+    }
+}
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::canonicalize;
+    use std::path::PathBuf;
+
+    use crate::ast::treesitter::language_id::LanguageId;
+    use crate::ast::treesitter::parsers::AstLanguageParser;
+    use crate::ast::treesitter::parsers::kotlin::KotlinParser;
+    use crate::ast::treesitter::parsers::tests::{base_declaration_formatter_test, base_parser_test, base_skeletonizer_test};
+
+    const MAIN_KOTLIN_CODE: &str = include_str!("cases/kotlin/main.kt");
+    const MAIN_KOTLIN_SYMBOLS: &str = include_str!("cases/kotlin/main.kt.json");
+
+    const PERSON_KOTLIN_CODE: &str = include_str!("cases/kotlin/person.kt");
+    const PERSON_KOTLIN_SKELETON: &str = include_str!("cases/kotlin/person.kt.skeleton");
+    const PERSON_KOTLIN_DECLS: &str = include_str!("cases/kotlin/person.kt.decl_json");
+
+    #[test]
+    fn parser_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(KotlinParser::new().expect("KotlinParser::new"));
+        let path = PathBuf::from("file:///main.kt");
+        base_parser_test(&mut parser, &path, MAIN_KOTLIN_CODE, MAIN_KOTLIN_SYMBOLS);
+    }
+
+    #[test]
+    fn skeletonizer_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(KotlinParser::new().expect("KotlinParser::new"));
+        let file = canonicalize(PathBuf::from(file!())).unwrap().parent().unwrap().join("cases/kotlin/person.kt");
+        assert!(file.exists());
+
+        base_skeletonizer_test(&LanguageId::Kotlin, &mut parser, &file, PERSON_KOTLIN_CODE, PERSON_KOTLIN_SKELETON);
+    }
+
+    #[test]
+    fn declaration_formatter_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(KotlinParser::new().expect("KotlinParser::new"));
+        let file = canonicalize(PathBuf::from(file!())).unwrap().parent().unwrap().join("cases/kotlin/person.kt");
+        assert!(file.exists());
+        base_declaration_formatter_test(&LanguageId::Kotlin, &mut parser, &file, PERSON_KOTLIN_CODE, PERSON_KOTLIN_DECLS);
+    }
+}
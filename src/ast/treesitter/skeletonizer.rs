@@ -4,6 +4,7 @@ use uuid::Uuid;
 
 use crate::ast::treesitter::ast_instance_structs::SymbolInformation;
 use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::go::GoSkeletonFormatter;
 use crate::ast::treesitter::parsers::python::PythonSkeletonFormatter;
 use crate::ast::treesitter::structs::SymbolType;
 
@@ -62,7 +63,7 @@ pub trait SkeletonFormatter {
             .map(|x| x.replace("\r", "")
                 .replace("\t", "    ").to_string())
             .collect::<Vec<_>>();
-        let indent_n = content.iter().map(|x| {
+        let indent_n = lines.iter().map(|x| {
             if x.is_empty() {
                 return usize::MAX;
             } else {
@@ -81,49 +82,7 @@ pub trait SkeletonFormatter {
                                      text: &String,
                                      _guid_to_children: &HashMap<Uuid, Vec<Uuid>>,
                                      guid_to_info: &HashMap<Uuid, &SymbolInformation>) -> (String, (usize, usize)) {
-        let mut res_line: VecDeque<String> = Default::default();
-        let mut top_row = symbol.full_range.start_point.row;
-        let mut all_top_syms = guid_to_info.values().filter(|info| info.full_range.start_point.row < top_row).collect::<Vec<_>>();
-        // reverse sort
-        all_top_syms.sort_by(|a, b| b.full_range.start_point.row.cmp(&a.full_range.start_point.row));
-
-        let mut need_syms: Vec<&&SymbolInformation> = vec![];
-        {
-            for idx in 0..all_top_syms.len() {
-                let sym = all_top_syms[idx];
-                if sym.symbol_type != SymbolType::CommentDefinition {
-                    break;
-                }
-                let all_sym_on_this_line = all_top_syms.iter()
-                    .filter(|info|
-                        info.full_range.start_point.row == sym.full_range.start_point.row ||
-                            info.full_range.end_point.row == sym.full_range.start_point.row).collect::<Vec<_>>();
-
-                if all_sym_on_this_line.iter().all(|info| info.symbol_type == SymbolType::CommentDefinition) {
-                    need_syms.push(sym);
-                } else {
-                    break
-                }
-            }
-        }
-
-
-        for sym in need_syms {
-            if sym.symbol_type != SymbolType::CommentDefinition {
-                break;
-            }
-            top_row = sym.full_range.start_point.row;
-            let mut content = sym.get_content(text).unwrap();
-            if content.ends_with("\n") {
-                content.pop();
-            }
-            let lines = content.split("\n").collect::<Vec<_>>();
-            let lines = lines.iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>();
-            lines.into_iter().rev().for_each(|x| res_line.push_front(x));
-        }
-
+        let (mut res_line, top_row) = leading_comments(symbol, text, guid_to_info);
         let mut bottom_row = symbol.full_range.start_point.row;
         if symbol.symbol_type == SymbolType::StructDeclaration {
             if res_line.is_empty() {
@@ -149,13 +108,166 @@ pub trait SkeletonFormatter {
         let declaration = res_line.join("\n");
         (declaration, (top_row, bottom_row))
     }
+
+    // One text per definition for symbol-aware vectorization: the path, comments above, and the definition
+    // reduced to its outline, so the embedding is about what the symbol does rather than its every line
+    fn make_definition_summary(&self,
+                               symbol: &SymbolInformation,
+                               text: &String,
+                               guid_to_children: &HashMap<Uuid, Vec<Uuid>>,
+                               guid_to_info: &HashMap<Uuid, &SymbolInformation>) -> (String, (usize, usize)) {
+        let (comments, top_row) = leading_comments(symbol, text, guid_to_info);
+        let has_children = guid_to_children.get(&symbol.guid).map(|x| !x.is_empty()).unwrap_or(false);
+        let outline = if symbol.symbol_type == SymbolType::StructDeclaration && has_children {
+            self.make_skeleton(symbol, text, guid_to_children, guid_to_info)
+        } else {
+            let content = symbol.get_content(text).unwrap_or_default().split("\n").map(|x| x.to_string()).collect::<Vec<_>>();
+            skeletonize_body(&self.preprocess_content(content), SUMMARY_KEEP_INDENT, SUMMARY_MAX_LINES)
+        };
+        let mut res_line = vec![symbol.symbol_path.clone()];
+        res_line.extend(self.preprocess_content(Vec::from_iter(comments.into_iter())));
+        res_line.push(outline);
+        (res_line.join("\n"), (top_row, symbol.full_range.end_point.row))
+    }
+}
+
+const SUMMARY_KEEP_INDENT: usize = 8;
+const SUMMARY_MAX_LINES: usize = 40;
+
+// Comment lines right above the symbol, and the row where they start
+pub fn leading_comments(symbol: &SymbolInformation,
+                        text: &String,
+                        guid_to_info: &HashMap<Uuid, &SymbolInformation>) -> (VecDeque<String>, usize) {
+    let mut res_line: VecDeque<String> = Default::default();
+    let mut top_row = symbol.full_range.start_point.row;
+    let mut all_top_syms = guid_to_info.values().filter(|info| info.full_range.start_point.row < top_row).collect::<Vec<_>>();
+    // reverse sort
+    all_top_syms.sort_by(|a, b| b.full_range.start_point.row.cmp(&a.full_range.start_point.row));
+
+    let mut need_syms: Vec<&&SymbolInformation> = vec![];
+    {
+        for idx in 0..all_top_syms.len() {
+            let sym = all_top_syms[idx];
+            if sym.symbol_type != SymbolType::CommentDefinition {
+                break;
+            }
+            let all_sym_on_this_line = all_top_syms.iter()
+                .filter(|info|
+                    info.full_range.start_point.row == sym.full_range.start_point.row ||
+                        info.full_range.end_point.row == sym.full_range.start_point.row).collect::<Vec<_>>();
+
+            if all_sym_on_this_line.iter().all(|info| info.symbol_type == SymbolType::CommentDefinition) {
+                need_syms.push(sym);
+            } else {
+                break
+            }
+        }
+    }
+
+    for sym in need_syms {
+        if sym.symbol_type != SymbolType::CommentDefinition {
+            break;
+        }
+        top_row = sym.full_range.start_point.row;
+        let mut content = sym.get_content(text).unwrap();
+        if content.ends_with("\n") {
+            content.pop();
+        }
+        let lines = content.split("\n").collect::<Vec<_>>();
+        let lines = lines.iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        lines.into_iter().rev().for_each(|x| res_line.push_front(x));
+    }
+    (res_line, top_row)
+}
+
+// Keeps the lines up to keep_indent, deeper blocks become a single "...", blank lines are dropped
+pub fn skeletonize_body(lines: &Vec<String>, keep_indent: usize, max_lines: usize) -> String {
+    let mut result: Vec<String> = vec![];
+    let mut skipping = false;
+    for line in lines.iter() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if result.len() >= max_lines {
+            result.push("...".to_string());
+            break;
+        }
+        let indent = line.len() - line.trim_start().len();
+        if indent > keep_indent {
+            if !skipping {
+                result.push(format!("{}...", " ".repeat(indent)));
+                skipping = true;
+            }
+            continue;
+        }
+        skipping = false;
+        result.push(line.clone());
+    }
+    result.join("\n")
 }
 
 impl SkeletonFormatter for BaseSkeletonFormatter {}
 
 pub fn make_formatter(language_id: &LanguageId) -> Box<dyn SkeletonFormatter> {
     match language_id {
+        LanguageId::Go => Box::new(GoSkeletonFormatter {}),
         LanguageId::Python => Box::new(PythonSkeletonFormatter {}),
         _ => Box::new(BaseSkeletonFormatter {})
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::ast::treesitter::parsers::get_ast_parser_by_filename;
+    use crate::files_in_workspace::Document;
+
+    #[test]
+    fn test_skeletonize_body() {
+        let lines = vec![
+            "fn f(x: i32) -> i32 {",
+            "    let mut y = 0;",
+            "",
+            "    for i in 0..x {",
+            "        if i % 2 == 0 {",
+            "            y += i;",
+            "            y *= 2;",
+            "        }",
+            "    }",
+            "    y",
+            "}",
+        ].into_iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            skeletonize_body(&lines, 4, 100),
+            "fn f(x: i32) -> i32 {\n    let mut y = 0;\n    for i in 0..x {\n        ...\n    }\n    y\n}",
+        );
+        assert_eq!(skeletonize_body(&lines, 8, 2), "fn f(x: i32) -> i32 {\n    let mut y = 0;\n...");
+    }
+
+    #[test]
+    fn test_make_definition_summary() {
+        let text = "// Parses the headers\n// of an http request\nfn parse_headers(s: &str) -> usize {\n    let mut n = 0;\n    for line in s.lines() {\n        if line.contains(':') {\n            if !line.is_empty() {\n                n += 1;\n            }\n        }\n    }\n    n\n}\n".to_string();
+        let path = PathBuf::from("/tmp/headers.rs");
+        let (mut parser, language) = get_ast_parser_by_filename(&path).unwrap();
+        let symbols: Vec<SymbolInformation> = parser.parse(&text, &path).into_iter().map(|s| s.read().symbol_info_struct()).collect();
+        let mut doc = Document::new(&path);
+        doc.update_text(&text);
+        let markup = crate::ast::lowlevel_file_markup(&doc, &symbols).unwrap();
+        let guid_to_info: HashMap<Uuid, &SymbolInformation> = markup.symbols_sorted_by_path_len.iter().map(|s| (s.guid.clone(), s)).collect();
+        let guid_to_children: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+        let func = markup.symbols_sorted_by_path_len.iter().find(|s| s.symbol_type == SymbolType::FunctionDeclaration).unwrap();
+
+        let (summary, (top_row, bottom_row)) = make_formatter(&language).make_definition_summary(func, &text, &guid_to_children, &guid_to_info);
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines[0], func.symbol_path);
+        assert_eq!(lines[1], "// Parses the headers");
+        assert_eq!(lines[3], "fn parse_headers(s: &str) -> usize {");
+        assert!(summary.contains("        if line.contains(':') {\n            ...\n        }"));
+        assert!(!summary.contains("n += 1"));
+        assert_eq!((top_row, bottom_row), (0, 12));
+    }
+}
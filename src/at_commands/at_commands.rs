@@ -79,7 +79,7 @@ pub trait AtCommand: Send + Sync {
     fn params(&self) -> &Vec<Arc<AMutex<dyn AtParam>>>;
     // returns (messages_for_postprocessing, text_on_clip)
     async fn at_execute(&self, ccx: Arc<AMutex<AtCommandsContext>>, cmd: &mut AtCommandMember, args: &mut Vec<AtCommandMember>) -> Result<(Vec<ContextEnum>, String), String>;
-    fn depends_on(&self) -> Vec<String> { vec![] }   // "ast", "vecdb"
+    fn depends_on(&self) -> Vec<String> { vec![] }   // "ast", "vecdb", "vecdb_or_bm25"
 }
 
 #[async_trait]
@@ -100,17 +100,16 @@ pub async fn at_commands_dict(gcx: Arc<ARwLock<GlobalContext>>) -> HashMap<Strin
         // ("@diff".to_string(), Arc::new(AMutex::new(Box::new(AtDiff::new()) as Box<dyn AtCommand + Send>))),
         // ("@diff-rev".to_string(), Arc::new(AMutex::new(Box::new(AtDiffRev::new()) as Box<dyn AtCommand + Send>))),
         ("@web".to_string(), Arc::new(AMutex::new(Box::new(AtWeb::new()) as Box<dyn AtCommand + Send>))),
-        #[cfg(feature="vecdb")]
         ("@search".to_string(), Arc::new(AMutex::new(Box::new(crate::at_commands::at_search::AtSearch::new()) as Box<dyn AtCommand + Send>))),
     ]);
 
-    let (ast_on, vecdb_on) = {
+    let (ast_on, vecdb_on, bm25_on) = {
         let gcx_locked = gcx.read().await;
         #[cfg(feature="vecdb")]
         let vecdb_on = gcx_locked.vec_db.lock().await.is_some();
         #[cfg(not(feature="vecdb"))]
         let vecdb_on = false;
-        (gcx_locked.ast_service.is_some(), vecdb_on)
+        (gcx_locked.ast_service.is_some(), vecdb_on, gcx_locked.bm25_service.is_some())
     };
 
     let mut result = HashMap::new();
@@ -123,6 +122,9 @@ pub async fn at_commands_dict(gcx: Arc<ARwLock<GlobalContext>>) -> HashMap<Strin
         if depends_on.contains(&"vecdb".to_string()) && !vecdb_on {
             continue;
         }
+        if depends_on.contains(&"vecdb_or_bm25".to_string()) && !vecdb_on && !bm25_on {
+            continue;
+        }
         result.insert(key, value.clone());
     }
 
@@ -1,14 +1,20 @@
 use crate::at_commands::at_commands::{vec_context_file_to_context_tools, AtCommand, AtCommandsContext, AtParam};
 use async_trait::async_trait;
 use std::sync::Arc;
-use tokio::sync::Mutex as AMutex;
-use tracing::info;
+use tokio::sync::{Mutex as AMutex, RwLock as ARwLock};
+use tracing::{info, warn};
 use crate::nicer_logs::last_n_chars;
 
 use crate::at_commands::execute_at::AtCommandMember;
+use crate::bm25::bm25_hybrid::{reciprocal_rank_fusion, RankedHit};
+use crate::bm25::bm25_thread::bm25_search;
 use crate::call_validation::{ContextEnum, ContextFile};
+#[cfg(feature="vecdb")]
 use crate::caps::get_custom_embedding_api_key;
-use crate::vecdb;
+use crate::global_context::GlobalContext;
+use crate::rerank::rerank_highlev::rerank_hits;
+use crate::search_filter::{ResolvedSearchFilter, ScopeFilter};
+#[cfg(feature="vecdb")]
 use crate::vecdb::vdb_structs::VecdbSearch;
 
 
@@ -16,7 +22,7 @@ pub fn text_on_clip(query: &String, from_tool_call: bool) -> String {
     if !from_tool_call {
         return query.clone();
     }
-    return format!("performed vecdb search, results below");
+    return format!("performed search, results below");
 }
 
 
@@ -32,26 +38,19 @@ impl AtSearch {
     }
 }
 
-fn results2message(results: &Vec<vecdb::vdb_structs::VecdbRecord>) -> Vec<ContextFile> {
+fn results2message(results: &Vec<RankedHit>) -> Vec<ContextFile> {
     let mut vector_of_context_file: Vec<ContextFile> = vec![];
     for r in results {
         let file_name = r.file_path.to_str().unwrap().to_string();
-        let mut usefulness = r.usefulness;
-        // diversifying results
-        let same_file_again =  vector_of_context_file.iter().map(|x|&x.file_name).filter(|x|**x == file_name).count();
-        let same_file_discount = 1. / (same_file_again as f32 * 0.1 + 1.);
-        usefulness *= same_file_discount;
-        info!("results {} usefulness {:.2} after same-file discount {:.2}",
-            last_n_chars(&file_name, 30),
-            usefulness,
-            same_file_discount,
-        );
+        // same-file results are already discounted by the reranker
+        let usefulness = r.usefulness;
+        info!("results {} usefulness {:.2}", last_n_chars(&file_name, 30), usefulness);
         vector_of_context_file.push(ContextFile {
             file_name,
             file_content: "".to_string(),
             line1: r.start_line as usize + 1,
             line2: r.end_line as usize + 1,
-            symbols: vec![],
+            symbols: if r.symbol_path.is_empty() { vec![] } else { vec![r.symbol_path.clone()] },
             gradient_type: -1,
             usefulness,
         });
@@ -59,34 +58,82 @@ fn results2message(results: &Vec<vecdb::vdb_structs::VecdbRecord>) -> Vec<Contex
     vector_of_context_file
 }
 
+#[cfg(feature="vecdb")]
+async fn vecdb_search_hits(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    query: &String,
+    top_n: usize,
+    vecdb_scope_filter_mb: Option<ScopeFilter>,
+) -> Result<Option<Vec<RankedHit>>, String> {
+    let vec_db = gcx.read().await.vec_db.clone();
+    let vec_db_locked = vec_db.lock().await;
+    let db = match *vec_db_locked {
+        Some(ref db) => db,
+        None => return Ok(None),
+    };
+    let api_key = get_custom_embedding_api_key(gcx.clone()).await.map_err(|e| e.message)?;
+    // TODO: this code sucks, release lock, don't hold anything during the search
+    let search_result = db.vecdb_search(query.clone(), top_n, vecdb_scope_filter_mb, &api_key).await?;
+    Ok(Some(search_result.results.into_iter().map(|r| RankedHit {
+        file_path: r.file_path,
+        start_line: r.start_line,
+        end_line: r.end_line,
+        usefulness: r.usefulness,
+        symbol_path: r.symbol_path,
+    }).collect()))
+}
+
 pub async fn execute_at_search(
     ccx: Arc<AMutex<AtCommandsContext>>,
     query: &String,
-    vecdb_scope_filter_mb: Option<String>,
+    filter_mb: Option<&ResolvedSearchFilter>,
 ) -> Result<Vec<ContextFile>, String> {
     let (gcx, top_n) = {
         let ccx_locked = ccx.lock().await;
         (ccx_locked.global_context.clone(), ccx_locked.top_n)
     };
+    let top_n_twice_as_big = top_n * 2;  // top_n will be cut at postprocessing stage, and we really care about top_n files, not pieces
+    let fetch_n = filter_mb.map(|f| f.fetch_n(top_n_twice_as_big)).unwrap_or(top_n_twice_as_big);
+    let scope_filter_mb = filter_mb.and_then(|f| f.pushdown.clone());
 
-    let api_key = get_custom_embedding_api_key(gcx.clone()).await;
-    if let Err(err) = api_key {
-        return Err(err.message);
+    // vector results go first, so their windows win when both retrievers found overlapping lines
+    let mut ranked_lists = vec![];
+    let mut errors = vec![];
+    #[cfg(feature="vecdb")]
+    match vecdb_search_hits(gcx.clone(), query, fetch_n, scope_filter_mb.clone()).await {
+        Ok(Some(hits)) => ranked_lists.push(hits),
+        Ok(None) => {}
+        Err(e) => {
+            warn!("vecdb search failed, will use lexical results only: {}", e);
+            errors.push(e);
+        }
     }
-    let api_key = api_key.unwrap();
-
-    let vec_db = gcx.read().await.vec_db.clone();
-    let r = match *vec_db.lock().await {
-        Some(ref db) => {
-            let top_n_twice_as_big = top_n * 2;  // top_n will be cut at postprocessing stage, and we really care about top_n files, not pieces
-            // TODO: this code sucks, release lock, don't hold anything during the search
-            let search_result = db.vecdb_search(query.clone(), top_n_twice_as_big, vecdb_scope_filter_mb, &api_key).await?;
-            let results = search_result.results.clone();
-            return Ok(results2message(&results));
+    let bm25_service_mb = gcx.read().await.bm25_service.clone();
+    if let Some(bm25_service) = bm25_service_mb {
+        match bm25_search(bm25_service, query, fetch_n, scope_filter_mb).await {
+            Ok(hits) => ranked_lists.push(hits),
+            Err(e) => {
+                warn!("{}", e);
+                errors.push(e);
+            }
         }
-        None => Err("VecDB is not active. Possible reasons: VecDB is turned off in settings, or perhaps a vectorization model is not available.".to_string())
-    };
-    r
+    }
+    if ranked_lists.is_empty() {
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        return Err("Neither VecDB nor the BM25 index is active. Possible reasons: VecDB is turned off in settings, or perhaps a vectorization model is not available, and --bm25 is not set.".to_string());
+    }
+    if let Some(filter) = filter_mb {
+        let mut filtered_lists = vec![];
+        for hits in ranked_lists {
+            filtered_lists.push(filter.retain(gcx.clone(), hits, |h| (h.file_path.to_string_lossy().to_string(), h.start_line, h.end_line)).await);
+        }
+        ranked_lists = filtered_lists;
+    }
+    let results = reciprocal_rank_fusion(ranked_lists, top_n_twice_as_big);
+    let results = rerank_hits(gcx.clone(), query, results).await;
+    Ok(results2message(&results))
 }
 
 #[async_trait]
@@ -118,6 +165,6 @@ impl AtCommand for AtSearch {
     }
 
     fn depends_on(&self) -> Vec<String> {
-        vec!["vecdb".to_string()]
+        vec!["vecdb_or_bm25".to_string()]
     }
 }
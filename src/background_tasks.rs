@@ -50,6 +50,10 @@ pub async fn start_background_tasks(gcx: Arc<ARwLock<GlobalContext>>) -> Backgro
     if let Some(ast_service) = ast {
         bg.extend(crate::ast::ast_indexer_thread::ast_indexer_start(ast_service, gcx.clone()).await);
     }
+    let bm25 = gcx.clone().read().await.bm25_service.clone();
+    if let Some(bm25_service) = bm25 {
+        bg.extend(crate::bm25::bm25_thread::bm25_indexer_start(bm25_service, gcx.clone()).await);
+    }
     let files_jsonl_path = gcx.clone().read().await.cmdline.files_jsonl_path.clone();
     if !files_jsonl_path.is_empty() {
         bg.extend(vec![
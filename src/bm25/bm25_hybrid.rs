@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+
+
+// Standard constant from the original RRF paper, dampens the influence of the very top ranks
+const RRF_K: f32 = 60.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedHit {
+    pub file_path: PathBuf,
+    pub start_line: u64,
+    pub end_line: u64,
+    pub usefulness: f32,
+    pub symbol_path: String,  // empty unless the window is one definition
+}
+
+fn overlaps(a: &RankedHit, b: &RankedHit) -> bool {
+    a.file_path == b.file_path && a.start_line <= b.end_line && b.start_line <= a.end_line
+}
+
+// Lists come in priority order: when windows from different lists overlap, the line range of the earlier list is kept
+pub fn reciprocal_rank_fusion(ranked_lists: Vec<Vec<RankedHit>>, top_n: usize) -> Vec<RankedHit> {
+    let non_empty: Vec<Vec<RankedHit>> = ranked_lists.into_iter().filter(|x| !x.is_empty()).collect();
+    if non_empty.len() <= 1 {
+        // nothing to fuse, keep the scores that the only retriever produced
+        return non_empty.into_iter().next().unwrap_or_default().into_iter().take(top_n).collect();
+    }
+    let mut fused: Vec<(RankedHit, f32)> = vec![];
+    for list in non_empty {
+        let mut seen_in_this_list: Vec<usize> = vec![];
+        for (rank, hit) in list.into_iter().enumerate() {
+            let rrf = 1.0 / (RRF_K + rank as f32 + 1.0);
+            match fused.iter().position(|(x, _)| overlaps(x, &hit)) {
+                Some(idx) if !seen_in_this_list.contains(&idx) => {
+                    fused[idx].1 += rrf;
+                    seen_in_this_list.push(idx);
+                }
+                Some(_) => {}
+                None => {
+                    seen_in_this_list.push(fused.len());
+                    fused.push((hit, rrf));
+                }
+            }
+        }
+    }
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let top_score = fused.first().map(|x| x.1).unwrap_or(1.0);
+    fused.into_iter().take(top_n).map(|(mut hit, score)| {
+        hit.usefulness = 25.0 + 75.0 * score / top_score;
+        hit
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(file: &str, start_line: u64, end_line: u64) -> RankedHit {
+        RankedHit { file_path: PathBuf::from(file), start_line, end_line, usefulness: 50.0, symbol_path: "".to_string() }
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let vector = vec![hit("a.rs", 0, 20), hit("b.rs", 0, 10), hit("c.rs", 5, 9)];
+        let lexical = vec![hit("c.rs", 0, 6), hit("d.rs", 0, 3), hit("a.rs", 50, 60)];
+        let fused = reciprocal_rank_fusion(vec![vector, lexical], 10);
+        // c.rs is found by both retrievers and wins, keeping the vector window lines
+        assert_eq!(fused[0], RankedHit { usefulness: 100.0, ..hit("c.rs", 5, 9) });
+        assert_eq!(fused.len(), 5);
+        assert_eq!(fused[1].file_path, PathBuf::from("a.rs"));
+        assert!(fused.iter().all(|x| x.usefulness > 25.0 && x.usefulness <= 100.0));
+
+        let only_lexical = reciprocal_rank_fusion(vec![vec![], vec![hit("d.rs", 0, 3), hit("e.rs", 0, 3)]], 1);
+        assert_eq!(only_lexical, vec![hit("d.rs", 0, 3)]);
+    }
+}
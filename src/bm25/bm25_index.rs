@@ -0,0 +1,322 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use rusqlite::{params, params_from_iter, OpenFlags, OptionalExtension};
+use tokio_rusqlite::Connection;
+use tracing::info;
+
+use crate::bm25::bm25_hybrid::RankedHit;
+use crate::search_filter::ScopeFilter;
+
+
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+const BM25_SCHEMA_VERSION: i32 = 1;
+const TOKEN_MIN_CHARS: usize = 2;
+const TOKEN_MAX_CHARS: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Bm25Window {
+    pub start_line: u64,
+    pub end_line: u64,
+    pub symbol_path: String,
+    pub window_text: String,
+}
+
+pub struct Bm25Index {
+    db: Connection,
+}
+
+fn push_token(token: &str, out: &mut Vec<String>) {
+    let n = token.chars().count();
+    if n >= TOKEN_MIN_CHARS && n <= TOKEN_MAX_CHARS {
+        out.push(token.to_lowercase());
+    }
+}
+
+// Identifiers are indexed whole and also split into parts: `getUserName` -> getusername, get, user, name
+pub fn bm25_tokenize(text: &str) -> Vec<String> {
+    let mut result = vec![];
+    for word in text.split(|c: char| !c.is_alphanumeric() && c != '_') {
+        if word.is_empty() {
+            continue;
+        }
+        push_token(word, &mut result);
+        let mut parts: Vec<String> = vec![];
+        for piece in word.split('_').filter(|x| !x.is_empty()) {
+            let chars: Vec<char> = piece.chars().collect();
+            let mut current = String::new();
+            for (i, c) in chars.iter().enumerate() {
+                let boundary = i > 0 && (
+                    (c.is_uppercase() && chars[i - 1].is_lowercase()) ||
+                    (c.is_uppercase() && chars[i - 1].is_uppercase() && chars.get(i + 1).map(|x| x.is_lowercase()).unwrap_or(false)) ||
+                    (c.is_ascii_digit() != chars[i - 1].is_ascii_digit())
+                );
+                if boundary && !current.is_empty() {
+                    parts.push(std::mem::take(&mut current));
+                }
+                current.push(*c);
+            }
+            if !current.is_empty() {
+                parts.push(current);
+            }
+        }
+        if parts.len() > 1 {
+            for p in parts.iter() {
+                push_token(p, &mut result);
+            }
+        }
+    }
+    result
+}
+
+fn bm25_idf(n_windows: f32, df: f32) -> f32 {
+    ((n_windows - df + 0.5) / (df + 0.5) + 1.0).ln()
+}
+
+impl Bm25Index {
+    pub async fn init(db_path: Option<PathBuf>) -> Result<Bm25Index, String> {
+        let db = match &db_path {
+            Some(path) => {
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await.map_err(|e| format!("{:?}", e))?;
+                }
+                Connection::open_with_flags(
+                    path.clone(), OpenFlags::SQLITE_OPEN_READ_WRITE
+                        | OpenFlags::SQLITE_OPEN_CREATE
+                        | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                        | OpenFlags::SQLITE_OPEN_URI).await.map_err(|e| format!("{:?}", e))?
+            }
+            None => Connection::open_in_memory().await.map_err(|e| format!("{:?}", e))?,
+        };
+        db.call(move |conn| {
+            let _ = conn.execute_batch("PRAGMA journal_mode=WAL;");
+            let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+            if version != BM25_SCHEMA_VERSION {
+                conn.execute_batch(
+                    "DROP TABLE IF EXISTS bm25_postings;
+                    DROP TABLE IF EXISTS bm25_windows;
+                    DROP TABLE IF EXISTS bm25_files;"
+                )?;
+            }
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS bm25_files (
+                    scope TEXT PRIMARY KEY,
+                    text_md5 TEXT NOT NULL
+                );
+                CREATE TABLE IF NOT EXISTS bm25_windows (
+                    id INTEGER PRIMARY KEY,
+                    scope TEXT NOT NULL,
+                    start_line INTEGER NOT NULL,
+                    end_line INTEGER NOT NULL,
+                    symbol_path TEXT NOT NULL,
+                    window_len INTEGER NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_bm25_windows_scope ON bm25_windows (scope);
+                CREATE TABLE IF NOT EXISTS bm25_postings (
+                    term TEXT NOT NULL,
+                    window_id INTEGER NOT NULL,
+                    tf INTEGER NOT NULL,
+                    PRIMARY KEY (term, window_id)
+                ) WITHOUT ROWID;
+                CREATE INDEX IF NOT EXISTS idx_bm25_postings_window ON bm25_postings (window_id);
+                PRAGMA user_version = {BM25_SCHEMA_VERSION};"
+            ))?;
+            Ok(())
+        }).await.map_err(|e| format!("{:?}", e))?;
+        info!("bm25 index at {:?}", db_path.unwrap_or(PathBuf::from(":memory:")));
+        Ok(Bm25Index { db })
+    }
+
+    pub async fn file_md5(&self, cpath: &String) -> Option<String> {
+        let cpath = cpath.clone();
+        self.db.call(move |conn| {
+            Ok(conn.query_row("SELECT text_md5 FROM bm25_files WHERE scope = ?1", params![cpath], |row| row.get(0)).optional()?)
+        }).await.unwrap_or(None)
+    }
+
+    pub async fn file_replace(&self, cpath: &String, text_md5: &String, windows: Vec<Bm25Window>) -> Result<(), String> {
+        let cpath = cpath.clone();
+        let text_md5 = text_md5.clone();
+        self.db.call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM bm25_postings WHERE window_id IN (SELECT id FROM bm25_windows WHERE scope = ?1)", params![cpath])?;
+            tx.execute("DELETE FROM bm25_windows WHERE scope = ?1", params![cpath])?;
+            for w in windows.iter() {
+                let tokens = bm25_tokenize(&w.window_text);
+                let mut tf: HashMap<&String, i64> = HashMap::new();
+                for t in tokens.iter() {
+                    *tf.entry(t).or_insert(0) += 1;
+                }
+                tx.execute(
+                    "INSERT INTO bm25_windows (scope, start_line, end_line, symbol_path, window_len) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![cpath, w.start_line as i64, w.end_line as i64, w.symbol_path, tokens.len() as i64],
+                )?;
+                let window_id = tx.last_insert_rowid();
+                let mut stmt = tx.prepare_cached("INSERT INTO bm25_postings (term, window_id, tf) VALUES (?1, ?2, ?3)")?;
+                for (term, n) in tf {
+                    stmt.execute(params![term, window_id, n])?;
+                }
+            }
+            tx.execute("INSERT OR REPLACE INTO bm25_files (scope, text_md5) VALUES (?1, ?2)", params![cpath, text_md5])?;
+            tx.commit()?;
+            Ok(())
+        }).await.map_err(|e| format!("{:?}", e))
+    }
+
+    pub async fn file_remove(&self, cpath: &String) -> Result<(), String> {
+        let cpath = cpath.clone();
+        self.db.call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM bm25_postings WHERE window_id IN (SELECT id FROM bm25_windows WHERE scope = ?1)", params![cpath])?;
+            tx.execute("DELETE FROM bm25_windows WHERE scope = ?1", params![cpath])?;
+            tx.execute("DELETE FROM bm25_files WHERE scope = ?1", params![cpath])?;
+            tx.commit()?;
+            Ok(())
+        }).await.map_err(|e| format!("{:?}", e))
+    }
+
+    pub async fn size(&self) -> Result<(usize, usize), String> {
+        self.db.call(move |conn| {
+            let files: i64 = conn.query_row("SELECT COUNT(*) FROM bm25_files", [], |row| row.get(0))?;
+            let windows: i64 = conn.query_row("SELECT COUNT(*) FROM bm25_windows", [], |row| row.get(0))?;
+            Ok((files as usize, windows as usize))
+        }).await.map_err(|e| format!("{:?}", e))
+    }
+
+    pub async fn search(
+        &self,
+        query: &String,
+        top_n: usize,
+        scope_filter_mb: Option<ScopeFilter>,
+        active_files: Option<HashSet<String>>,
+    ) -> Result<Vec<RankedHit>, String> {
+        let terms: Vec<String> = bm25_tokenize(query).into_iter().collect::<HashSet<_>>().into_iter().collect();
+        if terms.is_empty() {
+            return Ok(vec![]);
+        }
+        let scored = self.db.call(move |conn| {
+            let (n_windows, avg_len): (i64, f64) = conn.query_row(
+                "SELECT COUNT(*), COALESCE(AVG(window_len), 0) FROM bm25_windows", [], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            let mut scores: HashMap<i64, f32> = HashMap::new();
+            if n_windows == 0 {
+                return Ok(vec![]);
+            }
+            // the term is ?1, the filter values follow
+            let (filter_sql, filter_params) = match &scope_filter_mb {
+                Some(filter) => {
+                    let (sql, params) = filter.to_sqlite_filter("w.scope", 2);
+                    (format!(" AND {}", sql), params)
+                }
+                None => ("".to_string(), vec![]),
+            };
+            let mut df_stmt = conn.prepare("SELECT COUNT(*) FROM bm25_postings WHERE term = ?1")?;
+            let mut postings_stmt = conn.prepare(&format!(
+                "SELECT p.window_id, p.tf, w.window_len FROM bm25_postings p JOIN bm25_windows w ON w.id = p.window_id WHERE p.term = ?1{}",
+                filter_sql
+            ))?;
+            for term in terms.iter() {
+                let df: i64 = df_stmt.query_row(params![term], |row| row.get(0))?;
+                if df == 0 {
+                    continue;
+                }
+                let idf = bm25_idf(n_windows as f32, df as f32);
+                let rows = postings_stmt.query_map(params_from_iter(std::iter::once(term).chain(filter_params.iter())), |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?))
+                })?;
+                for row in rows {
+                    let (window_id, tf, window_len) = row?;
+                    let tf = tf as f32;
+                    let norm = 1.0 - BM25_B + BM25_B * window_len as f32 / (avg_len as f32).max(1.0);
+                    *scores.entry(window_id).or_insert(0.0) += idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm);
+                }
+            }
+            let mut scored: Vec<(i64, f32)> = scores.into_iter().collect();
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+            let mut window_stmt = conn.prepare("SELECT scope, start_line, end_line, symbol_path FROM bm25_windows WHERE id = ?1")?;
+            let mut result = vec![];
+            for (window_id, score) in scored {
+                if result.len() >= top_n {
+                    break;
+                }
+                let (scope, start_line, end_line, symbol_path): (String, i64, i64, String) = window_stmt.query_row(
+                    params![window_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?;
+                if let Some(active) = &active_files {
+                    if !active.contains(&scope) {
+                        continue;
+                    }
+                }
+                result.push((scope, start_line as u64, end_line as u64, symbol_path, score));
+            }
+            Ok(result)
+        }).await.map_err(|e| format!("bm25 search failed: {:?}", e))?;
+
+        let top_score = scored.first().map(|x| x.4).unwrap_or(1.0).max(f32::EPSILON);
+        Ok(scored.into_iter().map(|(scope, start_line, end_line, symbol_path, score)| RankedHit {
+            file_path: PathBuf::from(scope),
+            start_line,
+            end_line,
+            usefulness: 25.0 + 75.0 * score / top_score,
+            symbol_path,
+        }).collect())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(start_line: u64, end_line: u64, text: &str) -> Bm25Window {
+        Bm25Window { start_line, end_line, symbol_path: "".to_string(), window_text: text.to_string() }
+    }
+
+    #[test]
+    fn test_bm25_tokenize() {
+        assert_eq!(bm25_tokenize("getUserName"), vec!["getusername", "get", "user", "name"]);
+        assert_eq!(bm25_tokenize("max_new_tokens: 5"), vec!["max_new_tokens", "max", "new", "tokens"]);
+        assert_eq!(bm25_tokenize("HTTPServer x"), vec!["httpserver", "http", "server"]);
+        assert_eq!(bm25_tokenize("ERR_CONNECTION_REFUSED!"), vec!["err_connection_refused", "err", "connection", "refused"]);
+    }
+
+    #[tokio::test]
+    async fn test_bm25_search_finds_exact_identifiers() {
+        let index = Bm25Index::init(None).await.unwrap();
+        let a = "/proj/src/a.rs".to_string();
+        let b = "/proj/lib/b.py".to_string();
+        index.file_replace(&a, &"md5a".to_string(), vec![
+            window(0, 9, "fn connect_to_database(url: &str) { retry_policy(url) }"),
+            window(10, 19, "fn render_page() { draw(); draw(); }"),
+        ]).await.unwrap();
+        index.file_replace(&b, &"md5b".to_string(), vec![
+            window(0, 5, "def parse_config(): raise ValueError('invalid max_new_tokens')"),
+        ]).await.unwrap();
+        assert_eq!(index.file_md5(&a).await, Some("md5a".to_string()));
+        assert_eq!(index.size().await.unwrap(), (2, 3));
+
+        let hits = index.search(&"max_new_tokens".to_string(), 10, None, None).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].file_path, PathBuf::from(&b));
+        assert_eq!(hits[0].usefulness, 100.0);
+
+        let hits = index.search(&"ConnectToDatabase retry".to_string(), 10, None, None).await.unwrap();
+        assert_eq!(hits[0].file_path, PathBuf::from(&a));
+        assert_eq!((hits[0].start_line, hits[0].end_line), (0, 9));
+
+        let hits = index.search(&"draw".to_string(), 10, Some(ScopeFilter { files: vec![], dirs: vec!["/proj/lib/".to_string()] }), None).await.unwrap();
+        assert!(hits.is_empty());
+        let hits = index.search(&"draw".to_string(), 10, Some(ScopeFilter { files: vec![a.clone()], dirs: vec![] }), None).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        let hits = index.search(&"draw".to_string(), 10, None, Some(HashSet::from([b.clone()]))).await.unwrap();
+        assert!(hits.is_empty());
+
+        index.file_remove(&a).await.unwrap();
+        assert_eq!(index.size().await.unwrap(), (1, 1));
+        assert!(index.search(&"draw".to_string(), 10, None, None).await.unwrap().is_empty());
+
+        let quoted = "/proj/it's \"here\"/c.rs".to_string();
+        index.file_replace(&quoted, &"md5c".to_string(), vec![window(0, 3, "fn draw() {}")]).await.unwrap();
+        let hits = index.search(&"draw".to_string(), 10, Some(ScopeFilter { files: vec![], dirs: vec!["/proj/it's \"here\"/".to_string()] }), None).await.unwrap();
+        assert_eq!(hits.len(), 1);
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use indexmap::IndexSet;
+use serde::Serialize;
+use tokio::sync::{Mutex as AMutex, Notify as ANotify, RwLock as ARwLock};
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::ast::file_splitter::AstBasedFileSplitter;
+use crate::bm25::bm25_hybrid::RankedHit;
+use crate::bm25::bm25_index::{Bm25Index, Bm25Window};
+use crate::files_in_workspace::Document;
+use crate::global_context::GlobalContext;
+use crate::search_filter::ScopeFilter;
+
+// Same order of magnitude as the vecdb windows (embedding_n_ctx / 2 and embedding_n_ctx), so both retrievers see similar pieces
+const BM25_SPLITTER_WINDOW_SIZE: usize = 256;
+const BM25_SPLITTER_TOKENS_LIMIT: usize = 512;
+
+
+#[derive(Serialize, Clone, Default, Debug)]
+pub struct Bm25Status {
+    pub state: String,   // "starting", "indexing", "done"
+    pub files_unprocessed: usize,
+    pub files_total: usize,  // resets to 0 when done
+    pub db_files: usize,
+    pub db_windows: usize,
+}
+
+pub struct Bm25IndexService {
+    pub bm25_index: Arc<AMutex<Bm25Index>>,
+    pub bm25_status: Arc<AMutex<Bm25Status>>,
+    pub bm25_sleeping_point: Arc<ANotify>,
+    pub bm25_todo: IndexSet<String>,
+    pub bm25_forget: IndexSet<String>,  // in bm25_todo too, but to be removed from the index even if the file is still there
+    // the database is shared between runs, search only returns files seen in this session
+    pub active_files: Arc<AMutex<HashSet<String>>>,
+    pub workspace_key: String,
+}
+
+async fn bm25_index_one_file(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    bm25_index: Arc<AMutex<Bm25Index>>,
+    cpath: &String,
+) -> Result<bool, String> {
+    let path = PathBuf::from(cpath);
+    let file_text = crate::files_in_workspace::get_file_text_for_indexing(gcx.clone(), &path).await?;
+    let text_md5 = format!("{:x}", md5::compute(file_text.as_bytes()));
+    if bm25_index.lock().await.file_md5(cpath).await.as_ref() == Some(&text_md5) {
+        return Ok(true);
+    }
+    let mut doc = Document::new(&path);
+    doc.update_text(&file_text);
+    doc.does_text_look_good()?;
+
+    let splitter = AstBasedFileSplitter::new(BM25_SPLITTER_WINDOW_SIZE, false);
+    let splits = splitter.vectorization_split(&doc, None, gcx.clone(), BM25_SPLITTER_TOKENS_LIMIT).await?;
+    let mut windows: Vec<Bm25Window> = splits.into_iter().map(|s| Bm25Window {
+        start_line: s.start_line,
+        end_line: s.end_line,
+        symbol_path: s.symbol_path,
+        window_text: s.window_text,
+    }).collect();
+    // Adding the filename so it can also be searched, the same way vecdb does
+    if let Some(filename) = path.file_name().map(|f| f.to_string_lossy().to_string()) {
+        windows.push(Bm25Window {
+            start_line: 0,
+            end_line: file_text.lines().count().saturating_sub(1) as u64,
+            symbol_path: "".to_string(),
+            window_text: filename,
+        });
+    }
+    bm25_index.lock().await.file_replace(cpath, &text_md5, windows).await?;
+    Ok(false)
+}
+
+async fn bm25_indexer_thread(
+    gcx_weak: Weak<ARwLock<GlobalContext>>,
+    bm25_service: Arc<AMutex<Bm25IndexService>>,
+) {
+    let (bm25_index, bm25_status, bm25_sleeping_point, active_files) = {
+        let service_locked = bm25_service.lock().await;
+        (
+            service_locked.bm25_index.clone(),
+            service_locked.bm25_status.clone(),
+            service_locked.bm25_sleeping_point.clone(),
+            service_locked.active_files.clone(),
+        )
+    };
+    let mut stats_indexed_cnt = 0;
+    let mut stats_unchanged_cnt = 0;
+    let mut stats_t0 = std::time::Instant::now();
+
+    loop {
+        let (cpath, forget, left_todo_count) = {
+            let mut service_locked = bm25_service.lock().await;
+            let cpath = service_locked.bm25_todo.pop();
+            let forget = cpath.as_ref().map(|x| service_locked.bm25_forget.swap_remove(x)).unwrap_or(false);
+            (cpath, forget, service_locked.bm25_todo.len())
+        };
+
+        let cpath = match cpath {
+            Some(x) => x,
+            None => {
+                let mut status_locked = bm25_status.lock().await;
+                if status_locked.state != "done" {
+                    if let Ok((db_files, db_windows)) = bm25_index.lock().await.size().await {
+                        status_locked.db_files = db_files;
+                        status_locked.db_windows = db_windows;
+                    }
+                    status_locked.state = "done".to_string();
+                    status_locked.files_unprocessed = 0;
+                    status_locked.files_total = 0;
+                    info!("bm25 indexed {} files, {} unchanged, {:.3}s", stats_indexed_cnt, stats_unchanged_cnt, stats_t0.elapsed().as_secs_f32());
+                    stats_indexed_cnt = 0;
+                    stats_unchanged_cnt = 0;
+                }
+                drop(status_locked);
+                tokio::select! {
+                    _ = bm25_sleeping_point.notified() => {},
+                    _ = tokio::time::sleep(tokio::time::Duration::from_millis(1_000)) => {},
+                }
+                continue;
+            }
+        };
+
+        let gcx = match gcx_weak.upgrade() {
+            Some(x) => x,
+            None => {
+                info!("detected program shutdown, quit");
+                break;
+            }
+        };
+        {
+            let mut status_locked = bm25_status.lock().await;
+            if status_locked.state != "indexing" {
+                status_locked.state = "indexing".to_string();
+                stats_t0 = std::time::Instant::now();
+            }
+            status_locked.files_unprocessed = left_todo_count + 1;
+            status_locked.files_total = status_locked.files_total.max(left_todo_count + 1);
+        }
+
+        let result = if forget { Err("forget".to_string()) } else { bm25_index_one_file(gcx.clone(), bm25_index.clone(), &cpath).await };
+        match result {
+            Ok(unchanged) => {
+                if unchanged {
+                    stats_unchanged_cnt += 1;
+                } else {
+                    stats_indexed_cnt += 1;
+                }
+                active_files.lock().await.insert(cpath);
+            }
+            Err(_) => {
+                // deleted, unreadable or not a text file: don't care what the error is, drop it from the index
+                let _ = bm25_index.lock().await.file_remove(&cpath).await;
+                active_files.lock().await.remove(&cpath);
+            }
+        }
+    }
+}
+
+// One database per set of workspace folders, the same way vecdb has a table per workspace: document frequencies
+// and average lengths of one project don't skew the search in another
+fn bm25_db_path(cache_dir: &PathBuf, workspace_key: &str) -> PathBuf {
+    cache_dir.join("refact_bm25").join(format!("bm25_index_ws_{}.sqlite", workspace_key))
+}
+
+async fn bm25_open_index(db_path: PathBuf) -> Bm25Index {
+    match Bm25Index::init(Some(db_path.clone())).await {
+        Ok(x) => x,
+        Err(e) => {
+            tracing::error!("cannot open bm25 index {:?}, will use memory: {}", db_path, e);
+            Bm25Index::init(None).await.expect("in-memory sqlite should always work")
+        }
+    }
+}
+
+pub async fn bm25_service_init(cache_dir: &PathBuf, workspace_key: &str) -> Arc<AMutex<Bm25IndexService>>
+{
+    let bm25_index = bm25_open_index(bm25_db_path(cache_dir, workspace_key)).await;
+    let bm25_service = Bm25IndexService {
+        bm25_index: Arc::new(AMutex::new(bm25_index)),
+        bm25_status: Arc::new(AMutex::new(Bm25Status { state: "starting".to_string(), ..Default::default() })),
+        bm25_sleeping_point: Arc::new(ANotify::new()),
+        bm25_todo: IndexSet::new(),
+        bm25_forget: IndexSet::new(),
+        active_files: Arc::new(AMutex::new(HashSet::new())),
+        workspace_key: workspace_key.to_string(),
+    };
+    Arc::new(AMutex::new(bm25_service))
+}
+
+// Called when the workspace folders are (re)scanned, the files of the new set get enqueued right after
+pub async fn bm25_switch_workspace(bm25_service: Arc<AMutex<Bm25IndexService>>, cache_dir: &PathBuf, workspace_key: &str)
+{
+    let mut service_locked = bm25_service.lock().await;
+    if service_locked.workspace_key == workspace_key {
+        return;
+    }
+    info!("bm25 switches to the database of workspace {}", workspace_key);
+    *service_locked.bm25_index.lock().await = bm25_open_index(bm25_db_path(cache_dir, workspace_key)).await;
+    service_locked.active_files.lock().await.clear();
+    service_locked.bm25_status.lock().await.state = "starting".to_string();
+    service_locked.workspace_key = workspace_key.to_string();
+}
+
+pub async fn bm25_indexer_start(
+    bm25_service: Arc<AMutex<Bm25IndexService>>,
+    gcx: Arc<ARwLock<GlobalContext>>,
+) -> Vec<JoinHandle<()>>
+{
+    let indexer_handle = tokio::spawn(
+        bm25_indexer_thread(
+            Arc::downgrade(&gcx),
+            bm25_service.clone(),
+        )
+    );
+    return vec![indexer_handle];
+}
+
+pub async fn bm25_indexer_enqueue_files(bm25_service: Arc<AMutex<Bm25IndexService>>, cpaths: &Vec<String>, wake_up_indexer: bool)
+{
+    let mut service_locked = bm25_service.lock().await;
+    for cpath in cpaths {
+        service_locked.bm25_todo.insert(cpath.clone());
+        service_locked.bm25_forget.swap_remove(cpath);
+    }
+    if wake_up_indexer {
+        service_locked.bm25_sleeping_point.notify_waiters();
+    }
+}
+
+// For files that are still on disk but shouldn't be indexed anymore, like the ones that became ignored
+pub async fn bm25_indexer_forget_files(bm25_service: Arc<AMutex<Bm25IndexService>>, cpaths: &Vec<String>)
+{
+    let mut service_locked = bm25_service.lock().await;
+    for cpath in cpaths {
+        service_locked.bm25_todo.insert(cpath.clone());
+        service_locked.bm25_forget.insert(cpath.clone());
+    }
+    service_locked.bm25_sleeping_point.notify_waiters();
+}
+
+pub async fn bm25_indexed_files(bm25_service: Arc<AMutex<Bm25IndexService>>) -> Vec<String>
+{
+    let active_files = bm25_service.lock().await.active_files.clone();
+    let x = active_files.lock().await.iter().cloned().collect();
+    x
+}
+
+pub async fn bm25_search(
+    bm25_service: Arc<AMutex<Bm25IndexService>>,
+    query: &String,
+    top_n: usize,
+    scope_filter_mb: Option<ScopeFilter>,
+) -> Result<Vec<RankedHit>, String> {
+    let (bm25_index, active_files) = {
+        let service_locked = bm25_service.lock().await;
+        (service_locked.bm25_index.clone(), service_locked.active_files.clone())
+    };
+    let active_files = active_files.lock().await.clone();
+    let t0 = std::time::Instant::now();
+    let hits = bm25_index.lock().await.search(query, top_n, scope_filter_mb, Some(active_files)).await?;
+    info!("bm25 search {:?} found {} windows, {:.3}s", query, hits.len(), t0.elapsed().as_secs_f64());
+    Ok(hits)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bm25_database_per_workspace() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache_dir = tmp.path().to_path_buf();
+        let bm25_service = bm25_service_init(&cache_dir, "ws1").await;
+        let bm25_index = bm25_service.lock().await.bm25_index.clone();
+        let window = Bm25Window { start_line: 0, end_line: 1, symbol_path: "".to_string(), window_text: "fn connect_to_database() {}".to_string() };
+        bm25_index.lock().await.file_replace(&"/ws1/a.rs".to_string(), &"md5".to_string(), vec![window]).await.unwrap();
+
+        bm25_switch_workspace(bm25_service.clone(), &cache_dir, "ws2").await;
+        assert_eq!(bm25_index.lock().await.size().await.unwrap(), (0, 0));
+        bm25_switch_workspace(bm25_service.clone(), &cache_dir, "ws1").await;
+        assert_eq!(bm25_index.lock().await.size().await.unwrap(), (1, 1));
+    }
+}
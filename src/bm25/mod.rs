@@ -0,0 +1,3 @@
+pub mod bm25_index;
+pub mod bm25_hybrid;
+pub mod bm25_thread;
@@ -210,6 +210,8 @@ pub struct ChatPost {
     pub meta: ChatMeta,
     #[serde(default)]
     pub style: Option<String>,
+    #[serde(default)]
+    pub resume_chat: bool,  // messages continue the chat stored under meta.chat_id, instead of being the whole history
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -144,6 +144,22 @@ pub struct CodeAssistantCaps {
     #[serde(default)]
     pub embedding_n_ctx: usize,
     #[serde(default)]
+    pub vecdb_remote_endpoint: String,  // search a centrally built index instead of vectorizing the workspace locally
+    #[serde(default)]
+    pub vecdb_remote_apikey: String,
+    #[serde(default)]
+    pub vecdb_remote_page_size: usize,
+    #[serde(default)]
+    pub rerank_endpoint: String,
+    #[serde(default = "default_endpoint_style")]
+    pub rerank_endpoint_style: String,  // "openai" (cohere/jina-like) or "tei"
+    #[serde(default)]
+    pub rerank_model: String,
+    #[serde(default)]
+    pub rerank_apikey: String,
+    #[serde(default)]
+    pub rerank_mode: String,  // "heuristic", "endpoint" or "both", empty means "both" if rerank_endpoint is set
+    #[serde(default)]
     pub running_models: Vec<String>,  // check there if a model is available or not, not in other places
     #[serde(default)]
     pub caps_version: i64,  // need to reload if it increases on server, that happens when server configuration changes
@@ -212,6 +228,8 @@ fn load_caps_from_buf(
     r1.telemetry_basic_dest = relative_to_full_url(&caps_url, &r1.telemetry_basic_dest)?;
     r1.telemetry_basic_retrieve_my_own = relative_to_full_url(&caps_url, &r1.telemetry_basic_retrieve_my_own)?;
     r1.endpoint_embeddings_template = relative_to_full_url(&caps_url, &r1.endpoint_embeddings_template)?;
+    r1.vecdb_remote_endpoint = relative_to_full_url(&caps_url, &r1.vecdb_remote_endpoint)?;
+    r1.rerank_endpoint = relative_to_full_url(&caps_url, &r1.rerank_endpoint)?;
     r1.tokenizer_path_template = relative_to_full_url(&caps_url, &r1.tokenizer_path_template)?;
     if r1.embedding_n_ctx == 0 {
         r1.embedding_n_ctx = 512;
@@ -281,6 +299,17 @@ pub async fn get_custom_embedding_api_key(gcx: Arc<ARwLock<GlobalContext>>) -> R
     Ok(get_api_key_macro!(gcx, caps, embedding_apikey))
 }
 
+#[cfg(feature="vecdb")]
+pub async fn get_custom_vecdb_remote_api_key(gcx: Arc<ARwLock<GlobalContext>>) -> Result<String, ScratchError> {
+    let caps = try_load_caps_quickly_if_not_present(gcx.clone(), 0).await?;
+    Ok(get_api_key_macro!(gcx, caps, vecdb_remote_apikey))
+}
+
+pub async fn get_custom_rerank_api_key(gcx: Arc<ARwLock<GlobalContext>>) -> Result<String, ScratchError> {
+    let caps = try_load_caps_quickly_if_not_present(gcx.clone(), 0).await?;
+    Ok(get_api_key_macro!(gcx, caps, rerank_apikey))
+}
+
 #[allow(dead_code)]
 async fn get_custom_completion_api_key(gcx: Arc<ARwLock<GlobalContext>>) -> Result<String, ScratchError> {
     let caps = try_load_caps_quickly_if_not_present(gcx.clone(), 0).await?;
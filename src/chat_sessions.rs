@@ -0,0 +1,530 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
+use futures::StreamExt;
+use hyper::{Body, Response, StatusCode};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{Mutex as AMutex, OwnedMutexGuard, RwLock as ARwLock};
+use tracing::warn;
+
+use crate::call_validation::{ChatMessage, ChatMode, ChatToolCall, ChatToolFunction, ChatUsage};
+use crate::custom_error::ScratchError;
+use crate::git::checkpoints::Checkpoint;
+use crate::global_context::GlobalContext;
+
+
+const CHAT_TITLE_MAX_CHARS: usize = 80;
+
+// Every chat is one json file, loading and saving go through this lock so concurrent requests don't lose each other's writes
+static CHAT_SESSIONS_LOCK: Lazy<AMutex<()>> = Lazy::new(|| AMutex::new(()));
+// One per chat, held by a request until its answer is saved, so the next request to the same chat sees that answer
+static CHAT_ANSWER_LOCKS: Lazy<StdMutex<HashMap<String, Arc<AMutex<()>>>>> = Lazy::new(|| StdMutex::new(HashMap::new()));
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ChatSessionInfo {
+    pub chat_id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub chat_mode: ChatMode,
+    #[serde(default)]
+    pub created_ts: f64,
+    #[serde(default)]
+    pub updated_ts: f64,
+    #[serde(default)]
+    pub messages_count: usize,
+    #[serde(default)]
+    pub usage: ChatUsage,  // sum over all the model answers in this chat
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub forked_from: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_at_message: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ChatSession {
+    #[serde(flatten)]
+    pub info: ChatSessionInfo,
+    #[serde(default, deserialize_with = "deserialize_stored_messages")]
+    pub messages: Vec<ChatMessage>,
+}
+
+// ChatMessage ignores usage and checkpoints coming from the client, the stored chat is written by us and keeps them
+fn deserialize_stored_messages<'de, D>(deserializer: D) -> Result<Vec<ChatMessage>, D::Error> where D: serde::Deserializer<'de> {
+    let values: Vec<Value> = Deserialize::deserialize(deserializer)?;
+    values.into_iter().map(|value| {
+        let mut message: ChatMessage = serde_json::from_value(value.clone()).map_err(serde::de::Error::custom)?;
+        message.usage = value.get("usage").and_then(|v| serde_json::from_value::<ChatUsage>(v.clone()).ok());
+        message.checkpoints = value.get("checkpoints").and_then(|v| serde_json::from_value::<Vec<Checkpoint>>(v.clone()).ok()).unwrap_or_default();
+        Ok(message)
+    }).collect()
+}
+
+fn now_ts() -> f64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as f64 / 1000.0
+}
+
+fn title_from_messages(messages: &Vec<ChatMessage>) -> String {
+    let text = messages.iter().find(|m| m.role == "user").map(|m| m.content.content_text_only()).unwrap_or_default();
+    let first_line = text.lines().map(|l| l.trim()).find(|l| !l.is_empty()).unwrap_or("");
+    first_line.chars().take(CHAT_TITLE_MAX_CHARS).collect()
+}
+
+fn add_usage(total: &mut ChatUsage, usage: &ChatUsage) {
+    total.prompt_tokens += usage.prompt_tokens;
+    total.completion_tokens += usage.completion_tokens;
+    total.total_tokens += usage.total_tokens;
+}
+
+pub async fn chat_sessions_dir(gcx: Arc<ARwLock<GlobalContext>>) -> PathBuf {
+    gcx.read().await.cache_dir.join("chats")
+}
+
+fn chat_session_path(chats_dir: &PathBuf, chat_id: &str) -> Result<PathBuf, String> {
+    let valid = !chat_id.is_empty() && chat_id.len() <= 200 && !chat_id.starts_with('.')
+        && chat_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !valid {
+        return Err(format!("invalid chat_id {:?}", chat_id));
+    }
+    Ok(chats_dir.join(format!("{}.json", chat_id)))
+}
+
+async fn _load(chats_dir: &PathBuf, chat_id: &str) -> Result<ChatSession, String> {
+    let path = chat_session_path(chats_dir, chat_id)?;
+    let text = tokio::fs::read_to_string(&path).await.map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound { format!("chat {:?} not found", chat_id) } else { format!("can't read {:?}: {}", path, e) }
+    })?;
+    serde_json::from_str(&text).map_err(|e| format!("can't parse {:?}: {}", path, e))
+}
+
+async fn _save(chats_dir: &PathBuf, session: &mut ChatSession) -> Result<(), String> {
+    let path = chat_session_path(chats_dir, &session.info.chat_id)?;
+    session.info.messages_count = session.messages.len();
+    if session.info.title.is_empty() {
+        session.info.title = title_from_messages(&session.messages);
+    }
+    tokio::fs::create_dir_all(chats_dir).await.map_err(|e| format!("can't create {:?}: {}", chats_dir, e))?;
+    // a crash in the middle of writing should leave the previous version intact
+    let tmp_path = path.with_extension("json.tmp");
+    let text = serde_json::to_string(&session).map_err(|e| e.to_string())?;
+    tokio::fs::write(&tmp_path, text).await.map_err(|e| format!("can't write {:?}: {}", tmp_path, e))?;
+    tokio::fs::rename(&tmp_path, &path).await.map_err(|e| format!("can't rename {:?}: {}", tmp_path, e))
+}
+
+// Take it before loading or saving the chat for a new request, and pass it to chat_session_record_response()
+pub async fn chat_session_answer_lock(chat_id: &str) -> OwnedMutexGuard<()> {
+    let lock = {
+        let mut locks = CHAT_ANSWER_LOCKS.lock().unwrap();
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        locks.entry(chat_id.to_string()).or_default().clone()
+    };
+    lock.lock_owned().await
+}
+
+pub async fn chat_session_load(chats_dir: &PathBuf, chat_id: &str) -> Result<ChatSession, String> {
+    let _lock = CHAT_SESSIONS_LOCK.lock().await;
+    _load(chats_dir, chat_id).await
+}
+
+// Most recently updated first
+pub async fn chat_sessions_list(chats_dir: &PathBuf) -> Result<Vec<ChatSessionInfo>, String> {
+    let _lock = CHAT_SESSIONS_LOCK.lock().await;
+    let mut result = vec![];
+    let mut entries = match tokio::fs::read_dir(chats_dir).await {
+        Ok(x) => x,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(result),
+        Err(e) => return Err(format!("can't list {:?}: {}", chats_dir, e)),
+    };
+    while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+        let path = entry.path();
+        if path.extension().map(|x| x != "json").unwrap_or(true) {
+            continue;
+        }
+        // the messages are skipped without parsing them into anything
+        match tokio::fs::read_to_string(&path).await.map_err(|e| e.to_string())
+            .and_then(|text| serde_json::from_str::<ChatSessionInfo>(&text).map_err(|e| e.to_string())) {
+            Ok(info) => result.push(info),
+            Err(e) => warn!("skipping chat {:?}: {}", path, e),
+        }
+    }
+    result.sort_by(|a, b| b.updated_ts.partial_cmp(&a.updated_ts).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(result)
+}
+
+// The client is the source of truth for the history: whatever it sends replaces the stored messages
+pub async fn chat_session_save_messages(
+    chats_dir: &PathBuf,
+    chat_id: &str,
+    model: &str,
+    chat_mode: ChatMode,
+    messages: &Vec<ChatMessage>,
+) -> Result<(), String> {
+    let _lock = CHAT_SESSIONS_LOCK.lock().await;
+    let mut session = match _load(chats_dir, chat_id).await {
+        Ok(x) => x,
+        Err(_) => ChatSession {
+            info: ChatSessionInfo { chat_id: chat_id.to_string(), created_ts: now_ts(), ..Default::default() },
+            messages: vec![],
+        },
+    };
+    session.info.model = model.to_string();
+    session.info.chat_mode = chat_mode;
+    session.info.updated_ts = now_ts();
+    session.messages = messages.clone();
+    _save(chats_dir, &mut session).await
+}
+
+// Returns the chat with the answer in it, None if there was nothing to append
+pub async fn chat_session_append_answer(chats_dir: &PathBuf, chat_id: &str, recorder: ChatResponseRecorder) -> Result<Option<ChatSession>, String> {
+    let (new_messages, usage) = recorder.finish();
+    if new_messages.is_empty() {
+        return Ok(None);
+    }
+    let _lock = CHAT_SESSIONS_LOCK.lock().await;
+    let mut session = _load(chats_dir, chat_id).await?;
+    if let Some(usage) = usage {
+        add_usage(&mut session.info.usage, &usage);
+    }
+    session.messages.extend(new_messages);
+    session.info.updated_ts = now_ts();
+    _save(chats_dir, &mut session).await?;
+    Ok(Some(session))
+}
+
+// Empty title goes back to the one made from the first user message
+pub async fn chat_session_rename(chats_dir: &PathBuf, chat_id: &str, title: &str) -> Result<ChatSessionInfo, String> {
+    let _lock = CHAT_SESSIONS_LOCK.lock().await;
+    let mut session = _load(chats_dir, chat_id).await?;
+    session.info.title = title.trim().to_string();
+    _save(chats_dir, &mut session).await?;
+    Ok(session.info)
+}
+
+pub async fn chat_session_delete(chats_dir: &PathBuf, chat_id: &str) -> Result<(), String> {
+    let _lock = CHAT_SESSIONS_LOCK.lock().await;
+    let path = chat_session_path(chats_dir, chat_id)?;
+    tokio::fs::remove_file(&path).await.map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound { format!("chat {:?} not found", chat_id) } else { format!("can't delete {:?}: {}", path, e) }
+    })
+}
+
+// The new chat gets the messages up to and including message_index, usage starts from zero
+pub async fn chat_session_fork(chats_dir: &PathBuf, chat_id: &str, message_index: usize, new_chat_id: &str) -> Result<ChatSessionInfo, String> {
+    let _lock = CHAT_SESSIONS_LOCK.lock().await;
+    let session = _load(chats_dir, chat_id).await?;
+    if message_index >= session.messages.len() {
+        return Err(format!("message_index {} is out of range, chat {:?} has {} messages", message_index, chat_id, session.messages.len()));
+    }
+    if chat_session_path(chats_dir, new_chat_id)?.exists() {
+        return Err(format!("chat {:?} already exists", new_chat_id));
+    }
+    let now = now_ts();
+    let mut forked = ChatSession {
+        info: ChatSessionInfo {
+            chat_id: new_chat_id.to_string(),
+            title: if session.info.title.is_empty() { "".to_string() } else { format!("{} (fork)", session.info.title) },
+            model: session.info.model.clone(),
+            chat_mode: session.info.chat_mode,
+            created_ts: now,
+            updated_ts: now,
+            forked_from: chat_id.to_string(),
+            forked_at_message: Some(message_index),
+            ..Default::default()
+        },
+        messages: session.messages[..=message_index].to_vec(),
+    };
+    _save(chats_dir, &mut forked).await?;
+    Ok(forked.info)
+}
+
+// Collects what the server adds to the chat from the response: deterministic messages (context files, tool results)
+// and the assistant answer assembled from the streaming deltas
+#[derive(Default)]
+pub struct ChatResponseRecorder {
+    streaming: bool,
+    pub done: bool,  // [DONE] has arrived
+    buffer: String,
+    deterministic: Vec<ChatMessage>,
+    content: String,
+    tool_calls: Vec<ChatToolCall>,
+    finish_reason: Option<String>,
+    usage: Option<ChatUsage>,
+}
+
+impl ChatResponseRecorder {
+    pub fn new(streaming: bool) -> Self {
+        ChatResponseRecorder { streaming, ..Default::default() }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.push_str(&String::from_utf8_lossy(bytes));
+        if !self.streaming {
+            return;
+        }
+        while let Some(pos) = self.buffer.find("\n\n") {
+            let event: String = self.buffer.drain(..pos + 2).collect();
+            let data = event.trim();
+            let data = data.strip_prefix("data:").unwrap_or(data).trim();
+            if data.starts_with("[DONE]") {
+                self.done = true;
+                continue;
+            }
+            if data.is_empty() {
+                continue;
+            }
+            if let Ok(value) = serde_json::from_str::<Value>(data) {
+                self.feed_value(&value);
+            }
+        }
+    }
+
+    fn feed_value(&mut self, value: &Value) {
+        if value.get("role").is_some() {
+            match serde_json::from_value::<ChatMessage>(value.clone()) {
+                Ok(message) => self.deterministic.push(message),
+                Err(e) => warn!("can't record a chat message: {}", e),
+            }
+            return;
+        }
+        if let Some(messages) = value.get("deterministic_messages").and_then(|x| x.as_array()) {
+            for m in messages {
+                self.feed_value(m);
+            }
+        }
+        if let Some(usage) = value.get("usage").filter(|x| !x.is_null()) {
+            if let Ok(usage) = serde_json::from_value::<ChatUsage>(usage.clone()) {
+                self.usage = Some(usage);
+            }
+        }
+        let choice0 = match value.get("choices").and_then(|x| x.get(0)) {
+            Some(x) => x,
+            None => return,
+        };
+        if let Some(finish_reason) = choice0.get("finish_reason").and_then(|x| x.as_str()) {
+            self.finish_reason = Some(finish_reason.to_string());
+        }
+        let delta = match choice0.get("delta").or(choice0.get("message")) {
+            Some(x) => x,
+            None => return,
+        };
+        if let Some(content) = delta.get("content").and_then(|x| x.as_str()) {
+            self.content.push_str(content);
+        }
+        for call in delta.get("tool_calls").and_then(|x| x.as_array()).cloned().unwrap_or_default() {
+            self.feed_tool_call(&call);
+        }
+    }
+
+    // Streaming sends the id and the name once, and the arguments in pieces, all with the same index
+    fn feed_tool_call(&mut self, call: &Value) {
+        let str_field = |v: Option<&Value>| v.and_then(|x| x.as_str()).unwrap_or("").to_string();
+        let index = call.get("index").and_then(|x| x.as_u64()).map(|x| x as usize).unwrap_or(self.tool_calls.len());
+        while self.tool_calls.len() <= index {
+            self.tool_calls.push(ChatToolCall {
+                id: String::new(),
+                function: ChatToolFunction { arguments: String::new(), name: String::new() },
+                tool_type: "function".to_string(),
+            });
+        }
+        let tool_call = &mut self.tool_calls[index];
+        let id = str_field(call.get("id"));
+        if tool_call.id.is_empty() && !id.is_empty() {
+            tool_call.id = id;
+        }
+        let function = call.get("function");
+        let name = str_field(function.and_then(|f| f.get("name")));
+        if tool_call.function.name.is_empty() && !name.is_empty() {
+            tool_call.function.name = name;
+        }
+        tool_call.function.arguments.push_str(&str_field(function.and_then(|f| f.get("arguments"))));
+    }
+
+    pub fn finish(mut self) -> (Vec<ChatMessage>, Option<ChatUsage>) {
+        if !self.streaming {
+            if let Ok(value) = serde_json::from_str::<Value>(&self.buffer) {
+                self.feed_value(&value);
+            }
+        }
+        let mut messages = self.deterministic;
+        let tool_calls: Vec<ChatToolCall> = self.tool_calls.into_iter().filter(|x| !x.function.name.is_empty()).collect();
+        if !self.content.is_empty() || !tool_calls.is_empty() {
+            messages.push(ChatMessage {
+                role: "assistant".to_string(),
+                content: crate::call_validation::ChatContent::SimpleText(self.content),
+                finish_reason: self.finish_reason,
+                tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+                usage: self.usage.clone(),
+                ..Default::default()
+            });
+        }
+        (messages, self.usage)
+    }
+}
+
+async fn chat_session_save_answer(gcx: Arc<ARwLock<GlobalContext>>, chats_dir: &PathBuf, chat_id: &str, recorder: ChatResponseRecorder) {
+    let session = match chat_session_append_answer(chats_dir, chat_id, recorder).await {
+        Ok(Some(x)) => x,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("can't save chat {:?}: {}", chat_id, e);
+            return;
+        }
+    };
+    // a finished agent turn becomes a local trajectory for the knowledge tool, compress_trajectory() tells if it's finished
+    if session.info.chat_mode == ChatMode::AGENT {
+        let chat_id = chat_id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = crate::trajectories::try_to_record_local_trajectory(gcx, &chat_id, &session.messages).await {
+                warn!("failed to record local trajectory: {}", e);
+            }
+        });
+    }
+}
+
+// Passes the response through unchanged, and appends what it contains to the stored chat. A streaming answer is saved
+// before [DONE] goes out, so the client disconnecting right after it doesn't lose it. The answer lock is released once saved.
+pub async fn chat_session_record_response(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    chat_id: String,
+    response: Response<Body>,
+    streaming: bool,
+    answer_lock: OwnedMutexGuard<()>,
+) -> Result<Response<Body>, ScratchError> {
+    let chats_dir = chat_sessions_dir(gcx.clone()).await;
+    let (parts, mut body) = response.into_parts();
+    if !streaming {
+        let bytes = hyper::body::to_bytes(body).await.map_err(|e| {
+            ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("can't read the response: {}", e))
+        })?;
+        let mut recorder = ChatResponseRecorder::new(false);
+        recorder.feed(&bytes);
+        chat_session_save_answer(gcx, &chats_dir, &chat_id, recorder).await;
+        drop(answer_lock);
+        return Ok(Response::from_parts(parts, Body::from(bytes)));
+    }
+    let evstream = async_stream::stream! {
+        let mut answer_lock = Some(answer_lock);
+        let mut recorder = ChatResponseRecorder::new(true);
+        while let Some(chunk) = body.next().await {
+            if let Ok(bytes) = &chunk {
+                recorder.feed(bytes);
+            }
+            if recorder.done && answer_lock.is_some() {
+                chat_session_save_answer(gcx.clone(), &chats_dir, &chat_id, std::mem::take(&mut recorder)).await;
+                answer_lock = None;
+            }
+            yield chunk;
+        }
+        if answer_lock.is_some() {
+            chat_session_save_answer(gcx, &chats_dir, &chat_id, recorder).await;
+        }
+    };
+    Ok(Response::from_parts(parts, Body::wrap_stream(evstream)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::call_validation::ChatContent;
+
+    fn user_message(text: &str) -> ChatMessage {
+        ChatMessage { role: "user".to_string(), content: ChatContent::SimpleText(text.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn test_recorder_assembles_streamed_answer() {
+        let mut recorder = ChatResponseRecorder::new(true);
+        let chunks = [
+            "data: {\"role\":\"context_file\",\"content\":\"[]\"}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Let me \"},\"finish_reason\":null}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"look.\",\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"cat\",\"arguments\":\"{\\\"paths\\\":\"}}]},\"finish_reason\":null}]}\n\ndata: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"a.rs\\\"}\"}}]},\"finish_reason\":null}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"\"},\"finish_reason\":\"tool_calls\"}],\"usage\":{\"prompt_tokens\":10,\"completion_tokens\":5,\"total_tokens\":15}}\n\n",
+            "data: [DONE]\n\n",
+        ];
+        for chunk in chunks {
+            assert!(!recorder.done);
+            recorder.feed(chunk.as_bytes());
+        }
+        assert!(recorder.done);
+        let (messages, usage) = recorder.finish();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, "context_file");
+        assert_eq!(messages[1].role, "assistant");
+        assert_eq!(messages[1].content.content_text_only(), "Let me look.");
+        assert_eq!(messages[1].finish_reason, Some("tool_calls".to_string()));
+        let tool_calls = messages[1].tool_calls.clone().unwrap();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].id, "call_1");
+        assert_eq!(tool_calls[0].function.name, "cat");
+        assert_eq!(tool_calls[0].function.arguments, "{\"paths\":\"a.rs\"}");
+        assert_eq!(usage.unwrap().total_tokens, 15);
+    }
+
+    #[tokio::test]
+    async fn test_chat_sessions_save_fork_rename_delete() {
+        let tmp = tempfile::tempdir().unwrap();
+        let chats_dir = tmp.path().join("chats");
+        let messages = vec![user_message("  \n  Fix the bug in parser\nplease"), user_message("second")];
+        chat_session_save_messages(&chats_dir, "chat-1", "gpt-4o", ChatMode::AGENT, &messages).await.unwrap();
+
+        let mut recorder = ChatResponseRecorder::new(false);
+        recorder.feed(br#"{"choices":[{"index":0,"message":{"role":"assistant","content":"done"},"finish_reason":"stop"}],"deterministic_messages":[],"usage":{"prompt_tokens":3,"completion_tokens":2,"total_tokens":5}}"#);
+        chat_session_append_answer(&chats_dir, "chat-1", recorder).await.unwrap();
+
+        let session = chat_session_load(&chats_dir, "chat-1").await.unwrap();
+        assert_eq!(session.info.title, "Fix the bug in parser");
+        assert_eq!(session.info.messages_count, 3);
+        assert_eq!(session.info.usage.total_tokens, 5);
+        assert_eq!(session.messages[2].usage.as_ref().unwrap().completion_tokens, 2);
+
+        let forked = chat_session_fork(&chats_dir, "chat-1", 0, "chat-2").await.unwrap();
+        assert_eq!(forked.messages_count, 1);
+        assert_eq!(forked.forked_from, "chat-1");
+        assert!(chat_session_fork(&chats_dir, "chat-1", 3, "chat-3").await.is_err());
+        assert!(chat_session_fork(&chats_dir, "chat-1", 0, "chat-2").await.is_err());
+
+        chat_session_rename(&chats_dir, "chat-1", "Parser").await.unwrap();
+        let list = chat_sessions_list(&chats_dir).await.unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.iter().find(|x| x.chat_id == "chat-1").unwrap().title, "Parser");
+        assert_eq!(list.iter().find(|x| x.chat_id == "chat-2").unwrap().title, "Fix the bug in parser (fork)");
+
+        chat_session_delete(&chats_dir, "chat-2").await.unwrap();
+        assert!(chat_session_load(&chats_dir, "chat-2").await.is_err());
+        assert!(chat_session_load(&chats_dir, "../chat-1").await.is_err());
+    }
+
+    #[test]
+    fn test_only_stored_messages_keep_usage_and_checkpoints() {
+        let message = serde_json::json!({
+            "role": "user",
+            "content": "hi",
+            "usage": {"prompt_tokens": 1, "completion_tokens": 2, "total_tokens": 3},
+            "checkpoints": [{"workspace_folder": "/tmp/project", "commit_hash": "abc"}],
+        });
+        let from_client: ChatMessage = serde_json::from_value(message.clone()).unwrap();
+        assert!(from_client.usage.is_none());
+        assert!(from_client.checkpoints.is_empty());
+        let stored: ChatSession = serde_json::from_value(serde_json::json!({"chat_id": "chat-1", "messages": [message]})).unwrap();
+        assert_eq!(stored.messages[0].usage.as_ref().unwrap().total_tokens, 3);
+        assert_eq!(stored.messages[0].checkpoints[0].commit_hash, "abc");
+    }
+
+    #[tokio::test]
+    async fn test_answer_lock_waits_for_previous_answer() {
+        let first = chat_session_answer_lock("chat-lock").await;
+        let second = tokio::spawn(async { chat_session_answer_lock("chat-lock").await; });
+        let other = chat_session_answer_lock("chat-other").await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        assert!(!second.is_finished());
+        drop(first);
+        tokio::time::timeout(tokio::time::Duration::from_secs(5), second).await.unwrap().unwrap();
+        drop(other);
+    }
+}
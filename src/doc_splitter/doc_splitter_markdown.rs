@@ -0,0 +1,106 @@
+use crate::doc_splitter::{sections_by_headings, DocSection};
+
+
+// ATX "## Title ##" headings, returns (level, title)
+pub fn markdown_atx_heading(line: &str) -> Option<(usize, String)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;  // indented code
+    }
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    let rest = &trimmed[level..];
+    if !rest.is_empty() && !rest.starts_with(' ') && !rest.starts_with('\t') {
+        return None;  // #hashtag
+    }
+    let title = rest.trim().trim_end_matches('#').trim();
+    if title.is_empty() {
+        return None;
+    }
+    Some((level, title.to_string()))
+}
+
+fn is_fence(line: &str) -> bool {
+    let t = line.trim_start();
+    t.starts_with("```") || t.starts_with("~~~")
+}
+
+// Headings as (row, level, title), ignoring fenced code blocks where "#" is a comment
+pub fn markdown_headings(lines: &Vec<&str>) -> Vec<(usize, usize, String)> {
+    let mut result = vec![];
+    let mut in_fence = false;
+    for (row, line) in lines.iter().enumerate() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        if let Some((level, title)) = markdown_atx_heading(line) {
+            result.push((row, level, title));
+            continue;
+        }
+        // setext: "Title" underlined with "===" or "---"
+        if row > 0 && !line.is_empty() {
+            let underline = line.trim_end();
+            let prev = lines[row - 1].trim();
+            let prev_is_heading = result.last().map(|x: &(usize, usize, String)| x.0 == row - 1).unwrap_or(false);
+            if !prev.is_empty() && !prev_is_heading && !prev.starts_with('-') && !prev.starts_with('*') && !is_fence(prev) && underline.len() >= 3 {
+                if underline.chars().all(|c| c == '=') {
+                    result.push((row - 1, 1, prev.to_string()));
+                } else if underline.chars().all(|c| c == '-') {
+                    result.push((row - 1, 2, prev.to_string()));
+                }
+            }
+        }
+    }
+    result
+}
+
+pub fn markdown_sections(text: &String) -> Vec<DocSection> {
+    let lines: Vec<&str> = text.split("\n").collect();
+    sections_by_headings(&lines, &markdown_headings(&lines))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_sections() {
+        let text = [
+            "Intro line",
+            "",
+            "# Architecture",
+            "Overview",
+            "## Storage",
+            "Disks",
+            "```bash",
+            "# not a heading",
+            "```",
+            "### Sharding ###",
+            "By user id",
+            "Replication",
+            "-----------",
+            "Three copies",
+            "#hashtag is not a heading",
+            "# API",
+            "REST",
+        ].join("\n");
+        let sections = markdown_sections(&text);
+        let paths: Vec<(&str, usize, usize)> = sections.iter().map(|s| (s.heading_path.as_str(), s.top_row, s.bottom_row)).collect();
+        assert_eq!(paths, vec![
+            ("", 0, 1),
+            ("Architecture", 2, 3),
+            ("Architecture › Storage", 4, 8),
+            ("Architecture › Storage › Sharding", 9, 10),
+            ("Architecture › Replication", 11, 14),
+            ("API", 15, 16),
+        ]);
+        assert!(sections[2].text.contains("# not a heading"));
+    }
+}
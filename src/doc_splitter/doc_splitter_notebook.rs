@@ -0,0 +1,112 @@
+use serde_json::Value;
+
+use crate::doc_splitter::{DocSection, HeadingStack, HEADING_PATH_SEPARATOR};
+use crate::doc_splitter::doc_splitter_markdown::markdown_atx_heading;
+
+
+fn cell_source(cell: &Value) -> String {
+    match cell.get("source") {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(parts)) => parts.iter().filter_map(|x| x.as_str()).collect::<Vec<_>>().join(""),
+        _ => String::new(),
+    }
+}
+
+// Rows of the raw json where cells start, the k-th line with "cell_type" belongs to the k-th cell
+fn cell_rows(text: &String) -> Vec<usize> {
+    text.split("\n").enumerate().filter(|(_, line)| line.contains("\"cell_type\":")).map(|(row, _)| row).collect()
+}
+
+// Code and markdown cells only, outputs are dropped. Rows point into the json text, a cell maps to its whole range
+pub fn notebook_sections(text: &String) -> Option<Vec<DocSection>> {
+    let notebook: Value = match serde_json::from_str(text) {
+        Ok(x) => x,
+        Err(e) => {
+            tracing::warn!("cannot parse notebook, will split as text: {}", e);
+            return None;
+        }
+    };
+    let cells = notebook.get("cells")?.as_array()?;
+    let total_rows = text.split("\n").count();
+    let mut rows = cell_rows(text);
+    if rows.len() != cells.len() {
+        rows = vec![];
+    }
+
+    let mut result = vec![];
+    let mut stack = HeadingStack::default();
+    for (i, cell) in cells.iter().enumerate() {
+        let cell_type = cell.get("cell_type").and_then(|x| x.as_str()).unwrap_or("");
+        if cell_type != "markdown" && cell_type != "code" {
+            continue;
+        }
+        let source = cell_source(cell);
+        if cell_type == "markdown" {
+            let mut in_fence = false;
+            for line in source.lines() {
+                if line.trim_start().starts_with("```") {
+                    in_fence = !in_fence;
+                } else if !in_fence {
+                    if let Some((level, title)) = markdown_atx_heading(line) {
+                        stack.push(level, &title);
+                    }
+                }
+            }
+        }
+        if source.trim().is_empty() {
+            continue;
+        }
+        let (top_row, bottom_row) = if rows.is_empty() {
+            (0, total_rows.saturating_sub(1))
+        } else {
+            // a cell starts on the line before "cell_type" (the "{"), harmless if it doesn't
+            let top = rows[i].saturating_sub(1);
+            let bottom = rows.get(i + 1).map(|x| x.saturating_sub(2)).unwrap_or(total_rows.saturating_sub(1));
+            (top, bottom.max(top))
+        };
+        let headings = stack.path();
+        let cell_name = format!("cell {}", i + 1);
+        result.push(DocSection {
+            heading_path: if headings.is_empty() { cell_name } else { format!("{}{}{}", headings, HEADING_PATH_SEPARATOR, cell_name) },
+            text: source,
+            top_row,
+            bottom_row,
+            whole_range: true,
+        });
+    }
+    Some(result)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notebook_sections() {
+        let notebook = serde_json::json!({
+            "cells": [
+                {"cell_type": "markdown", "metadata": {}, "source": ["# Training\n", "Some words"]},
+                {"cell_type": "code", "metadata": {}, "execution_count": 1, "source": ["import torch\n", "model = torch.nn.Linear(2, 2)"],
+                 "outputs": [{"output_type": "display_data", "data": {"image/png": "iVBORw0KGgoAAAANSUhEUgAAAAEAAAAB"}}]},
+                {"cell_type": "raw", "metadata": {}, "source": "raw text"},
+                {"cell_type": "markdown", "metadata": {}, "source": "## Evaluation"},
+                {"cell_type": "code", "metadata": {}, "source": "", "outputs": []},
+            ],
+            "metadata": {},
+            "nbformat": 4,
+            "nbformat_minor": 5,
+        });
+        let text = serde_json::to_string_pretty(&notebook).unwrap();
+        let sections = notebook_sections(&text).unwrap();
+        let paths: Vec<&str> = sections.iter().map(|s| s.heading_path.as_str()).collect();
+        assert_eq!(paths, vec!["Training › cell 1", "Training › cell 2", "Training › Evaluation › cell 4"]);
+        assert_eq!(sections[1].text, "import torch\nmodel = torch.nn.Linear(2, 2)");
+        assert!(sections.iter().all(|s| !s.text.contains("iVBORw0KGgo")));
+        let lines: Vec<&str> = text.split("\n").collect();
+        for s in sections.iter() {
+            assert!(s.top_row <= s.bottom_row && s.bottom_row < lines.len());
+        }
+        assert!(notebook_sections(&"not json".to_string()).is_none());
+    }
+}
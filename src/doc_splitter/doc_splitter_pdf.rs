@@ -0,0 +1,89 @@
+use crate::doc_splitter::{sections_by_headings, DocSection};
+
+
+// The text extracted from a PDF is indexed with these markers between the pages, the pages become sections
+const PAGE_MARKER_PREFIX: &str = "--- page ";
+const PAGE_MARKER_SUFFIX: &str = " ---";
+
+pub fn pdf_page_marker(page: usize) -> String {
+    format!("{}{}{}", PAGE_MARKER_PREFIX, page, PAGE_MARKER_SUFFIX)
+}
+
+pub fn pdf_to_text(bytes: &[u8]) -> Result<String, String> {
+    if !bytes.starts_with(b"%PDF") {
+        return Err("not a PDF file".to_string());
+    }
+    // pdf-extract panics on some broken files, that shouldn't take the indexing thread down
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes))
+        .map_err(|_| "PDF parser panicked".to_string())?
+        .map_err(|e| format!("can't extract text from PDF: {}", e))?;
+    let mut result = vec![];
+    for (i, page) in pages.iter().enumerate() {
+        let lines: Vec<&str> = page.lines().map(|x| x.trim()).filter(|x| !x.is_empty()).collect();
+        if !lines.is_empty() {
+            result.push(format!("{}\n{}\n", pdf_page_marker(i + 1), lines.join("\n")));
+        }
+    }
+    if result.is_empty() {
+        return Err("no text found in PDF".to_string());
+    }
+    Ok(result.join("\n"))
+}
+
+pub fn pdf_sections(text: &String) -> Vec<DocSection> {
+    let lines: Vec<&str> = text.split("\n").collect();
+    let headings = lines.iter().enumerate().filter_map(|(row, line)| {
+        let page = line.strip_prefix(PAGE_MARKER_PREFIX)?.strip_suffix(PAGE_MARKER_SUFFIX)?;
+        page.parse::<usize>().ok().map(|_| (row, 1, format!("page {}", page)))
+    }).collect();
+    sections_by_headings(&lines, &headings)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A minimal valid PDF: catalog, pages, one Helvetica font, and a content stream per page
+    fn make_pdf(pages: Vec<&str>) -> Vec<u8> {
+        let n = pages.len();
+        let page_ids: Vec<usize> = (0..n).map(|i| 4 + 2 * i).collect();
+        let mut objects: Vec<String> = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!("<< /Type /Pages /Kids [{}] /Count {} >>", page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "), n),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+        ];
+        for (i, content) in pages.iter().enumerate() {
+            objects.push(format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>", page_ids[i] + 1));
+            objects.push(format!("<< /Length {} >>\nstream\n{}\nendstream", content.len() + 1, content));
+        }
+        let mut pdf = "%PDF-1.4\n".to_string();
+        let mut offsets = vec![];
+        for (i, obj) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, obj));
+        }
+        let xref_offset = pdf.len();
+        pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+        for offset in offsets {
+            pdf.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        pdf.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref_offset));
+        pdf.into_bytes()
+    }
+
+    #[test]
+    fn test_pdf_to_text() {
+        let pdf = make_pdf(vec![
+            "BT /F1 24 Tf 72 720 Td (Storage) Tj 0 -40 Td (Sharding rules) Tj ET",
+            "BT /F1 12 Tf 72 720 Td (Second page) Tj ET",
+        ]);
+        let text = pdf_to_text(&pdf).unwrap();
+        assert_eq!(text, "--- page 1 ---\nStorage\nSharding rules\n\n--- page 2 ---\nSecond page\n");
+        let sections = pdf_sections(&text);
+        let paths: Vec<(&str, usize, usize)> = sections.iter().map(|s| (s.heading_path.as_str(), s.top_row, s.bottom_row)).collect();
+        assert_eq!(paths, vec![("page 1", 0, 3), ("page 2", 4, 6)]);
+        assert!(pdf_to_text(b"hello").is_err());
+        assert!(pdf_to_text(b"%PDF-1.4\ngarbage").is_err());
+    }
+}
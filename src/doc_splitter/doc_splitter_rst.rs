@@ -0,0 +1,86 @@
+use crate::doc_splitter::{sections_by_headings, DocSection};
+
+
+fn adornment_char(line: &str) -> Option<char> {
+    let t = line.trim_end();
+    let c = t.chars().next()?;
+    if t.chars().count() < 2 || c.is_alphanumeric() || c.is_whitespace() || !c.is_ascii_punctuation() {
+        return None;
+    }
+    if t.chars().all(|x| x == c) { Some(c) } else { None }
+}
+
+// reStructuredText has no fixed heading levels, the level is the order in which adornment styles first appear
+pub fn rst_headings(lines: &Vec<&str>) -> Vec<(usize, usize, String)> {
+    let mut styles: Vec<(char, bool)> = vec![];
+    let mut result = vec![];
+    let mut row = 1;
+    while row < lines.len() {
+        let (c, title) = match (adornment_char(lines[row]), lines[row - 1]) {
+            (Some(c), title) if !title.trim().is_empty() && !title.starts_with(' ') && !title.starts_with('\t') && adornment_char(title).is_none() => (c, title),
+            _ => {
+                row += 1;
+                continue;
+            }
+        };
+        if lines[row].trim_end().chars().count() < title.trim().chars().count() {
+            row += 1;
+            continue;
+        }
+        let has_overline = row >= 2 && adornment_char(lines[row - 2]) == Some(c);
+        let style = (c, has_overline);
+        let level = match styles.iter().position(|x| *x == style) {
+            Some(i) => i + 1,
+            None => {
+                styles.push(style);
+                styles.len()
+            }
+        };
+        result.push((if has_overline { row - 2 } else { row - 1 }, level, title.trim().to_string()));
+        row += 2;
+    }
+    result
+}
+
+pub fn rst_sections(text: &String) -> Vec<DocSection> {
+    let lines: Vec<&str> = text.split("\n").collect();
+    sections_by_headings(&lines, &rst_headings(&lines))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rst_sections() {
+        let text = [
+            "==========",
+            "User Guide",
+            "==========",
+            "",
+            "Install",
+            "=======",
+            "pip install it",
+            "",
+            "From source",
+            "-----------",
+            "Run::",
+            "",
+            "    make",
+            "    ----",
+            "",
+            "Usage",
+            "=====",
+            "Call it",
+        ].join("\n");
+        let sections = rst_sections(&text);
+        let paths: Vec<(&str, usize, usize)> = sections.iter().map(|s| (s.heading_path.as_str(), s.top_row, s.bottom_row)).collect();
+        assert_eq!(paths, vec![
+            ("User Guide", 0, 3),
+            ("User Guide › Install", 4, 7),
+            ("User Guide › Install › From source", 8, 14),
+            ("User Guide › Usage", 15, 17),
+        ]);
+    }
+}
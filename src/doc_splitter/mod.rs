@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::RwLock as StdRwLock;
+use tokenizers::Tokenizer;
+
+use crate::ast::chunk_utils::get_chunks;
+use crate::ast::file_splitter::LINES_OVERLAP;
+use crate::vecdb::vdb_structs::SplitResult;
+
+pub mod doc_splitter_markdown;
+pub mod doc_splitter_notebook;
+pub mod doc_splitter_pdf;
+pub mod doc_splitter_rst;
+
+
+// Shown to the model as "docs/architecture.md › Storage › Sharding", stored in symbol_path
+pub const HEADING_PATH_SEPARATOR: &str = " › ";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocSection {
+    pub heading_path: String,
+    pub text: String,
+    pub top_row: usize,
+    pub bottom_row: usize,
+    pub whole_range: bool,  // text is not the file lines (notebook cells), all the chunks point to the whole section
+}
+
+// A stack of headings, a new heading of some level closes all the deeper ones
+#[derive(Default)]
+pub struct HeadingStack {
+    stack: Vec<(usize, String)>,
+}
+
+impl HeadingStack {
+    pub fn push(&mut self, level: usize, title: &str) {
+        self.stack.retain(|(l, _)| *l < level);
+        self.stack.push((level, title.trim().to_string()));
+    }
+
+    pub fn path(&self) -> String {
+        self.stack.iter().map(|(_, t)| t.as_str()).collect::<Vec<_>>().join(HEADING_PATH_SEPARATOR)
+    }
+}
+
+// Sections that start at heading rows, lines before the first heading make a section with an empty path
+pub fn sections_by_headings(lines: &Vec<&str>, headings: &Vec<(usize, usize, String)>) -> Vec<DocSection> {
+    let mut result = vec![];
+    let mut stack = HeadingStack::default();
+    let mut starts: Vec<(usize, String)> = vec![(0, "".to_string())];
+    for (row, level, title) in headings.iter() {
+        stack.push(*level, title);
+        if *row == 0 {
+            starts.clear();
+        }
+        starts.push((*row, stack.path()));
+    }
+    for (i, (top_row, heading_path)) in starts.iter().enumerate() {
+        let next_row = starts.get(i + 1).map(|x| x.0).unwrap_or(lines.len());
+        if next_row <= *top_row {
+            continue;
+        }
+        let text = lines[*top_row..next_row].join("\n");
+        if text.trim().is_empty() {
+            continue;
+        }
+        result.push(DocSection {
+            heading_path: heading_path.clone(),
+            text,
+            top_row: *top_row,
+            bottom_row: next_row - 1,
+            whole_range: false,
+        });
+    }
+    result
+}
+
+const DOC_EXTENSIONS: &[&str] = &["md", "markdown", "rmd", "rst", "ipynb", "pdf"];
+
+fn lowercase_extension(path: &PathBuf) -> String {
+    path.extension().map(|x| x.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+pub fn is_doc_file(path: &PathBuf) -> bool {
+    DOC_EXTENSIONS.contains(&lowercase_extension(path).as_str())
+}
+
+// Markdown, notebooks, reStructuredText and PDF have their own splitters, None for everything else
+pub fn doc_sections(path: &PathBuf, text: &String) -> Option<Vec<DocSection>> {
+    match lowercase_extension(path).as_str() {
+        "md" | "markdown" | "rmd" => Some(doc_splitter_markdown::markdown_sections(text)),
+        "rst" => Some(doc_splitter_rst::rst_sections(text)),
+        "ipynb" => doc_splitter_notebook::notebook_sections(text),
+        "pdf" => Some(doc_splitter_pdf::pdf_sections(text)),
+        _ => None,
+    }
+}
+
+pub fn doc_split(
+    path: &PathBuf,
+    text: &String,
+    tokenizer: Option<Arc<StdRwLock<Tokenizer>>>,
+    tokens_limit: usize,
+) -> Option<Vec<SplitResult>> {
+    let sections = doc_sections(path, text)?;
+    let mut chunks = vec![];
+    for s in sections.iter() {
+        chunks.extend(get_chunks(&s.text, path, &s.heading_path, (s.top_row, s.bottom_row),
+                                 tokenizer.clone(), tokens_limit, LINES_OVERLAP, s.whole_range));
+    }
+    Some(chunks)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections_by_headings() {
+        let lines = vec!["intro", "# A", "text a", "## B", "text b", "# C", ""];
+        let headings = vec![(1, 1, "A".to_string()), (3, 2, "B".to_string()), (5, 1, "C".to_string())];
+        let sections = sections_by_headings(&lines, &headings);
+        let paths: Vec<(&str, usize, usize)> = sections.iter().map(|s| (s.heading_path.as_str(), s.top_row, s.bottom_row)).collect();
+        assert_eq!(paths, vec![("", 0, 0), ("A", 1, 2), ("A › B", 3, 4), ("C", 5, 6)]);
+    }
+}
@@ -1,10 +1,14 @@
+use std::collections::HashMap;
 use std::fs;
 #[cfg(not(windows))]
 use std::os::unix::fs::PermissionsExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 
 const LARGE_FILE_SIZE_THRESHOLD: u64 = 180*1024; // 180k files (180k is ~0.2% of all files on our dataset)
 const SMALL_FILE_SIZE_THRESHOLD: u64 = 5;        // 5 Bytes
+const LARGE_DOC_SIZE_THRESHOLD: u64 = 10*1024*1024; // pdf and ipynb, most of the size is not text (fonts, images, outputs)
+const LARGE_DOC_EXTENSIONS: &[&str] = &["pdf", "ipynb"];
 
 pub const SOURCE_FILE_EXTENSIONS: &[&str] = &[
     "c", "cpp", "cc", "h", "hpp", "cs", "java", "py", "rb", "go", "rs", "swift",
@@ -13,9 +17,10 @@ pub const SOURCE_FILE_EXTENSIONS: &[&str] = &[
     "scss", "sass", "less", "json", "xml", "yml", "yaml", "md", "sql", "db", "sqlite",
     "mdf", "cfg", "conf", "ini", "toml", "dockerfile", "ipynb", "rmd", "xml", "kt",
     "xaml", "unity", "gd", "uproject", "uasset", "asm", "s", "tex", "makefile", "mk",
-    "cmake", "gradle", "liquid"
+    "cmake", "gradle", "liquid", "markdown", "rst", "pdf"
 ];
 
+// Used only when there are no ignore files (see IGNORE_FILENAMES) that tell what to skip
 pub(crate) const BLACKLISTED_DIRS: &[&str] = &[
     "target", "node_modules", "vendor", "build", "dist",
     "bin", "pkg", "lib", "lib64", "obj",
@@ -24,6 +29,9 @@ pub(crate) const BLACKLISTED_DIRS: &[&str] = &[
     "_trajectories", ".gradle"
 ];
 
+// In the order of priority, .refactignore is for things AI should not see even if they are under version control
+pub const IGNORE_FILENAMES: &[&str] = &[".refactignore", ".ignore", ".gitignore"];
+
 pub fn is_valid_file(path: &PathBuf, allow_hidden_folders: bool, ignore_size_thresholds: bool) -> Result<(), Box<dyn std::error::Error>> {
     if !path.is_file() {
         return Err("Path is not a file".into());
@@ -42,7 +50,11 @@ pub fn is_valid_file(path: &PathBuf, allow_hidden_folders: bool, ignore_size_thr
         if !ignore_size_thresholds && file_size < SMALL_FILE_SIZE_THRESHOLD {
             return Err("File size is too small".into());
         }
-        if !ignore_size_thresholds && file_size > LARGE_FILE_SIZE_THRESHOLD {
+        let is_large_doc = path.extension()
+            .map(|x| LARGE_DOC_EXTENSIONS.contains(&x.to_string_lossy().to_lowercase().as_str()))
+            .unwrap_or(false);
+        let size_threshold = if is_large_doc { LARGE_DOC_SIZE_THRESHOLD } else { LARGE_FILE_SIZE_THRESHOLD };
+        if !ignore_size_thresholds && file_size > size_threshold {
             return Err("File size is too large".into());
         }
         #[cfg(not(windows))]
@@ -58,21 +70,150 @@ pub fn is_valid_file(path: &PathBuf, allow_hidden_folders: bool, ignore_size_thr
     Ok(())
 }
 
-pub fn is_this_inside_blacklisted_dir(path: &PathBuf) -> bool {
-    let mut path = path.clone();
-    while path.parent().is_some() {
-        path = path.parent().unwrap().to_path_buf();
-        if let Some(file_name) = path.file_name() {
-            if BLACKLISTED_DIRS.contains(&file_name.to_str().unwrap_or_default()) {
+pub fn is_this_inside_blacklisted_dir(path: &PathBuf, ignore_rules: &mut IgnoreRules) -> bool {
+    let mut dir = path.clone();
+    while dir.parent().is_some() {
+        dir = dir.parent().unwrap().to_path_buf();
+        if let Some(file_name_str) = dir.file_name().and_then(|x| x.to_str()) {
+            if file_name_str.starts_with(".") {
                 return true;
             }
-            if let Some(file_name_str) = file_name.to_str() {
-                if file_name_str.starts_with(".") {
+        }
+    }
+    ignore_rules.is_ignored(path, false)
+}
+
+pub fn is_ignore_file(path: &Path) -> bool {
+    path.file_name().and_then(|x| x.to_str()).map(|x| IGNORE_FILENAMES.contains(&x)).unwrap_or(false)
+}
+
+// Gitignore semantics for .gitignore, .ignore and .refactignore: nested files, negations, .git/info/exclude
+// and the global excludes file. Ignore files are read lazily and cached, so create one IgnoreRules for
+// a walk over many files, or keep one around and replace it when an ignore file changes.
+pub struct IgnoreRules {
+    dir_matchers: HashMap<PathBuf, Vec<Gitignore>>,
+    global: Option<Gitignore>,
+}
+
+impl IgnoreRules {
+    pub fn new() -> Self {
+        IgnoreRules {
+            dir_matchers: HashMap::new(),
+            global: None,
+        }
+    }
+
+    fn matchers_for_dir(&mut self, dir: &Path) -> &Vec<Gitignore> {
+        self.dir_matchers.entry(dir.to_path_buf()).or_insert_with(|| {
+            let mut matchers = vec![];
+            for ignore_filename in IGNORE_FILENAMES {
+                let ignore_path = dir.join(ignore_filename);
+                if !ignore_path.is_file() {
+                    continue;
+                }
+                let (matcher, err) = Gitignore::new(&ignore_path);
+                if let Some(err) = err {
+                    tracing::warn!("problem in {}: {}", ignore_path.display(), err);
+                }
+                matchers.push(matcher);
+            }
+            let exclude_path = dir.join(".git").join("info").join("exclude");
+            if exclude_path.is_file() {
+                let mut builder = GitignoreBuilder::new(dir);
+                builder.add(&exclude_path);
+                if let Ok(matcher) = builder.build() {
+                    matchers.push(matcher);
+                }
+            }
+            matchers
+        })
+    }
+
+    fn global_matcher(&mut self) -> &Gitignore {
+        self.global.get_or_insert_with(|| Gitignore::global().0)
+    }
+
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let mut any_rules = false;
+        let mut vcs_root: Option<PathBuf> = None;
+        for dir in path.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() {
+                break;
+            }
+            let matchers = self.matchers_for_dir(dir);
+            any_rules |= !matchers.is_empty();
+            for matcher in matchers {
+                let m = matcher.matched_path_or_any_parents(path, is_dir);
+                if m.is_ignore() {
+                    return true;
+                }
+                if m.is_whitelist() {
+                    return false;
+                }
+            }
+            if dir.join(".git").exists() {
+                vcs_root = Some(dir.to_path_buf());
+                break;
+            }
+        }
+        if let Some(vcs_root) = vcs_root {
+            // global excludes file has no root, it needs a path relative to the repository
+            if let Ok(relative) = path.strip_prefix(&vcs_root) {
+                if self.global_matcher().matched_path_or_any_parents(relative, is_dir).is_ignore() {
                     return true;
                 }
             }
+            let below_vcs_root = path.strip_prefix(&vcs_root).map(|x| x.to_path_buf()).unwrap_or_default();
+            return !any_rules && _has_blacklisted_component(&below_vcs_root, is_dir);
         }
+        !any_rules && _has_blacklisted_component(path, is_dir)
+    }
+}
+
+fn _has_blacklisted_component(path: &Path, is_dir: bool) -> bool {
+    let dirs = if is_dir { path } else { path.parent().unwrap_or(Path::new("")) };
+    dirs.components().any(|c| BLACKLISTED_DIRS.contains(&c.as_os_str().to_str().unwrap_or_default()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn test_ignore_rules_nested_and_negations() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        write(&root.join(".gitignore"), "*.pb.go\nfixtures/\n");
+        write(&root.join("api").join(".gitignore"), "!keep.pb.go\n");
+        write(&root.join(".refactignore"), "secrets/\n");
+
+        let mut rules = IgnoreRules::new();
+        assert!(rules.is_ignored(&root.join("api").join("service.pb.go"), false));
+        assert!(!rules.is_ignored(&root.join("api").join("keep.pb.go"), false));
+        assert!(rules.is_ignored(&root.join("tests").join("fixtures").join("a.py"), false));
+        assert!(rules.is_ignored(&root.join("secrets").join("a.py"), false));
+        assert!(!rules.is_ignored(&root.join("src").join("main.go"), false));
+        // there are ignore files, so the hardcoded list of directories doesn't apply
+        assert!(!rules.is_ignored(&root.join("lib").join("util.go"), false));
+        assert!(!rules.is_ignored(&root.join("build"), true));
+    }
+
+    #[test]
+    fn test_ignore_rules_fallback_to_blacklisted_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        let mut rules = IgnoreRules::new();
+        assert!(rules.is_ignored(&root.join("node_modules"), true));
+        assert!(rules.is_ignored(&root.join("node_modules").join("x.js"), false));
+        assert!(!rules.is_ignored(&root.join("src").join("x.js"), false));
     }
-    false
 }
 
@@ -12,6 +12,7 @@ use tokio::sync::RwLock as ARwLock;
 
 use crate::global_context::GlobalContext;
 use crate::ast::ast_indexer_thread::ast_indexer_enqueue_files;
+use crate::bm25::bm25_thread::bm25_indexer_enqueue_files;
 
 
 pub async fn enqueue_all_docs_from_jsonl(
@@ -27,7 +28,7 @@ pub async fn enqueue_all_docs_from_jsonl(
     for d in paths.iter() {
         docs.push(d.to_string_lossy().to_string());
     }
-    let (vec_db_module, ast_service) = {
+    let (vec_db_module, ast_service, bm25_service) = {
         let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64();
         let gcx_locked = gcx.write().await;
         *gcx_locked.documents_state.cache_dirty.lock().await = now;
@@ -38,13 +39,18 @@ pub async fn enqueue_all_docs_from_jsonl(
         let vec_db_module = gcx_locked.vec_db.clone();
         #[cfg(not(feature="vecdb"))]
         let vec_db_module = false;
-        (vec_db_module, gcx_locked.ast_service.clone())
+        (vec_db_module, gcx_locked.ast_service.clone(), gcx_locked.bm25_service.clone())
     };
     if let Some(ast) = &ast_service {
         if !vecdb_only {
             ast_indexer_enqueue_files(ast.clone(), &docs, force).await;
         }
     }
+    if let Some(bm25) = &bm25_service {
+        if !vecdb_only {
+            bm25_indexer_enqueue_files(bm25.clone(), &docs, force).await;
+        }
+    }
     #[cfg(feature="vecdb")]
     match *vec_db_module.lock().await {
         Some(ref mut db) => db.vectorizer_enqueue_files(&docs, false).await,
@@ -9,6 +9,7 @@ use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watche
 use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind};
 use ropey::Rope;
 use tokio::sync::{RwLock as ARwLock, Mutex as AMutex};
+use tower_lsp::lsp_types::{Position, Range, TextDocumentContentChangeEvent};
 use walkdir::WalkDir;
 use which::which;
 use tracing::info;
@@ -16,8 +17,11 @@ use tracing::info;
 use crate::git::operations::git_ls_files;
 use crate::global_context::GlobalContext;
 use crate::telemetry;
-use crate::file_filter::{is_this_inside_blacklisted_dir, is_valid_file, BLACKLISTED_DIRS, SOURCE_FILE_EXTENSIONS};
-use crate::ast::ast_indexer_thread::ast_indexer_enqueue_files;
+use crate::file_filter::{is_ignore_file, is_this_inside_blacklisted_dir, is_valid_file, IgnoreRules, SOURCE_FILE_EXTENSIONS};
+use crate::ast::ast_indexer_thread::{ast_indexer_enqueue_files, ast_indexer_forget_files};
+use crate::ast::ast_db::doc_cpaths_all;
+use crate::ast::chunk_utils::official_text_hashing_function;
+use crate::bm25::bm25_thread::{bm25_indexed_files, bm25_indexer_enqueue_files, bm25_indexer_forget_files, bm25_switch_workspace};
 use crate::privacy::{check_file_privacy, load_privacy_if_needed, PrivacySettings, FilePrivacyLevel};
 
 
@@ -25,6 +29,26 @@ use crate::privacy::{check_file_privacy, load_privacy_if_needed, PrivacySettings
 pub struct Document {
     pub doc_path: PathBuf,
     pub doc_text: Option<Rope>,
+    pub doc_version: Option<i32>,  // version reported by the IDE, None if the text didn't come from the IDE
+}
+
+fn text_looks_good(r: &Rope) -> Result<(), String> {
+    // Some simple tests to find if the text is suitable to parse (not generated or compressed code)
+    let total_chars = r.chars().count();
+    let total_lines = r.lines().count();
+    let avg_line_length = total_chars / total_lines;
+    if avg_line_length > 150 {
+        return Err("generated, avg line length > 150".to_string());
+    }
+
+    // example: hl.min.js
+    let total_spaces = r.chars().filter(|x| x.is_whitespace()).count();
+    let spaces_percentage = total_spaces as f32 / total_chars as f32;
+    if total_lines >= 5 && spaces_percentage <= 0.05 {
+        return Err(format!("generated or compressed, {:.1}% spaces < 5%", 100.0*spaces_percentage));
+    }
+
+    Ok(())
 }
 
 pub async fn get_file_text_from_memory_or_disk(global_context: Arc<ARwLock<GlobalContext>>, file_path: &PathBuf) -> Result<String, String>
@@ -44,12 +68,12 @@ pub async fn get_file_text_from_memory_or_disk(global_context: Arc<ARwLock<Globa
 
 impl Document {
     pub fn new(doc_path: &PathBuf) -> Self {
-        Self { doc_path: doc_path.clone(),  doc_text: None }
+        Self { doc_path: doc_path.clone(),  doc_text: None, doc_version: None }
     }
 
     #[cfg(feature="vecdb")]
-    pub async fn update_text_from_disk(&mut self, gcx: Arc<ARwLock<GlobalContext>>) -> Result<(), String> {
-        match read_file_from_disk(load_privacy_if_needed(gcx.clone()).await, &self.doc_path).await {
+    pub async fn update_text_from_disk_for_indexing(&mut self, gcx: Arc<ARwLock<GlobalContext>>) -> Result<(), String> {
+        match read_file_from_disk_for_indexing(load_privacy_if_needed(gcx.clone()).await, &self.doc_path).await {
             Ok(res) => {
                 self.doc_text = Some(res);
                 return Ok(());
@@ -71,6 +95,49 @@ impl Document {
         self.doc_text = Some(Rope::from_str(text));
     }
 
+    pub fn apply_edit(&mut self, range: &Range, text: &str) -> Result<(), String> {
+        let rope = self.doc_text.as_mut().ok_or(format!("no text loaded in {}", self.doc_path.display()))?;
+        let start = lsp_position_to_char_idx(rope, &range.start)?;
+        let end = lsp_position_to_char_idx(rope, &range.end)?;
+        if start > end {
+            return Err(format!("range start {:?} is after range end {:?}", range.start, range.end));
+        }
+        rope.remove(start..end);
+        rope.insert(start, text);
+        Ok(())
+    }
+
+    // Versions only grow, an old or a repeated version means we missed or reordered something. Once out of sync,
+    // the text is dropped (readers fall back to disk) and range edits are ignored until the IDE sends the full text
+    pub fn apply_changes(&mut self, version: i32, changes: &Vec<TextDocumentContentChangeEvent>) -> Result<(), String> {
+        let mut in_sync: Result<(), String> = match (self.doc_version, &self.doc_text) {
+            (_, None) => Err("document is not in sync".to_string()),
+            (Some(v), _) if version <= v => Err(format!("got version {} after version {}", version, v)),
+            _ => Ok(()),
+        };
+        for change in changes {
+            match &change.range {
+                None => {
+                    self.update_text(&change.text);
+                    in_sync = Ok(());
+                }
+                Some(range) => {
+                    if in_sync.is_ok() {
+                        in_sync = self.apply_edit(range, &change.text);
+                    }
+                }
+            }
+        }
+        match in_sync {
+            Ok(()) => self.doc_version = Some(version),
+            Err(_) => {
+                self.doc_text = None;
+                self.doc_version = None;
+            }
+        }
+        in_sync
+    }
+
     #[cfg(feature="vecdb")]
     pub fn text_as_string(&self) -> Result<String, String> {
         if let Some(r) = &self.doc_text {
@@ -80,25 +147,16 @@ impl Document {
     }
 
     pub fn does_text_look_good(&self) -> Result<(), String> {
-        // Some simple tests to find if the text is suitable to parse (not generated or compressed code)
         assert!(self.doc_text.is_some());
         let r = self.doc_text.as_ref().unwrap();
-
-        let total_chars = r.chars().count();
-        let total_lines = r.lines().count();
-        let avg_line_length = total_chars / total_lines;
-        if avg_line_length > 150 {
-            return Err("generated, avg line length > 150".to_string());
-        }
-
-        // example: hl.min.js
-        let total_spaces = r.chars().filter(|x| x.is_whitespace()).count();
-        let spaces_percentage = total_spaces as f32 / total_chars as f32;
-        if total_lines >= 5 && spaces_percentage <= 0.05 {
-            return Err(format!("generated or compressed, {:.1}% spaces < 5%", 100.0*spaces_percentage));
+        // a notebook is judged by the text of its cells, the outputs (base64 images) are not indexed anyway
+        if self.doc_path.extension().map(|x| x == "ipynb").unwrap_or(false) {
+            if let Some(sections) = crate::doc_splitter::doc_splitter_notebook::notebook_sections(&r.to_string()) {
+                let cells_text = sections.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join("\n");
+                return text_looks_good(&Rope::from_str(&cells_text));
+            }
         }
-
-        Ok(())
+        text_looks_good(r)
     }
 }
 
@@ -115,6 +173,27 @@ pub struct DocumentsState {
     pub cache_correction: Arc<HashMap<String, HashSet<String>>>,  // map dir3/file.ext -> to /dir1/dir2/dir3/file.ext
     pub cache_shortened: Arc<HashSet<String>>,
     pub fs_watcher: Arc<ARwLock<RecommendedWatcher>>,
+    pub ignore_rules: Arc<StdMutex<IgnoreRules>>,  // replaced with a fresh one when an ignore file changes
+}
+
+// LSP positions count characters in UTF-16 code units, ropey counts unicode chars
+pub fn lsp_position_to_char_idx(rope: &Rope, position: &Position) -> Result<usize, String> {
+    let line_idx = position.line as usize;
+    if line_idx == rope.len_lines() {
+        return Ok(rope.len_chars());
+    }
+    if line_idx > rope.len_lines() {
+        return Err(format!("line {} is out of range, document has {} lines", line_idx, rope.len_lines()));
+    }
+    let line = rope.line(line_idx);
+    let mut line_len = line.len_chars();
+    // a position past the end of the line points to the end of the line, not to the next one
+    while line_len > 0 && matches!(line.char(line_len - 1), '\n' | '\r') {
+        line_len -= 1;
+    }
+    let line = line.slice(..line_len);
+    let cu = (position.character as usize).min(line.len_utf16_cu());
+    Ok(rope.line_to_char(line_idx) + line.utf16_cu_to_char(cu))
 }
 
 async fn mem_overwrite_or_create_document(
@@ -150,6 +229,7 @@ impl DocumentsState {
             cache_correction: Arc::new(HashMap::<String, HashSet<String>>::new()),
             cache_shortened: Arc::new(HashSet::<String>::new()),
             fs_watcher: Arc::new(ARwLock::new(watcher)),
+            ignore_rules: Arc::new(StdMutex::new(IgnoreRules::new())),
         }
     }
 }
@@ -200,6 +280,37 @@ pub async fn read_file_from_disk(
     read_file_from_disk_without_privacy_check(path).await
 }
 
+// For indexing only: a PDF becomes its extracted text, so never write back what this returns
+pub async fn read_file_from_disk_for_indexing(
+    privacy_settings: Arc<PrivacySettings>,
+    path: &PathBuf,
+) -> Result<Rope, String> {
+    if !path.extension().map(|x| x.to_string_lossy().to_lowercase() == "pdf").unwrap_or(false) {
+        return read_file_from_disk(privacy_settings, path).await;
+    }
+    check_file_privacy(privacy_settings, path, &FilePrivacyLevel::AllowToSendAnywhere)?;
+    let bytes = tokio::fs::read(path).await.map_err(|e|
+        format!("failed to read file {}: {}", crate::nicer_logs::last_n_chars(&path.display().to_string(), 30), e)
+    )?;
+    let path_clone = path.clone();
+    tokio::task::spawn_blocking(move || crate::doc_splitter::doc_splitter_pdf::pdf_to_text(&bytes))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|x| Rope::from_str(&x))
+        .map_err(|e| format!("failed to read file {}: {}", crate::nicer_logs::last_n_chars(&path_clone.display().to_string(), 30), e))
+}
+
+pub async fn get_file_text_for_indexing(gcx: Arc<ARwLock<GlobalContext>>, file_path: &PathBuf) -> Result<String, String> {
+    if let Some(doc) = gcx.read().await.documents_state.memory_document_map.get(file_path) {
+        let doc = doc.read().await;
+        if doc.doc_text.is_some() {
+            check_file_privacy(load_privacy_if_needed(gcx.clone()).await, &file_path, &FilePrivacyLevel::AllowToSendAnywhere)?;
+            return Ok(doc.doc_text.as_ref().unwrap().to_string());
+        }
+    }
+    read_file_from_disk_for_indexing(load_privacy_if_needed(gcx.clone()).await, file_path).await.map(|x| x.to_string())
+}
+
 async fn _run_command(cmd: &str, args: &[&str], path: &PathBuf, filter_out_status: bool) -> Option<Vec<PathBuf>> {
     info!("{} EXEC {} {}", path.display(), cmd, args.join(" "));
     let output = async_process::Command::new(cmd)
@@ -248,6 +359,7 @@ pub fn ls_files(path: &PathBuf, recursive: bool) -> Result<Vec<PathBuf>, String>
 
     let mut paths = vec![];
     let mut dirs_to_visit = vec![path.clone()];
+    let mut ignore_rules = IgnoreRules::new();
 
     while let Some(dir) = dirs_to_visit.pop() {
         let ls_maybe = fs::read_dir(&dir);
@@ -267,10 +379,10 @@ pub fn ls_files(path: &PathBuf, recursive: bool) -> Result<Vec<PathBuf>, String>
             let path = entry.path();
             if recursive && path.is_dir() && !(
                 path.file_name().unwrap_or_default().to_str().unwrap_or_default().starts_with(".") ||
-                BLACKLISTED_DIRS.contains(&path.file_name().unwrap_or_default().to_str().unwrap_or_default())
+                ignore_rules.is_ignored(&path, true)
             ) {
                 dirs_to_visit.push(path);
-            } else if path.is_file() {
+            } else if path.is_file() && !ignore_rules.is_ignored(&path, false) {
                 paths.push(path);
             }
         }
@@ -338,9 +450,14 @@ async fn _ls_files_under_version_control_recursive(
     let mut candidates: Vec<PathBuf> = vec![path.clone()];
     let mut rejected_reasons: HashMap<String, usize> = HashMap::new();
     let mut blacklisted_dirs_cnt: usize = 0;
+    let mut ignore_rules = IgnoreRules::new();
     while !candidates.is_empty() {
         let local_path = candidates.pop().unwrap();
         if local_path.is_file() {
+            if ignore_rules.is_ignored(&local_path, false) {
+                rejected_reasons.entry("ignored by .gitignore, .ignore or .refactignore".to_string()).and_modify(|x| *x += 1).or_insert(1);
+                continue;
+            }
             let maybe_valid = is_valid_file(
                 &local_path, allow_files_in_hidden_folders, ignore_size_thresholds);
             match maybe_valid {
@@ -354,7 +471,7 @@ async fn _ls_files_under_version_control_recursive(
             }
         }
         if local_path.is_dir() {
-            if BLACKLISTED_DIRS.contains(&local_path.file_name().unwrap().to_str().unwrap()) {
+            if ignore_rules.is_ignored(&local_path, true) {
                 blacklisted_dirs_cnt += 1;
                 continue;
             }
@@ -362,6 +479,10 @@ async fn _ls_files_under_version_control_recursive(
             if let Some(v) = maybe_files {
                 vcs_folders.push(local_path.clone());
                 for x in v.iter() {
+                    if ignore_rules.is_ignored(x, false) {
+                        rejected_reasons.entry("ignored by .gitignore, .ignore or .refactignore".to_string()).and_modify(|x| *x += 1).or_insert(1);
+                        continue;
+                    }
                     let maybe_valid = is_valid_file(
                         x, allow_files_in_hidden_folders, ignore_size_thresholds);
                     match maybe_valid {
@@ -391,7 +512,7 @@ async fn _ls_files_under_version_control_recursive(
     if rejected_reasons.is_empty() {
         info!("    no bad files at all");
     }
-    info!("also the loop bumped into {} blacklisted dirs", blacklisted_dirs_cnt);
+    info!("also the loop bumped into {} ignored or blacklisted dirs", blacklisted_dirs_cnt);
 }
 
 pub async fn retrieve_files_in_workspace_folders(
@@ -437,9 +558,9 @@ async fn enqueue_some_docs(
     if paths.len() > 5 {
         info!("    ...");
     }
-    let (vec_db_module, ast_service) = {
+    let (vec_db_module, ast_service, bm25_service) = {
         let cx = gcx.read().await;
-        (cx.vec_db.clone(), cx.ast_service.clone())
+        (cx.vec_db.clone(), cx.ast_service.clone(), cx.bm25_service.clone())
     };
     #[cfg(feature="vecdb")]
     if let Some(ref mut db) = *vec_db_module.lock().await {
@@ -450,6 +571,9 @@ async fn enqueue_some_docs(
     if let Some(ast) = &ast_service {
         ast_indexer_enqueue_files(ast.clone(), paths, force).await;
     }
+    if let Some(bm25) = &bm25_service {
+        bm25_indexer_enqueue_files(bm25.clone(), paths, force).await;
+    }
     let (cache_correction_arc, _) = crate::files_correction::files_cache_rebuild_as_needed(gcx.clone()).await;
     let mut moar_files: Vec<PathBuf> = Vec::new();
     for p in paths {
@@ -469,6 +593,21 @@ async fn enqueue_some_docs(
     }
 }
 
+// Workspace folders usually arrive from LSP after the start, the indexes that depend on the set of folders are keyed by this
+pub async fn workspace_key(gcx: Arc<ARwLock<GlobalContext>>) -> String {
+    let (workspace_folders, files_jsonl_path) = {
+        let gcx_locked = gcx.read().await;
+        let folders = gcx_locked.documents_state.workspace_folders.lock().unwrap().clone();
+        (folders, gcx_locked.cmdline.files_jsonl_path.clone())
+    };
+    let mut parts: Vec<String> = workspace_folders.iter().map(|x| x.to_string_lossy().to_string()).collect();
+    parts.sort();
+    if !files_jsonl_path.is_empty() {
+        parts.push(files_jsonl_path);
+    }
+    official_text_hashing_function(&parts.join("\n"))
+}
+
 pub async fn enqueue_all_files_from_workspace_folders(
     gcx: Arc<ARwLock<GlobalContext>>,
     wake_up_indexers: bool,
@@ -478,7 +617,7 @@ pub async fn enqueue_all_files_from_workspace_folders(
 
     info!("enqueue_all_files_from_workspace_folders started files search with {} folders", folders.len());
     let (all_files, vcs_folders) = retrieve_files_in_workspace_folders(
-        folders,
+        folders.clone(),
         false,
         false
     ).await;
@@ -501,17 +640,18 @@ pub async fn enqueue_all_files_from_workspace_folders(
 
     *cache_dirty.lock().await = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64();
 
-    let (vec_db_module, ast_service) = {
+    let (vec_db_module, ast_service, bm25_service) = {
         let cx_locked = gcx.read().await;
-        (cx_locked.vec_db.clone(), cx_locked.ast_service.clone())
+        (cx_locked.vec_db.clone(), cx_locked.ast_service.clone(), cx_locked.bm25_service.clone())
     };
 
-    // Both vecdb and ast support paths to non-existant files (possibly previously existing files) as a way to remove them from index
+    if let Some(bm25) = &bm25_service {
+        let cache_dir = gcx.read().await.cache_dir.clone();
+        bm25_switch_workspace(bm25.clone(), &cache_dir, &workspace_key(gcx.clone()).await).await;
+    }
 
-    let mut updated_or_removed: IndexSet<String> = IndexSet::new();
-    updated_or_removed.extend(all_files.iter().map(|file| file.to_string_lossy().to_string()));
-    updated_or_removed.extend(old_workspace_files.iter().map(|p| p.to_string_lossy().to_string()));
-    let paths_nodups: Vec<String> = updated_or_removed.into_iter().collect();
+    let paths_nodups: Vec<String> = all_files.iter().map(|file| file.to_string_lossy().to_string()).collect::<IndexSet<String>>().into_iter().collect();
+    forget_files_gone_from_workspace(gcx.clone(), &folders, &paths_nodups, &old_workspace_files, vecdb_only).await;
 
     #[cfg(feature="vecdb")]
     if let Some(ref mut db) = *vec_db_module.lock().await {
@@ -525,9 +665,74 @@ pub async fn enqueue_all_files_from_workspace_folders(
             ast_indexer_enqueue_files(ast.clone(), &paths_nodups, wake_up_indexers).await;
         }
     }
+    if let Some(bm25) = bm25_service {
+        if !vecdb_only {
+            bm25_indexer_enqueue_files(bm25.clone(), &paths_nodups, wake_up_indexers).await;
+        }
+    }
     all_files.len() as i32
 }
 
+// The indexes can be shared with other projects, only what's inside the workspace folders is ours to remove
+fn files_gone_from_workspace(
+    indexed: Vec<String>,
+    keep: &HashSet<&String>,
+    folders: &Vec<PathBuf>,
+    old_workspace_files: &Vec<PathBuf>,
+) -> Vec<String> {
+    let mut result: IndexSet<String> = indexed.into_iter()
+        .filter(|x| !keep.contains(x) && folders.iter().any(|f| PathBuf::from(x).starts_with(f)))
+        .collect();
+    result.extend(old_workspace_files.iter().map(|p| p.to_string_lossy().to_string()).filter(|x| !keep.contains(x)));
+    result.into_iter().collect()
+}
+
+// Deleted files, files that became ignored, files of a removed workspace folder: the indexes might still have them,
+// and re-enqueueing doesn't help for the ones that are still on disk, they would be indexed again
+async fn forget_files_gone_from_workspace(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    folders: &Vec<PathBuf>,
+    workspace_files: &Vec<String>,
+    old_workspace_files: &Vec<PathBuf>,
+    vecdb_only: bool,
+) {
+    let (vec_db_module, ast_service, bm25_service) = {
+        let cx_locked = gcx.read().await;
+        (cx_locked.vec_db.clone(), cx_locked.ast_service.clone(), cx_locked.bm25_service.clone())
+    };
+    let keep: HashSet<&String> = workspace_files.iter().collect();
+    let gone = |indexed: Vec<String>| files_gone_from_workspace(indexed, &keep, folders, old_workspace_files);
+
+    #[cfg(feature="vecdb")]
+    if let Some(ref mut db) = *vec_db_module.lock().await {
+        let forget = gone(db.indexed_files().await);
+        if !forget.is_empty() {
+            info!("vecdb: {} files are not in the workspace anymore", forget.len());
+            db.vectorizer_forget_files(&forget).await;
+        }
+    }
+    #[cfg(not(feature="vecdb"))]
+    let _ = vec_db_module;
+    if vecdb_only {
+        return;
+    }
+    if let Some(ast) = ast_service {
+        let ast_index = ast.lock().await.ast_index.clone();
+        let forget = gone(doc_cpaths_all(ast_index).await);
+        if !forget.is_empty() {
+            info!("ast: {} files are not in the workspace anymore", forget.len());
+            ast_indexer_forget_files(ast, &forget).await;
+        }
+    }
+    if let Some(bm25) = bm25_service {
+        let forget = gone(bm25_indexed_files(bm25.clone()).await);
+        if !forget.is_empty() {
+            info!("bm25: {} files are not in the workspace anymore", forget.len());
+            bm25_indexer_forget_files(bm25, &forget).await;
+        }
+    }
+}
+
 pub async fn on_workspaces_init(gcx: Arc<ARwLock<GlobalContext>>) -> i32
 {
     // Called from lsp and lsp_like
@@ -548,9 +753,11 @@ pub async fn on_did_open(
     cpath: &PathBuf,
     text: &String,
     _language_id: &String,
+    version: i32,
 ) {
     let mut doc = Document::new(cpath);
     doc.update_text(text);
+    doc.doc_version = Some(version);
     info!("on_did_open {}", crate::nicer_logs::last_n_chars(&cpath.display().to_string(), 30));
     let (_doc_arc, dirty_arc, mark_dirty) = mem_overwrite_or_create_document(gcx.clone(), doc).await;
     if mark_dirty {
@@ -577,21 +784,64 @@ pub async fn on_did_change(
     gcx: Arc<ARwLock<GlobalContext>>,
     path: &PathBuf,
     text: &String,
+) {
+    let t0 = Instant::now();
+    let mut doc = Document::new(path);
+    doc.update_text(text);
+    let (_doc_arc, dirty_arc, mark_dirty) = mem_overwrite_or_create_document(gcx.clone(), doc).await;
+    on_document_changed(gcx, path, text, dirty_arc, mark_dirty, t0).await
+}
+
+pub async fn on_did_change_incremental(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    path: &PathBuf,
+    version: i32,
+    changes: &Vec<TextDocumentContentChangeEvent>,
 ) {
     let t0 = Instant::now();
     let (doc_arc, dirty_arc, mark_dirty) = {
-        let mut doc = Document::new(path);
-        doc.update_text(text);
-        let (doc_arc, dirty_arc, set_mark_dirty) = mem_overwrite_or_create_document(gcx.clone(), doc).await;
-        (doc_arc, dirty_arc, set_mark_dirty)
+        let mut cx = gcx.write().await;
+        let dirty_arc = cx.documents_state.cache_dirty.clone();
+        let doc_map = &mut cx.documents_state.memory_document_map;
+        match doc_map.get(path) {
+            Some(doc_arc) => (doc_arc.clone(), dirty_arc, false),
+            None => {
+                let doc_arc = Arc::new(ARwLock::new(Document::new(path)));
+                doc_map.insert(path.clone(), doc_arc.clone());
+                (doc_arc, dirty_arc, true)
+            }
+        }
     };
+    // the lock is held from reading the text to storing it, so concurrent changes can't overwrite each other
+    let text = {
+        let mut doc = doc_arc.write().await;
+        if let Err(e) = doc.apply_changes(version, changes) {
+            tracing::warn!("{} is out of sync with the IDE: {}, ignoring edits until the full text arrives", crate::nicer_logs::last_n_chars(&path.display().to_string(), 30), e);
+            return;
+        }
+        doc.doc_text.as_ref().map(|x| x.to_string()).unwrap_or_default()
+    };
+    on_document_changed(gcx, path, &text, dirty_arc, mark_dirty, t0).await
+}
 
+async fn on_document_changed(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    path: &PathBuf,
+    text: &String,
+    dirty_arc: Arc<AMutex<f64>>,
+    mark_dirty: bool,
+    t0: Instant,
+) {
     if mark_dirty {
         let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64();
         *dirty_arc.lock().await = now;
     }
 
-    gcx.write().await.documents_state.active_file_path = Some(path.clone());
+    let ignore_rules = {
+        let mut gcx_locked = gcx.write().await;
+        gcx_locked.documents_state.active_file_path = Some(path.clone());
+        gcx_locked.documents_state.ignore_rules.clone()
+    };
 
     let mut go_ahead = true;
     {
@@ -599,12 +849,14 @@ pub async fn on_did_change(
         if is_it_good.is_err() {
             info!("{:?} ignoring changes: {}", path, is_it_good.err().unwrap());
             go_ahead = false;
+        } else if ignore_rules.lock().unwrap().is_ignored(path, false) {
+            info!("{:?} ignoring changes: ignored by .gitignore, .ignore or .refactignore", path);
+            go_ahead = false;
         }
     }
 
-    let cpath = doc_arc.read().await.doc_path.clone().to_string_lossy().to_string();
     if go_ahead {
-        enqueue_some_docs(gcx.clone(), &vec![cpath], false).await;
+        enqueue_some_docs(gcx.clone(), &vec![path.to_string_lossy().to_string()], false).await;
     }
 
     telemetry::snippets_collection::sources_changed(
@@ -620,10 +872,10 @@ pub async fn on_did_delete(gcx: Arc<ARwLock<GlobalContext>>, path: &PathBuf)
 {
     info!("on_did_delete {}", crate::nicer_logs::last_n_chars(&path.to_string_lossy().to_string(), 30));
 
-    let (vec_db_module, ast_service, dirty_arc) = {
+    let (vec_db_module, ast_service, bm25_service, dirty_arc) = {
         let mut cx = gcx.write().await;
         cx.documents_state.memory_document_map.remove(path);
-        (cx.vec_db.clone(), cx.ast_service.clone(), cx.documents_state.cache_dirty.clone())
+        (cx.vec_db.clone(), cx.ast_service.clone(), cx.bm25_service.clone(), cx.documents_state.cache_dirty.clone())
     };
 
     let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64();
@@ -640,6 +892,9 @@ pub async fn on_did_delete(gcx: Arc<ARwLock<GlobalContext>>, path: &PathBuf)
         let cpath = path.to_string_lossy().to_string();
         ast_indexer_enqueue_files(ast.clone(), &vec![cpath], false).await;
     }
+    if let Some(bm25) = &bm25_service {
+        bm25_indexer_enqueue_files(bm25.clone(), &vec![path.to_string_lossy().to_string()], false).await;
+    }
 }
 
 pub async fn add_folder(gcx: Arc<ARwLock<GlobalContext>>, fpath: &PathBuf)
@@ -670,10 +925,32 @@ pub async fn remove_folder(gcx: Arc<ARwLock<GlobalContext>>, path: &PathBuf)
 
 pub async fn file_watcher_event(event: Event, gcx_weak: Weak<ARwLock<GlobalContext>>)
 {
-    async fn on_create_modify(gcx_weak: Weak<ARwLock<GlobalContext>>, event: Event) {
+    // true if the event was about ignore files, then everything is rescanned with fresh rules
+    async fn on_ignore_files_changed(gcx: Arc<ARwLock<GlobalContext>>, event: &Event, ignore_rules: &Arc<StdMutex<IgnoreRules>>) -> bool {
+        let changed = {
+            let mut rules_locked = ignore_rules.lock().unwrap();
+            event.paths.iter().any(|p| is_ignore_file(p) && !is_this_inside_blacklisted_dir(p, &mut rules_locked))
+        };
+        if !changed {
+            return false;
+        }
+        // ignore rules changed, what's in the index might be wrong in both directions
+        info!("ignore file changed, rescanning workspace folders");
+        *ignore_rules.lock().unwrap() = IgnoreRules::new();
+        tokio::spawn(async move {
+            enqueue_all_files_from_workspace_folders(gcx, false, false).await;
+        });
+        true
+    }
+
+    async fn on_create_modify(gcx: Arc<ARwLock<GlobalContext>>, event: Event) {
+        let ignore_rules = gcx.read().await.documents_state.ignore_rules.clone();
+        if on_ignore_files_changed(gcx.clone(), &event, &ignore_rules).await {
+            return;
+        }
         let mut docs = vec![];
         for p in &event.paths {
-            if is_this_inside_blacklisted_dir(&p) {  // important to filter BEFORE canonical_path
+            if is_this_inside_blacklisted_dir(&p, &mut ignore_rules.lock().unwrap()) {  // important to filter BEFORE canonical_path
                 continue;
             }
 
@@ -695,41 +972,137 @@ pub async fn file_watcher_event(event: Event, gcx_weak: Weak<ARwLock<GlobalConte
             return;
         }
         // info!("EventKind::Create/Modify {} paths", event.paths.len());
-        if let Some(gcx) = gcx_weak.clone().upgrade() {
-            enqueue_some_docs(gcx, &docs, false).await;
-        }
+        enqueue_some_docs(gcx, &docs, false).await;
     }
 
-    async fn on_remove(gcx_weak: Weak<ARwLock<GlobalContext>>, event: Event) {
-        let mut never_mind = true;
-        for p in &event.paths {
-            never_mind &= is_this_inside_blacklisted_dir(&p);
+    async fn on_remove(gcx: Arc<ARwLock<GlobalContext>>, event: Event) {
+        let ignore_rules = gcx.read().await.documents_state.ignore_rules.clone();
+        if on_ignore_files_changed(gcx.clone(), &event, &ignore_rules).await {
+            return;
         }
         let mut docs = vec![];
-        if !never_mind {
-            for p in &event.paths {
-                if is_this_inside_blacklisted_dir(&p) {
-                    continue;
-                }
-                let cpath = crate::files_correction::canonical_path(&p.to_string_lossy().to_string());
-                docs.push(cpath.to_string_lossy().to_string());
+        for p in &event.paths {
+            if is_this_inside_blacklisted_dir(&p, &mut ignore_rules.lock().unwrap()) {
+                continue;
             }
+            let cpath = crate::files_correction::canonical_path(&p.to_string_lossy().to_string());
+            docs.push(cpath.to_string_lossy().to_string());
         }
         if docs.is_empty() {
             return;
         }
-        if let Some(gcx) = gcx_weak.clone().upgrade() {
-            enqueue_some_docs(gcx, &docs, false).await;
-        }
+        enqueue_some_docs(gcx, &docs, false).await;
     }
 
+    let gcx = match gcx_weak.upgrade() {
+        Some(x) => x,
+        None => return,
+    };
     match event.kind {
         EventKind::Any => {},
         EventKind::Access(_) => {},
-        EventKind::Create(CreateKind::File) => on_create_modify(gcx_weak.clone(), event).await,
-        EventKind::Remove(RemoveKind::File) => on_remove(gcx_weak.clone(), event).await,
-        EventKind::Modify(ModifyKind::Data(DataChange::Content)) => on_create_modify(gcx_weak.clone(), event).await,
+        EventKind::Create(CreateKind::File) => on_create_modify(gcx, event).await,
+        EventKind::Remove(RemoveKind::File) => on_remove(gcx, event).await,
+        EventKind::Modify(ModifyKind::Data(DataChange::Content)) => on_create_modify(gcx, event).await,
         EventKind::Other => {}
         _ => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc_with_text(text: &str) -> Document {
+        let mut doc = Document::new(&PathBuf::from("/tmp/test.py"));
+        doc.update_text(&text.to_string());
+        doc
+    }
+
+    fn range(start_line: u32, start_char: u32, end_line: u32, end_char: u32) -> Range {
+        Range::new(Position::new(start_line, start_char), Position::new(end_line, end_char))
+    }
+
+    #[test]
+    fn test_apply_edit() {
+        let mut doc = doc_with_text("def f():\n    return 1\n");
+        doc.apply_edit(&range(1, 11, 1, 12), "42").unwrap();
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "def f():\n    return 42\n");
+        doc.apply_edit(&range(0, 8, 1, 4), " ").unwrap();
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "def f(): return 42\n");
+        doc.apply_edit(&range(1, 0, 1, 0), "f()\n").unwrap();
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "def f(): return 42\nf()\n");
+    }
+
+    #[test]
+    fn test_apply_edit_utf16() {
+        // the emoji is one char in the rope, but two UTF-16 code units in LSP positions
+        let mut doc = doc_with_text("x = \"😀\" + y\r\nz = 1\r\n");
+        doc.apply_edit(&range(0, 8, 0, 12), "").unwrap();
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "x = \"😀\"\r\nz = 1\r\n");
+        // past the end of the line means the end of the line
+        doc.apply_edit(&range(0, 100, 0, 100), ";").unwrap();
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "x = \"😀\";\r\nz = 1\r\n");
+    }
+
+    #[test]
+    fn test_apply_edit_out_of_range() {
+        let mut doc = doc_with_text("a\nb\n");
+        assert!(doc.apply_edit(&range(5, 0, 5, 0), "c").is_err());
+        assert!(doc.apply_edit(&range(1, 1, 0, 0), "c").is_err());
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "a\nb\n");
+    }
+
+    #[test]
+    fn test_notebook_looks_good_by_cells() {
+        let image = "iVBORw0KGgo".repeat(200);
+        let code = "import numpy as np\nx = np.arange(10)\nprint(x)\n";
+        let notebook = format!(r#"{{"cells": [{{"cell_type": "code", "source": {}, "outputs": [{{"data": {{"image/png": "{}"}}}}]}}]}}"#, serde_json::json!(code), image);
+        let mut doc = Document::new(&PathBuf::from("/tmp/test.ipynb"));
+        doc.update_text(&notebook);
+        assert!(doc.does_text_look_good().is_ok());
+
+        let minified = "var a=1;".repeat(100);
+        let notebook = format!(r#"{{"cells": [{{"cell_type": "code", "source": {}, "outputs": []}}]}}"#, serde_json::json!(minified));
+        doc.update_text(&notebook);
+        assert!(doc.does_text_look_good().is_err());
+    }
+
+    #[test]
+    fn test_files_gone_from_workspace() {
+        let workspace_files = vec!["/ws/src/a.rs".to_string(), "/ws/src/b.rs".to_string()];
+        let keep: HashSet<&String> = workspace_files.iter().collect();
+        let indexed = vec![
+            "/ws/src/a.rs".to_string(),
+            "/ws/generated/now_ignored.rs".to_string(),
+            "/other_project/c.rs".to_string(),
+        ];
+        let old_workspace_files = vec![PathBuf::from("/ws/src/b.rs"), PathBuf::from("/removed_folder/d.rs")];
+        let gone = files_gone_from_workspace(indexed, &keep, &vec![PathBuf::from("/ws")], &old_workspace_files);
+        assert_eq!(gone, vec!["/ws/generated/now_ignored.rs".to_string(), "/removed_folder/d.rs".to_string()]);
+    }
+
+    fn change(range: Option<Range>, text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent { range, range_length: None, text: text.to_string() }
+    }
+
+    #[test]
+    fn test_apply_changes_out_of_sync() {
+        let mut doc = doc_with_text("a\nb\n");
+        doc.doc_version = Some(1);
+        doc.apply_changes(2, &vec![change(Some(range(0, 1, 0, 1)), "x")]).unwrap();
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "ax\nb\n");
+        assert_eq!(doc.doc_version, Some(2));
+
+        // a repeated version: the text is dropped, and range edits are ignored from now on
+        assert!(doc.apply_changes(2, &vec![change(Some(range(0, 0, 0, 0)), "y")]).is_err());
+        assert!(doc.doc_text.is_none());
+        assert!(doc.apply_changes(3, &vec![change(Some(range(0, 0, 0, 0)), "y")]).is_err());
+        assert!(doc.doc_text.is_none());
+
+        // the full text brings it back, edits after it in the same notification apply
+        doc.apply_changes(4, &vec![change(None, "c\n"), change(Some(range(0, 1, 0, 1)), "d")]).unwrap();
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "cd\n");
+        assert_eq!(doc.doc_version, Some(4));
+    }
+}
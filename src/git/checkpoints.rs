@@ -8,7 +8,7 @@ use serde::{Serialize, Deserialize};
 
 use crate::ast::chunk_utils::official_text_hashing_function;
 use crate::custom_error::MapErrToString;
-use crate::file_filter::BLACKLISTED_DIRS;
+use crate::file_filter::{IgnoreRules, BLACKLISTED_DIRS, IGNORE_FILENAMES};
 use crate::files_correction::{deserialize_path, get_active_workspace_folder, get_project_dirs, serialize_path};
 use crate::global_context::GlobalContext;
 use crate::git::{FileChange, FileChangeStatus, DiffStatusType};
@@ -45,9 +45,24 @@ async fn open_shadow_repo_and_nested_repos(
                 Repository::open(&git_dir_path).map_err_to_string()
             }?;
             nested_repo.set_workdir(path, false).map_err_to_string()?;
-            for blacklisted_dir in  BLACKLISTED_DIRS {
-                if let Err(e) = nested_repo.add_ignore_rule(blacklisted_dir) {
-                    tracing::warn!("Failed to add ignore rule for {blacklisted_dir}: {e}");
+            // .gitignore works by itself, top level .ignore and .refactignore are here to avoid walking into big ignored dirs,
+            // precise rules including nested files are applied later in filter_out_ignored_changes()
+            let mut has_ignore_files = false;
+            for ignore_filename in IGNORE_FILENAMES {
+                if let Ok(rules) = std::fs::read_to_string(path.join(ignore_filename)) {
+                    has_ignore_files = true;
+                    if *ignore_filename != ".gitignore" {
+                        if let Err(e) = nested_repo.add_ignore_rule(&rules) {
+                            tracing::warn!("Failed to add ignore rules from {ignore_filename}: {e}");
+                        }
+                    }
+                }
+            }
+            if !has_ignore_files {
+                for blacklisted_dir in BLACKLISTED_DIRS {
+                    if let Err(e) = nested_repo.add_ignore_rule(blacklisted_dir) {
+                        tracing::warn!("Failed to add ignore rule for {blacklisted_dir}: {e}");
+                    }
                 }
             }
             result.push(nested_repo);
@@ -73,6 +88,18 @@ async fn open_shadow_repo_and_nested_repos(
     Ok((repo, nested_repos, workspace_folder_hash))
 }
 
+fn filter_out_ignored_changes(repo: &Repository, file_changes: Vec<FileChange>) -> Vec<FileChange> {
+    let workdir = match repo.workdir() {
+        Some(workdir) => workdir.to_path_buf(),
+        None => return file_changes,
+    };
+    let mut ignore_rules = IgnoreRules::new();
+    // deletions pass, so files that became ignored can leave the shadow repo
+    file_changes.into_iter()
+        .filter(|change| change.status == FileChangeStatus::DELETED || !ignore_rules.is_ignored(&workdir.join(&change.relative_path), false))
+        .collect()
+}
+
 fn get_file_changes_from_nested_repos<'a>(
     parent_repo: &'a Repository, nested_repos: &'a [Repository], include_abs_paths: bool
 ) -> Result<(Vec<(&'a Repository, Vec<FileChange>)>, Vec<FileChange>), String> {
@@ -81,7 +108,8 @@ fn get_file_changes_from_nested_repos<'a>(
     let mut file_changes_flatened = Vec::new();
 
     for nested_repo in nested_repos {
-        let nested_repo_changes = get_diff_statuses(DiffStatusType::WorkdirToIndex, nested_repo, include_abs_paths)?;
+        let nested_repo_changes = filter_out_ignored_changes(nested_repo,
+            get_diff_statuses(DiffStatusType::WorkdirToIndex, nested_repo, include_abs_paths)?);
         let nested_repo_workdir = nested_repo.workdir()
             .ok_or("Failed to get nested repo workdir".to_string())?;
         let nested_repo_rel_path = nested_repo_workdir.strip_prefix(repo_workdir).map_err_to_string()?;
@@ -125,7 +153,8 @@ pub async fn create_workspace_checkpoint(
     let checkpoint = {
         let branch = get_or_create_branch(&repo, &format!("refact-{chat_id}"))?;
 
-        let mut file_changes = get_diff_statuses(DiffStatusType::WorkdirToIndex, &repo, false)?;
+        let mut file_changes = filter_out_ignored_changes(&repo,
+            get_diff_statuses(DiffStatusType::WorkdirToIndex, &repo, false)?);
 
         let (nested_file_changes, flatened_nested_file_changes) = 
             get_file_changes_from_nested_repos(&repo, &nested_repos, false)?;
@@ -227,7 +256,8 @@ pub async fn init_shadow_repos_if_needed(gcx: Arc<ARwLock<GlobalContext>>) -> ()
         let t0 = Instant::now();
 
         let initial_commit_result: Result<Oid, String> = (|| {
-            let mut file_changes = get_diff_statuses(DiffStatusType::WorkdirToIndex, &repo, false)?;
+            let mut file_changes = filter_out_ignored_changes(&repo,
+                get_diff_statuses(DiffStatusType::WorkdirToIndex, &repo, false)?);
             let (nested_file_changes, all_nested_changes) = 
                 get_file_changes_from_nested_repos(&repo, &nested_repos, false)?;
             file_changes.extend(all_nested_changes);
@@ -198,6 +198,14 @@ pub fn get_diff_statuses_workdir_to_head(repository: &Repository) -> Result<Vec<
     Ok(result)
 }
 
+// Files that differ from HEAD and still exist on disk, untracked files included
+pub fn get_changed_files_vs_head(repository: &Repository) -> Result<Vec<PathBuf>, String> {
+    Ok(get_diff_statuses_workdir_to_head(repository)?.into_iter()
+        .filter(|x| x.status != FileChangeStatus::DELETED)
+        .map(|x| x.absolute_path)
+        .collect())
+}
+
 pub fn get_diff_statuses_index_to_commit(repository: &Repository, commit_oid: &git2::Oid, include_abs_paths: bool) -> Result<Vec<FileChange>, String> {
     let head = repository.head().map_err_with_prefix("Failed to get HEAD:")?;
     let original_head_ref = head.is_branch().then(|| head.name().map(ToString::to_string)).flatten();
@@ -61,7 +61,7 @@ pub struct CommandLine {
     // pub ast_light_mode: bool,
     #[structopt(long, default_value="50000", help="Maximum files for AST index, to avoid OOM on large projects.")]
     pub ast_max_files: usize,
-    #[structopt(long, default_value="", help="Give it a path for AST database to make it permanent, if there is the database already, process starts without parsing all the files (careful). This quick start is helpful for automated solution search.")]
+    #[structopt(long, default_value="", help="Give it a path for AST database to make it permanent, if there is the database already, process starts without parsing all the files, only the files changed since the last run get parsed again. This quick start is helpful for large projects and automated solution search.")]
     pub ast_permanent: String,
 
     #[cfg(feature="vecdb")]
@@ -71,11 +71,32 @@ pub struct CommandLine {
     #[structopt(long, help="Delete all memories, start with empty memory.")]
     pub reset_memory: bool,
     #[cfg(feature="vecdb")]
+    #[structopt(long, default_value="500", help="Keep at most this many memories per project, the least useful ones are erased first, 0 means no limit.")]
+    pub memories_max_per_project: usize,
+    #[cfg(feature="vecdb")]
+    #[structopt(long, default_value="0.98", help="Each use of a memory multiplies its retention score by this, positive feedback adds to it.")]
+    pub memories_decay_per_use: f64,
+    #[cfg(feature="vecdb")]
+    #[structopt(long, default_value="3", help="Erase a memory when it's marked incorrect this many times more than correct, 0 means never.")]
+    pub memories_prune_incorrect: i32,
+    #[cfg(feature="vecdb")]
+    #[structopt(long, default_value="", help="Import memories from a jsonl file when VecDB starts, memories already present are skipped.")]
+    pub memories_import: String,
+    #[cfg(feature="vecdb")]
+    #[structopt(long, default_value="", help="Export all memories to a jsonl file when VecDB starts, after --memories-import if both are given.")]
+    pub memories_export: String,
+    #[cfg(feature="vecdb")]
     #[structopt(long, default_value="15000", help="Maximum files count for VecDB index, to avoid OOM.")]
     pub vecdb_max_files: usize,
     #[cfg(feature="vecdb")]
     #[structopt(long, default_value="", help="Set VecDB storage path manually.")]
     pub vecdb_force_path: String,
+    #[cfg(feature="vecdb")]
+    #[structopt(long, help="Vectorize one record per AST definition (signature, comments, outline of the body) instead of line windows, search results point to symbols.")]
+    pub vecdb_symbols: bool,
+
+    #[structopt(long, help="Use BM25 lexical index for search() and @search, works without an embedding model. Always on together with --vecdb.")]
+    pub bm25: bool,
 
     #[structopt(long, short="f", default_value="", help="A path to jsonl file with {\"path\": ...} on each line, files will immediately go to VecDB and AST.")]
     pub files_jsonl_path: String,
@@ -158,6 +179,7 @@ pub struct GlobalContext {
     pub vec_db: bool,
     pub vec_db_error: String,
     pub ast_service: Option<Arc<AMutex<AstIndexService>>>,
+    pub bm25_service: Option<Arc<AMutex<crate::bm25::bm25_thread::Bm25IndexService>>>,
     pub ask_shutdown_sender: Arc<StdMutex<std::sync::mpsc::Sender<String>>>,
     pub documents_state: DocumentsState,
     pub at_commands_preview_cache: Arc<AMutex<AtCommandsPreviewCache>>,
@@ -334,8 +356,19 @@ pub async fn create_global_context(
     config_dir: PathBuf,
 ) -> (Arc<ARwLock<GlobalContext>>, std::sync::mpsc::Receiver<String>, Arc<AtomicBool>, CommandLine) {
     let cmdline = CommandLine::from_args();
-    let (ask_shutdown_sender, ask_shutdown_receiver) = std::sync::mpsc::channel::<String>();
+    let (gcx, ask_shutdown_receiver) = global_context_from_cmdline(cmdline.clone(), cache_dir, config_dir).await;
     let shutdown_flag = Arc::new(AtomicBool::new(false));
+    crate::files_in_workspace::watcher_init(gcx.clone()).await;
+    (gcx, ask_shutdown_receiver, shutdown_flag, cmdline)
+}
+
+// Without the file watcher, tests use it directly
+pub async fn global_context_from_cmdline(
+    cmdline: CommandLine,
+    cache_dir: PathBuf,
+    config_dir: PathBuf,
+) -> (Arc<ARwLock<GlobalContext>>, std::sync::mpsc::Receiver<String>) {
+    let (ask_shutdown_sender, ask_shutdown_receiver) = std::sync::mpsc::channel::<String>();
     let mut http_client_builder = reqwest::Client::builder();
     if cmdline.insecure {
         http_client_builder = http_client_builder.danger_accept_invalid_certs(true)
@@ -367,6 +400,7 @@ pub async fn create_global_context(
         vec_db: false,
         vec_db_error: String::new(),
         ast_service: None,
+        bm25_service: None,
         ask_shutdown_sender: Arc::new(StdMutex::new(ask_shutdown_sender)),
         documents_state: DocumentsState::new(workspace_dirs).await,
         at_commands_preview_cache: Arc::new(AMutex::new(AtCommandsPreviewCache::new())),
@@ -375,9 +409,7 @@ pub async fn create_global_context(
         codelens_cache: Arc::new(AMutex::new(crate::http::routers::v1::code_lens::CodeLensCache::default())),
         docker_ssh_tunnel: Arc::new(AMutex::new(None)),
     };
-    let gcx = Arc::new(ARwLock::new(cx));
-    crate::files_in_workspace::watcher_init(gcx.clone()).await;
-    (gcx, ask_shutdown_receiver, shutdown_flag, cmdline)
+    (Arc::new(ARwLock::new(cx)), ask_shutdown_receiver)
 }
 
 pub async fn is_metadata_supported(gcx: Arc<ARwLock<GlobalContext>>) -> bool {
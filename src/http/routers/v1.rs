@@ -15,11 +15,13 @@ use crate::global_context::SharedGlobalContext;
 use crate::http::routers::v1::code_completion::{handle_v1_code_completion_web, handle_v1_code_completion_prompt};
 use crate::http::routers::v1::code_lens::handle_v1_code_lens;
 use crate::http::routers::v1::ast::{handle_v1_ast_file_dump, handle_v1_ast_file_symbols, handle_v1_ast_status};
+use crate::http::routers::v1::diagnostics::handle_v1_diagnostics;
 use crate::http::routers::v1::at_commands::{handle_v1_command_completion, handle_v1_command_preview, handle_v1_at_command_execute};
 use crate::http::routers::v1::at_tools::{handle_v1_tools, handle_v1_tools_check_if_confirmation_needed, handle_v1_tools_execute};
 use crate::http::routers::v1::caps::handle_v1_caps;
 use crate::http::routers::v1::caps::handle_v1_ping;
 use crate::http::routers::v1::chat::{handle_v1_chat, handle_v1_chat_completions};
+use crate::http::routers::v1::chat_sessions::{handle_v1_chats_list, handle_v1_chat_load, handle_v1_chat_rename, handle_v1_chat_delete, handle_v1_chat_fork};
 use crate::http::routers::v1::chat_based_handlers::handle_v1_commit_message_from_diff;
 use crate::http::routers::v1::dashboard::get_dashboard_plots;
 use crate::http::routers::v1::docker::{handle_v1_docker_container_action, handle_v1_docker_container_list};
@@ -42,13 +44,14 @@ use crate::http::routers::v1::system_prompt::handle_v1_prepend_system_prompt_and
 #[cfg(feature="vecdb")]
 use crate::http::routers::v1::vecdb::{handle_v1_vecdb_search, handle_v1_vecdb_status};
 #[cfg(feature="vecdb")]
-use crate::http::routers::v1::handlers_memdb::{handle_mem_query, handle_mem_add, handle_mem_erase, handle_mem_update_used, handle_mem_block_until_vectorized, handle_mem_list};
+use crate::http::routers::v1::handlers_memdb::{handle_mem_query, handle_mem_add, handle_mem_erase, handle_mem_update_used, handle_mem_block_until_vectorized, handle_mem_list, handle_mem_export, handle_mem_import};
 use crate::http::routers::v1::v1_integrations::{handle_v1_integration_get, handle_v1_integration_icon, handle_v1_integration_save, handle_v1_integration_delete, handle_v1_integrations, handle_v1_integrations_filtered, handle_v1_integration_json_schema};
 use crate::http::utils::telemetry_wrapper;
 
 pub mod code_completion;
 pub mod code_lens;
 pub mod chat;
+mod chat_sessions;
 pub mod telemetry_network;
 pub mod telemetry_chat;
 pub mod snippet_accepted;
@@ -62,6 +65,7 @@ pub mod lsp_like_handlers;
 pub mod customization;
 pub mod at_commands;
 mod ast;
+mod diagnostics;
 pub mod at_tools;
 mod status;
 mod subchat;
@@ -88,6 +92,11 @@ pub fn make_v1_router() -> Router {
 
         .route("/chat", telemetry_post!(handle_v1_chat))
         .route("/chat/completions", telemetry_post!(handle_v1_chat_completions))  // standard
+        .route("/chats-list", telemetry_get!(handle_v1_chats_list))
+        .route("/chat-load", telemetry_post!(handle_v1_chat_load))
+        .route("/chat-rename", telemetry_post!(handle_v1_chat_rename))
+        .route("/chat-delete", telemetry_post!(handle_v1_chat_delete))
+        .route("/chat-fork", telemetry_post!(handle_v1_chat_fork))
 
         .route("/telemetry-network", telemetry_post!(handle_v1_telemetry_network))
         .route("/telemetry-chat", telemetry_post!(handle_v1_telemetry_chat))
@@ -108,6 +117,7 @@ pub fn make_v1_router() -> Router {
         .route("/ast-file-symbols", telemetry_post!(handle_v1_ast_file_symbols))
         .route("/ast-file-dump", telemetry_post!(handle_v1_ast_file_dump))
         .route("/ast-status", telemetry_get!(handle_v1_ast_status))
+        .route("/diagnostics", telemetry_post!(handle_v1_diagnostics))
 
         .route("/rag-status", telemetry_get!(handle_v1_rag_status))
         .route("/config-path", telemetry_get!(handle_v1_config_path))
@@ -167,6 +177,8 @@ pub fn make_v1_router() -> Router {
         .route("/mem-update-used", telemetry_post!(handle_mem_update_used))
         .route("/mem-block-until-vectorized", telemetry_get!(handle_mem_block_until_vectorized))
         .route("/mem-list", telemetry_get!(handle_mem_list))
+        .route("/mem-export", telemetry_get!(handle_mem_export))
+        .route("/mem-import", telemetry_post!(handle_mem_import))
         ;
 
     builder.layer(CorsLayer::very_permissive())
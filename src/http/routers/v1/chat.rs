@@ -121,6 +121,14 @@ async fn _chat(
         ScratchError::new(StatusCode::BAD_REQUEST, format!("JSON problem: {}", e))
     })?;
     let mut messages = deserialize_messages_from_post(&chat_post.messages)?;
+    let chats_dir = crate::chat_sessions::chat_sessions_dir(gcx.clone()).await;
+    // waits for the previous request to this chat to save its answer
+    let answer_lock = if chat_post.meta.chat_id.is_empty() { None } else { Some(crate::chat_sessions::chat_session_answer_lock(&chat_post.meta.chat_id).await) };
+    if chat_post.resume_chat {
+        let session = crate::chat_sessions::chat_session_load(&chats_dir, &chat_post.meta.chat_id).await
+            .map_err(|e| ScratchError::new(StatusCode::NOT_FOUND, e))?;
+        messages = session.messages.into_iter().chain(messages.into_iter()).collect();
+    }
 
     tracing::info!("chat_mode {:?}\n", chat_post.meta.chat_mode);
 
@@ -239,6 +247,13 @@ async fn _chat(
         }
     }
 
+    // the chat is saved before the model is called, so a crash loses at most the answer in progress
+    if !chat_post.meta.chat_id.is_empty() {
+        if let Err(e) = crate::chat_sessions::chat_session_save_messages(&chats_dir, &chat_post.meta.chat_id, &model_name, chat_post.meta.chat_mode, &messages).await {
+            tracing::warn!("can't save chat {:?}: {}", chat_post.meta.chat_id, e);
+        }
+    }
+
     // SYSTEM PROMPT WAS HERE
 
 
@@ -258,19 +273,6 @@ async fn _chat(
     ).await.map_err(|e|
         ScratchError::new(StatusCode::BAD_REQUEST, e)
     )?;
-    // if !chat_post.chat_id.is_empty() {
-    //     let cache_dir = {
-    //         let gcx_locked = gcx.read().await;
-    //         gcx_locked.cache_dir.clone()
-    //     };
-    //     let notes_dir_path = cache_dir.join("chats");
-    //     let _ = std::fs::create_dir_all(&notes_dir_path);
-    //     let notes_path = notes_dir_path.join(format!("chat{}_{}.json",
-    //         chrono::Local::now().format("%Y%m%d"),
-    //         chat_post.chat_id,
-    //     ));
-    //     let _ = std::fs::write(&notes_path, serde_json::to_string_pretty(&chat_post.messages).unwrap());
-    // }
     let mut ccx = AtCommandsContext::new(
         gcx.clone(),
         n_ctx,
@@ -284,7 +286,8 @@ async fn _chat(
     ccx.postprocess_parameters = chat_post.postprocess_parameters.clone();
     let ccx_arc = Arc::new(AMutex::new(ccx));
 
-    if chat_post.stream.is_some() && !chat_post.stream.unwrap() {
+    let streaming = !(chat_post.stream.is_some() && !chat_post.stream.unwrap());
+    let response = if !streaming {
         crate::restream::scratchpad_interaction_not_stream(
             ccx_arc.clone(),
             &mut scratchpad,
@@ -304,5 +307,9 @@ async fn _chat(
             chat_post.only_deterministic_messages,
             meta
         ).await
+    }?;
+    match answer_lock {
+        Some(answer_lock) => crate::chat_sessions::chat_session_record_response(gcx.clone(), chat_post.meta.chat_id.clone(), response, streaming, answer_lock).await,
+        None => Ok(response),
     }
 }
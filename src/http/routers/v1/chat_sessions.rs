@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use tokio::sync::RwLock as ARwLock;
+use serde_json::json;
+
+use axum::Extension;
+use axum::response::Result;
+use hyper::{Body, Response, StatusCode};
+use serde::Deserialize;
+use serde::Serialize;
+use crate::custom_error::ScratchError;
+use crate::global_context::GlobalContext;
+use crate::chat_sessions::{chat_sessions_dir, chat_sessions_list, chat_session_load, chat_session_rename, chat_session_delete, chat_session_fork};
+
+
+#[derive(Deserialize)]
+struct ChatIdPost {
+    chat_id: String,
+}
+
+#[derive(Deserialize)]
+struct ChatRenamePost {
+    chat_id: String,
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct ChatForkPost {
+    chat_id: String,
+    message_index: usize,  // this message is the last one in the new chat
+    #[serde(default)]
+    new_chat_id: String,   // generated if empty
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response<Body> {
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(value).unwrap()))
+        .unwrap()
+}
+
+fn parse_post<'a, T: Deserialize<'a>>(body_bytes: &'a hyper::body::Bytes) -> Result<T, ScratchError> {
+    serde_json::from_slice::<T>(body_bytes).map_err(|e| {
+        tracing::info!("cannot parse input:\n{:?}", body_bytes);
+        ScratchError::new(StatusCode::BAD_REQUEST, format!("JSON problem: {}", e))
+    })
+}
+
+pub async fn handle_v1_chats_list(
+    Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
+    _: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let chats = chat_sessions_list(&chat_sessions_dir(gcx).await).await
+        .map_err(|e| ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(json_response(&json!({"chats": chats})))
+}
+
+pub async fn handle_v1_chat_load(
+    Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let post: ChatIdPost = parse_post(&body_bytes)?;
+    let session = chat_session_load(&chat_sessions_dir(gcx).await, &post.chat_id).await
+        .map_err(|e| ScratchError::new(StatusCode::NOT_FOUND, e))?;
+    Ok(json_response(&session))
+}
+
+pub async fn handle_v1_chat_rename(
+    Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let post: ChatRenamePost = parse_post(&body_bytes)?;
+    let info = chat_session_rename(&chat_sessions_dir(gcx).await, &post.chat_id, &post.title).await
+        .map_err(|e| ScratchError::new(StatusCode::NOT_FOUND, e))?;
+    Ok(json_response(&info))
+}
+
+pub async fn handle_v1_chat_delete(
+    Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let post: ChatIdPost = parse_post(&body_bytes)?;
+    chat_session_delete(&chat_sessions_dir(gcx).await, &post.chat_id).await
+        .map_err(|e| ScratchError::new(StatusCode::NOT_FOUND, e))?;
+    Ok(json_response(&json!({})))
+}
+
+pub async fn handle_v1_chat_fork(
+    Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let post: ChatForkPost = parse_post(&body_bytes)?;
+    let new_chat_id = if post.new_chat_id.is_empty() { uuid::Uuid::new_v4().to_string() } else { post.new_chat_id.clone() };
+    let info = chat_session_fork(&chat_sessions_dir(gcx).await, &post.chat_id, post.message_index, &new_chat_id).await
+        .map_err(|e| ScratchError::new(StatusCode::BAD_REQUEST, e))?;
+    Ok(json_response(&info))
+}
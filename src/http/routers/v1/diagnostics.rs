@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+use axum::Extension;
+use axum::response::Result;
+use hyper::{Body, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::custom_error::ScratchError;
+use crate::global_context::SharedGlobalContext;
+
+
+#[derive(Serialize, Deserialize, Clone)]
+struct DiagnosticsPost {
+    file_name: String,
+}
+
+pub async fn handle_v1_diagnostics(
+    Extension(global_context): Extension<SharedGlobalContext>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let post = serde_json::from_slice::<DiagnosticsPost>(&body_bytes).map_err(|e| {
+        ScratchError::new(StatusCode::BAD_REQUEST, format!("JSON problem: {}", e))
+    })?;
+
+    let candidates = crate::files_correction::correct_to_nearest_filename(
+        global_context.clone(),
+        &post.file_name,
+        false,
+        1,
+    ).await;
+    if candidates.len() != 1 {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from(serde_json::to_string_pretty(&json!({"detail": format!("file not found or ambiguous, candidates {:?}", candidates)})).unwrap()))
+            .unwrap());
+    }
+
+    let cpath = PathBuf::from(candidates[0].clone());
+    let diagnostics = crate::ast::diagnostics::diagnostics_for_file(global_context.clone(), &cpath).await.map_err(|e|
+        ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, e)
+    )?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&json!({
+            "file_name": cpath.to_string_lossy(),
+            "diagnostics": diagnostics,
+        })).unwrap()))
+        .unwrap())
+}
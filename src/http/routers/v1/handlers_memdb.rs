@@ -17,6 +17,8 @@ struct MemAddRequest {
     project: String,
     payload: String,
     origin: String,   // TODO: upgrade to serde_json::Value
+    #[serde(default)]
+    scope: String,    // "global", a project remote like github.com/org/repo or a path, or empty for the active project
 }
 
 #[derive(Deserialize)]
@@ -37,6 +39,8 @@ struct MemQuery {
     #[allow(unused)]
     project: String,
     top_n: usize,
+    #[serde(default)]
+    scope: String,    // empty for the active project, global memories are always included, "legacy" for the ones saved before scopes
 }
 
 pub async fn handle_mem_add(
@@ -48,6 +52,11 @@ pub async fn handle_mem_add(
         ScratchError::new(StatusCode::BAD_REQUEST, format!("JSON problem: {}", e))
     })?;
 
+    let scope = if post.scope.is_empty() {
+        crate::vecdb::vdb_highlev::memories_active_scope(gcx.clone()).await
+    } else {
+        post.scope.clone()
+    };
     let vec_db = gcx.read().await.vec_db.clone();
     let memid = crate::vecdb::vdb_highlev::memories_add(
         vec_db,
@@ -55,7 +64,8 @@ pub async fn handle_mem_add(
         &post.goal,
         &post.project,
         &post.payload,
-        &post.origin
+        &post.origin,
+        &scope,
     ).await.map_err(|e| {
         ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e))
     })?;
@@ -151,6 +161,7 @@ pub async fn handle_mem_query(
         gcx.clone(),
         &post.goal,
         post.top_n,
+        Some(post.scope.clone()),
     ).await.map_err(|e| {
         ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{e}"))
     })?;
@@ -170,11 +181,19 @@ pub async fn handle_mem_list(
 ) -> Result<Response<Body>, ScratchError> {
     let vec_db = gcx.read().await.vec_db.clone();
 
-    let memories = crate::vecdb::vdb_highlev::memories_select_all(vec_db).await.map_err(|e| {
+    let (memories, policy) = crate::vecdb::vdb_highlev::memories_select_all_with_retention(vec_db).await.map_err(|e| {
         ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e))
     })?;
 
-    let response_body = serde_json::to_string_pretty(&memories).unwrap();
+    // still a list of memories as before, retention fields are added to each one
+    let memories_json: Vec<serde_json::Value> = memories.into_iter().map(|(m, retention_score, retention_keep)| {
+        let mut j = serde_json::to_value(&m).unwrap();
+        j["retention_score"] = json!(retention_score);
+        j["retention_keep"] = json!(retention_keep);
+        j["retention_max_per_project"] = json!(policy.max_per_scope);
+        j
+    }).collect();
+    let response_body = serde_json::to_string_pretty(&memories_json).unwrap();
 
     let response = Response::builder()
         .header("Content-Type", "application/json")
@@ -184,3 +203,42 @@ pub async fn handle_mem_list(
     Ok(response)
 }
 
+
+pub async fn handle_mem_export(
+    Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
+    _body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let vec_db = gcx.read().await.vec_db.clone();
+    let jsonl = crate::vecdb::vdb_highlev::memories_export(vec_db).await.map_err(|e| {
+        ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e))
+    })?;
+
+    let response = Response::builder()
+        .header("Content-Type", "application/x-ndjson")
+        .body(Body::from(jsonl))
+        .unwrap();
+
+    Ok(response)
+}
+
+pub async fn handle_mem_import(
+    Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let jsonl = String::from_utf8(body_bytes.to_vec()).map_err(|e| {
+        ScratchError::new(StatusCode::BAD_REQUEST, format!("body is not utf-8: {}", e))
+    })?;
+
+    let default_scope = crate::vecdb::vdb_highlev::memories_import_scope(gcx.clone()).await;
+    let vec_db = gcx.read().await.vec_db.clone();
+    let (imported_cnt, duplicates_cnt) = crate::vecdb::vdb_highlev::memories_import(vec_db, &jsonl, &default_scope).await.map_err(|e| {
+        ScratchError::new(StatusCode::BAD_REQUEST, format!("{}", e))
+    })?;
+
+    let response = Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&json!({"imported": imported_cnt, "duplicates": duplicates_cnt})).unwrap()))
+        .unwrap();
+
+    Ok(response)
+}
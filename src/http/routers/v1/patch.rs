@@ -15,7 +15,8 @@ use crate::global_context::GlobalContext;
 use crate::http::routers::v1::chat::deserialize_messages_from_post;
 use crate::tools::tool_patch_aux::tickets_parsing::{correct_and_validate_active_ticket, get_and_correct_active_tickets, get_tickets_from_messages, TicketToApply};
 use crate::tools::tool_patch::process_tickets;
-use crate::tools::tool_patch_aux::diff_apply::diff_apply;
+use crate::tools::tool_patch_aux::diff_apply::diff_apply_transaction;
+use crate::tools::tool_patch_aux::patch_transaction::PatchTransactionSummary;
 use crate::tools::tool_patch_aux::postprocessing_utils::fill_out_already_applied_status;
 use crate::tools::tools_execute::unwrap_subchat_params;
 
@@ -29,6 +30,8 @@ pub struct PatchPost {
 #[derive(Deserialize)]
 pub struct PatchApplyAllPost {
     pub messages: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub checkpoint_chat_id: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -41,6 +44,7 @@ pub struct PatchResponse {
 #[derive(Serialize)]
 pub struct PatchApplyAllResponse {
     chunks: Vec<DiffChunk>,
+    summary: PatchTransactionSummary,
 }
 
 pub fn resolve_diff_apply_outputs(
@@ -209,20 +213,24 @@ pub async fn handle_v1_patch_apply_all(
                 StatusCode::UNPROCESSABLE_ENTITY, format!("Couldn't process some of the tickets: {bad_ticket_ids}"
                 )))
         }
-        let mut diff_chunks = diff_chunks_maybe.map_err(|(e, _)|
+        let diff_chunks = diff_chunks_maybe.map_err(|(e, _)|
             ScratchError::new(StatusCode::UNPROCESSABLE_ENTITY, e)
         )?;
-        diff_apply(global_context.clone(), &mut diff_chunks).await.map_err(|err| ScratchError::new(
-            StatusCode::UNPROCESSABLE_ENTITY, format!("Couldn't apply the diff: {err}"))
-        )?;
         all_diff_chunks.extend(diff_chunks);
     }
 
+    // all files are applied in one transaction: either everything lands on disk or nothing does
+    let summary = diff_apply_transaction(global_context.clone(), &mut all_diff_chunks, post.checkpoint_chat_id.clone()).await
+        .map_err(|err| ScratchError::new(
+            StatusCode::UNPROCESSABLE_ENTITY, format!("Couldn't apply the diff: {err}"))
+        )?;
+
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
         .body(Body::from(serde_json::to_string_pretty(&PatchApplyAllResponse {
-            chunks: all_diff_chunks
+            chunks: all_diff_chunks,
+            summary,
         }).unwrap()))
         .unwrap())
 }
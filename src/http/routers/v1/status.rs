@@ -4,6 +4,7 @@ use hyper::{Body, Response, StatusCode};
 use serde::Serialize;
 
 use crate::ast::ast_structs::AstStatus;
+use crate::bm25::bm25_thread::Bm25Status;
 use crate::custom_error::ScratchError;
 use crate::global_context::SharedGlobalContext;
 
@@ -15,15 +16,17 @@ struct RagStatus {
     vecdb: Option<crate::vecdb::vdb_structs::VecDbStatus>,
     vecdb_alive: String,
     vec_db_error: String,
+    bm25: Option<Bm25Status>,
+    bm25_alive: String,
 }
 
 pub async fn handle_v1_rag_status(
     Extension(gcx): Extension<SharedGlobalContext>,
     _: hyper::body::Bytes,
 ) -> Result<Response<Body>, ScratchError> {
-    let (vec_db_module, vec_db_error, ast_module) = {
+    let (vec_db_module, vec_db_error, ast_module, bm25_module) = {
         let gcx_locked = gcx.write().await;
-        (gcx_locked.vec_db.clone(), gcx_locked.vec_db_error.clone(), gcx_locked.ast_service.clone())
+        (gcx_locked.vec_db.clone(), gcx_locked.vec_db_error.clone(), gcx_locked.ast_service.clone(), gcx_locked.bm25_service.clone())
     };
 
     #[cfg(feature="vecdb")]
@@ -45,6 +48,15 @@ pub async fn handle_v1_rag_status(
         None => (None, "turned_off".to_string())
     };
 
+    let (maybe_bm25_status, bm25_message) = match &bm25_module {
+        Some(bm25_service) => {
+            let bm25_status = bm25_service.lock().await.bm25_status.clone();
+            let status = bm25_status.lock().await.clone();
+            (Some(status), "working".to_string())
+        }
+        None => (None, "turned_off".to_string())
+    };
+
     let status = RagStatus {
         ast: maybe_ast_status,
         ast_alive: ast_message,
@@ -52,6 +64,8 @@ pub async fn handle_v1_rag_status(
         vecdb: maybe_vecdb_status,
         vecdb_alive: vecdb_message,
         vec_db_error,
+        bm25: maybe_bm25_status,
+        bm25_alive: bm25_message,
     };
 
     let json_string = serde_json::to_string_pretty(&status).map_err(|e| {
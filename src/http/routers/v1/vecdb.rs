@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use crate::caps::get_custom_embedding_api_key;
 use crate::custom_error::ScratchError;
 use crate::global_context::SharedGlobalContext;
+use crate::search_filter::SearchFilter;
 use crate::vecdb::vdb_structs::VecdbSearch;
 
 
@@ -13,6 +14,8 @@ use crate::vecdb::vdb_structs::VecdbSearch;
 struct VecDBPost {
     query: String,
     top_n: usize,
+    #[serde(default)]
+    filter: Option<SearchFilter>,
 }
 
 const NO_VECDB: &str = "Vector db is not running, check if you have --vecdb parameter and a vectorization model is running on server side.";
@@ -26,11 +29,17 @@ pub async fn handle_v1_vecdb_search(
         ScratchError::new(StatusCode::BAD_REQUEST, format!("JSON problem: {}", e))
     })?;
 
-    let api_key = get_custom_embedding_api_key(gcx.clone()).await?;
-    let cx_locked = gcx.read().await;
+    let resolved_filter = match &post.filter {
+        Some(filter) => Some(filter.resolve(gcx.clone()).await.map_err(|e| ScratchError::new(StatusCode::BAD_REQUEST, e))?),
+        None => None,
+    };
+    let fetch_n = resolved_filter.as_ref().map(|f| f.fetch_n(post.top_n)).unwrap_or(post.top_n);
+    let scope_filter_mb = resolved_filter.as_ref().and_then(|f| f.pushdown.clone());
 
-    let search_res = match *cx_locked.vec_db.lock().await {
-        Some(ref db) => db.vecdb_search(post.query.to_string(), post.top_n, None, &api_key).await,
+    let api_key = get_custom_embedding_api_key(gcx.clone()).await?;
+    let vec_db = gcx.read().await.vec_db.clone();
+    let search_res = match *vec_db.lock().await {
+        Some(ref db) => db.vecdb_search(post.query.to_string(), fetch_n, scope_filter_mb, &api_key).await,
         None => {
             return Err(ScratchError::new(
                 StatusCode::INTERNAL_SERVER_ERROR, NO_VECDB.to_string(),
@@ -39,7 +48,11 @@ pub async fn handle_v1_vecdb_search(
     };
 
     match search_res {
-        Ok(search_res) => {
+        Ok(mut search_res) => {
+            if let Some(filter) = &resolved_filter {
+                search_res.results = filter.retain(gcx.clone(), search_res.results, |r| (r.file_path.to_string_lossy().to_string(), r.start_line, r.end_line)).await;
+                search_res.results.truncate(post.top_n);
+            }
             let json_string = serde_json::to_string_pretty(&search_res).map_err(|e| {
                 ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("JSON serialization problem: {}", e))
             })?;
@@ -1,7 +1,13 @@
+use std::sync::Arc;
+use async_trait::async_trait;
 use serde::Deserialize;
 use serde::Serialize;
+use tokio::sync::RwLock as ARwLock;
 
+use crate::global_context::GlobalContext;
 
+
+#[async_trait]
 pub trait IntegrationTrait: Send + Sync {
     fn as_any(&self) -> &dyn std::any::Any;
     fn integr_schema(&self) -> &str;
@@ -9,6 +15,10 @@ pub trait IntegrationTrait: Send + Sync {
     fn integr_settings_as_json(&self) -> serde_json::Value;
     fn integr_common(&self) -> IntegrationCommon;
     fn integr_tools(&self, integr_name: &str) -> Vec<Box<dyn crate::tools::tools_description::Tool + Send>>;  // integr_name is sometimes different, "cmdline_compile_my_project" != "cmdline"
+    // Tools known only to a running session, like the ones an MCP server lists. Should return what's cached without waiting for the session to start
+    async fn integr_session_tools(&self, _gcx: Arc<ARwLock<GlobalContext>>, _integr_name: &str) -> Vec<Box<dyn crate::tools::tools_description::Tool + Send>> {
+        vec![]
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Default)]
@@ -297,6 +297,7 @@ impl Tool for ToolChrome {
                 name: "commands".to_string(),
                 param_type: "string".to_string(),
                 description,
+                schema: None,
             }],
             parameters_required: vec!["commands".to_string()],
         }
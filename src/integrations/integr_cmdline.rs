@@ -10,12 +10,14 @@ use tokio::process::Command;
 use tracing::info;
 
 use crate::at_commands::at_commands::AtCommandsContext;
-use crate::tools::tools_description::{ToolParam, Tool, ToolDesc};
+use crate::tools::tools_description::{ToolParam, Tool, ToolDesc, MatchConfirmDeny};
 use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
 use crate::postprocessing::pp_command_output::{CmdlineOutputFilter, output_mini_postprocessing};
 use crate::integrations::integr_abstract::{IntegrationTrait, IntegrationCommon, IntegrationConfirmation};
 use crate::integrations::utils::{serialize_num_to_str, deserialize_str_to_num, serialize_opt_num_to_str, deserialize_str_to_opt_num};
 use crate::integrations::setting_up_integrations::YamlError;
+use crate::integrations::shell_ast::match_shell_command_against_rules;
+use crate::integrations::sandbox::{SandboxSettings, sandbox_command};
 
 
 #[derive(Deserialize, Serialize, Clone, Default)]
@@ -42,6 +44,9 @@ pub struct CmdlineToolConfig {
     pub startup_wait: u64,
     #[serde(default)]
     pub startup_wait_keyword: String,
+
+    #[serde(flatten)]
+    pub sandbox: SandboxSettings,
 }
 
 fn _default_startup_wait() -> u64 {
@@ -172,7 +177,8 @@ pub async fn execute_blocking_command(
     info!("EXEC workdir {:?}:\n{:?}", command_workdir, command);
 
     let command_future = async {
-        let mut cmd = create_command_from_string(command, command_workdir, env_variables, project_dirs)?;
+        let mut cmd = create_command_from_string(command, command_workdir, env_variables, project_dirs.clone())?;
+        sandbox_command(&mut cmd, &cfg.sandbox, &project_dirs)?;
         let t0 = tokio::time::Instant::now();
         let result = cmd
             .stdout(Stdio::piped())
@@ -283,6 +289,17 @@ impl Tool for ToolCmdline {
         }
     }
 
+    async fn match_against_confirm_deny(
+        &self,
+        _ccx: Arc<AMutex<AtCommandsContext>>,
+        args: &HashMap<String, serde_json::Value>,
+    ) -> Result<MatchConfirmDeny, String> {
+        let command_to_match = self.command_to_match_against_confirm_deny(&args).map_err(|e| {
+            format!("Error getting tool command to match: {}", e)
+        })?;
+        Ok(match_shell_command_against_rules(&command_to_match, &self.common.confirmation))
+    }
+
     fn command_to_match_against_confirm_deny(
         &self,
         args: &HashMap<String, serde_json::Value>,
@@ -325,6 +342,29 @@ fields:
     f_desc: "The output from the command can be long or even quasi-infinite. This section allows to set limits, prioritize top or bottom, or use regexp to show the model the relevant part."
     f_placeholder: "filter"
     f_extra: true
+  sandbox:
+    f_type: bool
+    f_desc: "Run the command in a lightweight sandbox (Linux only): the workspace except .git and .refact and the temp dir are writable, the rest of the filesystem is read-only. With the sandbox on, the command runs without confirmation unless it matches ask_user rules."
+    f_extra: true
+  sandbox_network:
+    f_type: bool
+    f_desc: "Allow network access inside the sandbox."
+    f_extra: true
+  sandbox_writable_paths:
+    f_type: string_long
+    f_desc: "Comma separated paths that are writable inside the sandbox, in addition to the workspace and the temp dir."
+    f_placeholder: "~/.cache, ~/.cargo"
+    f_extra: true
+  sandbox_cpu_seconds:
+    f_type: string_short
+    f_desc: "CPU time limit for the sandboxed command, in seconds."
+    f_placeholder: "60"
+    f_extra: true
+  sandbox_memory_mb:
+    f_type: string_short
+    f_desc: "Memory (address space) limit for the sandboxed command, in megabytes."
+    f_placeholder: "2048"
+    f_extra: true
 description: |
   There you can adapt any command line tool for use by AI model. You can give the model instructions why to call it, which parameters to provide,
   set a timeout and restrict the output. If you want a tool that runs in the background such as a web server, use service_* instead.
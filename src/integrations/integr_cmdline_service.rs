@@ -174,7 +174,8 @@ async fn execute_background_command(
         actions_log.push_str(&format!("Starting service with the following command line:\n{}\n", command_str));
         let project_dirs = crate::files_correction::get_project_dirs(gcx.clone()).await;
 
-        let mut command = create_command_from_string(&command_str, cmdline_workdir, env_variables, project_dirs)?;
+        let mut command = create_command_from_string(&command_str, cmdline_workdir, env_variables, project_dirs.clone())?;
+        crate::integrations::sandbox::sandbox_command(&mut command, &cfg.sandbox, &project_dirs)?;
         command.stdout(Stdio::piped());
         command.stderr(Stdio::piped());
         let mut command_wrap = TokioCommandWrap::from(command);
@@ -322,6 +323,7 @@ impl Tool for ToolService {
             name: "action".to_string(),
             param_type: "string".to_string(),
             description: "Action to perform: start, restart, stop, status".to_string(),
+            schema: None,
         });
 
         let parameters_required = self.cfg.parameters_required.clone().unwrap_or_else(|| {
@@ -375,6 +377,29 @@ fields:
     f_type: string
     f_desc: "Wait until a keyword appears in stdout or stderr at startup."
     f_placeholder: "Ready"
+  sandbox:
+    f_type: bool
+    f_desc: "Run the command in a lightweight sandbox (Linux only): the workspace except .git and .refact and the temp dir are writable, the rest of the filesystem is read-only."
+    f_extra: true
+  sandbox_network:
+    f_type: bool
+    f_desc: "Allow network access inside the sandbox."
+    f_extra: true
+  sandbox_writable_paths:
+    f_type: string_long
+    f_desc: "Comma separated paths that are writable inside the sandbox, in addition to the workspace and the temp dir."
+    f_placeholder: "~/.cache, ~/.cargo"
+    f_extra: true
+  sandbox_cpu_seconds:
+    f_type: string_short
+    f_desc: "CPU time limit for the sandboxed command, in seconds."
+    f_placeholder: "60"
+    f_extra: true
+  sandbox_memory_mb:
+    f_type: string_short
+    f_desc: "Memory (address space) limit for the sandboxed command, in megabytes."
+    f_placeholder: "2048"
+    f_extra: true
 description: |
   As opposed to command line argumenets
 
@@ -0,0 +1,971 @@
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use async_trait::async_trait;
+use futures::StreamExt;
+use process_wrap::tokio::*;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE};
+use reqwest_eventsource::{Event, EventSource};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::ChildStdin;
+use tokio::sync::{oneshot, Mutex as AMutex, RwLock as ARwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatContent, ChatMessage, ContextEnum};
+use crate::global_context::GlobalContext;
+use crate::integrations::integr_abstract::{IntegrationCommon, IntegrationConfirmation, IntegrationTrait};
+use crate::integrations::integr_cmdline::create_command_from_string;
+use crate::integrations::sessions::{get_session_hashmap_key, IntegrationSession};
+use crate::integrations::setting_up_integrations::YamlError;
+use crate::integrations::utils::{serialize_num_to_str, deserialize_str_to_num};
+use crate::tools::tools_description::{Tool, ToolDesc, ToolParam};
+
+
+const MCP_PROTOCOL_VERSION: &str = "2025-03-26";
+const MCP_RETRY_AFTER_FAILURE: Duration = Duration::from_secs(60);
+const MCP_STDERR_TAIL_CHARS: usize = 2000;
+const MCP_TOOL_NAME_MAX_LEN: usize = 64;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct SettingsMCP {
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub command_workdir: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub transport: String,  // "sse" or "http", empty means sse if the url ends with /sse, http otherwise
+    #[serde(default)]
+    pub headers: String,    // one "Name: value" per line
+    #[serde(default = "_default_init_timeout", serialize_with = "serialize_num_to_str", deserialize_with = "deserialize_str_to_num")]
+    pub init_timeout: u64,
+    #[serde(default = "_default_request_timeout", serialize_with = "serialize_num_to_str", deserialize_with = "deserialize_str_to_num")]
+    pub request_timeout: u64,
+}
+
+fn _default_init_timeout() -> u64 { 30 }
+fn _default_request_timeout() -> u64 { 120 }
+
+impl Default for SettingsMCP {
+    fn default() -> Self {
+        SettingsMCP {
+            command: String::new(),
+            command_workdir: String::new(),
+            url: String::new(),
+            transport: String::new(),
+            headers: String::new(),
+            init_timeout: _default_init_timeout(),
+            request_timeout: _default_request_timeout(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct IntegrationMCP {
+    pub common: IntegrationCommon,
+    pub cfg: SettingsMCP,
+    pub config_path: String,
+}
+
+#[async_trait]
+impl IntegrationTrait for IntegrationMCP {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn integr_settings_apply(&mut self, value: &Value, config_path: String) -> Result<(), String> {
+        match serde_json::from_value::<SettingsMCP>(value.clone()) {
+            Ok(x) => self.cfg = x,
+            Err(e) => {
+                tracing::error!("Failed to apply settings: {}\n{:?}", e, value);
+                return Err(e.to_string());
+            }
+        }
+        match serde_json::from_value::<IntegrationCommon>(value.clone()) {
+            Ok(x) => self.common = x,
+            Err(e) => {
+                tracing::error!("Failed to apply common settings: {}\n{:?}", e, value);
+                return Err(e.to_string());
+            }
+        }
+        self.config_path = config_path;
+        Ok(())
+    }
+
+    fn integr_settings_as_json(&self) -> Value {
+        serde_json::to_value(&self.cfg).unwrap()
+    }
+
+    fn integr_common(&self) -> IntegrationCommon {
+        self.common.clone()
+    }
+
+    fn integr_tools(&self, _integr_name: &str) -> Vec<Box<dyn Tool + Send>> {
+        vec![]  // tools come from the server, see integr_session_tools()
+    }
+
+    async fn integr_session_tools(&self, gcx: Arc<ARwLock<GlobalContext>>, integr_name: &str) -> Vec<Box<dyn Tool + Send>> {
+        let mcp_tools = mcp_session_cached_tools(gcx, integr_name, &self.cfg).await;
+        mcp_tools.into_iter().map(|mcp_tool| Box::new(ToolMCP {
+            common: self.common.clone(),
+            cfg: self.cfg.clone(),
+            config_path: self.config_path.clone(),
+            integr_name: integr_name.to_string(),
+            tool_name: mcp_tool_name(integr_name, &mcp_tool.name),
+            mcp_tool,
+        }) as Box<dyn Tool + Send>).collect()
+    }
+
+    fn integr_schema(&self) -> &str {
+        MCP_INTEGRATION_SCHEMA
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct McpTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default, rename = "inputSchema")]
+    pub input_schema: Value,
+}
+
+// Everything the background tasks need to route incoming messages
+#[derive(Default)]
+struct McpShared {
+    pending: StdMutex<HashMap<u64, oneshot::Sender<Result<Value, String>>>>,
+    alive: AtomicBool,
+    tools_changed: AtomicBool,
+    stderr_tail: StdMutex<String>,
+}
+
+impl McpShared {
+    // Returns messages to send back, for the requests that come from the server
+    fn dispatch_incoming(&self, msg: Value) -> Vec<Value> {
+        if let Value::Array(batch) = msg {
+            return batch.into_iter().flat_map(|m| self.dispatch_incoming(m)).collect();
+        }
+        let method = msg.get("method").and_then(|x| x.as_str()).unwrap_or("");
+        let id = msg.get("id").cloned().unwrap_or(Value::Null);
+        if method.is_empty() {
+            let Some(id) = id.as_u64() else {
+                tracing::warn!("MCP reply with unexpected id: {}", msg);
+                return vec![];
+            };
+            let reply = match msg.get("error") {
+                Some(err) => Err(format!(
+                    "MCP error {}: {}",
+                    err.get("code").and_then(|x| x.as_i64()).unwrap_or(0),
+                    err.get("message").and_then(|x| x.as_str()).unwrap_or("unknown error"),
+                )),
+                None => Ok(msg.get("result").cloned().unwrap_or(Value::Null)),
+            };
+            if let Some(sender) = self.pending.lock().unwrap().remove(&id) {
+                let _ = sender.send(reply);
+            }
+            return vec![];
+        }
+        if id.is_null() {
+            if method == "notifications/tools/list_changed" {
+                self.tools_changed.store(true, Ordering::SeqCst);
+            }
+            return vec![];
+        }
+        if method == "ping" {
+            return vec![json!({"jsonrpc": "2.0", "id": id, "result": {}})];
+        }
+        vec![json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32601, "message": format!("method {} is not supported", method)}})]
+    }
+
+    fn disconnected(&self) {
+        self.alive.store(false, Ordering::SeqCst);
+        self.pending.lock().unwrap().clear();  // dropped senders wake up the waiting requests
+    }
+
+    fn stderr_push(&self, line: &str) {
+        let mut tail = self.stderr_tail.lock().unwrap();
+        tail.push_str(line);
+        tail.push('\n');
+        if tail.len() > MCP_STDERR_TAIL_CHARS {
+            let cut = tail.len() - MCP_STDERR_TAIL_CHARS;
+            let cut = (cut..tail.len()).find(|i| tail.is_char_boundary(*i)).unwrap_or(tail.len());
+            tail.drain(..cut);
+        }
+    }
+}
+
+enum McpTransport {
+    Stdio {
+        stdin: Arc<AMutex<ChildStdin>>,
+        process: AMutex<Box<dyn TokioChildWrapper>>,
+    },
+    Sse {
+        http: reqwest::Client,
+        headers: HeaderMap,
+        post_url: String,
+    },
+    StreamableHttp {
+        http: reqwest::Client,
+        headers: HeaderMap,
+        url: String,
+        session_id: AMutex<Option<String>>,
+    },
+}
+
+pub struct McpClient {
+    transport: McpTransport,
+    shared: Arc<McpShared>,
+    next_id: AtomicU64,
+    request_timeout: Duration,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Drop for McpClient {
+    fn drop(&mut self) {
+        for t in self.tasks.iter() {
+            t.abort();
+        }
+    }
+}
+
+fn parse_headers(headers: &str) -> Result<HeaderMap, String> {
+    let mut result = HeaderMap::new();
+    for line in headers.lines().map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        let (name, value) = line.split_once(':').ok_or(format!("header should look like \"Name: value\", got {:?}", line))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| format!("bad header name {:?}: {}", name, e))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|e| format!("bad header value for {}: {}", name, e))?;
+        result.insert(name, value);
+    }
+    Ok(result)
+}
+
+// Data lines of "text/event-stream" events, complete events are removed from the buffer
+fn sse_take_events(buf: &mut String) -> Vec<String> {
+    let mut events = vec![];
+    loop {
+        let normalized = buf.replace("\r\n", "\n");
+        let Some(end) = normalized.find("\n\n") else {
+            *buf = normalized;
+            break;
+        };
+        let data = normalized[..end].lines()
+            .filter_map(|l| l.strip_prefix("data:"))
+            .map(|l| l.strip_prefix(' ').unwrap_or(l))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !data.is_empty() {
+            events.push(data);
+        }
+        *buf = normalized[end + 2..].to_string();
+    }
+    events
+}
+
+impl McpClient {
+    async fn connect(
+        cfg: &SettingsMCP,
+        env_variables: &HashMap<String, String>,
+        project_dirs: Vec<std::path::PathBuf>,
+    ) -> Result<McpClient, String> {
+        let shared = Arc::new(McpShared::default());
+        shared.alive.store(true, Ordering::SeqCst);
+        let init_timeout = Duration::from_secs(cfg.init_timeout);
+        let mut tasks = vec![];
+        let transport = if !cfg.command.is_empty() {
+            let mut command = create_command_from_string(&cfg.command, &cfg.command_workdir, env_variables, project_dirs)?;
+            command.stdin(Stdio::piped());
+            command.stdout(Stdio::piped());
+            command.stderr(Stdio::piped());
+            command.kill_on_drop(true);
+            let mut command_wrap = TokioCommandWrap::from(command);
+            #[cfg(unix)]
+            command_wrap.wrap(ProcessGroup::leader());
+            #[cfg(windows)]
+            command_wrap.wrap(JobObject);
+            let mut process = command_wrap.spawn().map_err(|e| format!("failed to start {:?}: {}", cfg.command, e))?;
+            let stdin = Arc::new(AMutex::new(process.stdin().take().ok_or("Failed to open stdin")?));
+            let stdout = process.stdout().take().ok_or("Failed to open stdout")?;
+            let stderr = process.stderr().take().ok_or("Failed to open stderr")?;
+
+            let shared_c = shared.clone();
+            let stdin_c = stdin.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut lines = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let msg = match serde_json::from_str::<Value>(&line) {
+                        Ok(x) => x,
+                        Err(_) => {
+                            tracing::warn!("MCP server printed not a json-rpc message: {}", crate::nicer_logs::first_n_chars(&line, 200));
+                            continue;
+                        }
+                    };
+                    for reply in shared_c.dispatch_incoming(msg) {
+                        let _ = stdio_write(&stdin_c, &reply).await;
+                    }
+                }
+                shared_c.disconnected();
+            }));
+            let shared_c = shared.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    shared_c.stderr_push(&line);
+                }
+            }));
+            McpTransport::Stdio { stdin, process: AMutex::new(process) }
+
+        } else if !cfg.url.is_empty() {
+            let http = reqwest::Client::new();
+            let headers = parse_headers(&cfg.headers)?;
+            let use_sse = match cfg.transport.as_str() {
+                "sse" => true,
+                "http" => false,
+                "" => cfg.url.trim_end_matches('/').ends_with("/sse"),
+                other => return Err(format!("unknown transport {:?}, use \"sse\" or \"http\"", other)),
+            };
+            if use_sse {
+                let base_url = url::Url::parse(&cfg.url).map_err(|e| format!("bad url {:?}: {}", cfg.url, e))?;
+                let mut event_source = EventSource::new(http.get(&cfg.url).headers(headers.clone()))
+                    .map_err(|e| format!("can't create event source: {}", e))?;
+                event_source.set_retry_policy(Box::new(reqwest_eventsource::retry::Never));
+                let (endpoint_tx, endpoint_rx) = oneshot::channel::<String>();
+                let shared_c = shared.clone();
+                let http_c = http.clone();
+                let headers_c = headers.clone();
+                tasks.push(tokio::spawn(async move {
+                    let mut endpoint_tx = Some(endpoint_tx);
+                    let mut post_url = String::new();
+                    while let Some(event) = event_source.next().await {
+                        let message = match event {
+                            Ok(Event::Open) => continue,
+                            Ok(Event::Message(message)) => message,
+                            Err(e) => {
+                                tracing::warn!("MCP sse stream ended: {}", e);
+                                break;
+                            }
+                        };
+                        if message.event == "endpoint" {
+                            post_url = base_url.join(message.data.trim()).map(|x| x.to_string()).unwrap_or_default();
+                            if let Some(tx) = endpoint_tx.take() {
+                                let _ = tx.send(post_url.clone());
+                            }
+                            continue;
+                        }
+                        let Ok(msg) = serde_json::from_str::<Value>(&message.data) else {
+                            tracing::warn!("MCP sse event is not json: {}", crate::nicer_logs::first_n_chars(&message.data, 200));
+                            continue;
+                        };
+                        for reply in shared_c.dispatch_incoming(msg) {
+                            let _ = http_c.post(&post_url).headers(headers_c.clone()).json(&reply).send().await;
+                        }
+                    }
+                    event_source.close();
+                    shared_c.disconnected();
+                }));
+                let post_url = tokio::time::timeout(init_timeout, endpoint_rx).await
+                    .map_err(|_| format!("timeout waiting for the endpoint event from {}", cfg.url))?
+                    .map_err(|_| format!("{} closed the stream without sending the endpoint event", cfg.url))?;
+                McpTransport::Sse { http, headers, post_url }
+            } else {
+                McpTransport::StreamableHttp { http, headers, url: cfg.url.clone(), session_id: AMutex::new(None) }
+            }
+
+        } else {
+            return Err("either command or url should be set".to_string());
+        };
+
+        let client = McpClient {
+            transport,
+            shared,
+            next_id: AtomicU64::new(1),
+            request_timeout: Duration::from_secs(cfg.request_timeout),
+            tasks,
+        };
+        client.request_with_timeout("initialize", json!({
+            "protocolVersion": MCP_PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": {"name": "refact-lsp", "version": crate::version::build_info::PKG_VERSION},
+        }), init_timeout).await.map_err(|e| client.with_stderr(e))?;
+        client.send(&json!({"jsonrpc": "2.0", "method": "notifications/initialized"})).await?;
+        Ok(client)
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.shared.alive.load(Ordering::SeqCst)
+    }
+
+    fn with_stderr(&self, error: String) -> String {
+        let tail = self.shared.stderr_tail.lock().unwrap().clone();
+        if tail.trim().is_empty() {
+            error
+        } else {
+            format!("{}\nserver stderr:\n{}", error, tail)
+        }
+    }
+
+    async fn send(&self, msg: &Value) -> Result<(), String> {
+        match &self.transport {
+            McpTransport::Stdio { stdin, .. } => stdio_write(stdin, msg).await,
+            McpTransport::Sse { http, headers, post_url } => {
+                let resp = http.post(post_url).headers(headers.clone()).json(msg).send().await.map_err(|e| e.to_string())?;
+                if !resp.status().is_success() {
+                    return Err(format!("{} returned {}", post_url, resp.status()));
+                }
+                Ok(())
+            },
+            McpTransport::StreamableHttp { http, headers, url, session_id } => {
+                let mut req = http.post(url).headers(headers.clone())
+                    .header(ACCEPT, "application/json, text/event-stream")
+                    .json(msg);
+                if let Some(sid) = session_id.lock().await.as_ref() {
+                    req = req.header("Mcp-Session-Id", sid);
+                }
+                let resp = req.send().await.map_err(|e| e.to_string())?;
+                if !resp.status().is_success() {
+                    let status = resp.status();
+                    let body = resp.text().await.unwrap_or_default();
+                    return Err(format!("{} returned {} {}", url, status, crate::nicer_logs::first_n_chars(&body, 200)));
+                }
+                if let Some(sid) = resp.headers().get("Mcp-Session-Id").and_then(|x| x.to_str().ok()) {
+                    *session_id.lock().await = Some(sid.to_string());
+                }
+                let is_sse = resp.headers().get(CONTENT_TYPE).and_then(|x| x.to_str().ok())
+                    .map(|x| x.starts_with("text/event-stream")).unwrap_or(false);
+                let mut replies = vec![];
+                if is_sse {
+                    let waiting_id = msg.get("id").and_then(|x| x.as_u64());
+                    let mut stream = resp.bytes_stream();
+                    let mut buf = String::new();
+                    while let Some(chunk) = stream.next().await {
+                        buf.push_str(&String::from_utf8_lossy(&chunk.map_err(|e| e.to_string())?));
+                        for data in sse_take_events(&mut buf) {
+                            if let Ok(incoming) = serde_json::from_str::<Value>(&data) {
+                                replies.extend(self.shared.dispatch_incoming(incoming));
+                            }
+                        }
+                        if waiting_id.map(|id| !self.shared.pending.lock().unwrap().contains_key(&id)).unwrap_or(true) {
+                            break;
+                        }
+                    }
+                } else {
+                    let body = resp.text().await.map_err(|e| e.to_string())?;
+                    if !body.trim().is_empty() {
+                        let incoming = serde_json::from_str::<Value>(&body).map_err(|e| format!("reply is not json: {}", e))?;
+                        replies.extend(self.shared.dispatch_incoming(incoming));
+                    }
+                }
+                for reply in replies {
+                    let mut req = http.post(url).headers(headers.clone()).json(&reply);
+                    if let Some(sid) = session_id.lock().await.as_ref() {
+                        req = req.header("Mcp-Session-Id", sid);
+                    }
+                    let _ = req.send().await;
+                }
+                Ok(())
+            },
+        }
+    }
+
+    async fn request_with_timeout(&self, method: &str, params: Value, timeout: Duration) -> Result<Value, String> {
+        if !self.is_alive() {
+            return Err("MCP server is not running".to_string());
+        }
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.shared.pending.lock().unwrap().insert(id, tx);
+        let msg = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        let result = tokio::time::timeout(timeout, async {
+            self.send(&msg).await?;
+            rx.await.map_err(|_| "MCP server disconnected".to_string())?
+        }).await;
+        self.shared.pending.lock().unwrap().remove(&id);
+        match result {
+            Ok(x) => x,
+            Err(_) => Err(format!("timeout {}s waiting for {}", timeout.as_secs(), method)),
+        }
+    }
+
+    pub async fn list_tools(&self) -> Result<Vec<McpTool>, String> {
+        self.shared.tools_changed.store(false, Ordering::SeqCst);
+        let mut tools = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(c) => json!({"cursor": c}),
+                None => json!({}),
+            };
+            let result = self.request_with_timeout("tools/list", params, self.request_timeout).await?;
+            let page: Vec<McpTool> = serde_json::from_value(result.get("tools").cloned().unwrap_or(json!([])))
+                .map_err(|e| format!("can't parse tools/list: {}", e))?;
+            tools.extend(page);
+            cursor = result.get("nextCursor").and_then(|x| x.as_str()).map(|x| x.to_string());
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Ok(tools)
+    }
+
+    pub async fn call_tool(&self, name: &str, args: &HashMap<String, Value>) -> Result<String, String> {
+        let result = self.request_with_timeout("tools/call", json!({"name": name, "arguments": args}), self.request_timeout).await
+            .map_err(|e| self.with_stderr(e))?;
+        let text = mcp_content_to_text(&result);
+        if result.get("isError").and_then(|x| x.as_bool()).unwrap_or(false) {
+            return Err(text);
+        }
+        Ok(text)
+    }
+
+    async fn stop(&self) -> String {
+        for t in self.tasks.iter() {
+            t.abort();
+        }
+        self.shared.disconnected();
+        match &self.transport {
+            McpTransport::Stdio { process, .. } => {
+                match Box::into_pin(process.lock().await.kill()).await {
+                    Ok(_) => "MCP server stopped\n".to_string(),
+                    Err(e) => format!("Failed to kill MCP server: {}. Assuming it died on its own.\n", e),
+                }
+            },
+            McpTransport::Sse { .. } => "Disconnected from MCP server\n".to_string(),
+            McpTransport::StreamableHttp { http, headers, url, session_id } => {
+                if let Some(sid) = session_id.lock().await.as_ref() {
+                    let _ = http.delete(url).headers(headers.clone()).header("Mcp-Session-Id", sid).send().await;
+                }
+                "Disconnected from MCP server\n".to_string()
+            },
+        }
+    }
+}
+
+async fn stdio_write(stdin: &Arc<AMutex<ChildStdin>>, msg: &Value) -> Result<(), String> {
+    let mut line = serde_json::to_string(msg).map_err(|e| e.to_string())?;
+    line.push('\n');
+    let mut stdin_locked = stdin.lock().await;
+    stdin_locked.write_all(line.as_bytes()).await.map_err(|e| format!("can't write to MCP server: {}", e))?;
+    stdin_locked.flush().await.map_err(|e| format!("can't write to MCP server: {}", e))
+}
+
+fn mcp_content_to_text(result: &Value) -> String {
+    let mut parts = vec![];
+    for item in result.get("content").and_then(|x| x.as_array()).cloned().unwrap_or_default() {
+        match item.get("type").and_then(|x| x.as_str()).unwrap_or("") {
+            "text" => parts.push(item.get("text").and_then(|x| x.as_str()).unwrap_or("").to_string()),
+            "image" | "audio" => parts.push(format!("[{} {}]",
+                item.get("type").and_then(|x| x.as_str()).unwrap_or(""),
+                item.get("mimeType").and_then(|x| x.as_str()).unwrap_or(""))),
+            "resource" => {
+                let resource = item.get("resource").cloned().unwrap_or_default();
+                match resource.get("text").and_then(|x| x.as_str()) {
+                    Some(text) => parts.push(text.to_string()),
+                    None => parts.push(format!("[resource {}]", resource.get("uri").and_then(|x| x.as_str()).unwrap_or(""))),
+                }
+            },
+            _ => parts.push(item.to_string()),
+        }
+    }
+    if parts.is_empty() {
+        if let Some(structured) = result.get("structuredContent") {
+            return structured.to_string();
+        }
+    }
+    parts.join("\n")
+}
+
+pub struct SessionMCP {
+    cfg: SettingsMCP,
+    client: Option<Arc<McpClient>>,
+    tools: Vec<McpTool>,
+    last_error: String,
+    last_attempt: Instant,
+}
+
+impl IntegrationSession for SessionMCP {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn is_expired(&self) -> bool {
+        let alive = self.client.as_ref().map(|c| c.is_alive()).unwrap_or(false);
+        !alive && self.last_attempt.elapsed() > MCP_RETRY_AFTER_FAILURE
+    }
+
+    fn try_stop(&mut self) -> Box<dyn Future<Output = String> + Send + '_> {
+        Box::new(async {
+            match self.client.take() {
+                Some(client) => client.stop().await,
+                None => String::new(),
+            }
+        })
+    }
+}
+
+impl SessionMCP {
+    fn needs_restart(&self, cfg: &SettingsMCP) -> bool {
+        let alive = self.client.as_ref().map(|c| c.is_alive()).unwrap_or(false);
+        self.cfg != *cfg || (!alive && self.last_attempt.elapsed() > MCP_RETRY_AFTER_FAILURE)
+    }
+
+    fn needs_tools_list(&self) -> bool {
+        match &self.client {
+            Some(client) => client.is_alive() && (self.tools.is_empty() || client.shared.tools_changed.load(Ordering::SeqCst)),
+            None => false,
+        }
+    }
+
+    async fn refresh(&mut self, gcx: Arc<ARwLock<GlobalContext>>, integr_name: &str, cfg: &SettingsMCP) -> Result<Arc<McpClient>, String> {
+        if self.needs_restart(cfg) {
+            if let Some(client) = self.client.take() {
+                client.stop().await;
+            }
+            self.cfg = cfg.clone();
+            self.tools.clear();
+            self.last_attempt = Instant::now();
+            tracing::info!("MCP {} starting", integr_name);
+            let mut error_log = Vec::<YamlError>::new();
+            let env_variables = crate::integrations::setting_up_integrations::get_vars_for_replacements(gcx.clone(), &mut error_log).await;
+            let project_dirs = crate::files_correction::get_project_dirs(gcx.clone()).await;
+            match McpClient::connect(cfg, &env_variables, project_dirs).await {
+                Ok(client) => {
+                    self.last_error.clear();
+                    self.client = Some(Arc::new(client));
+                },
+                Err(e) => self.last_error = e,
+            }
+        }
+        let client = self.client.clone().ok_or(self.last_error.clone())?;
+        if self.tools.is_empty() || client.shared.tools_changed.load(Ordering::SeqCst) {
+            self.tools = client.list_tools().await?;
+            tracing::info!("MCP {} has {} tools", integr_name, self.tools.len());
+        }
+        Ok(client)
+    }
+}
+
+async fn mcp_session(gcx: Arc<ARwLock<GlobalContext>>, integr_name: &str) -> Arc<AMutex<Box<dyn IntegrationSession>>> {
+    let session_key = get_session_hashmap_key("mcp", integr_name);
+    let mut gcx_locked = gcx.write().await;
+    gcx_locked.integration_sessions.entry(session_key).or_insert_with(|| {
+        Arc::new(AMutex::new(Box::new(SessionMCP {
+            cfg: SettingsMCP::default(),
+            client: None,
+            tools: vec![],
+            last_error: String::new(),
+            last_attempt: Instant::now() - MCP_RETRY_AFTER_FAILURE * 2,
+        }) as Box<dyn IntegrationSession>))
+    }).clone()
+}
+
+// Doesn't wait for the server: if it needs to be (re)started or asked for tools, that happens in the background,
+// and the tools show up in a later request. Returns nothing while the session is busy starting or listing.
+async fn mcp_session_cached_tools(gcx: Arc<ARwLock<GlobalContext>>, integr_name: &str, cfg: &SettingsMCP) -> Vec<McpTool> {
+    let session_arc = mcp_session(gcx.clone(), integr_name).await;
+    let mut session_locked = match session_arc.try_lock_owned() {
+        Ok(x) => x,
+        Err(_) => return vec![],
+    };
+    let session = session_locked.as_any_mut().downcast_mut::<SessionMCP>().unwrap();
+    let restart = session.needs_restart(cfg);
+    let tools = if restart { vec![] } else { session.tools.clone() };
+    if restart || session.needs_tools_list() {
+        let (integr_name, cfg) = (integr_name.to_string(), cfg.clone());
+        tokio::spawn(async move {
+            let session = session_locked.as_any_mut().downcast_mut::<SessionMCP>().unwrap();
+            if let Err(e) = session.refresh(gcx, &integr_name, &cfg).await {
+                tracing::error!("MCP {}: {}", integr_name, e);
+            }
+        });
+    }
+    tools
+}
+
+async fn mcp_session_client(gcx: Arc<ARwLock<GlobalContext>>, integr_name: &str, cfg: &SettingsMCP) -> Result<Arc<McpClient>, String> {
+    let session_arc = mcp_session(gcx.clone(), integr_name).await;
+    let mut session_locked = session_arc.lock().await;
+    let session = session_locked.as_any_mut().downcast_mut::<SessionMCP>().unwrap();
+    session.refresh(gcx, integr_name, cfg).await
+}
+
+// OpenAI wants ^[a-zA-Z0-9_-]{1,64}$
+pub fn mcp_tool_name(integr_name: &str, mcp_tool_name: &str) -> String {
+    format!("{}_{}", integr_name, mcp_tool_name).chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(MCP_TOOL_NAME_MAX_LEN)
+        .collect()
+}
+
+// Replaces {"$ref": "#/$defs/X"} with the definition, servers generated from pydantic models use those a lot
+fn inline_schema_refs(schema: &Value, defs: &Value, depth: usize) -> Value {
+    match schema {
+        Value::Object(map) => {
+            if let Some(r) = map.get("$ref").and_then(|x| x.as_str()) {
+                let name = r.rsplit('/').next().unwrap_or("");
+                if let Some(def) = defs.get(name) {
+                    if depth > 0 {
+                        return inline_schema_refs(def, defs, depth - 1);
+                    }
+                }
+                return json!({"type": "object"});
+            }
+            Value::Object(map.iter().map(|(k, v)| (k.clone(), inline_schema_refs(v, defs, depth))).collect())
+        },
+        Value::Array(arr) => Value::Array(arr.iter().map(|v| inline_schema_refs(v, defs, depth)).collect()),
+        _ => schema.clone(),
+    }
+}
+
+pub fn mcp_tool_to_tool_desc(tool_name: &str, mcp_tool: &McpTool) -> ToolDesc {
+    let defs = mcp_tool.input_schema.get("$defs").or(mcp_tool.input_schema.get("definitions")).cloned().unwrap_or(json!({}));
+    let mut parameters = vec![];
+    if let Some(properties) = mcp_tool.input_schema.get("properties").and_then(|x| x.as_object()) {
+        for (name, prop_schema) in properties {
+            let prop_schema = inline_schema_refs(prop_schema, &defs, 5);
+            let param_type = match prop_schema.get("type") {
+                Some(Value::String(t)) => t.clone(),
+                Some(Value::Array(types)) => types.iter().filter_map(|t| t.as_str()).find(|t| *t != "null").unwrap_or("string").to_string(),
+                _ => "string".to_string(),
+            };
+            parameters.push(ToolParam {
+                name: name.clone(),
+                param_type,
+                description: prop_schema.get("description").and_then(|x| x.as_str()).unwrap_or("").to_string(),
+                schema: Some(prop_schema),
+            });
+        }
+    }
+    let parameters_required = mcp_tool.input_schema.get("required").and_then(|x| x.as_array())
+        .map(|arr| arr.iter().filter_map(|x| x.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default();
+    ToolDesc {
+        name: tool_name.to_string(),
+        agentic: true,
+        experimental: false,
+        description: mcp_tool.description.clone().unwrap_or_default(),
+        parameters,
+        parameters_required,
+    }
+}
+
+pub struct ToolMCP {
+    pub common: IntegrationCommon,
+    pub cfg: SettingsMCP,
+    pub config_path: String,
+    pub integr_name: String,
+    pub tool_name: String,
+    pub mcp_tool: McpTool,
+}
+
+#[async_trait]
+impl Tool for ToolMCP {
+    fn as_any(&self) -> &dyn Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let gcx = ccx.lock().await.global_context.clone();
+        let client = mcp_session_client(gcx, &self.integr_name, &self.cfg).await?;
+        let output = client.call_tool(&self.mcp_tool.name, args).await?;
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(output),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn command_to_match_against_confirm_deny(
+        &self,
+        args: &HashMap<String, Value>,
+    ) -> Result<String, String> {
+        let sorted_args: BTreeMap<&String, &Value> = args.iter().collect();
+        Ok(format!("{} {}", self.tool_name, serde_json::to_string(&sorted_args).unwrap_or_default()))
+    }
+
+    fn confirm_deny_rules(&self) -> Option<IntegrationConfirmation> {
+        Some(self.common.confirmation.clone())
+    }
+
+    fn has_config_path(&self) -> Option<String> {
+        Some(self.config_path.clone())
+    }
+
+    fn tool_name(&self) -> String {
+        self.tool_name.clone()
+    }
+
+    fn tool_description(&self) -> ToolDesc {
+        mcp_tool_to_tool_desc(&self.tool_name, &self.mcp_tool)
+    }
+}
+
+pub const MCP_INTEGRATION_SCHEMA: &str = r#"
+fields:
+  command:
+    f_type: string_long
+    f_desc: "Command that starts a stdio MCP server, leave empty to connect to a url instead."
+    f_placeholder: "npx -y @modelcontextprotocol/server-github"
+  command_workdir:
+    f_type: string_long
+    f_desc: "The working directory for the command, the first project directory if empty."
+    f_placeholder: "/path/to/workdir"
+  url:
+    f_type: string_long
+    f_desc: "Url of a remote MCP server."
+    f_placeholder: "http://localhost:8000/mcp"
+  transport:
+    f_type: string_short
+    f_desc: "For url: sse or http (streamable HTTP). If empty, urls ending with /sse use sse."
+    f_placeholder: "http"
+  headers:
+    f_type: string_long
+    f_desc: "For url: HTTP headers, one \"Name: value\" per line."
+    f_placeholder: "Authorization: Bearer $MY_TOKEN"
+  init_timeout:
+    f_type: string_short
+    f_desc: "Seconds to wait for the server to start and initialize."
+    f_default: "30"
+  request_timeout:
+    f_type: string_short
+    f_desc: "Seconds to wait for a tool call."
+    f_default: "120"
+description: |
+  Model Context Protocol server, all its tools become available to the model as mcp_<name>_<tool>.
+  The server is started once and kept running in the background.
+available:
+  on_your_laptop_possible: true
+  when_isolated_possible: true
+confirmation:
+  ask_user_default: ["*"]
+  deny_default: []
+smartlinks:
+  - sl_label: "Test"
+    sl_chat:
+      - role: "user"
+        content: |
+          🔧 Test the tools that correspond to %CURRENT_CONFIG%
+          If the tools aren't available or don't work, go through the usual plan in the system prompt. If they work express happiness, and change nothing.
+    sl_enable_only_with_tool: true
+"#;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mcp_tool_to_tool_desc() {
+        let mcp_tool: McpTool = serde_json::from_value(json!({
+            "name": "create.issue",
+            "description": "Create a ticket",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "title": {"type": "string", "description": "Short summary"},
+                    "priority": {"type": ["integer", "null"]},
+                    "labels": {"type": "array", "items": {"type": "string"}},
+                    "assignee": {"$ref": "#/$defs/User"}
+                },
+                "required": ["title"],
+                "$defs": {"User": {"type": "object", "properties": {"login": {"type": "string"}}}}
+            }
+        })).unwrap();
+        let tool_name = mcp_tool_name("mcp_tickets", &mcp_tool.name);
+        assert_eq!(tool_name, "mcp_tickets_create_issue");
+        let desc = mcp_tool_to_tool_desc(&tool_name, &mcp_tool);
+        assert_eq!(desc.description, "Create a ticket");
+        assert_eq!(desc.parameters_required, vec!["title".to_string()]);
+        let types: Vec<(&str, &str)> = desc.parameters.iter().map(|p| (p.name.as_str(), p.param_type.as_str())).collect();
+        assert_eq!(types, vec![("title", "string"), ("priority", "integer"), ("labels", "array"), ("assignee", "object")]);
+        assert_eq!(desc.parameters[3].schema.as_ref().unwrap()["properties"]["login"]["type"], "string");
+        let openai = desc.into_openai_style();
+        assert_eq!(openai["function"]["parameters"]["properties"]["labels"]["items"]["type"], "string");
+        assert_eq!(openai["function"]["parameters"]["properties"]["title"]["description"], "Short summary");
+        assert_eq!(mcp_tool_name("mcp_x", &"a".repeat(100)).len(), 64);
+    }
+
+    #[test]
+    fn test_dispatch_incoming() {
+        let shared = McpShared::default();
+        let (tx, mut rx) = oneshot::channel();
+        shared.pending.lock().unwrap().insert(7, tx);
+        assert!(shared.dispatch_incoming(json!({"jsonrpc": "2.0", "id": 7, "result": {"ok": true}})).is_empty());
+        assert_eq!(rx.try_recv().unwrap().unwrap(), json!({"ok": true}));
+        let replies = shared.dispatch_incoming(json!([
+            {"jsonrpc": "2.0", "id": "p1", "method": "ping"},
+            {"jsonrpc": "2.0", "method": "notifications/tools/list_changed"},
+            {"jsonrpc": "2.0", "id": 3, "method": "sampling/createMessage", "params": {}},
+        ]));
+        assert_eq!(replies[0], json!({"jsonrpc": "2.0", "id": "p1", "result": {}}));
+        assert_eq!(replies[1]["error"]["code"], -32601);
+        assert!(shared.tools_changed.load(Ordering::SeqCst));
+        let mut buf = "event: message\r\ndata: {\"a\":1}\r\n\r\ndata: {\"b\"".to_string();
+        assert_eq!(sse_take_events(&mut buf), vec!["{\"a\":1}".to_string()]);
+        assert_eq!(buf, "data: {\"b\"");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stdio_server_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("server.sh");
+        std::fs::write(&script, r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2025-03-26","capabilities":{"tools":{}},"serverInfo":{"name":"fake","version":"1"}}}\n' "$id" ;;
+    *'"tools/list"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"tools":[{"name":"echo","inputSchema":{"type":"object","properties":{"text":{"type":"string"}}}}]}}\n' "$id" ;;
+    *'"tools/call"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"pong"}],"isError":false}}\n' "$id" ;;
+  esac
+done
+"#).unwrap();
+        let cfg = SettingsMCP {
+            command: format!("sh {}", script.display()),
+            command_workdir: dir.path().to_string_lossy().to_string(),
+            init_timeout: 10,
+            request_timeout: 10,
+            ..Default::default()
+        };
+        let client = McpClient::connect(&cfg, &HashMap::new(), vec![]).await.unwrap();
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "echo");
+        let output = client.call_tool("echo", &HashMap::from([("text".to_string(), json!("ping"))])).await.unwrap();
+        assert_eq!(output, "pong");
+
+        let mut session = SessionMCP {
+            cfg: cfg.clone(),
+            client: Some(Arc::new(client)),
+            tools: vec![],
+            last_error: String::new(),
+            last_attempt: Instant::now(),
+        };
+        assert!(!session.needs_restart(&cfg));
+        assert!(session.needs_tools_list());
+        session.tools = tools;
+        assert!(!session.needs_tools_list());
+        assert!(session.needs_restart(&SettingsMCP { request_timeout: 11, ..cfg.clone() }));
+        let client = session.client.clone().unwrap();
+        client.stop().await;
+        assert!(!client.is_alive());
+        // a dead server is restarted only after the backoff
+        assert!(!session.needs_restart(&cfg));
+        assert!(!session.needs_tools_list());
+        session.last_attempt = Instant::now() - MCP_RETRY_AFTER_FAILURE * 2;
+        assert!(session.needs_restart(&cfg));
+    }
+}
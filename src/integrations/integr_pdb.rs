@@ -190,11 +190,13 @@ impl Tool for ToolPdb {
                     name: "command".to_string(),
                     param_type: "string".to_string(),
                     description: "Examples: 'python -m pdb script.py', 'break module_name.function_name', 'break 10', 'continue', 'print(variable_name)', 'list', 'quit'".to_string(),
+                    schema: None,
                 },
                 ToolParam {
                     name: "workdir".to_string(),
                     param_type: "string".to_string(),
                     description: "Working directory for the command, needed to start a pdb session from a relative path.".to_string(),
+                    schema: None,
                 },
             ],
             parameters_required: vec!["command".to_string()],
@@ -15,9 +15,10 @@ use crate::global_context::GlobalContext;
 use crate::tools::tools_description::{ToolParam, Tool, ToolDesc, MatchConfirmDeny, MatchConfirmDenyResult};
 use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
 use crate::postprocessing::pp_command_output::CmdlineOutputFilter;
-use crate::integrations::integr_abstract::{IntegrationCommon, IntegrationTrait};
+use crate::integrations::integr_abstract::{IntegrationCommon, IntegrationConfirmation, IntegrationTrait};
 use crate::integrations::setting_up_integrations::YamlError;
-use crate::tools::tools_execute::command_should_be_denied;
+use crate::integrations::shell_ast::match_shell_command_against_rules;
+use crate::integrations::sandbox::{SandboxSettings, sandbox_command};
 
 
 #[derive(Deserialize, Serialize, Clone, Default)]
@@ -26,6 +27,8 @@ pub struct SettingsShell {
     pub timeout: String,
     #[serde(default)]
     pub output_filter: CmdlineOutputFilter,
+    #[serde(flatten)]
+    pub sandbox: SandboxSettings,
 }
 
 #[derive(Default)]
@@ -101,6 +104,7 @@ impl Tool for ToolShell {
             &workdir_maybe,
             timeout,
             &self.cfg.output_filter,
+            &self.cfg.sandbox,
             &env_variables,
             gcx.clone(),
         ).await?;
@@ -131,11 +135,13 @@ impl Tool for ToolShell {
                     name: "command".to_string(),
                     param_type: "string".to_string(),
                     description: "shell command to execute".to_string(),
+                    schema: None,
                 },
                 ToolParam {
                     name: "workdir".to_string(),
                     param_type: "string".to_string(),
                     description: "workdir for the command".to_string(),
+                    schema: None,
                 },
             ],
             parameters_required: vec![
@@ -156,15 +162,13 @@ impl Tool for ToolShell {
         if command_to_match.is_empty() {
             return Err("Empty command to match".to_string());
         }
-        if let Some(rules) = &self.confirm_deny_rules() {
-            let (is_denied, deny_rule) = command_should_be_denied(&command_to_match, &rules.deny);
-            if is_denied {
-                return Ok(MatchConfirmDeny {
-                    result: MatchConfirmDenyResult::DENY,
-                    command: command_to_match.clone(),
-                    rule: deny_rule.clone(),
-                });
-            }
+        let result = match_shell_command_against_rules(&command_to_match, &self.common.confirmation);
+        if matches!(result.result, MatchConfirmDenyResult::DENY | MatchConfirmDenyResult::CONFIRMATION) {
+            return Ok(result);
+        }
+        // inside the sandbox the ask_user rules decide, outside of it every command waits for the user
+        if self.cfg.sandbox.sandbox {
+            return Ok(result);
         }
         // NOTE: do not match command if not denied, always wait for confirmation from user
         Ok(MatchConfirmDeny {
@@ -182,6 +186,10 @@ impl Tool for ToolShell {
         Ok(command)
     }
 
+    fn confirm_deny_rules(&self) -> Option<IntegrationConfirmation> {
+        Some(self.common.confirmation.clone())
+    }
+
     fn has_config_path(&self) -> Option<String> {
         Some(self.config_path.clone())
     }
@@ -192,6 +200,7 @@ pub async fn execute_shell_command(
     workdir_maybe: &Option<PathBuf>,
     timeout: u64,
     output_filter: &CmdlineOutputFilter,
+    sandbox: &SandboxSettings,
     env_variables: &HashMap<String, String>,
     gcx: Arc<ARwLock<GlobalContext>>,
 ) -> Result<String, String> {
@@ -215,6 +224,7 @@ pub async fn execute_shell_command(
     cmd.stdin(Stdio::null());
     cmd.stdout(Stdio::piped());
     cmd.stderr(Stdio::piped());
+    sandbox_command(&mut cmd, sandbox, &crate::files_correction::get_project_dirs(gcx.clone()).await)?;
 
     let t0 = tokio::time::Instant::now();
     tracing::info!("SHELL: running command directory {:?}\n{:?}", workdir_maybe, command);
@@ -280,6 +290,29 @@ fields:
     f_type: "output_filter"
     f_desc: "The output from the command can be long or even quasi-infinite. This section allows to set limits, prioritize top or bottom, or use regexp to show the model the relevant part."
     f_extra: true
+  sandbox:
+    f_type: bool
+    f_desc: "Run the command in a lightweight sandbox (Linux only): the workspace except .git and .refact and the temp dir are writable, the rest of the filesystem is read-only. With the sandbox on, the command runs without confirmation unless it matches ask_user rules."
+    f_extra: true
+  sandbox_network:
+    f_type: bool
+    f_desc: "Allow network access inside the sandbox."
+    f_extra: true
+  sandbox_writable_paths:
+    f_type: string_long
+    f_desc: "Comma separated paths that are writable inside the sandbox, in addition to the workspace and the temp dir."
+    f_placeholder: "~/.cache, ~/.cargo"
+    f_extra: true
+  sandbox_cpu_seconds:
+    f_type: string_short
+    f_desc: "CPU time limit for the sandboxed command, in seconds."
+    f_placeholder: "60"
+    f_extra: true
+  sandbox_memory_mb:
+    f_type: string_short
+    f_desc: "Memory (address space) limit for the sandboxed command, in megabytes."
+    f_placeholder: "2048"
+    f_extra: true
 description: |
   Allows to execute any command line tool with confirmation from the chat itself.
 available:
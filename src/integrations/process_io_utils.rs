@@ -1,7 +1,9 @@
+use process_wrap::tokio::*;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio::process::ChildStdin;
+use tokio::process::{ChildStdin, Command};
 use tokio::time::Duration;
+use std::process::Stdio;
 use std::time::Instant;
 use tracing::error;
 
@@ -73,6 +75,62 @@ pub async fn blocking_read_until_token_or_timeout<
     Ok((String::from_utf8_lossy(&output).to_string(), String::from_utf8_lossy(&error).to_string(), have_the_token))
 }
 
+// The command gets its own process group, when the shell exits or the timeout hits everything it started is killed,
+// so a build tool's children can't keep running in the background or hold the pipes open
+pub async fn execute_command_with_timeout(
+    mut cmd: Command,
+    timeout_secs: u64,
+) -> Result<(String, String, i32), String> {
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    let mut command_wrap = TokioCommandWrap::from(cmd);
+    #[cfg(unix)]
+    command_wrap.wrap(ProcessGroup::leader());
+    #[cfg(windows)]
+    command_wrap.wrap(JobObject);
+    command_wrap.wrap(KillOnDrop);
+    let mut process = command_wrap.spawn().map_err(|e| format!("cannot run command: {}", e))?;
+    #[cfg(unix)]
+    let kill_group = process.id().map(|pid| KillProcessGroupOnDrop(pid as i32));
+    let stdout_task = tokio::spawn(read_to_end(process.stdout().take()));
+    let stderr_task = tokio::spawn(read_to_end(process.stderr().take()));
+
+    let status = tokio::time::timeout(Duration::from_secs(timeout_secs), Box::into_pin(process.wait())).await;
+    drop(process);
+    #[cfg(unix)]
+    drop(kill_group);
+    let status = status
+        .map_err(|_| format!("command timed out after {} seconds", timeout_secs))?
+        .map_err(|e| format!("command failed: {}", e))?;
+    let stdout = stdout_task.await.unwrap_or_default();
+    let stderr = stderr_task.await.unwrap_or_default();
+    Ok((
+        String::from_utf8_lossy(&stdout).to_string(),
+        String::from_utf8_lossy(&stderr).to_string(),
+        status.code().unwrap_or(-1),
+    ))
+}
+
+async fn read_to_end<R: AsyncRead + Unpin>(pipe: Option<R>) -> Vec<u8> {
+    let mut result = vec![];
+    if let Some(mut pipe) = pipe {
+        let _ = pipe.read_to_end(&mut result).await;
+    }
+    result
+}
+
+#[cfg(unix)]
+struct KillProcessGroupOnDrop(i32);
+
+#[cfg(unix)]
+impl Drop for KillProcessGroupOnDrop {
+    fn drop(&mut self) {
+        // ESRCH if nothing is left in the group, that's fine
+        let _ = nix::sys::signal::killpg(nix::unistd::Pid::from_raw(self.0), nix::sys::signal::Signal::SIGKILL);
+    }
+}
+
 pub async fn is_someone_listening_on_that_tcp_port(port: u16, timeout: tokio::time::Duration) -> bool {
     match tokio::time::timeout(timeout, TcpStream::connect(&format!("127.0.0.1:{}", port))).await {
         Ok(Ok(_)) => true,    // Connection successful
@@ -110,3 +168,32 @@ pub fn last_n_lines(msg: &str, n: usize) -> String {
 
     output
 }
+
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_execute_command_with_timeout_kills_the_group() {
+        let tmp = tempfile::tempdir().unwrap();
+        let marker = tmp.path().join("marker");
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(format!("(sleep 1; touch {}) & echo started", marker.display()));
+        let t0 = Instant::now();
+        let (stdout, _stderr, exit_code) = execute_command_with_timeout(cmd, 10).await.unwrap();
+        assert_eq!((stdout.as_str(), exit_code), ("started\n", 0));
+        assert!(t0.elapsed() < Duration::from_millis(900), "waited for the background child");
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists(), "the background child survived");
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(format!("sleep 2 && touch {}; sleep 30", marker.display()));
+        let t0 = Instant::now();
+        let err = execute_command_with_timeout(cmd, 1).await.unwrap_err();
+        assert!(err.contains("timed out"), "{}", err);
+        assert!(t0.elapsed() < Duration::from_secs(2));
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists(), "the command kept running after the timeout");
+    }
+}
@@ -16,7 +16,9 @@ pub async fn load_integration_tools(
     for (name, integr) in integraions_map {
         // if integr.can_upgrade_to_tool() {
             // tools.insert(name.clone(), integr.integr_upgrade_to_tool(&name));
-        for tool in integr.integr_tools(&name) {
+        let mut integr_tools = integr.integr_tools(&name);
+        integr_tools.extend(integr.integr_session_tools(gcx.clone(), &name).await);
+        for tool in integr_tools {
             let mut tool_name = tool.tool_name();
             if tool_name.is_empty() {
                 tool_name = name.clone();
@@ -0,0 +1,338 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::integrations::utils::{serialize_opt_num_to_str, deserialize_str_to_opt_num};
+
+
+// Flattened into the shell and cmdline settings, so in yaml it's `sandbox: true`, `sandbox_network: false` and so on
+#[derive(Deserialize, Serialize, Clone, Default, Debug, PartialEq)]
+pub struct SandboxSettings {
+    #[serde(default)]
+    pub sandbox: bool,
+    #[serde(default)]
+    pub sandbox_network: bool,
+    #[serde(default)]
+    pub sandbox_writable_paths: String,  // comma separated, in addition to the project dirs and the temp dir
+    #[serde(default, serialize_with = "serialize_opt_num_to_str", deserialize_with = "deserialize_str_to_opt_num")]
+    pub sandbox_cpu_seconds: Option<u64>,
+    #[serde(default, serialize_with = "serialize_opt_num_to_str", deserialize_with = "deserialize_str_to_opt_num")]
+    pub sandbox_memory_mb: Option<u64>,
+}
+
+pub fn sandbox_writable_paths(settings: &SandboxSettings, project_dirs: &Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut paths = project_dirs.clone();
+    paths.push(std::env::temp_dir());
+    for p in settings.sandbox_writable_paths.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        match (p.strip_prefix("~/"), home::home_dir()) {
+            (Some(rest), Some(home)) => paths.push(home.join(rest)),
+            _ => paths.push(PathBuf::from(p)),
+        }
+    }
+    paths
+}
+
+// A command that can write there can change what refact runs next (integrations) or what git runs (hooks)
+pub fn sandbox_protected_paths(project_dirs: &Vec<PathBuf>) -> Vec<PathBuf> {
+    project_dirs.iter().flat_map(|p| [p.join(".git"), p.join(".refact")]).collect()
+}
+
+// Project dirs and the temp dir are writable, the rest of the filesystem is read-only (landlock). Landlock can only grant,
+// so .git and .refact are bind mounted read-only on top of that in a new user and mount namespace. Network is cut off
+// unless allowed (a new network namespace), CPU time and memory are limited with rlimits.
+// If the kernel can't do that, the command doesn't run at all.
+pub fn sandbox_command(cmd: &mut Command, settings: &SandboxSettings, project_dirs: &Vec<PathBuf>) -> Result<(), String> {
+    if !settings.sandbox {
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    {
+        // only an existing path can be a mount point, and the command must not be able to create .refact/integrations.d
+        for project_dir in project_dirs.iter().filter(|p| p.is_dir()) {
+            if let Err(e) = std::fs::create_dir_all(project_dir.join(".refact")) {
+                return Err(format!("sandbox: can't create {:?}: {}", project_dir.join(".refact"), e));
+            }
+        }
+        let protected: Vec<PathBuf> = sandbox_protected_paths(project_dirs).into_iter().filter(|p| p.exists()).collect();
+        linux::apply(cmd, settings, &sandbox_writable_paths(settings, project_dirs), &protected)?;
+        cmd.kill_on_drop(true);
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (cmd, project_dirs);
+        Err("sandbox is only supported on Linux".to_string())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::path::PathBuf;
+    use nix::sched::{unshare, CloneFlags};
+    use nix::sys::prctl::set_no_new_privs;
+    use nix::sys::resource::{setrlimit, Resource};
+    use nix::unistd::{getgid, getuid};
+    use tokio::process::Command;
+
+    use super::SandboxSettings;
+
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_ALL: u64 = 0b1111111 << 6;  // char, dir, reg, sock, fifo, block, sym
+    const ACCESS_FS_REFER: u64 = 1 << 13;    // ABI 2
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14; // ABI 3
+
+    #[repr(C)]
+    struct LandlockRulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct LandlockPathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    fn landlock_abi() -> i64 {
+        unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, std::ptr::null::<LandlockRulesetAttr>(), 0usize, LANDLOCK_CREATE_RULESET_VERSION) }
+    }
+
+    // Only the writing is restricted, reading and executing stay allowed everywhere
+    fn landlock_ruleset(writable: &Vec<PathBuf>) -> Result<OwnedFd, String> {
+        let abi = landlock_abi();
+        if abi < 1 {
+            return Err(format!("sandbox needs landlock, this kernel doesn't have it: {}", io::Error::last_os_error()));
+        }
+        let file_access = ACCESS_FS_WRITE_FILE | if abi >= 3 { ACCESS_FS_TRUNCATE } else { 0 };
+        let dir_access = file_access | ACCESS_FS_REMOVE_DIR | ACCESS_FS_REMOVE_FILE | ACCESS_FS_MAKE_ALL | if abi >= 2 { ACCESS_FS_REFER } else { 0 };
+        let attr = LandlockRulesetAttr { handled_access_fs: dir_access };
+        let fd = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, &attr as *const LandlockRulesetAttr, std::mem::size_of::<LandlockRulesetAttr>(), 0u32) };
+        if fd < 0 {
+            return Err(format!("can't create landlock ruleset: {}", io::Error::last_os_error()));
+        }
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        // /dev/null, /dev/tty and friends can be written to, but nothing can be created there
+        let mut rules = vec![(PathBuf::from("/dev"), file_access)];
+        rules.extend(writable.iter().map(|p| (p.clone(), if p.is_dir() { dir_access } else { file_access })));
+        for (path, allowed_access) in rules {
+            let parent = match std::fs::OpenOptions::new().read(true).custom_flags(libc::O_PATH | libc::O_CLOEXEC).open(&path) {
+                Ok(x) => x,
+                Err(e) => {
+                    tracing::warn!("sandbox: skipping writable path {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            let rule = LandlockPathBeneathAttr { allowed_access, parent_fd: parent.as_raw_fd() };
+            let r = unsafe { libc::syscall(libc::SYS_landlock_add_rule, ruleset.as_raw_fd(), LANDLOCK_RULE_PATH_BENEATH, &rule as *const LandlockPathBeneathAttr, 0u32) };
+            if r != 0 {
+                return Err(format!("can't add landlock rule for {:?}: {}", path, io::Error::last_os_error()));
+            }
+        }
+        Ok(ruleset)
+    }
+
+    // Runs in the child between fork and exec: only syscalls, no allocations
+    fn write_proc_file(path: &[u8], content: &[u8]) -> io::Result<()> {
+        unsafe {
+            let fd = libc::open(path.as_ptr() as *const libc::c_char, libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, content.as_ptr() as *const libc::c_void, content.len());
+            libc::close(fd);
+            if written != content.len() as isize {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    // A fresh network namespace has only loopback, and it's down
+    fn loopback_up() {
+        unsafe {
+            let sock = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+            if sock < 0 {
+                return;
+            }
+            let mut ifr: libc::ifreq = std::mem::zeroed();
+            for (i, c) in b"lo".iter().enumerate() {
+                ifr.ifr_name[i] = *c as libc::c_char;
+            }
+            if libc::ioctl(sock, libc::SIOCGIFFLAGS as _, &mut ifr) == 0 {
+                ifr.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+                libc::ioctl(sock, libc::SIOCSIFFLAGS as _, &mut ifr);
+            }
+            libc::close(sock);
+        }
+    }
+
+    // In a user namespace a bind mount can only be made read-only if it keeps nosuid, nodev and the rest of the flags
+    // of the mount it comes from, so they are collected here, before fork
+    fn readonly_remount(path: &PathBuf) -> Result<(CString, libc::c_ulong), String> {
+        let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|e| format!("bad path {:?}: {}", path, e))?;
+        let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut st) } != 0 {
+            return Err(format!("can't statvfs {:?}: {}", path, io::Error::last_os_error()));
+        }
+        let mut flags = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY;
+        for (st_flag, ms_flag) in [
+            (libc::ST_NOSUID, libc::MS_NOSUID),
+            (libc::ST_NODEV, libc::MS_NODEV),
+            (libc::ST_NOEXEC, libc::MS_NOEXEC),
+            (libc::ST_NOATIME, libc::MS_NOATIME),
+            (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+            (libc::ST_RELATIME, libc::MS_RELATIME),
+        ] {
+            if st.f_flag & st_flag != 0 {
+                flags |= ms_flag;
+            }
+        }
+        Ok((c_path, flags))
+    }
+
+    // Runs in the child between fork and exec, the mount namespace is already private to it
+    fn mount_readonly(path: &CString, remount_flags: libc::c_ulong) -> io::Result<()> {
+        unsafe {
+            if libc::mount(path.as_ptr(), path.as_ptr(), std::ptr::null(), libc::MS_BIND | libc::MS_REC, std::ptr::null()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::mount(std::ptr::null(), path.as_ptr(), std::ptr::null(), remount_flags, std::ptr::null()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply(cmd: &mut Command, settings: &SandboxSettings, writable: &Vec<PathBuf>, protected: &Vec<PathBuf>) -> Result<(), String> {
+        let ruleset = landlock_ruleset(writable)?;
+        let readonly = protected.iter().map(readonly_remount).collect::<Result<Vec<_>, _>>()?;
+        let clone_flags = CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNS | if settings.sandbox_network { CloneFlags::empty() } else { CloneFlags::CLONE_NEWNET };
+        let (uid, gid) = (getuid(), getgid());
+        let uid_map = format!("{} {} 1", uid, uid).into_bytes();
+        let gid_map = format!("{} {} 1", gid, gid).into_bytes();
+        let cpu_seconds = settings.sandbox_cpu_seconds;
+        let memory_bytes = settings.sandbox_memory_mb.map(|mb| mb.saturating_mul(1024 * 1024));
+        unsafe {
+            cmd.pre_exec(move || {
+                unshare(clone_flags)?;
+                // same uid and gid inside, otherwise the files look like they belong to nobody
+                write_proc_file(b"/proc/self/setgroups\0", b"deny")?;
+                write_proc_file(b"/proc/self/uid_map\0", &uid_map)?;
+                write_proc_file(b"/proc/self/gid_map\0", &gid_map)?;
+                if clone_flags.contains(CloneFlags::CLONE_NEWNET) {
+                    loopback_up();
+                }
+                // the mounts below must not propagate back to the parent namespace
+                if libc::mount(std::ptr::null(), b"/\0".as_ptr() as *const libc::c_char, std::ptr::null(), libc::MS_REC | libc::MS_PRIVATE, std::ptr::null()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                for (path, remount_flags) in readonly.iter() {
+                    mount_readonly(path, *remount_flags)?;
+                }
+                if let Some(cpu_seconds) = cpu_seconds {
+                    setrlimit(Resource::RLIMIT_CPU, cpu_seconds, cpu_seconds)?;
+                }
+                if let Some(memory_bytes) = memory_bytes {
+                    setrlimit(Resource::RLIMIT_AS, memory_bytes, memory_bytes)?;
+                }
+                // landlock also forbids mount and umount from here on, so the read-only mounts stay
+                set_no_new_privs()?;
+                if libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    // None if this machine can't sandbox (no landlock, no user namespaces), the test has nothing to check then
+    async fn run_sandboxed(settings: &SandboxSettings, project_dir: &PathBuf, script: &str) -> Option<(bool, String)> {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script).current_dir(project_dir);
+        if let Err(e) = sandbox_command(&mut cmd, settings, &vec![project_dir.clone()]) {
+            eprintln!("skipping, no sandbox here: {}", e);
+            return None;
+        }
+        let output = match cmd.output().await {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("skipping, no sandbox here: {}", e);
+                return None;
+            }
+        };
+        Some((output.status.success(), String::from_utf8_lossy(&output.stdout).to_string() + &String::from_utf8_lossy(&output.stderr)))
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_filesystem_and_network() {
+        let project = tempfile::tempdir().unwrap();
+        let project_dir = project.path().to_path_buf();
+        std::fs::create_dir_all(project_dir.join(".git").join("hooks")).unwrap();
+        std::fs::create_dir_all(project_dir.join("src")).unwrap();
+        let settings = SandboxSettings { sandbox: true, ..Default::default() };
+
+        let Some((ok, out)) = run_sandboxed(&settings, &project_dir, "echo hi > src/inside.txt && cat src/inside.txt && echo x > /dev/null").await else { return };
+        assert!(ok, "{}", out);
+        assert_eq!(out.trim(), "hi");
+
+        let hook = project_dir.join(".git").join("hooks").join("pre-commit");
+        let (ok, _) = run_sandboxed(&settings, &project_dir, &format!("echo hi > {}", hook.display())).await.unwrap();
+        assert!(!ok);
+        assert!(!hook.exists());
+        let (ok, _) = run_sandboxed(&settings, &project_dir, "mkdir -p .refact/integrations.d && echo x > .refact/integrations.d/shell.yaml").await.unwrap();
+        assert!(!ok);
+        assert!(!project_dir.join(".refact").join("integrations.d").exists());
+        let (ok, _) = run_sandboxed(&settings, &project_dir, "mv .git git2 || rm -rf .git").await.unwrap();
+        assert!(!ok);
+        assert!(project_dir.join(".git").join("hooks").exists());
+
+        let (ok, _) = run_sandboxed(&settings, &project_dir, "echo hi > /etc/sandbox-test.txt").await.unwrap();
+        assert!(!ok);
+
+        // no interfaces except loopback in the new network namespace
+        let (_, out) = run_sandboxed(&settings, &project_dir, "cat /proc/net/dev").await.unwrap();
+        assert!(out.contains("lo:"), "{}", out);
+        assert_eq!(out.lines().filter(|l| l.contains(':')).count(), 1, "{}", out);
+
+        let (ok, out) = run_sandboxed(&SandboxSettings { sandbox_memory_mb: Some(64), ..settings.clone() }, &project_dir, "ulimit -v").await.unwrap();
+        assert!(ok, "{}", out);
+        assert_eq!(out.trim(), "65536");
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_project_root_writable() {
+        // cargo creates target/, npm creates node_modules/, editors write a temp file and rename it over the original
+        let project = tempfile::tempdir().unwrap();
+        let project_dir = project.path().to_path_buf();
+        std::fs::create_dir_all(project_dir.join(".git")).unwrap();
+        std::fs::write(project_dir.join("Cargo.toml"), "old").unwrap();
+        let settings = SandboxSettings { sandbox: true, sandbox_network: true, ..Default::default() };
+
+        let script = "echo x > new.txt && mkdir -p target/debug && echo y > target/debug/out && echo new > Cargo.toml.tmp && mv Cargo.toml.tmp Cargo.toml && rm new.txt";
+        let Some((ok, out)) = run_sandboxed(&settings, &project_dir, script).await else { return };
+        assert!(ok, "{}", out);
+        assert_eq!(std::fs::read_to_string(project_dir.join("target").join("debug").join("out")).unwrap().trim(), "y");
+        assert_eq!(std::fs::read_to_string(project_dir.join("Cargo.toml")).unwrap().trim(), "new");
+        assert!(!project_dir.join("new.txt").exists());
+
+        let (ok, _) = run_sandboxed(&settings, &project_dir, "echo x > .git/config").await.unwrap();
+        assert!(!ok);
+        assert!(!project_dir.join(".git").join("config").exists());
+    }
+}
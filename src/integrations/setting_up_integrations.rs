@@ -119,7 +119,7 @@ pub fn read_integrations_d(
             };
             files_to_read.push((path_str, integr_name.to_string(), project_path));
         }
-        // Find special files that start with cmdline_* and service_*
+        // Find special files that start with cmdline_*, service_* and mcp_*
         if let Ok(entries) = fs::read_dir(config_dir.join("integrations.d")) {
             let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
             entries.sort_by_key(|entry| entry.file_name());
@@ -137,7 +137,7 @@ pub fn read_integrations_d(
                         continue;
                     }
                 };
-                if file_name_str.starts_with("cmdline_") || file_name_str.starts_with("service_") {
+                if file_name_str.starts_with("cmdline_") || file_name_str.starts_with("service_") || file_name_str.starts_with("mcp_") {
                     files_to_read.push((entry.path().to_string_lossy().to_string(), file_name_str_no_yaml.to_string(), project_path));
                 }
             }
@@ -195,7 +195,7 @@ pub fn read_integrations_d(
                     if let Some(mapping) = y.as_mapping() {
                         for (key, value) in mapping {
                             if let Some(key_str) = key.as_str() {
-                                if key_str.starts_with("cmdline_") || key_str.starts_with("service_") {
+                                if key_str.starts_with("cmdline_") || key_str.starts_with("service_") || key_str.starts_with("mcp_") {
                                     let mut rec: IntegrationRecord = Default::default();
                                     rec.integr_config_path = integrations_yaml_path.clone();
                                     rec.integr_name = key_str.to_string();
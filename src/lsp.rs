@@ -2,6 +2,8 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::io::Write;
 
 use serde::{Deserialize, Serialize};
@@ -14,13 +16,20 @@ use tower_lsp::lsp_types::*;
 use tracing::{error, info};
 
 use crate::call_validation::{CodeCompletionInputs, CodeCompletionPost, CursorPosition, SamplingParameters};
+use crate::ast::diagnostics::AstDiagnosticSeverity;
 use crate::files_in_workspace;
-use crate::files_in_workspace::{on_did_change, on_did_delete};
+use crate::files_in_workspace::{on_did_change_incremental, on_did_delete};
+use crate::lsp_navigation;
 use crate::global_context::{CommandLine, GlobalContext};
 use crate::http::routers::v1::code_completion::handle_v1_code_completion;
 use crate::telemetry::snippets_collection;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const ACCEPT_COMPLETION_COMMAND: &str = "refact.acceptCompletion";
+const INLINE_COMPLETION_MAX_NEW_TOKENS: u32 = 50;
+const INLINE_COMPLETION_MULTILINE_MAX_NEW_TOKENS: u32 = 256;
+const INLINE_COMPLETION_TEMPERATURE: f32 = 0.2;
+const DIAGNOSTICS_DEBOUNCE_MS: u64 = 300;
 
 
 #[derive(Debug, Deserialize)]
@@ -38,6 +47,8 @@ impl Display for APIError {
 pub struct LspBackend {
     pub gcx: Arc<ARwLock<GlobalContext>>,
     pub client: tower_lsp::Client,
+    pub inline_completion_dynamic: Arc<AtomicBool>,  // the client registers textDocument/inlineCompletion dynamically, set by LspCapabilitiesPatch
+    pub diagnostics_pending: Arc<StdMutex<HashMap<Url, i32>>>,  // the latest version changed by didChange, not diagnosed yet
 }
 
 
@@ -118,6 +129,53 @@ pub struct SuccessRes {
     pub success: bool,
 }
 
+// textDocument/inlineCompletion is LSP 3.18, lsp-types we use doesn't have it yet
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineCompletionContext {
+    pub trigger_kind: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selected_completion_info: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineCompletionParams {
+    #[serde(flatten)]
+    pub text_document_position: TextDocumentPositionParams,
+    pub context: InlineCompletionContext,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineCompletionItem {
+    pub insert_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct InlineCompletionList {
+    pub items: Vec<InlineCompletionItem>,
+}
+
+// Same rule as in IDE plugins: nothing but whitespace after the cursor means multiline completion,
+// and multiline completion replaces the trailing whitespace on the cursor line
+fn inline_completion_range(txt: &str, position: &Position) -> Result<(bool, Range)> {
+    let rope = ropey::Rope::from_str(txt);
+    let cursor_idx = files_in_workspace::lsp_position_to_char_idx(&rope, position).map_err(|e| internal_error(e))?;
+    let line_end_idx = files_in_workspace::lsp_position_to_char_idx(&rope, &Position::new(position.line, u32::MAX)).map_err(|e| internal_error(e))?;
+    let line_start_idx = files_in_workspace::lsp_position_to_char_idx(&rope, &Position::new(position.line, 0)).map_err(|e| internal_error(e))?;
+    let multiline = rope.slice(cursor_idx..line_end_idx).to_string().trim().is_empty();
+    if !multiline {
+        return Ok((false, Range::new(*position, *position)));
+    }
+    let line_end_character = rope.slice(line_start_idx..line_end_idx).to_string().encode_utf16().count() as u32;
+    Ok((true, Range::new(*position, Position::new(position.line, line_end_character))))
+}
+
 impl LspBackend {
     async fn flat_params_to_code_completion_post(&self, params: &CompletionParams1) -> Result<CodeCompletionPost> {
         let path = crate::files_correction::canonical_path(&params.text_document_position.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
@@ -169,6 +227,39 @@ impl LspBackend {
         Ok(value)
     }
 
+    pub async fn inline_completion(&self, params: InlineCompletionParams) -> Result<InlineCompletionList> {
+        let position = params.text_document_position.position;
+        let path = crate::files_correction::canonical_path(&params.text_document_position.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
+        let txt = match self.gcx.read().await.documents_state.memory_document_map.get(&path) {
+            Some(doc) => doc.read().await.clone().get_text_or_read_from_disk(self.gcx.clone()).await.unwrap_or_default(),
+            None => return Err(internal_error("document not found"))
+        };
+        let (multiline, range) = inline_completion_range(&txt, &position)?;
+
+        let completion_res = self.get_completions(CompletionParams1 {
+            text_document_position: params.text_document_position,
+            parameters: RequestParams {
+                max_new_tokens: if multiline { INLINE_COMPLETION_MULTILINE_MAX_NEW_TOKENS } else { INLINE_COMPLETION_MAX_NEW_TOKENS },
+                temperature: INLINE_COMPLETION_TEMPERATURE,
+            },
+            multiline,
+        }).await?;
+
+        let items = completion_res.choices.into_iter()
+            .filter(|choice| !choice.code_completion.is_empty())
+            .map(|choice| InlineCompletionItem {
+                insert_text: choice.code_completion,
+                range: Some(range),
+                command: Some(Command {
+                    title: "Accept Completion".to_string(),
+                    command: ACCEPT_COMPLETION_COMMAND.to_string(),
+                    arguments: Some(vec![serde_json::json!({"snippet_telemetry_id": completion_res.snippet_telemetry_id})]),
+                }),
+            })
+            .collect();
+        Ok(InlineCompletionList { items })
+    }
+
     pub async fn accept_snippet(&self, params: SnippetAcceptedParams) -> Result<SuccessRes> {
         let success = snippets_collection::snippet_accepted(self.gcx.clone(), params.snippet_telemetry_id).await;
         Ok(SuccessRes { success })
@@ -181,6 +272,23 @@ impl LspBackend {
         Ok(SuccessRes { success: true })
     }
 
+    // Typing sends didChange on every key, diagnostics go out once the document stops changing for a moment
+    fn publish_diagnostics_debounced(&self, uri: Url, cpath: PathBuf, version: i32) {
+        self.diagnostics_pending.lock().unwrap().insert(uri.clone(), version);
+        let (gcx, client, diagnostics_pending) = (self.gcx.clone(), self.client.clone(), self.diagnostics_pending.clone());
+        tokio::spawn(async move {
+            tokio::time::sleep(tokio::time::Duration::from_millis(DIAGNOSTICS_DEBOUNCE_MS)).await;
+            {
+                let mut pending_locked = diagnostics_pending.lock().unwrap();
+                if pending_locked.get(&uri) != Some(&version) {
+                    return;  // a newer change has its own timer, or the document was closed
+                }
+                pending_locked.remove(&uri);
+            }
+            publish_diagnostics(gcx, client, uri, &cpath, Some(version)).await;
+        });
+    }
+
     async fn ping_http_server(&self) -> Result<()> {
         let (port, http_client) = {
             let gcx_locked = self.gcx.write().await;
@@ -206,6 +314,51 @@ impl LspBackend {
  }
 
 
+// With expected_version, nothing is published if the document has changed since, the newer version will be diagnosed
+async fn publish_diagnostics(gcx: Arc<ARwLock<GlobalContext>>, client: tower_lsp::Client, uri: Url, cpath: &PathBuf, expected_version: Option<i32>) {
+    let doc_maybe = gcx.read().await.documents_state.memory_document_map.get(cpath).cloned();
+    let (text, version) = match doc_maybe {
+        Some(doc) => {
+            let doc_locked = doc.read().await;
+            (doc_locked.doc_text.as_ref().map(|x| x.to_string()), doc_locked.doc_version)
+        }
+        None => (None, None),
+    };
+    let text = match text {
+        Some(x) => x,
+        None => return,
+    };
+    if expected_version.is_some() && version != expected_version {
+        return;
+    }
+    let diagnostics = crate::ast::diagnostics::diagnostics_for_text(cpath, &text).into_iter().map(|d| Diagnostic {
+        range: Range::new(
+            Position::new(d.line1.saturating_sub(1) as u32, d.col1 as u32),
+            Position::new(d.line2.saturating_sub(1) as u32, d.col2 as u32),
+        ),
+        severity: Some(match d.severity {
+            AstDiagnosticSeverity::Error => DiagnosticSeverity::ERROR,
+            AstDiagnosticSeverity::Warning => DiagnosticSeverity::WARNING,
+        }),
+        source: Some(format!("refact-{}", d.source)),
+        message: d.message,
+        ..Default::default()
+    }).collect();
+    if expected_version.is_some() && current_doc_version(gcx.clone(), cpath).await != expected_version {
+        return;
+    }
+    client.publish_diagnostics(uri, diagnostics, version).await;
+}
+
+async fn current_doc_version(gcx: Arc<ARwLock<GlobalContext>>, cpath: &PathBuf) -> Option<i32> {
+    let doc_maybe = gcx.read().await.documents_state.memory_document_map.get(cpath).cloned();
+    match doc_maybe {
+        Some(doc) => doc.read().await.doc_version,
+        None => None,
+    }
+}
+
+
 #[tower_lsp::async_trait]
 impl LanguageServer for LspBackend {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
@@ -256,9 +409,18 @@ impl LanguageServer for LspBackend {
             }),
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
                 completion_provider: Some(completion_options),
+                definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![ACCEPT_COMPLETION_COMMAND.to_string()],
+                    work_done_progress_options: WorkDoneProgressOptions { work_done_progress: Some(false) },
+                }),
+                // typeHierarchyProvider and inlineCompletionProvider are added by LspCapabilitiesPatch
                 workspace: Some(WorkspaceServerCapabilities {
                     workspace_folders: Some(WorkspaceFoldersServerCapabilities {
                         supported: Some(true),
@@ -283,6 +445,17 @@ impl LanguageServer for LspBackend {
             .log_message(MessageType::INFO, "rust LSP received initialized()")
             .await;
         let _ = info!("rust LSP received initialized()");
+        if !self.inline_completion_dynamic.load(Ordering::SeqCst) {
+            return;
+        }
+        let registration = Registration {
+            id: "refact-inline-completion".to_string(),
+            method: "textDocument/inlineCompletion".to_string(),
+            register_options: None,
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            info!("client didn't accept textDocument/inlineCompletion registration: {}", e);
+        }
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
@@ -294,8 +467,10 @@ impl LanguageServer for LspBackend {
             self.gcx.clone(),
             &cpath,
             &params.text_document.text,
-            &params.text_document.language_id
-        ).await
+            &params.text_document.language_id,
+            params.text_document.version,
+        ).await;
+        publish_diagnostics(self.gcx.clone(), self.client.clone(), params.text_document.uri, &cpath, None).await;
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
@@ -307,15 +482,19 @@ impl LanguageServer for LspBackend {
             self.gcx.clone(),
             &cpath,
         ).await;
+        self.diagnostics_pending.lock().unwrap().remove(&params.text_document.uri);
+        self.client.publish_diagnostics(params.text_document.uri, vec![], None).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let path = crate::files_correction::canonical_path(&params.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
-        on_did_change(
+        on_did_change_incremental(
             self.gcx.clone(),
             &path,
-            &params.content_changes[0].text  // TODO: This text could be just a part of the whole file (if range is not none)
-        ).await
+            params.text_document.version,
+            &params.content_changes,
+        ).await;
+        self.publish_diagnostics_debounced(params.text_document.uri, path, params.text_document.version);
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
@@ -337,6 +516,65 @@ impl LanguageServer for LspBackend {
         Ok(Some(CompletionResponse::Array(vec![])))
     }
 
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<serde_json::Value>> {
+        if params.command != ACCEPT_COMPLETION_COMMAND {
+            return Err(Error::invalid_params(format!("unknown command {:?}", params.command)));
+        }
+        let accepted_params = params.arguments.into_iter().next()
+            .and_then(|arg| serde_json::from_value::<SnippetAcceptedParams>(arg).ok())
+            .ok_or(Error::invalid_params("expected {\"snippet_telemetry_id\": N} as the first argument"))?;
+        let res = self.accept_snippet(accepted_params).await?;
+        Ok(Some(serde_json::json!(res)))
+    }
+
+    async fn goto_definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
+        let cpath = crate::files_correction::canonical_path(&params.text_document_position_params.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
+        let locations = lsp_navigation::goto_definition(self.gcx.clone(), &cpath, &params.text_document_position_params.position).await;
+        if locations.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(GotoDefinitionResponse::Array(locations)))
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let cpath = crate::files_correction::canonical_path(&params.text_document_position.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
+        let locations = lsp_navigation::references(
+            self.gcx.clone(),
+            &cpath,
+            &params.text_document_position.position,
+            params.context.include_declaration,
+        ).await;
+        Ok(Some(locations))
+    }
+
+    async fn document_symbol(&self, params: DocumentSymbolParams) -> Result<Option<DocumentSymbolResponse>> {
+        let cpath = crate::files_correction::canonical_path(&params.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
+        let symbols = lsp_navigation::document_symbols(self.gcx.clone(), &cpath).await;
+        Ok(Some(DocumentSymbolResponse::Nested(symbols)))
+    }
+
+    async fn symbol(&self, params: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+        let symbols = lsp_navigation::workspace_symbols(self.gcx.clone(), &params.query).await;
+        Ok(Some(symbols))
+    }
+
+    async fn prepare_type_hierarchy(&self, params: TypeHierarchyPrepareParams) -> Result<Option<Vec<TypeHierarchyItem>>> {
+        let cpath = crate::files_correction::canonical_path(&params.text_document_position_params.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
+        let items = lsp_navigation::prepare_type_hierarchy(self.gcx.clone(), &cpath, &params.text_document_position_params.position).await;
+        if items.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(items))
+    }
+
+    async fn supertypes(&self, params: TypeHierarchySupertypesParams) -> Result<Option<Vec<TypeHierarchyItem>>> {
+        Ok(Some(lsp_navigation::type_hierarchy_neighbours(self.gcx.clone(), &params.item, true).await))
+    }
+
+    async fn subtypes(&self, params: TypeHierarchySubtypesParams) -> Result<Option<Vec<TypeHierarchyItem>>> {
+        Ok(Some(lsp_navigation::type_hierarchy_neighbours(self.gcx.clone(), &params.item, false).await))
+    }
+
     async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
         for folder in params.event.added {
             info!("did_change_workspace_folders/add {}", folder.name);
@@ -366,18 +604,83 @@ impl LanguageServer for LspBackend {
     }
 }
 
+// lsp-types 0.94 that tower-lsp 0.20 depends on has no typeHierarchyProvider in ServerCapabilities and predates
+// textDocument/inlineCompletion: the client capability is read from the raw initialize request, and the server
+// capabilities are added to the raw initialize response
+pub struct LspCapabilitiesPatch<S> {
+    inner: S,
+    inline_completion_dynamic: Arc<AtomicBool>,
+}
+
+fn client_registers_inline_completion_dynamically(initialize_params: Option<&serde_json::Value>) -> bool {
+    initialize_params
+        .and_then(|p| p.pointer("/capabilities/textDocument/inlineCompletion/dynamicRegistration"))
+        .and_then(|x| x.as_bool())
+        .unwrap_or(false)
+}
+
+fn patch_server_capabilities(response: tower_lsp::jsonrpc::Response, inline_completion_dynamic: bool) -> tower_lsp::jsonrpc::Response {
+    let (id, body) = response.into_parts();
+    let body = body.map(|mut result| {
+        if let Some(capabilities) = result.get_mut("capabilities").and_then(|x| x.as_object_mut()) {
+            capabilities.insert("typeHierarchyProvider".to_string(), serde_json::json!(true));
+            // registered in initialized() instead, a capability shouldn't be both static and dynamic
+            if !inline_completion_dynamic {
+                capabilities.insert("inlineCompletionProvider".to_string(), serde_json::json!(true));
+            }
+        }
+        result
+    });
+    tower_lsp::jsonrpc::Response::from_parts(id, body)
+}
+
+impl<S> tower::Service<tower_lsp::jsonrpc::Request> for LspCapabilitiesPatch<S>
+where
+    S: tower::Service<tower_lsp::jsonrpc::Request, Response = Option<tower_lsp::jsonrpc::Response>>,
+    S::Future: Send + 'static,
+{
+    type Response = Option<tower_lsp::jsonrpc::Response>;
+    type Error = S::Error;
+    type Future = futures::future::BoxFuture<'static, std::result::Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<std::result::Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: tower_lsp::jsonrpc::Request) -> Self::Future {
+        let is_initialize = request.method() == "initialize";
+        if is_initialize {
+            self.inline_completion_dynamic.store(client_registers_inline_completion_dynamically(request.params()), Ordering::SeqCst);
+        }
+        let inline_completion_dynamic = self.inline_completion_dynamic.load(Ordering::SeqCst);
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let response = future.await?;
+            if !is_initialize {
+                return Ok(response);
+            }
+            Ok(response.map(|r| patch_server_capabilities(r, inline_completion_dynamic)))
+        })
+    }
+}
+
 async fn build_lsp_service(
     gcx: Arc<ARwLock<GlobalContext>>,
-) -> (LspService::<LspBackend>, ClientSocket) {
+) -> (LspCapabilitiesPatch<LspService::<LspBackend>>, ClientSocket) {
+    let inline_completion_dynamic = Arc::new(AtomicBool::new(false));
+    let inline_completion_dynamic_t = inline_completion_dynamic.clone();
     let (lsp_service, socket) = LspService::build(|client| LspBackend {
         gcx,
         client,
+        inline_completion_dynamic: inline_completion_dynamic_t,
+        diagnostics_pending: Arc::new(StdMutex::new(HashMap::new())),
     })
         .custom_method("refact/getCompletions", LspBackend::get_completions)
+        .custom_method("textDocument/inlineCompletion", LspBackend::inline_completion)
         .custom_method("refact/acceptCompletion", LspBackend::accept_snippet)
         .custom_method("refact/setActiveDocument", LspBackend::set_active_document)
         .finish();
-    (lsp_service, socket)
+    (LspCapabilitiesPatch { inner: lsp_service, inline_completion_dynamic }, socket)
 }
 
 pub async fn spawn_lsp_task(
@@ -437,3 +740,51 @@ pub async fn spawn_lsp_task(
 
     None
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_inline_completion_range() {
+        let txt = "fn main() {\n    let x = \n    foo(1)   \n}\n";
+        assert_eq!(inline_completion_range(txt, &Position::new(1, 12)).unwrap(), (true, Range::new(Position::new(1, 12), Position::new(1, 12))));
+        assert_eq!(inline_completion_range(txt, &Position::new(1, 8)).unwrap(), (false, Range::new(Position::new(1, 8), Position::new(1, 8))));
+        assert_eq!(inline_completion_range(txt, &Position::new(2, 10)).unwrap(), (true, Range::new(Position::new(2, 10), Position::new(2, 13))));
+    }
+
+    #[test]
+    fn test_initialize_capabilities_patch() {
+        let dynamic = serde_json::json!({"capabilities": {"textDocument": {"inlineCompletion": {"dynamicRegistration": true}}}});
+        assert!(client_registers_inline_completion_dynamically(Some(&dynamic)));
+        assert!(!client_registers_inline_completion_dynamically(Some(&serde_json::json!({"capabilities": {}}))));
+        assert!(!client_registers_inline_completion_dynamically(None));
+
+        let response = || tower_lsp::jsonrpc::Response::from_ok(1.into(), serde_json::json!({"capabilities": {"definitionProvider": true}}));
+        let (_, body) = patch_server_capabilities(response(), false).into_parts();
+        assert_eq!(body.unwrap()["capabilities"], serde_json::json!({"definitionProvider": true, "typeHierarchyProvider": true, "inlineCompletionProvider": true}));
+        let (_, body) = patch_server_capabilities(response(), true).into_parts();
+        assert_eq!(body.unwrap()["capabilities"], serde_json::json!({"definitionProvider": true, "typeHierarchyProvider": true}));
+    }
+
+    #[tokio::test]
+    async fn test_execute_accept_completion_command() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cmdline = CommandLine::from_iter(["refact-lsp"]);
+        let (gcx, _ask_shutdown_receiver) = crate::global_context::global_context_from_cmdline(cmdline, tmp.path().to_path_buf(), tmp.path().to_path_buf()).await;
+        let (service, _socket) = build_lsp_service(gcx).await;
+        let backend = service.inner.inner();
+        let command = |command: &str, arguments: Vec<serde_json::Value>| ExecuteCommandParams {
+            command: command.to_string(),
+            arguments,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+        assert!(backend.execute_command(command("refact.unknown", vec![])).await.is_err());
+        assert!(backend.execute_command(command(ACCEPT_COMPLETION_COMMAND, vec![serde_json::json!("oops")])).await.is_err());
+        // nothing was completed in this process, so there's nothing to accept
+        let res = backend.execute_command(command(ACCEPT_COMPLETION_COMMAND, vec![serde_json::json!({"snippet_telemetry_id": 101})])).await.unwrap();
+        assert_eq!(res, Some(serde_json::json!({"success": false})));
+    }
+}
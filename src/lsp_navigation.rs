@@ -0,0 +1,389 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use ropey::Rope;
+use serde_json::json;
+use tokio::sync::{Mutex as AMutex, RwLock as ARwLock};
+use tower_lsp::lsp_types::*;
+
+use crate::ast::ast_db::{definition_paths_fuzzy, definitions, doc_defs, doc_usages, subclasses, superclasses, usages};
+use crate::ast::ast_structs::{AstDB, AstDefinition};
+use crate::ast::treesitter::structs::SymbolType;
+use crate::files_in_workspace::{get_file_text_from_memory_or_disk, lsp_position_to_char_idx};
+use crate::global_context::GlobalContext;
+
+
+const REFERENCES_LIMIT: usize = 1000;
+const WORKSPACE_SYMBOLS_TOP_N: usize = 100;
+const WORKSPACE_SYMBOLS_CANDIDATES: usize = 1000;
+
+// Files are read once per request, ranges are found by looking for the symbol name in the line
+struct TextCache {
+    gcx: Arc<ARwLock<GlobalContext>>,
+    texts: HashMap<String, Option<Rope>>,
+}
+
+impl TextCache {
+    fn new(gcx: Arc<ARwLock<GlobalContext>>) -> Self {
+        TextCache { gcx, texts: HashMap::new() }
+    }
+
+    async fn get(&mut self, cpath: &String) -> Option<&Rope> {
+        if !self.texts.contains_key(cpath) {
+            let text = get_file_text_from_memory_or_disk(self.gcx.clone(), &PathBuf::from(cpath)).await.ok();
+            self.texts.insert(cpath.clone(), text.map(|x| Rope::from_str(&x)));
+        }
+        self.texts.get(cpath).unwrap().as_ref()
+    }
+
+    // line0 starts from 0, falls back to the whole line if the name isn't there
+    async fn name_range(&mut self, cpath: &String, line0: usize, name: &str) -> Range {
+        let whole_line = Range::new(Position::new(line0 as u32, 0), Position::new(line0 as u32 + 1, 0));
+        let Some(rope) = self.get(cpath).await else {
+            return whole_line;
+        };
+        if line0 >= rope.len_lines() || name.is_empty() {
+            return whole_line;
+        }
+        let line = rope.line(line0).to_string();
+        let Some(byte_pos) = find_identifier(&line, name) else {
+            return whole_line;
+        };
+        let start: u32 = line[..byte_pos].encode_utf16().count() as u32;
+        let end: u32 = start + name.encode_utf16().count() as u32;
+        Range::new(Position::new(line0 as u32, start), Position::new(line0 as u32, end))
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// finds `name` as a whole word, so `age` doesn't match inside `page`
+fn find_identifier(line: &str, name: &str) -> Option<usize> {
+    line.match_indices(name).map(|(pos, _)| pos).find(|pos| {
+        let before = line[..*pos].chars().next_back();
+        let after = line[*pos + name.len()..].chars().next();
+        !before.map(is_identifier_char).unwrap_or(false) && !after.map(is_identifier_char).unwrap_or(false)
+    })
+}
+
+fn identifier_at(rope: &Rope, position: &Position) -> Option<String> {
+    let idx = lsp_position_to_char_idx(rope, position).ok()?;
+    let mut start = idx;
+    while start > 0 && is_identifier_char(rope.char(start - 1)) {
+        start -= 1;
+    }
+    let mut end = idx;
+    while end < rope.len_chars() && is_identifier_char(rope.char(end)) {
+        end += 1;
+    }
+    if start == end {
+        return None;
+    }
+    Some(rope.slice(start..end).to_string())
+}
+
+async fn ast_index_maybe(gcx: Arc<ARwLock<GlobalContext>>) -> Option<Arc<AMutex<AstDB>>> {
+    let ast_service = gcx.read().await.ast_service.clone()?;
+    let ast_index = ast_service.lock().await.ast_index.clone();
+    Some(ast_index)
+}
+
+fn symbol_kind(def: &AstDefinition) -> SymbolKind {
+    match def.symbol_type {
+        SymbolType::Module => SymbolKind::MODULE,
+        SymbolType::StructDeclaration => SymbolKind::CLASS,
+        SymbolType::TypeAlias => SymbolKind::INTERFACE,
+        SymbolType::ClassFieldDeclaration => SymbolKind::FIELD,
+        SymbolType::FunctionDeclaration => SymbolKind::FUNCTION,
+        _ => SymbolKind::VARIABLE,
+    }
+}
+
+fn full_range(def: &AstDefinition) -> Range {
+    Range::new(
+        Position::new(def.full_line1().saturating_sub(1) as u32, 0),
+        Position::new(def.full_line2() as u32, 0),
+    )
+}
+
+fn container_name(def: &AstDefinition) -> Option<String> {
+    let path = def.path_drop0();
+    path.rsplit_once("::").map(|(container, _)| container.to_string())
+}
+
+async fn def_location(texts: &mut TextCache, def: &AstDefinition) -> Option<Location> {
+    let uri = Url::from_file_path(&def.cpath).ok()?;
+    let range = texts.name_range(&def.cpath, def.decl_line1.saturating_sub(1), &def.name()).await;
+    Some(Location::new(uri, range))
+}
+
+async fn type_hierarchy_item(texts: &mut TextCache, def: &AstDefinition) -> Option<TypeHierarchyItem> {
+    let location = def_location(texts, def).await?;
+    let mut range = full_range(def);
+    // selection_range must be inside of range
+    range.start = range.start.min(location.range.start);
+    range.end = range.end.max(location.range.end);
+    Some(TypeHierarchyItem {
+        name: def.name(),
+        kind: symbol_kind(def),
+        tags: None,
+        detail: Some(def.path_drop0()),
+        uri: location.uri,
+        range,
+        selection_range: location.range,
+        data: Some(json!(def.path())),
+    })
+}
+
+fn unique_by_path(defs: Vec<Arc<AstDefinition>>) -> Vec<Arc<AstDefinition>> {
+    let mut seen = HashSet::new();
+    defs.into_iter().filter(|d| seen.insert(d.path())).collect()
+}
+
+pub async fn definitions_at_position(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    cpath: &PathBuf,
+    position: &Position,
+) -> Vec<Arc<AstDefinition>> {
+    let Some(ast_index) = ast_index_maybe(gcx.clone()).await else {
+        return vec![];
+    };
+    let text = match get_file_text_from_memory_or_disk(gcx.clone(), cpath).await {
+        Ok(text) => text,
+        Err(e) => {
+            tracing::info!("cannot look up symbol in {}: {}", cpath.display(), e);
+            return vec![];
+        }
+    };
+    let Some(name) = identifier_at(&Rope::from_str(&text), position) else {
+        return vec![];
+    };
+    let cpath_str = cpath.to_string_lossy().to_string();
+    let line1 = position.line as usize + 1;
+
+    // the indexer already knows where usages on this line point to
+    let mut result = vec![];
+    for (uline, resolved_as) in doc_usages(ast_index.clone(), &cpath_str).await {
+        if uline == line1 && resolved_as.rsplit("::").next() == Some(name.as_str()) {
+            result.extend(definitions(ast_index.clone(), &resolved_as).await);
+        }
+    }
+    if !result.is_empty() {
+        return unique_by_path(result);
+    }
+
+    // the cursor is on a definition
+    let defs_here: Vec<Arc<AstDefinition>> = doc_defs(ast_index.clone(), &cpath_str).await.into_iter()
+        .filter(|d| d.name() == name && d.decl_line1 <= line1 && line1 <= d.decl_line2)
+        .collect();
+    if !defs_here.is_empty() {
+        return defs_here;
+    }
+
+    // a usage the indexer could not link, guess by name
+    unique_by_path(definitions(ast_index, &name).await)
+}
+
+pub async fn goto_definition(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    cpath: &PathBuf,
+    position: &Position,
+) -> Vec<Location> {
+    let defs = definitions_at_position(gcx.clone(), cpath, position).await;
+    let mut texts = TextCache::new(gcx);
+    let mut locations = vec![];
+    for def in defs {
+        if let Some(location) = def_location(&mut texts, &def).await {
+            locations.push(location);
+        }
+    }
+    locations
+}
+
+pub async fn references(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    cpath: &PathBuf,
+    position: &Position,
+    include_declaration: bool,
+) -> Vec<Location> {
+    let Some(ast_index) = ast_index_maybe(gcx.clone()).await else {
+        return vec![];
+    };
+    let defs = definitions_at_position(gcx.clone(), cpath, position).await;
+    let mut texts = TextCache::new(gcx);
+    let mut locations = vec![];
+    for def in defs {
+        if include_declaration {
+            if let Some(location) = def_location(&mut texts, &def).await {
+                locations.push(location);
+            }
+        }
+        let name = def.name();
+        for (usedin, uline) in usages(ast_index.clone(), def.path(), REFERENCES_LIMIT).await {
+            let Ok(uri) = Url::from_file_path(&usedin.cpath) else {
+                continue;
+            };
+            let range = texts.name_range(&usedin.cpath, uline.saturating_sub(1), &name).await;
+            locations.push(Location::new(uri, range));
+        }
+    }
+    locations
+}
+
+#[allow(deprecated)]
+pub async fn workspace_symbols(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    query: &str,
+) -> Vec<SymbolInformation> {
+    if query.is_empty() {
+        return vec![];
+    }
+    let Some(ast_index) = ast_index_maybe(gcx.clone()).await else {
+        return vec![];
+    };
+    let paths = definition_paths_fuzzy(ast_index.clone(), query, WORKSPACE_SYMBOLS_TOP_N, WORKSPACE_SYMBOLS_CANDIDATES).await;
+    let mut defs = vec![];
+    for path in paths {
+        defs.extend(definitions(ast_index.clone(), &path).await);
+    }
+    let mut texts = TextCache::new(gcx);
+    let mut symbols = vec![];
+    for def in unique_by_path(defs) {
+        let Some(location) = def_location(&mut texts, &def).await else {
+            continue;
+        };
+        symbols.push(SymbolInformation {
+            name: def.name(),
+            kind: symbol_kind(&def),
+            tags: None,
+            deprecated: None,
+            location,
+            container_name: container_name(&def),
+        });
+    }
+    symbols
+}
+
+#[allow(deprecated)]
+pub async fn document_symbols(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    cpath: &PathBuf,
+) -> Vec<DocumentSymbol> {
+    let Some(ast_index) = ast_index_maybe(gcx.clone()).await else {
+        return vec![];
+    };
+    let cpath_str = cpath.to_string_lossy().to_string();
+    let defs = doc_defs(ast_index, &cpath_str).await;
+    let mut texts = TextCache::new(gcx);
+    let mut flat: Vec<(Vec<String>, DocumentSymbol)> = vec![];
+    for def in defs.iter() {
+        let selection_range = texts.name_range(&cpath_str, def.decl_line1.saturating_sub(1), &def.name()).await;
+        let mut range = full_range(def);
+        range.start = range.start.min(selection_range.start);
+        range.end = range.end.max(selection_range.end);
+        flat.push((def.official_path.clone(), DocumentSymbol {
+            name: def.name(),
+            detail: None,
+            kind: symbol_kind(def),
+            tags: None,
+            deprecated: None,
+            range,
+            selection_range,
+            children: None,
+        }));
+    }
+
+    // a.b.c goes into a.b if a.b is a definition in this file, otherwise it's on the top level
+    let all_paths: HashSet<Vec<String>> = flat.iter().map(|(path, _)| path.clone()).collect();
+    fn build(flat: &Vec<(Vec<String>, DocumentSymbol)>, parent: Option<&Vec<String>>, all_paths: &HashSet<Vec<String>>) -> Vec<DocumentSymbol> {
+        let mut result = vec![];
+        for (path, symbol) in flat.iter() {
+            let parent_path = path[..path.len().saturating_sub(1)].to_vec();
+            let belongs_here = match parent {
+                Some(parent) => parent_path == *parent,
+                None => !all_paths.contains(&parent_path),
+            };
+            if !belongs_here {
+                continue;
+            }
+            let mut symbol = symbol.clone();
+            let children = build(flat, Some(path), all_paths);
+            if !children.is_empty() {
+                symbol.children = Some(children);
+            }
+            result.push(symbol);
+        }
+        result.sort_by_key(|x| (x.range.start.line, x.range.start.character));
+        result
+    }
+    build(&flat, None, &all_paths)
+}
+
+pub async fn prepare_type_hierarchy(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    cpath: &PathBuf,
+    position: &Position,
+) -> Vec<TypeHierarchyItem> {
+    let defs = definitions_at_position(gcx.clone(), cpath, position).await;
+    let mut texts = TextCache::new(gcx);
+    let mut items = vec![];
+    for def in defs.iter().filter(|d| d.symbol_type == SymbolType::StructDeclaration) {
+        if let Some(item) = type_hierarchy_item(&mut texts, def).await {
+            items.push(item);
+        }
+    }
+    items
+}
+
+pub async fn type_hierarchy_neighbours(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    item: &TypeHierarchyItem,
+    want_supertypes: bool,
+) -> Vec<TypeHierarchyItem> {
+    let Some(ast_index) = ast_index_maybe(gcx.clone()).await else {
+        return vec![];
+    };
+    let Some(path) = item.data.as_ref().and_then(|x| x.as_str()) else {
+        return vec![];
+    };
+    let Some(def) = definitions(ast_index.clone(), path).await.into_iter().find(|d| d.path() == path) else {
+        return vec![];
+    };
+    let neighbours = if want_supertypes {
+        superclasses(ast_index.clone(), &def).await
+    } else {
+        subclasses(ast_index.clone(), &def.this_is_a_class).await
+    };
+    let mut texts = TextCache::new(gcx);
+    let mut items = vec![];
+    for neighbour in unique_by_path(neighbours) {
+        if let Some(item) = type_hierarchy_item(&mut texts, &neighbour).await {
+            items.push(item);
+        }
+    }
+    items
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identifier_at() {
+        let rope = Rope::from_str("let x = goat.jump(42);\n");
+        assert_eq!(identifier_at(&rope, &Position::new(0, 10)), Some("goat".to_string()));
+        assert_eq!(identifier_at(&rope, &Position::new(0, 13)), Some("jump".to_string()));
+        assert_eq!(identifier_at(&rope, &Position::new(0, 12)), Some("goat".to_string()));
+        assert_eq!(identifier_at(&rope, &Position::new(0, 7)), None);
+    }
+
+    #[test]
+    fn test_find_identifier() {
+        assert_eq!(find_identifier("page = age + 1", "age"), Some(7));
+        assert_eq!(find_identifier("page = 1", "age"), None);
+        assert_eq!(find_identifier("😀 age", "age"), Some(5));
+    }
+}
@@ -40,7 +40,12 @@ mod vecdb;
 mod knowledge;
 
 mod ast;
+mod doc_splitter;
+mod bm25;
+mod rerank;
+mod search_filter;
 mod subchat;
+mod chat_sessions;
 mod at_commands;
 mod tools;
 mod diffs;
@@ -60,6 +65,7 @@ mod restream;
 mod call_validation;
 mod dashboard;
 mod lsp;
+mod lsp_navigation;
 mod http;
 
 mod integrations;
@@ -158,6 +164,18 @@ async fn main() {
         gcx_locked.ast_service = tmp;
     }
 
+    #[cfg(feature="vecdb")]
+    let bm25_on = cmdline.bm25 || cmdline.vecdb;
+    #[cfg(not(feature="vecdb"))]
+    let bm25_on = cmdline.bm25;
+    if bm25_on {
+        let cache_dir = gcx.read().await.cache_dir.clone();
+        let workspace_key = crate::files_in_workspace::workspace_key(gcx.clone()).await;
+        let tmp = Some(crate::bm25::bm25_thread::bm25_service_init(&cache_dir, &workspace_key).await);
+        let mut gcx_locked = gcx.write().await;
+        gcx_locked.bm25_service = tmp;
+    }
+
     // Privacy before we do anything else, the default is to block everything
     let _ = crate::privacy::load_privacy_if_needed(gcx.clone()).await;
 
@@ -197,6 +215,11 @@ async fn main() {
 
     background_tasks.abort().await;
     integrations::sessions::stop_sessions(gcx.clone()).await;
+    let ast_service_maybe = gcx.read().await.ast_service.clone();
+    if let Some(ast_service) = ast_service_maybe {
+        let ast_index = ast_service.lock().await.ast_index.clone();
+        crate::ast::ast_db::ast_db_flush(ast_index).await;
+    }
     info!("saving telemetry without sending, so should be quick");
     basic_transmit::basic_telemetry_compress(gcx.clone()).await;
     info!("bb\n");
@@ -0,0 +1,4 @@
+pub mod rerank_structs;
+pub mod rerank_heuristic;
+pub mod rerank_endpoint;
+pub mod rerank_highlev;
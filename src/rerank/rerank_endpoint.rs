@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::rerank::rerank_structs::{RerankCandidate, Reranker};
+
+
+// Cross-encoder scores are 0..1, relevance below that floor still keeps a bit of the retrieval score
+const ENDPOINT_SCORE_SCALE: f32 = 100.0;
+const RETRIEVAL_SCORE_WEIGHT: f32 = 0.2;
+
+
+pub struct EndpointReranker {
+    pub http_client: reqwest::Client,
+    pub endpoint: String,
+    pub endpoint_style: String,  // "openai" or "tei"
+    pub model: String,
+    pub api_key: String,
+}
+
+#[derive(Deserialize)]
+struct RerankResultOpenAI {
+    index: usize,
+    relevance_score: f32,
+}
+
+#[derive(Deserialize)]
+struct RerankResultTEI {
+    index: usize,
+    score: f32,
+}
+
+fn rerank_payload(endpoint_style: &str, model: &String, query: &String, texts: Vec<String>) -> Result<Value, String> {
+    match endpoint_style {
+        // cohere, jina, vllm and friends: {"model", "query", "documents"} => {"results": [{"index", "relevance_score"}]}
+        "openai" | "" => Ok(json!({
+            "model": model,
+            "query": query,
+            "documents": texts,
+        })),
+        // text-embeddings-inference: {"query", "texts"} => [{"index", "score"}]
+        "tei" => Ok(json!({
+            "query": query,
+            "texts": texts,
+            "raw_scores": false,
+        })),
+        _ => Err(format!("invalid rerank_endpoint_style: {}", endpoint_style)),
+    }
+}
+
+fn parse_rerank_response(endpoint_style: &str, json: Value, n: usize) -> Result<Vec<f32>, String> {
+    let unordered: Vec<(usize, f32)> = match endpoint_style {
+        "tei" => serde_json::from_value::<Vec<RerankResultTEI>>(json)
+            .map_err(|e| format!("rerank: failed to parse the response: {}", e))?
+            .into_iter().map(|x| (x.index, x.score)).collect(),
+        _ => serde_json::from_value::<Vec<RerankResultOpenAI>>(json["results"].clone())
+            .map_err(|e| format!("rerank: failed to parse the response: {}", e))?
+            .into_iter().map(|x| (x.index, x.relevance_score)).collect(),
+    };
+    // the endpoint may return only its own top_n, the rest is considered irrelevant
+    let mut result = vec![0.0; n];
+    for (index, score) in unordered {
+        if index >= n {
+            return Err(format!("rerank: index {} out of bounds, {} documents were sent", index, n));
+        }
+        result[index] = score;
+    }
+    Ok(result)
+}
+
+#[async_trait]
+impl Reranker for EndpointReranker {
+    fn name(&self) -> String {
+        format!("endpoint:{}", self.model)
+    }
+
+    fn needs_text(&self) -> bool {
+        true
+    }
+
+    async fn rerank(&self, query: &String, candidates: &Vec<RerankCandidate>) -> Result<Vec<f32>, String> {
+        if candidates.is_empty() {
+            return Ok(vec![]);
+        }
+        if self.endpoint.is_empty() {
+            return Err("no rerank_endpoint configured".to_string());
+        }
+        let style = self.endpoint_style.to_lowercase();
+        let payload = rerank_payload(&style, &self.model, query, candidates.iter().map(|c| c.text.clone()).collect())?;
+        let t0 = std::time::Instant::now();
+        let mut request = self.http_client.post(&self.endpoint).json(&payload);
+        if !self.api_key.is_empty() {
+            request = request.bearer_auth(&self.api_key);
+        }
+        let response = request.send().await.map_err(|e| format!("rerank: failed to send a request: {:?}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("rerank: bad status: {:?}", response.status()));
+        }
+        let json = response.json::<Value>().await.map_err(|e| format!("rerank: failed to parse the response: {:?}", e))?;
+        let relevance = parse_rerank_response(&style, json, candidates.len())?;
+        info!("rerank {} documents with {}, {:.3}s", candidates.len(), self.model, t0.elapsed().as_secs_f32());
+        Ok(candidates.iter().zip(relevance).map(|(c, r)| {
+            ENDPOINT_SCORE_SCALE * r.clamp(0.0, 1.0) * (1.0 - RETRIEVAL_SCORE_WEIGHT) + c.score * RETRIEVAL_SCORE_WEIGHT
+        }).collect())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rerank_response() {
+        let openai = json!({"results": [{"index": 2, "relevance_score": 0.9}, {"index": 0, "relevance_score": 0.1}]});
+        assert_eq!(parse_rerank_response("openai", openai, 3).unwrap(), vec![0.1, 0.0, 0.9]);
+        let tei = json!([{"index": 1, "score": 0.7}, {"index": 0, "score": 0.2}]);
+        assert_eq!(parse_rerank_response("tei", tei, 2).unwrap(), vec![0.2, 0.7]);
+        assert!(parse_rerank_response("tei", json!([{"index": 5, "score": 0.7}]), 2).is_err());
+        assert!(rerank_payload("sparkles", &"m".to_string(), &"q".to_string(), vec![]).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use async_trait::async_trait;
+
+use crate::bm25::bm25_index::bm25_tokenize;
+use crate::rerank::rerank_structs::{RerankCandidate, Reranker};
+
+
+const RECENCY_BOOST: f32 = 0.15;
+const RECENCY_HALF_LIFE_HOURS: f32 = 72.0;
+const PROXIMITY_BOOST: f32 = 0.15;
+const SYMBOL_MATCH_BOOST: f32 = 0.25;
+const MSTAT_CORRECT_BOOST: f32 = 0.25;
+const MSTAT_RELEVANT_BOOST: f32 = 0.10;
+const SAME_FILE_PENALTY: f32 = 0.5;
+const OVERLAPPING_WINDOW_PENALTY: f32 = 0.25;
+
+
+pub struct HeuristicReranker {
+    pub active_file: Option<PathBuf>,
+    pub now: SystemTime,
+}
+
+impl HeuristicReranker {
+    pub fn new(active_file: Option<PathBuf>) -> Self {
+        HeuristicReranker { active_file, now: SystemTime::now() }
+    }
+}
+
+fn recency_multiplier(mtime: Option<SystemTime>, now: SystemTime) -> f32 {
+    let age_hours = match mtime.and_then(|t| now.duration_since(t).ok()) {
+        Some(age) => age.as_secs_f32() / 3600.0,
+        None => return 1.0,
+    };
+    1.0 + RECENCY_BOOST * (-age_hours * std::f32::consts::LN_2 / RECENCY_HALF_LIFE_HOURS).exp()
+}
+
+fn proximity_multiplier(active_file: &Option<PathBuf>, file_path: &Option<PathBuf>) -> f32 {
+    let (active_dir, candidate_dir) = match (active_file.as_ref().and_then(|x| x.parent()), file_path.as_ref().and_then(|x| x.parent())) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return 1.0,
+    };
+    let active_depth = active_dir.components().count();
+    if active_depth == 0 {
+        return 1.0;
+    }
+    let shared = active_dir.components().zip(candidate_dir.components()).take_while(|(a, b)| a == b).count();
+    1.0 + PROXIMITY_BOOST * shared as f32 / active_depth as f32
+}
+
+fn symbol_match_multiplier(query_tokens: &HashSet<String>, symbols: &Vec<String>) -> f32 {
+    if query_tokens.is_empty() || symbols.is_empty() {
+        return 1.0;
+    }
+    let symbol_tokens: HashSet<String> = symbols.iter().flat_map(|s| bm25_tokenize(s)).collect();
+    let matched = query_tokens.iter().filter(|t| symbol_tokens.contains(*t)).count();
+    1.0 + SYMBOL_MATCH_BOOST * matched as f32 / query_tokens.len() as f32
+}
+
+fn mstat_multiplier(c: &RerankCandidate) -> f32 {
+    if c.mstat_times_used <= 0 {
+        return 1.0;
+    }
+    let correct = (c.mstat_correct / c.mstat_times_used as f64).clamp(-1.0, 1.0) as f32;
+    let relevant = (c.mstat_relevant / c.mstat_times_used as f64).clamp(-1.0, 1.0) as f32;
+    1.0 + MSTAT_CORRECT_BOOST * correct + MSTAT_RELEVANT_BOOST * relevant
+}
+
+fn same_place(a: &RerankCandidate, b: &RerankCandidate) -> (bool, bool) {
+    if a.file_path.is_none() || a.file_path != b.file_path {
+        return (false, false);
+    }
+    (true, a.start_line <= b.end_line && b.start_line <= a.end_line)
+}
+
+// Greedy pick of the best remaining candidate, penalized by what was already picked from the same file.
+// Without this, top results are often several windows of one file saying the same thing.
+fn diversify(candidates: &Vec<RerankCandidate>, scores: &mut Vec<f32>) {
+    let mut picked: Vec<usize> = vec![];
+    let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+    while !remaining.is_empty() {
+        let mut best: Option<(usize, f32)> = None;
+        for (pos, &i) in remaining.iter().enumerate() {
+            let mut s = scores[i];
+            let mut same_file_cnt = 0;
+            for &j in picked.iter() {
+                let (same_file, overlaps) = same_place(&candidates[i], &candidates[j]);
+                if overlaps {
+                    s *= OVERLAPPING_WINDOW_PENALTY;
+                }
+                if same_file {
+                    same_file_cnt += 1;
+                }
+            }
+            s /= 1.0 + SAME_FILE_PENALTY * same_file_cnt as f32;
+            if best.map(|(_, b)| s > b).unwrap_or(true) {
+                best = Some((pos, s));
+            }
+        }
+        let (pos, s) = best.unwrap();
+        let i = remaining.remove(pos);
+        scores[i] = s;
+        picked.push(i);
+    }
+}
+
+#[async_trait]
+impl Reranker for HeuristicReranker {
+    fn name(&self) -> String {
+        "heuristic".to_string()
+    }
+
+    async fn rerank(&self, query: &String, candidates: &Vec<RerankCandidate>) -> Result<Vec<f32>, String> {
+        let query_tokens: HashSet<String> = bm25_tokenize(query).into_iter().collect();
+        let mut scores: Vec<f32> = candidates.iter().map(|c| {
+            c.score
+                * recency_multiplier(c.mtime, self.now)
+                * proximity_multiplier(&self.active_file, &c.file_path)
+                * symbol_match_multiplier(&query_tokens, &c.symbols)
+                * mstat_multiplier(c)
+        }).collect();
+        diversify(candidates, &mut scores);
+        Ok(scores)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::rerank::rerank_structs::rerank_and_sort;
+
+    fn window(file: &str, start_line: u64, end_line: u64, score: f32) -> RerankCandidate {
+        RerankCandidate { file_path: Some(PathBuf::from(file)), start_line, end_line, score, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_heuristic_diversifies_same_file() {
+        let reranker = HeuristicReranker::new(None);
+        let candidates = vec![
+            window("/p/a.rs", 0, 20, 100.0),
+            window("/p/a.rs", 10, 30, 99.0),
+            window("/p/a.rs", 100, 120, 98.0),
+            window("/p/b.rs", 0, 20, 80.0),
+        ];
+        let order: Vec<usize> = rerank_and_sort(&reranker, &"".to_string(), &candidates).await.into_iter().map(|x| x.0).collect();
+        assert_eq!(order, vec![0, 3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_heuristic_boosts() {
+        let now = SystemTime::now();
+        let reranker = HeuristicReranker { active_file: Some(PathBuf::from("/p/src/net/http.rs")), now };
+        let query = "parse http headers".to_string();
+        let far = window("/p/docs/x.rs", 0, 10, 50.0);
+        let near = window("/p/src/net/client.rs", 0, 10, 50.0);
+        let with_symbol = RerankCandidate { symbols: vec!["Request::parse_headers".to_string()], ..window("/p/docs/y.rs", 0, 10, 50.0) };
+        let fresh = RerankCandidate { mtime: Some(now - Duration::from_secs(60)), ..window("/p/docs/z.rs", 0, 10, 50.0) };
+        let old = RerankCandidate { mtime: Some(now - Duration::from_secs(3600 * 24 * 60)), ..window("/p/docs/w.rs", 0, 10, 50.0) };
+        let scores = reranker.rerank(&query, &vec![far, near, with_symbol, fresh, old]).await.unwrap();
+        assert!(scores[1] > scores[0]);
+        assert!(scores[2] > scores[1]);
+        assert!(scores[3] > scores[0] && scores[3] > scores[4]);
+
+        let liked = RerankCandidate { mstat_correct: 3.0, mstat_relevant: 3.0, mstat_times_used: 3, file_path: None, ..window("", 0, 0, 50.0) };
+        let disliked = RerankCandidate { mstat_correct: -2.0, mstat_relevant: 0.0, mstat_times_used: 2, file_path: None, ..window("", 0, 0, 50.0) };
+        let scores = reranker.rerank(&query, &vec![liked, disliked]).await.unwrap();
+        assert!(scores[0] > 50.0 && scores[1] < 50.0);
+    }
+}
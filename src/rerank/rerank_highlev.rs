@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock as ARwLock;
+use tracing::info;
+
+use crate::bm25::bm25_hybrid::RankedHit;
+use crate::call_validation::ContextFile;
+use crate::caps::get_custom_rerank_api_key;
+use crate::global_context::{try_load_caps_quickly_if_not_present, GlobalContext};
+use crate::rerank::rerank_endpoint::EndpointReranker;
+use crate::rerank::rerank_heuristic::HeuristicReranker;
+use crate::rerank::rerank_structs::{rerank_and_sort, RerankCandidate, Reranker, RerankerChain};
+#[cfg(feature="vecdb")]
+use crate::vecdb::vdb_structs::MemoRecord;
+
+
+// Endpoint goes first, heuristic runs on top of its scores and also takes care of diversity
+pub async fn reranker_from_caps(gcx: Arc<ARwLock<GlobalContext>>) -> Box<dyn Reranker> {
+    let active_file = gcx.read().await.documents_state.active_file_path.clone();
+    let heuristic = Box::new(HeuristicReranker::new(active_file));
+    let caps = match try_load_caps_quickly_if_not_present(gcx.clone(), 0).await {
+        Ok(caps) => caps,
+        Err(_) => return heuristic,
+    };
+    let (endpoint, endpoint_style, model, mode) = {
+        let caps_locked = caps.read().unwrap();
+        (
+            caps_locked.rerank_endpoint.clone(),
+            caps_locked.rerank_endpoint_style.clone(),
+            caps_locked.rerank_model.clone(),
+            caps_locked.rerank_mode.clone(),
+        )
+    };
+    let mode = if mode.is_empty() && !endpoint.is_empty() { "both".to_string() } else { mode };
+    if endpoint.is_empty() || (mode != "endpoint" && mode != "both") {
+        return heuristic;
+    }
+    let api_key = get_custom_rerank_api_key(gcx.clone()).await.unwrap_or_default();
+    let endpoint_reranker = Box::new(EndpointReranker {
+        http_client: gcx.read().await.http_client.clone(),
+        endpoint,
+        endpoint_style,
+        model,
+        api_key,
+    });
+    if mode == "endpoint" {
+        return endpoint_reranker;
+    }
+    Box::new(RerankerChain { stages: vec![endpoint_reranker, heuristic] })
+}
+
+async fn fill_code_candidates(gcx: Arc<ARwLock<GlobalContext>>, candidates: &mut Vec<RerankCandidate>, needs_text: bool) {
+    let ast_service = gcx.read().await.ast_service.clone();
+    let ast_index = match ast_service {
+        Some(ast) => Some(ast.lock().await.ast_index.clone()),
+        None => None,
+    };
+    let mut defs_cache = HashMap::new();
+    let mut text_cache: HashMap<PathBuf, String> = HashMap::new();
+    for c in candidates.iter_mut() {
+        let path = match &c.file_path {
+            Some(x) => x.clone(),
+            None => continue,
+        };
+        c.mtime = tokio::fs::metadata(&path).await.ok().and_then(|m| m.modified().ok());
+        if let Some(ast_index) = &ast_index {
+            let cpath = path.to_string_lossy().to_string();
+            if !defs_cache.contains_key(&cpath) {
+                let defs = crate::ast::ast_db::doc_defs(ast_index.clone(), &cpath).await;
+                defs_cache.insert(cpath.clone(), defs);
+            }
+            c.symbols = defs_cache[&cpath].iter()
+                .filter(|d| d.full_line1() as u64 <= c.end_line + 1 && c.start_line + 1 <= d.full_line2() as u64)
+                .map(|d| d.path_drop0())
+                .collect();
+        }
+        if needs_text {
+            if !text_cache.contains_key(&path) {
+                // privacy is checked there, files that can't be sent anywhere get empty text
+                let text = crate::files_in_workspace::get_file_text_from_memory_or_disk(gcx.clone(), &path).await.unwrap_or_default();
+                text_cache.insert(path.clone(), text);
+            }
+            c.text = text_cache[&path].lines()
+                .skip(c.start_line as usize)
+                .take((c.end_line.saturating_sub(c.start_line) + 1) as usize)
+                .collect::<Vec<_>>().join("\n");
+        }
+    }
+}
+
+fn normalize_usefulness(order: &Vec<(usize, f32)>) -> f32 {
+    // boosts can push scores above 100, usefulness is expected to stay within 0..100
+    let top = order.first().map(|x| x.1).unwrap_or(100.0);
+    if top > 100.0 { 100.0 / top } else { 1.0 }
+}
+
+pub async fn rerank_hits(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    query: &String,
+    hits: Vec<RankedHit>,
+) -> Vec<RankedHit> {
+    if hits.len() <= 1 {
+        return hits;
+    }
+    let t0 = std::time::Instant::now();
+    let reranker = reranker_from_caps(gcx.clone()).await;
+    let mut candidates: Vec<RerankCandidate> = hits.iter().map(|h| RerankCandidate {
+        file_path: Some(h.file_path.clone()),
+        start_line: h.start_line,
+        end_line: h.end_line,
+        score: h.usefulness,
+        ..Default::default()
+    }).collect();
+    fill_code_candidates(gcx.clone(), &mut candidates, reranker.needs_text()).await;
+    let order = rerank_and_sort(reranker.as_ref(), query, &candidates).await;
+    let k = normalize_usefulness(&order);
+    info!("rerank {} hits with {}, {:.3}s", hits.len(), reranker.name(), t0.elapsed().as_secs_f32());
+    order.into_iter().map(|(i, score)| RankedHit { usefulness: score * k, ..hits[i].clone() }).collect()
+}
+
+// Code completion is latency sensitive, only the heuristic runs here, never the network
+pub async fn rerank_context_files(
+    cursor_file: &PathBuf,
+    context_files: Vec<ContextFile>,
+) -> Vec<ContextFile> {
+    if context_files.len() <= 1 {
+        return context_files;
+    }
+    let reranker = HeuristicReranker::new(Some(cursor_file.clone()));
+    let mut candidates: Vec<RerankCandidate> = context_files.iter().map(|x| RerankCandidate {
+        file_path: Some(PathBuf::from(&x.file_name)),
+        start_line: x.line1.saturating_sub(1) as u64,
+        end_line: x.line2.saturating_sub(1) as u64,
+        symbols: x.symbols.clone(),
+        score: x.usefulness,
+        ..Default::default()
+    }).collect();
+    for c in candidates.iter_mut() {
+        c.mtime = tokio::fs::metadata(c.file_path.as_ref().unwrap()).await.ok().and_then(|m| m.modified().ok());
+    }
+    let order = rerank_and_sort(&reranker, &"".to_string(), &candidates).await;
+    let k = normalize_usefulness(&order);
+    order.into_iter().map(|(i, score)| ContextFile { usefulness: score * k, ..context_files[i].clone() }).collect()
+}
+
+#[cfg(feature="vecdb")]
+pub async fn rerank_memories(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    query: &String,
+    memories: Vec<MemoRecord>,
+) -> Vec<MemoRecord> {
+    if memories.len() <= 1 {
+        return memories;
+    }
+    let reranker = reranker_from_caps(gcx.clone()).await;
+    let candidates: Vec<RerankCandidate> = memories.iter().map(|m| RerankCandidate {
+        text: m.m_payload.clone(),
+        mstat_correct: m.mstat_correct,
+        mstat_relevant: m.mstat_relevant,
+        mstat_times_used: m.mstat_times_used,
+        score: 100.0 / (1.0 + m.distance.max(0.0)),
+        ..Default::default()
+    }).collect();
+    let order = rerank_and_sort(reranker.as_ref(), query, &candidates).await;
+    order.into_iter().map(|(i, _)| memories[i].clone()).collect()
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+use async_trait::async_trait;
+use tracing::warn;
+
+
+#[derive(Debug, Clone, Default)]
+pub struct RerankCandidate {
+    pub file_path: Option<PathBuf>,  // None for memories
+    pub start_line: u64,
+    pub end_line: u64,
+    pub text: String,  // only filled when a reranker needs_text()
+    pub symbols: Vec<String>,
+    pub mtime: Option<SystemTime>,
+    pub mstat_correct: f64,
+    pub mstat_relevant: f64,
+    pub mstat_times_used: i32,
+    pub score: f32,  // higher is better, same scale as usefulness
+}
+
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    fn name(&self) -> String;
+
+    fn needs_text(&self) -> bool {
+        false
+    }
+
+    // New score for each candidate, in the same order as the input
+    async fn rerank(&self, query: &String, candidates: &Vec<RerankCandidate>) -> Result<Vec<f32>, String>;
+}
+
+pub struct RerankerChain {
+    pub stages: Vec<Box<dyn Reranker>>,
+}
+
+#[async_trait]
+impl Reranker for RerankerChain {
+    fn name(&self) -> String {
+        self.stages.iter().map(|x| x.name()).collect::<Vec<_>>().join("+")
+    }
+
+    fn needs_text(&self) -> bool {
+        self.stages.iter().any(|x| x.needs_text())
+    }
+
+    async fn rerank(&self, query: &String, candidates: &Vec<RerankCandidate>) -> Result<Vec<f32>, String> {
+        let mut current = candidates.clone();
+        for stage in self.stages.iter() {
+            match stage.rerank(query, &current).await {
+                Ok(scores) if scores.len() == current.len() => {
+                    for (c, s) in current.iter_mut().zip(scores) {
+                        c.score = s;
+                    }
+                }
+                Ok(scores) => {
+                    warn!("reranker {} returned {} scores for {} candidates, skipped", stage.name(), scores.len(), current.len());
+                }
+                Err(e) => {
+                    // a reranker that is down should not break search, the next stages still work
+                    warn!("reranker {} failed, skipped: {}", stage.name(), e);
+                }
+            }
+        }
+        Ok(current.into_iter().map(|c| c.score).collect())
+    }
+}
+
+// Returns (index in candidates, new score), best first
+pub async fn rerank_and_sort(
+    reranker: &dyn Reranker,
+    query: &String,
+    candidates: &Vec<RerankCandidate>,
+) -> Vec<(usize, f32)> {
+    let scores = match reranker.rerank(query, candidates).await {
+        Ok(scores) if scores.len() == candidates.len() => scores,
+        Ok(_) => candidates.iter().map(|c| c.score).collect(),
+        Err(e) => {
+            warn!("reranker {} failed, keeping the original order: {}", reranker.name(), e);
+            candidates.iter().map(|c| c.score).collect()
+        }
+    };
+    let mut order: Vec<(usize, f32)> = scores.into_iter().enumerate().collect();
+    order.sort_by(|a, b| b.1.total_cmp(&a.1));
+    order
+}
@@ -3,6 +3,7 @@ use crate::ast::ast_structs::{AstDB, AstDefinition};
 use crate::call_validation::{ContextFile, CursorPosition, PostprocessSettings};
 use crate::global_context::GlobalContext;
 use crate::postprocessing::pp_context_files::postprocess_context_files;
+use crate::rerank::rerank_highlev::rerank_context_files;
 use crate::scratchpad_abstract::HasTokenizerAndEot;
 use serde_json::{json, Value};
 use std::collections::HashSet;
@@ -171,7 +172,7 @@ pub async fn retrieve_ast_based_extra_context(
     }
 
     let rag_t0 = Instant::now();
-    let mut ast_context_file_vec: Vec<ContextFile> = if let Some(ast) = &ast_service {
+    let ast_context_file_vec: Vec<ContextFile> = if let Some(ast) = &ast_service {
         let ast_index = ast.lock().await.ast_index.clone();
         _cursor_position_to_context_file(
             ast_index.clone(),
@@ -184,6 +185,8 @@ pub async fn retrieve_ast_based_extra_context(
         vec![]
     };
 
+    let mut ast_context_file_vec = rerank_context_files(cpath, ast_context_file_vec).await;
+
     let to_buckets_ms = rag_t0.elapsed().as_millis() as i32;
     if subblock_to_ignore_range.0 != i32::MAX && subblock_to_ignore_range.1 != i32::MIN {
         // disable (usefulness==-1) the FIM region around the cursor from getting into the results
@@ -0,0 +1,388 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock as ARwLock;
+use tracing::{info, warn};
+
+use crate::ast::treesitter::parsers::get_language_id_by_filename;
+use crate::ast::treesitter::structs::SymbolType;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::files_correction::{correct_to_nearest_dir_path, get_project_dirs};
+use crate::git::operations::get_changed_files_vs_head;
+use crate::global_context::GlobalContext;
+
+
+// Bigger file lists are not pushed down into the index, the results get filtered afterwards
+const PUSHDOWN_MAX_FILES: usize = 1000;
+// How many more results to ask for when some of them will be filtered out afterwards
+const POST_FILTER_OVERFETCH: usize = 3;
+
+// What vecdb and bm25 can filter on by themselves: a file is in scope if it's one of `files` or under one of `dirs`.
+// Dirs end with a separator.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ScopeFilter {
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub dirs: Vec<String>,
+}
+
+// Every path under "a/b/" sorts between "a/b/" and "a/b0", that works without LIKE and its escaping
+fn dir_upper_bound(dir: &str) -> String {
+    let mut result = dir.to_string();
+    let last = result.pop().unwrap_or(std::path::MAIN_SEPARATOR);
+    result.push(char::from_u32(last as u32 + 1).unwrap_or(char::MAX));
+    result
+}
+
+// Lance takes filters as text only, every string that goes into one is quoted by this
+pub fn lance_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+impl ScopeFilter {
+    pub fn matches(&self, path: &str) -> bool {
+        self.files.iter().any(|f| f == path) || self.dirs.iter().any(|d| path.starts_with(d.as_str()))
+    }
+
+    pub fn to_lance_filter(&self) -> String {
+        let mut conditions = vec![];
+        if !self.files.is_empty() {
+            conditions.push(format!("scope IN ({})", self.files.iter().map(|f| lance_literal(f)).collect::<Vec<_>>().join(", ")));
+        }
+        for d in self.dirs.iter() {
+            conditions.push(format!("(scope >= {} AND scope < {})", lance_literal(d), lance_literal(&dir_upper_bound(d))));
+        }
+        if conditions.is_empty() {
+            return "scope = ''".to_string();
+        }
+        format!("({})", conditions.join(" OR "))
+    }
+
+    // For sqlite, all the values are bound parameters numbered from `first_param`
+    pub fn to_sqlite_filter(&self, column: &str, first_param: usize) -> (String, Vec<String>) {
+        let mut params: Vec<String> = vec![];
+        let mut conditions = vec![];
+        if !self.files.is_empty() {
+            let placeholders = self.files.iter().enumerate().map(|(i, _)| format!("?{}", first_param + i)).collect::<Vec<_>>();
+            params.extend(self.files.iter().cloned());
+            conditions.push(format!("{} IN ({})", column, placeholders.join(", ")));
+        }
+        for d in self.dirs.iter() {
+            let n = first_param + params.len();
+            params.push(d.clone());
+            params.push(dir_upper_bound(d));
+            conditions.push(format!("({} >= ?{} AND {} < ?{})", column, n, column, n + 1));
+        }
+        if conditions.is_empty() {
+            return (format!("{} = ''", column), params);
+        }
+        (format!("({})", conditions.join(" OR ")), params)
+    }
+}
+
+// The filter as it comes from the search() tool or /v1/vdb-search, all the fields are optional
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SearchFilter {
+    #[serde(default)]
+    pub scope: String,              // "workspace" or empty, "dir/subdir/", "dir/file.ext"
+    #[serde(default)]
+    pub languages: Vec<String>,     // "python", "rust", or bare extensions like "md"
+    #[serde(default)]
+    pub include: Vec<String>,       // globs relative to workspace folders, without a slash they match file names
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub symbol_types: Vec<String>,  // "function", "class", "type", "variable"
+    #[serde(default)]
+    pub git_changed: bool,          // only files that differ from HEAD, untracked included
+    #[serde(default)]
+    pub open_files: bool,           // only files open in the editor
+}
+
+pub struct ResolvedSearchFilter {
+    pub pushdown: Option<ScopeFilter>,
+    pushdown_is_exact: bool,
+    scope: Option<ScopeFilter>,
+    only_files: Option<HashSet<String>>,
+    languages: Vec<String>,
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    project_dirs: Vec<PathBuf>,
+    symbol_types: Vec<SymbolType>,
+}
+
+fn parse_symbol_type(s: &str) -> Result<SymbolType, String> {
+    match s.trim().to_lowercase().as_str() {
+        "function" | "method" => Ok(SymbolType::FunctionDeclaration),
+        "class" | "struct" | "interface" => Ok(SymbolType::StructDeclaration),
+        "type" | "type_alias" => Ok(SymbolType::TypeAlias),
+        "variable" => Ok(SymbolType::VariableDefinition),
+        _ => Err(format!("unknown symbol type {:?}, use function, class, type or variable", s)),
+    }
+}
+
+fn build_globset(globs: &Vec<String>) -> Result<Option<GlobSet>, String> {
+    let globs: Vec<&str> = globs.iter().map(|x| x.trim()).filter(|x| !x.is_empty()).collect();
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for g in globs {
+        let pattern = if g.contains('/') { g.trim_start_matches('/').to_string() } else { format!("**/{}", g) };
+        let glob = GlobBuilder::new(&pattern).literal_separator(true).build()
+            .map_err(|e| format!("bad glob {:?}: {}", g, e))?;
+        builder.add(glob);
+    }
+    builder.build().map(Some).map_err(|e| format!("bad globs: {}", e))
+}
+
+async fn resolve_scope(gcx: Arc<ARwLock<GlobalContext>>, scope: &String, project_dirs: &Vec<PathBuf>) -> Result<Option<ScopeFilter>, String> {
+    if scope.is_empty() || scope == "workspace" {
+        return Ok(None);
+    }
+    let as_dir = |dir: String| {
+        let sep = std::path::MAIN_SEPARATOR.to_string();
+        ScopeFilter { files: vec![], dirs: vec![if dir.ends_with(&sep) { dir } else { dir + &sep }] }
+    };
+    let scope_is_dir = scope.ends_with('/') || scope.ends_with('\\');
+    if scope_is_dir {
+        return return_one_candidate_or_a_good_error(
+            gcx.clone(),
+            scope,
+            &correct_to_nearest_dir_path(gcx.clone(), scope, false, 10).await,
+            project_dirs,
+            true,
+        ).await.map(|dir| Some(as_dir(dir)));
+    }
+    match return_one_candidate_or_a_good_error(
+        gcx.clone(),
+        scope,
+        &file_repair_candidates(gcx.clone(), scope, 10, false).await,
+        project_dirs,
+        false,
+    ).await {
+        Ok(file) => Ok(Some(ScopeFilter { files: vec![file], dirs: vec![] })),
+        Err(file_err) => {
+            return_one_candidate_or_a_good_error(
+                gcx.clone(),
+                scope,
+                &correct_to_nearest_dir_path(gcx.clone(), scope, false, 10).await,
+                project_dirs,
+                true,
+            ).await.map(|dir| Some(as_dir(dir))).map_err(|_| file_err)
+        }
+    }
+}
+
+async fn git_changed_files(gcx: Arc<ARwLock<GlobalContext>>) -> HashSet<String> {
+    let workspace_vcs_roots_arc = gcx.read().await.documents_state.workspace_vcs_roots.clone();
+    let workspace_vcs_roots = workspace_vcs_roots_arc.lock().unwrap().clone();
+    let mut result = HashSet::new();
+    for project_path in workspace_vcs_roots {
+        let repository = match git2::Repository::open(&project_path) {
+            Ok(repo) => repo,
+            Err(e) => { warn!("{}", e); continue; }
+        };
+        match get_changed_files_vs_head(&repository) {
+            Ok(files) => result.extend(files.into_iter().map(|x| x.to_string_lossy().to_string())),
+            Err(e) => warn!("{}", e),
+        }
+    }
+    result
+}
+
+impl SearchFilter {
+    pub async fn resolve(&self, gcx: Arc<ARwLock<GlobalContext>>) -> Result<ResolvedSearchFilter, String> {
+        let project_dirs = get_project_dirs(gcx.clone()).await;
+        let symbol_types = self.symbol_types.iter().filter(|x| !x.trim().is_empty()).map(|x| parse_symbol_type(x)).collect::<Result<Vec<_>, _>>()?;
+        if !symbol_types.is_empty() && gcx.read().await.ast_service.is_none() {
+            return Err("Filtering by symbol type needs the AST index, it is turned off.".to_string());
+        }
+        let mut only_files: Option<HashSet<String>> = None;
+        if self.git_changed {
+            only_files = Some(git_changed_files(gcx.clone()).await);
+        }
+        if self.open_files {
+            let open: HashSet<String> = gcx.read().await.documents_state.memory_document_map.keys()
+                .map(|x| x.to_string_lossy().to_string()).collect();
+            only_files = Some(match only_files {
+                Some(x) => x.intersection(&open).cloned().collect(),
+                None => open,
+            });
+        }
+        let mut resolved = ResolvedSearchFilter {
+            pushdown: None,
+            pushdown_is_exact: true,
+            scope: resolve_scope(gcx.clone(), &self.scope, &project_dirs).await?,
+            only_files,
+            languages: self.languages.iter().map(|x| x.trim().trim_start_matches('.').to_lowercase()).filter(|x| !x.is_empty()).collect(),
+            include: build_globset(&self.include)?,
+            exclude: build_globset(&self.exclude)?,
+            project_dirs,
+            symbol_types,
+        };
+        resolved.make_pushdown(gcx.clone()).await?;
+        Ok(resolved)
+    }
+}
+
+impl ResolvedSearchFilter {
+    fn has_path_conditions(&self) -> bool {
+        self.only_files.is_some() || !self.languages.is_empty() || self.include.is_some() || self.exclude.is_some()
+    }
+
+    fn matches_glob(&self, globs: &GlobSet, path: &str) -> bool {
+        let path = PathBuf::from(path);
+        self.project_dirs.iter()
+            .filter_map(|dir| path.strip_prefix(dir).ok())
+            .any(|relative| globs.is_match(relative.to_string_lossy().replace('\\', "/")))
+    }
+
+    pub fn matches_path(&self, path: &str) -> bool {
+        if let Some(scope) = &self.scope {
+            if !scope.matches(path) {
+                return false;
+            }
+        }
+        if let Some(only_files) = &self.only_files {
+            if !only_files.contains(path) {
+                return false;
+            }
+        }
+        if !self.languages.is_empty() {
+            let path_buf = PathBuf::from(path);
+            let extension = path_buf.extension().map(|x| x.to_string_lossy().to_lowercase()).unwrap_or_default();
+            let language = get_language_id_by_filename(&path_buf).map(|x| x.to_string()).unwrap_or_default();
+            if !self.languages.iter().any(|l| *l == extension || *l == language) {
+                return false;
+            }
+        }
+        if let Some(include) = &self.include {
+            if !self.matches_glob(include, path) {
+                return false;
+            }
+        }
+        if let Some(exclude) = &self.exclude {
+            if self.matches_glob(exclude, path) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Turns path conditions into a list of files if it's short enough, otherwise only the scope goes into the index
+    async fn make_pushdown(&mut self, gcx: Arc<ARwLock<GlobalContext>>) -> Result<(), String> {
+        if !self.has_path_conditions() {
+            self.pushdown = self.scope.clone();
+            return Ok(());
+        }
+        let candidates: Vec<String> = match &self.only_files {
+            Some(x) => x.iter().cloned().collect(),
+            None => {
+                let workspace_files = gcx.read().await.documents_state.workspace_files.clone();
+                let files = workspace_files.lock().unwrap().iter().map(|x| x.to_string_lossy().to_string()).collect();
+                files
+            }
+        };
+        let mut files: Vec<String> = candidates.into_iter().filter(|x| self.matches_path(x)).collect();
+        if files.is_empty() {
+            return Err("No files match the search filter.".to_string());
+        }
+        if files.len() <= PUSHDOWN_MAX_FILES {
+            files.sort();
+            self.pushdown = Some(ScopeFilter { files, dirs: vec![] });
+        } else {
+            info!("search filter matches {} files, will filter the results instead", files.len());
+            self.pushdown = self.scope.clone();
+            self.pushdown_is_exact = false;
+        }
+        Ok(())
+    }
+
+    pub fn fetch_n(&self, top_n: usize) -> usize {
+        if self.pushdown_is_exact && self.symbol_types.is_empty() { top_n } else { top_n * POST_FILTER_OVERFETCH }
+    }
+
+    // Keeps results that match the paths, and if symbol types are given, overlap a definition of that type
+    pub async fn retain<T>(
+        &self,
+        gcx: Arc<ARwLock<GlobalContext>>,
+        items: Vec<T>,
+        location: impl Fn(&T) -> (String, u64, u64),
+    ) -> Vec<T> {
+        let ast_index = match (self.symbol_types.is_empty(), gcx.read().await.ast_service.clone()) {
+            (false, Some(ast)) => Some(ast.lock().await.ast_index.clone()),
+            _ => None,
+        };
+        let mut defs_cache = HashMap::new();
+        let mut result = vec![];
+        for item in items {
+            let (cpath, start_line, end_line) = location(&item);
+            if !self.matches_path(&cpath) {
+                continue;
+            }
+            if let Some(ast_index) = &ast_index {
+                if !defs_cache.contains_key(&cpath) {
+                    let defs = crate::ast::ast_db::doc_defs(ast_index.clone(), &cpath).await;
+                    defs_cache.insert(cpath.clone(), defs);
+                }
+                let has_symbol = defs_cache[&cpath].iter().any(|d| {
+                    self.symbol_types.contains(&d.symbol_type)
+                        && d.full_line1() as u64 <= end_line + 1 && start_line + 1 <= d.full_line2() as u64
+                });
+                if !has_symbol {
+                    continue;
+                }
+            }
+            result.push(item);
+        }
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_filter_rendering() {
+        let scope = ScopeFilter {
+            files: vec!["/proj/it's.py".to_string()],
+            dirs: vec!["/proj/lib/".to_string()],
+        };
+        assert_eq!(scope.to_lance_filter(), "(scope IN ('/proj/it''s.py') OR (scope >= '/proj/lib/' AND scope < '/proj/lib0'))");
+        let (sql, params) = scope.to_sqlite_filter("w.scope", 2);
+        assert_eq!(sql, "(w.scope IN (?2) OR (w.scope >= ?3 AND w.scope < ?4))");
+        assert_eq!(params, vec!["/proj/it's.py", "/proj/lib/", "/proj/lib0"]);
+        assert!(scope.matches("/proj/lib/a/b.rs"));
+        assert!(!scope.matches("/proj/library.rs"));
+        assert!("/proj/lib/~~~" < "/proj/lib0");
+    }
+
+    #[test]
+    fn test_path_conditions() {
+        let resolved = ResolvedSearchFilter {
+            pushdown: None,
+            pushdown_is_exact: true,
+            scope: Some(ScopeFilter { files: vec![], dirs: vec!["/proj/src/".to_string()] }),
+            only_files: None,
+            languages: vec!["rust".to_string(), "md".to_string()],
+            include: build_globset(&vec!["src/**".to_string()]).unwrap(),
+            exclude: build_globset(&vec!["*_test.rs".to_string(), "src/gen/*".to_string()]).unwrap(),
+            project_dirs: vec![PathBuf::from("/proj")],
+            symbol_types: vec![],
+        };
+        assert!(resolved.matches_path("/proj/src/main.rs"));
+        assert!(resolved.matches_path("/proj/src/docs/readme.md"));
+        assert!(!resolved.matches_path("/proj/src/main.py"));
+        assert!(!resolved.matches_path("/proj/src/deep/x_test.rs"));
+        assert!(!resolved.matches_path("/proj/src/gen/x.rs"));
+        assert!(resolved.matches_path("/proj/src/gen/deeper/x.rs"));
+        assert!(!resolved.matches_path("/proj/tests/main.rs"));
+        assert!(parse_symbol_type("Class").is_ok() && parse_symbol_type("banana").is_err());
+    }
+}
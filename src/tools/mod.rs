@@ -11,7 +11,6 @@ mod tool_cat;
 
 mod tool_deep_thinking;
 
-#[cfg(feature="vecdb")]
 mod tool_search;
 #[cfg(feature="vecdb")]
 mod tool_knowledge;
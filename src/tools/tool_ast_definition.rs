@@ -16,6 +16,10 @@ pub struct ToolAstDefinition;
 #[async_trait]
 impl Tool for ToolAstDefinition {
     fn as_any(&self) -> &dyn std::any::Any { self }
+
+    fn parallel_safe_copy(&self) -> Option<Box<dyn Tool + Send>> {
+        Some(Box::new(ToolAstDefinition))
+    }
     
     async fn tool_execute(
         &mut self,
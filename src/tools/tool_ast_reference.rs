@@ -16,6 +16,10 @@ pub struct ToolAstReference;
 #[async_trait]
 impl Tool for ToolAstReference {
     fn as_any(&self) -> &dyn std::any::Any { self }
+
+    fn parallel_safe_copy(&self) -> Option<Box<dyn Tool + Send>> {
+        Some(Box::new(ToolAstReference))
+    }
     
     async fn tool_execute(
         &mut self,
@@ -44,6 +44,10 @@ pub fn parse_skeleton_from_args(args: &HashMap<String, Value>) -> Result<bool, S
 impl Tool for ToolCat {
     fn as_any(&self) -> &dyn std::any::Any { self }
 
+    fn parallel_safe_copy(&self) -> Option<Box<dyn Tool + Send>> {
+        Some(Box::new(ToolCat))
+    }
+
     async fn tool_execute(
         &mut self,
         ccx: Arc<AMutex<AtCommandsContext>>,
@@ -8,7 +8,7 @@ use async_trait::async_trait;
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::tools::tools_description::Tool;
 use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
-use crate::vecdb::vdb_highlev::memories_search;
+use crate::vecdb::vdb_highlev::{memories_active_scope, memories_search};
 
 
 pub struct ToolGetKnowledge;
@@ -18,6 +18,10 @@ pub struct ToolGetKnowledge;
 impl Tool for ToolGetKnowledge {
     fn as_any(&self) -> &dyn std::any::Any { self }
 
+    fn parallel_safe_copy(&self) -> Option<Box<dyn Tool + Send>> {
+        Some(Box::new(ToolGetKnowledge))
+    }
+
     async fn tool_execute(
         &mut self,
         ccx: Arc<AMutex<AtCommandsContext>>,
@@ -53,10 +57,11 @@ impl Tool for ToolGetKnowledge {
         };
 
         let mem_top_n = 3;
-        let memories1: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &im_going_to_use_tools, mem_top_n).await?;
-        let memories2: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &im_going_to_apply_to, mem_top_n).await?;
-        let memories3: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &goal, mem_top_n).await?;
-        let memories4: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &language_slash_framework, mem_top_n).await?;
+        let scope = memories_active_scope(gcx.clone()).await;
+        let memories1: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &im_going_to_use_tools, mem_top_n, Some(scope.clone())).await?;
+        let memories2: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &im_going_to_apply_to, mem_top_n, Some(scope.clone())).await?;
+        let memories3: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &goal, mem_top_n, Some(scope.clone())).await?;
+        let memories4: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &language_slash_framework, mem_top_n, Some(scope.clone())).await?;
         let combined_memories = [memories1.results, memories2.results, memories3.results, memories4.results].concat();
         let mut seen_memids = HashSet::new();
         let unique_memories: Vec<_> = combined_memories.into_iter()
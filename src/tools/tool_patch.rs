@@ -10,7 +10,9 @@ use crate::files_correction::to_pathbuf_normalize;
 use crate::tools::tool_patch_aux::diff_apply::diff_apply;
 use crate::tools::tool_patch_aux::model_based_edit::partial_edit::partial_edit_tickets_to_chunks;
 use crate::tools::tool_patch_aux::no_model_edit::{full_rewrite_diff, rewrite_symbol_diff};
+use crate::tools::tool_patch_aux::post_patch_hooks::{hook_problems_to_text, patched_files, run_post_patch_hooks};
 use crate::tools::tool_patch_aux::postprocessing_utils::postprocess_diff_chunks;
+use crate::tools::tool_patch_aux::unified_diff::unified_diff_to_chunks;
 use crate::tools::tool_patch_aux::tickets_parsing::{get_and_correct_active_tickets, get_tickets_from_messages, good_error_text, PatchAction, TicketToApply};
 use crate::tools::tools_description::{MatchConfirmDeny, MatchConfirmDenyResult, Tool};
 use crate::tools::tools_execute::unwrap_subchat_params;
@@ -72,6 +74,22 @@ pub async fn process_tickets(
                 }
             }
         }
+        PatchAction::UnifiedDiff => {
+            match unified_diff_to_chunks(gcx.clone(), active_tickets).await {
+                Ok(mut chunks) => {
+                    postprocess_diff_chunks(gcx.clone(), &mut chunks)
+                        .await
+                        .map_err(|err| (err, None))
+                }
+                Err(err) => {
+                    Err(good_error_text(
+                        &err,
+                        &ticket_ids,
+                        Some("regenerate the diff with more unchanged context lines copied exactly from the file, or use 📍PARTIAL_EDIT".to_string()),
+                    ))
+                }
+            }
+        }
         _ => {
             Err(good_error_text(&format!("unknown action provided: '{:?}'.", action), &ticket_ids, None))
         }
@@ -255,7 +273,8 @@ impl Tool for ToolPatch {
         diff_apply(gcx.clone(), &mut diff_chunks).await.map_err(
             |err| format!("Couldn't apply the diff: {}", err)
         )?;
-        let results = vec![
+        let hook_problems = run_post_patch_hooks(gcx.clone(), &patched_files(&diff_chunks)).await;
+        let mut results = vec![
             ChatMessage {
                 role: "diff".to_string(),
                 content: ChatContent::SimpleText(json!(diff_chunks).to_string()),
@@ -264,7 +283,12 @@ impl Tool for ToolPatch {
                 usage: Some(usage),
                 ..Default::default()
             }
-        ]
+        ];
+        if !hook_problems.is_empty() {
+            // diff message content must stay a json list of chunks, so the problems go into a separate message
+            results.push(ChatMessage::new("cd_instruction".to_string(), hook_problems_to_text(&hook_problems)));
+        }
+        let results = results
             .into_iter()
             .map(|x| ContextEnum::ChatMessage(x))
             .collect::<Vec<_>>();
@@ -6,3 +6,6 @@ pub mod postprocessing_utils;
 pub mod tickets_parsing;
 pub mod fs_utils;
 pub mod diff_apply;
+pub mod post_patch_hooks;
+pub mod unified_diff;
+pub mod patch_transaction;
@@ -0,0 +1,346 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use itertools::Itertools;
+use serde::Serialize;
+use tempfile::NamedTempFile;
+use tracing::{error, warn};
+
+use crate::diffs::ApplyDiffResult;
+use crate::git::checkpoints::Checkpoint;
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum StagedOp {
+    Edit { path: PathBuf, text: String },
+    Add { path: PathBuf, text: String },
+    Rename { from: PathBuf, into: PathBuf },
+    Remove { path: PathBuf },
+}
+
+#[derive(Debug)]
+enum UndoOp {
+    Restore { path: PathBuf, original: Vec<u8> },
+    Delete { path: PathBuf },
+    RenameBack { from: PathBuf, into: PathBuf },
+    RecreateDir { path: PathBuf },
+}
+
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct PatchTransactionSummary {
+    pub chunks_applied: usize,
+    pub files_edited: Vec<String>,
+    pub files_added: Vec<String>,
+    pub files_removed: Vec<String>,
+    pub files_renamed: Vec<(String, String)>,
+    pub checkpoint: Option<Checkpoint>,
+}
+
+impl PatchTransactionSummary {
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!("{} chunk(s) applied", self.chunks_applied)];
+        for f in self.files_edited.iter() {
+            lines.push(format!("edited {}", f));
+        }
+        for f in self.files_added.iter() {
+            lines.push(format!("added {}", f));
+        }
+        for f in self.files_removed.iter() {
+            lines.push(format!("removed {}", f));
+        }
+        for (from, into) in self.files_renamed.iter() {
+            lines.push(format!("renamed {} -> {}", from, into));
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            lines.push(format!("checkpoint {}", checkpoint.commit_hash));
+        }
+        lines.join("\n")
+    }
+}
+
+// All changes are computed in memory first, then written through temp files and renames.
+// If anything fails midway, every file touched so far is restored to its original state.
+#[derive(Debug, Default)]
+pub struct PatchTransaction {
+    ops: Vec<StagedOp>,
+}
+
+impl PatchTransaction {
+    pub fn stage(results: &Vec<ApplyDiffResult>) -> Result<Self, String> {
+        let mut ops = vec![];
+        for r in results {
+            let op = match (&r.file_name_edit, &r.file_name_add, &r.file_name_delete, &r.file_text) {
+                (Some(path), _, _, Some(text)) => StagedOp::Edit { path: PathBuf::from(path), text: text.clone() },
+                (None, Some(into), Some(from), _) => StagedOp::Rename { from: PathBuf::from(from), into: PathBuf::from(into) },
+                (None, Some(path), None, Some(text)) => StagedOp::Add { path: PathBuf::from(path), text: text.clone() },
+                (None, None, Some(path), _) => StagedOp::Remove { path: PathBuf::from(path) },
+                _ => return Err(format!("Cannot stage an incomplete diff result: {:?}", r)),
+            };
+            ops.push(op);
+        }
+        Ok(PatchTransaction { ops })
+    }
+
+    pub fn summary(&self, chunks_applied: usize) -> PatchTransactionSummary {
+        let mut summary = PatchTransactionSummary { chunks_applied, ..Default::default() };
+        for op in self.ops.iter() {
+            match op {
+                StagedOp::Edit { path, .. } => summary.files_edited.push(path.to_string_lossy().to_string()),
+                StagedOp::Add { path, .. } => summary.files_added.push(path.to_string_lossy().to_string()),
+                StagedOp::Remove { path } => summary.files_removed.push(path.to_string_lossy().to_string()),
+                StagedOp::Rename { from, into } => summary.files_renamed.push(
+                    (from.to_string_lossy().to_string(), into.to_string_lossy().to_string())
+                ),
+            }
+        }
+        summary
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for op in self.ops.iter() {
+            match op {
+                StagedOp::Edit { path, .. } => {
+                    if !path.is_file() {
+                        return Err(format!("Failed to Edit: file '{}' does not exist", path.display()));
+                    }
+                }
+                StagedOp::Add { path, .. } => {
+                    if path.parent().is_none() {
+                        return Err(format!("Failed to Add: {}. Path is invalid.\nReason: path must have had a parent directory", path.display()));
+                    }
+                    if path.is_dir() {
+                        return Err(format!("Failed to Add: path '{}' is a directory", path.display()));
+                    }
+                }
+                StagedOp::Rename { from, into } => {
+                    if into.exists() {
+                        return Err(format!("Failed to Rename: path '{}' (rename into) already exists", into.display()));
+                    }
+                    if !from.exists() {
+                        return Err(format!("Failed to Rename: path '{}' (rename from) does not exist", from.display()));
+                    }
+                }
+                StagedOp::Remove { path } => {
+                    if !path.exists() {
+                        return Err(format!("Failed to Remove: path '{}' does not exist", path.display()));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the files that exist after the transaction and need re-indexing
+    pub fn commit(self) -> Result<Vec<PathBuf>, String> {
+        self.validate()?;
+
+        let mut created_dirs: Vec<PathBuf> = vec![];
+        let mut undo: Vec<UndoOp> = vec![];
+        let mut touched: Vec<PathBuf> = vec![];
+        for op in self.ops.iter() {
+            if let Err(e) = apply_op(op, &mut undo, &mut created_dirs, &mut touched) {
+                warn!("patch transaction failed, rolling back {} change(s): {}", undo.len(), e);
+                let rollback_errors = rollback(undo, created_dirs);
+                if rollback_errors.is_empty() {
+                    return Err(format!("{}\nAll changes were rolled back", e));
+                }
+                return Err(format!("{}\nRollback was incomplete:\n{}", e, rollback_errors.join("\n")));
+            }
+        }
+        Ok(touched.into_iter().unique().filter(|p| p.is_file()).collect())
+    }
+}
+
+fn write_atomically(path: &Path, content: &[u8]) -> Result<(), String> {
+    let parent = path.parent().ok_or(format!("Path '{}' has no parent directory", path.display()))?;
+    let mut tmp = NamedTempFile::new_in(parent)
+        .map_err(|e| format!("Failed to create a temp file next to {}\nERROR: {}", path.display(), e))?;
+    tmp.write_all(content)
+        .map_err(|e| format!("Failed to write a temp file for {}\nERROR: {}", path.display(), e))?;
+    if let Ok(meta) = fs::metadata(path) {
+        let _ = fs::set_permissions(tmp.path(), meta.permissions());
+    }
+    tmp.persist(path)
+        .map_err(|e| format!("Failed to write file {}\nERROR: {}", path.display(), e.error))?;
+    Ok(())
+}
+
+fn create_parent_dirs(path: &Path, created_dirs: &mut Vec<PathBuf>) -> Result<(), String> {
+    let mut missing = vec![];
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if d.as_os_str().is_empty() || d.exists() {
+            break;
+        }
+        missing.push(d.to_path_buf());
+        dir = d.parent();
+    }
+    for d in missing.into_iter().rev() {
+        fs::create_dir(&d).map_err(|e| format!("Failed to create dir {:?}\nERROR: {}", d, e))?;
+        created_dirs.push(d);
+    }
+    Ok(())
+}
+
+fn apply_op(
+    op: &StagedOp,
+    undo: &mut Vec<UndoOp>,
+    created_dirs: &mut Vec<PathBuf>,
+    touched: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    match op {
+        StagedOp::Edit { path, text } | StagedOp::Add { path, text } => {
+            let original = if path.is_file() {
+                Some(fs::read(path).map_err(|e| format!("Failed to read file {}\nERROR: {}", path.display(), e))?)
+            } else {
+                None
+            };
+            create_parent_dirs(path, created_dirs)?;
+            write_atomically(path, text.as_bytes())?;
+            undo.push(match original {
+                Some(original) => UndoOp::Restore { path: path.clone(), original },
+                None => UndoOp::Delete { path: path.clone() },
+            });
+            touched.push(path.clone());
+        }
+        StagedOp::Rename { from, into } => {
+            if into.exists() {
+                return Err(format!("Failed to Rename: path '{}' (rename into) already exists", into.display()));
+            }
+            fs::rename(from, into)
+                .map_err(|e| format!("Failed to Rename '{}' into '{}'\nERROR: {}", from.display(), into.display(), e))?;
+            undo.push(UndoOp::RenameBack { from: from.clone(), into: into.clone() });
+            touched.push(into.clone());
+        }
+        StagedOp::Remove { path } => {
+            if path.is_dir() {
+                fs::remove_dir(path).map_err(|e| format!("Failed to Remove dir: {:?}\nERROR: {}", path, e))?;
+                undo.push(UndoOp::RecreateDir { path: path.clone() });
+            } else {
+                let original = fs::read(path).map_err(|e| format!("Failed to read file {}\nERROR: {}", path.display(), e))?;
+                fs::remove_file(path).map_err(|e| format!("Failed to Remove file: {:?}\nERROR: {}", path, e))?;
+                undo.push(UndoOp::Restore { path: path.clone(), original });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn rollback(undo: Vec<UndoOp>, created_dirs: Vec<PathBuf>) -> Vec<String> {
+    let mut errors = vec![];
+    for u in undo.into_iter().rev() {
+        let res = match &u {
+            UndoOp::Restore { path, original } => write_atomically(path, original),
+            UndoOp::Delete { path } => fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e)),
+            UndoOp::RenameBack { from, into } => fs::rename(into, from)
+                .map_err(|e| format!("Failed to rename {} back into {}: {}", into.display(), from.display(), e)),
+            UndoOp::RecreateDir { path } => fs::create_dir(path).map_err(|e| format!("Failed to recreate dir {}: {}", path.display(), e)),
+        };
+        if let Err(e) = res {
+            error!("patch transaction rollback: {}", e);
+            errors.push(e);
+        }
+    }
+    for d in created_dirs.into_iter().rev() {
+        let _ = fs::remove_dir(&d);
+    }
+    errors
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(path: &Path, text: &str) -> ApplyDiffResult {
+        ApplyDiffResult {
+            file_text: Some(text.to_string()),
+            file_name_edit: Some(path.to_string_lossy().to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_commit_applies_all_changes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a.py");
+        let b = tmp.path().join("b.py");
+        let c = tmp.path().join("sub/dir/c.py");
+        fs::write(&a, "a = 1\n").unwrap();
+        fs::write(&b, "b = 1\n").unwrap();
+        let results = vec![
+            edit(&a, "a = 2\n"),
+            ApplyDiffResult {
+                file_text: Some("c = 1\n".to_string()),
+                file_name_add: Some(c.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            ApplyDiffResult {
+                file_name_delete: Some(b.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+        ];
+        let transaction = PatchTransaction::stage(&results).unwrap();
+        let summary = transaction.summary(3);
+        assert_eq!(summary.files_edited.len(), 1);
+        assert_eq!(summary.files_added.len(), 1);
+        assert_eq!(summary.files_removed.len(), 1);
+        let touched = transaction.commit().unwrap();
+        assert_eq!(touched, vec![a.clone(), c.clone()]);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a = 2\n");
+        assert_eq!(fs::read_to_string(&c).unwrap(), "c = 1\n");
+        assert!(!b.exists());
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 2, "temp files must not be left behind");
+    }
+
+    #[test]
+    fn test_failure_rolls_back_every_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a.py");
+        let b = tmp.path().join("b.py");
+        let gone = tmp.path().join("gone.py");
+        let added = tmp.path().join("new/added.py");
+        fs::write(&a, "a = 1\n").unwrap();
+        fs::write(&b, "b = 1\n").unwrap();
+        fs::write(&gone, "gone = 1\n").unwrap();
+        let results = vec![
+            edit(&a, "a = 2\n"),
+            ApplyDiffResult {
+                file_name_delete: Some(gone.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            ApplyDiffResult {
+                file_text: Some("added = 1\n".to_string()),
+                file_name_add: Some(added.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            edit(&b, "b = 2\n"),
+            // removing the same file twice fails after everything above is already on disk
+            ApplyDiffResult {
+                file_name_delete: Some(gone.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+        ];
+        let err = PatchTransaction::stage(&results).unwrap().commit().unwrap_err();
+        assert!(err.contains("rolled back"), "{}", err);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a = 1\n");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "b = 1\n");
+        assert_eq!(fs::read_to_string(&gone).unwrap(), "gone = 1\n");
+        assert!(!added.exists());
+        assert!(!tmp.path().join("new").exists());
+    }
+
+    #[test]
+    fn test_validation_fails_before_writing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a.py");
+        fs::write(&a, "a = 1\n").unwrap();
+        let results = vec![
+            edit(&a, "a = 2\n"),
+            edit(&tmp.path().join("missing.py"), "x = 1\n"),
+        ];
+        let err = PatchTransaction::stage(&results).unwrap().commit().unwrap_err();
+        assert!(err.contains("does not exist"), "{}", err);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a = 1\n");
+    }
+}
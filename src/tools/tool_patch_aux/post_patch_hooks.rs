@@ -0,0 +1,330 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use serde_json::Value;
+use tokio::sync::RwLock as ARwLock;
+
+use crate::ast::diagnostics::AstDiagnosticSeverity;
+use crate::call_validation::DiffChunk;
+use crate::global_context::GlobalContext;
+use crate::integrations::integr_cmdline::{create_command_from_string, replace_args};
+use crate::integrations::process_io_utils::{execute_command_with_timeout, last_n_lines};
+use crate::yaml_configs::customization_loader::load_customization;
+
+const HOOK_PROBLEMS_LIMIT: usize = 30;
+const HOOK_PLAIN_OUTPUT_LINES: usize = 20;
+
+
+#[derive(Debug, Clone)]
+pub struct HookProblem {
+    pub hook_name: String,
+    pub file_name: String,
+    pub line1: usize,  // 0 if the tool didn't report a line
+    pub col1: usize,
+    pub severity: AstDiagnosticSeverity,
+    pub message: String,
+}
+
+fn resolve_file_name(workdir: &PathBuf, file_name: &str) -> String {
+    let path = PathBuf::from(file_name);
+    if path.is_absolute() || file_name.is_empty() {
+        file_name.to_string()
+    } else {
+        workdir.join(path).to_string_lossy().to_string()
+    }
+}
+
+fn parse_ruff_json(hook_name: &str, stdout: &str, workdir: &PathBuf) -> Result<Vec<HookProblem>, String> {
+    let items: Vec<Value> = serde_json::from_str(stdout).map_err(|e| format!("cannot parse ruff json: {}", e))?;
+    Ok(items.iter().map(|item| {
+        let code = item["code"].as_str().unwrap_or("");
+        let message = item["message"].as_str().unwrap_or("").to_string();
+        HookProblem {
+            hook_name: hook_name.to_string(),
+            file_name: resolve_file_name(workdir, item["filename"].as_str().unwrap_or("")),
+            line1: item["location"]["row"].as_u64().unwrap_or(0) as usize,
+            col1: item["location"]["column"].as_u64().unwrap_or(0) as usize,
+            severity: AstDiagnosticSeverity::Error,
+            message: if code.is_empty() { message } else { format!("{} {}", code, message) },
+        }
+    }).collect())
+}
+
+fn parse_cargo_json(hook_name: &str, stdout: &str, workdir: &PathBuf) -> Result<Vec<HookProblem>, String> {
+    let mut problems = vec![];
+    for line in stdout.lines() {
+        let item: Value = match serde_json::from_str(line) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        if item["reason"].as_str() != Some("compiler-message") {
+            continue;
+        }
+        let msg = &item["message"];
+        let severity = match msg["level"].as_str() {
+            Some("error") => AstDiagnosticSeverity::Error,
+            Some("warning") => AstDiagnosticSeverity::Warning,
+            _ => continue,
+        };
+        // messages without spans are summaries like "aborting due to 2 previous errors"
+        let spans = msg["spans"].as_array().cloned().unwrap_or_default();
+        let span = match spans.iter().find(|s| s["is_primary"].as_bool() == Some(true)).or(spans.first()) {
+            Some(x) => x,
+            None => continue,
+        };
+        let code = msg["code"]["code"].as_str().unwrap_or("");
+        let message = msg["message"].as_str().unwrap_or("").to_string();
+        problems.push(HookProblem {
+            hook_name: hook_name.to_string(),
+            file_name: resolve_file_name(workdir, span["file_name"].as_str().unwrap_or("")),
+            line1: span["line_start"].as_u64().unwrap_or(0) as usize,
+            col1: span["column_start"].as_u64().unwrap_or(0) as usize,
+            severity,
+            message: if code.is_empty() { message } else { format!("{} {}", code, message) },
+        });
+    }
+    Ok(problems)
+}
+
+fn parse_eslint_json(hook_name: &str, stdout: &str, workdir: &PathBuf) -> Result<Vec<HookProblem>, String> {
+    let files: Vec<Value> = serde_json::from_str(stdout).map_err(|e| format!("cannot parse eslint json: {}", e))?;
+    let mut problems = vec![];
+    for file in files.iter() {
+        let file_name = resolve_file_name(workdir, file["filePath"].as_str().unwrap_or(""));
+        for msg in file["messages"].as_array().cloned().unwrap_or_default() {
+            let rule = msg["ruleId"].as_str().unwrap_or("");
+            let message = msg["message"].as_str().unwrap_or("").to_string();
+            problems.push(HookProblem {
+                hook_name: hook_name.to_string(),
+                file_name: file_name.clone(),
+                line1: msg["line"].as_u64().unwrap_or(0) as usize,
+                col1: msg["column"].as_u64().unwrap_or(0) as usize,
+                severity: if msg["severity"].as_u64() == Some(2) { AstDiagnosticSeverity::Error } else { AstDiagnosticSeverity::Warning },
+                message: if rule.is_empty() { message } else { format!("{} {}", rule, message) },
+            });
+        }
+    }
+    Ok(problems)
+}
+
+fn parse_gofmt_list(hook_name: &str, stdout: &str, workdir: &PathBuf) -> Vec<HookProblem> {
+    stdout.lines().filter(|l| !l.trim().is_empty()).map(|l| HookProblem {
+        hook_name: hook_name.to_string(),
+        file_name: resolve_file_name(workdir, l.trim()),
+        line1: 0,
+        col1: 0,
+        severity: AstDiagnosticSeverity::Warning,
+        message: "file is not formatted, run gofmt".to_string(),
+    }).collect()
+}
+
+pub fn parse_hook_output(
+    hook_name: &str,
+    output_format: &str,
+    stdout: &str,
+    stderr: &str,
+    exit_code: i32,
+    workdir: &PathBuf,
+    cpath: &PathBuf,
+) -> Vec<HookProblem> {
+    let parsed = match output_format {
+        "ruff_json" => parse_ruff_json(hook_name, stdout, workdir),
+        "cargo_json" => parse_cargo_json(hook_name, stdout, workdir),
+        "eslint_json" => parse_eslint_json(hook_name, stdout, workdir),
+        "gofmt_list" => Ok(parse_gofmt_list(hook_name, stdout, workdir)),
+        _ => Ok(vec![]),
+    };
+    let problems = match parsed {
+        Ok(problems) => problems,
+        Err(e) => {
+            tracing::warn!("post patch hook {}: {}", hook_name, e);
+            vec![]
+        }
+    };
+    if !problems.is_empty() || exit_code == 0 {
+        return problems;
+    }
+    vec![HookProblem {
+        hook_name: hook_name.to_string(),
+        file_name: cpath.to_string_lossy().to_string(),
+        line1: 0,
+        col1: 0,
+        severity: AstDiagnosticSeverity::Error,
+        message: format!("exit code {}\n{}", exit_code, last_n_lines(&format!("{}{}", stdout, stderr), HOOK_PLAIN_OUTPUT_LINES)),
+    }]
+}
+
+pub fn patched_files(chunks: &Vec<DiffChunk>) -> Vec<PathBuf> {
+    let mut result: Vec<PathBuf> = vec![];
+    for chunk in chunks.iter() {
+        let cpath = match (chunk.file_action.as_str(), &chunk.file_name_rename) {
+            ("remove", _) => continue,
+            ("rename", Some(new_name)) => PathBuf::from(new_name),
+            _ => PathBuf::from(&chunk.file_name),
+        };
+        if !result.contains(&cpath) {
+            result.push(cpath);
+        }
+    }
+    result
+}
+
+// Hooks like `cargo check` report the whole crate, problems in other files were most likely there before the patch.
+// Returns the problems to show and how many were dropped.
+fn only_in_patched_files(problems: Vec<HookProblem>, cpaths: &Vec<PathBuf>) -> (Vec<HookProblem>, usize) {
+    let patched: HashSet<PathBuf> = cpaths.iter().map(|p| crate::files_correction::canonical_path(&p.to_string_lossy().to_string())).collect();
+    let total = problems.len();
+    let kept: Vec<HookProblem> = problems.into_iter()
+        .filter(|p| p.file_name.is_empty() || patched.contains(&crate::files_correction::canonical_path(&p.file_name)))
+        .collect();
+    let dropped = total - kept.len();
+    (kept, dropped)
+}
+
+pub async fn run_post_patch_hooks(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    cpaths: &Vec<PathBuf>,
+) -> Vec<HookProblem> {
+    let mut error_log = Vec::new();
+    let hooks = load_customization(gcx.clone(), true, &mut error_log).await.post_patch_hooks;
+    for e in error_log.iter() {
+        tracing::error!("{}:{} {:?}", crate::nicer_logs::last_n_chars(&e.integr_config_path, 30), e.error_line, e.error_msg);
+    }
+    if hooks.is_empty() {
+        return vec![];
+    }
+    let project_dirs = crate::files_correction::get_project_dirs(gcx.clone()).await;
+
+    let mut problems = vec![];
+    let mut already_executed: HashSet<(PathBuf, String)> = HashSet::new();
+    for cpath in cpaths.iter() {
+        let ext = cpath.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        let workdir = project_dirs.iter().find(|d| cpath.starts_with(d)).cloned()
+            .or(cpath.parent().map(|p| p.to_path_buf()))
+            .unwrap_or_default();
+        for (hook_name, hook) in hooks.iter() {
+            if !hook.file_extensions.iter().any(|e| e.trim_start_matches('.').to_lowercase() == ext) {
+                continue;
+            }
+            let command = replace_args(&hook.command, &HashMap::from([
+                ("FILE".to_string(), shell_words::quote(&cpath.to_string_lossy()).to_string()),
+                ("PROJECT_DIR".to_string(), shell_words::quote(&workdir.to_string_lossy()).to_string()),
+            ]));
+            // commands without %FILE% such as `cargo check` run once for all patched files
+            if !already_executed.insert((workdir.clone(), command.clone())) {
+                continue;
+            }
+            let t0 = std::time::Instant::now();
+            let result = match create_command_from_string(&command, &workdir.to_string_lossy().to_string(), &HashMap::new(), vec![]) {
+                Ok(cmd) => execute_command_with_timeout(cmd, hook.timeout).await,
+                Err(e) => Err(e),
+            };
+            tracing::info!("post patch hook {} finished in {:.3}s", hook_name, t0.elapsed().as_secs_f64());
+            match result {
+                Ok((stdout, stderr, exit_code)) => {
+                    problems.extend(parse_hook_output(hook_name, &hook.output_format, &stdout, &stderr, exit_code, &workdir, cpath));
+                }
+                Err(e) => {
+                    problems.push(HookProblem {
+                        hook_name: hook_name.clone(),
+                        file_name: cpath.to_string_lossy().to_string(),
+                        line1: 0,
+                        col1: 0,
+                        severity: AstDiagnosticSeverity::Warning,
+                        message: format!("hook didn't run: {}", e),
+                    });
+                }
+            }
+        }
+    }
+    let (mut problems, elsewhere) = only_in_patched_files(problems, cpaths);
+    if elsewhere > 0 {
+        tracing::info!("post patch hooks: skipped {} problems in files the patch didn't touch", elsewhere);
+    }
+    // errors first, stable within the same severity
+    problems.sort_by_key(|p| p.severity != AstDiagnosticSeverity::Error);
+    problems
+}
+
+pub fn hook_problems_to_text(problems: &Vec<HookProblem>) -> String {
+    let mut text = "💿 Checks that run after the patch found problems, fix the errors before moving on:\n".to_string();
+    for p in problems.iter().take(HOOK_PROBLEMS_LIMIT) {
+        let severity = match p.severity {
+            AstDiagnosticSeverity::Error => "error",
+            AstDiagnosticSeverity::Warning => "warning",
+        };
+        let location = if p.line1 > 0 { format!("{}:{}:{}", p.file_name, p.line1, p.col1) } else { p.file_name.clone() };
+        text.push_str(&format!("{} {} [{}] {}\n", location, severity, p.hook_name, p.message.trim_end()));
+    }
+    if problems.len() > HOOK_PROBLEMS_LIMIT {
+        text.push_str(&format!("...and {} more\n", problems.len() - HOOK_PROBLEMS_LIMIT));
+    }
+    text
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_structured_outputs() {
+        let workdir = PathBuf::from("/proj");
+        let cpath = PathBuf::from("/proj/a.py");
+
+        let ruff = r#"[{"code": "F401", "filename": "/proj/a.py", "location": {"column": 8, "row": 1}, "message": "`os` imported but unused"}]"#;
+        let problems = parse_hook_output("ruff", "ruff_json", ruff, "", 1, &workdir, &cpath);
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].file_name.as_str(), problems[0].line1, problems[0].col1), ("/proj/a.py", 1, 8));
+        assert_eq!(problems[0].message, "F401 `os` imported but unused");
+
+        let cargo = [
+            r#"{"reason": "compiler-artifact", "package_id": "x"}"#,
+            r#"{"reason": "compiler-message", "message": {"level": "error", "message": "mismatched types", "code": {"code": "E0308"}, "spans": [{"file_name": "src/main.rs", "line_start": 3, "column_start": 5, "is_primary": true}]}}"#,
+            r#"{"reason": "compiler-message", "message": {"level": "error", "message": "aborting due to 1 previous error", "code": null, "spans": []}}"#,
+            r#"{"reason": "build-finished", "success": false}"#,
+        ].join("\n");
+        let problems = parse_hook_output("cargo", "cargo_json", &cargo, "", 101, &workdir, &cpath);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].file_name, "/proj/src/main.rs");
+        assert_eq!(problems[0].message, "E0308 mismatched types");
+
+        let eslint = r#"[{"filePath": "/proj/a.js", "messages": [{"ruleId": "no-unused-vars", "severity": 1, "message": "'x' is unused", "line": 2, "column": 7}]}]"#;
+        let problems = parse_hook_output("eslint", "eslint_json", eslint, "", 1, &workdir, &cpath);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].severity, AstDiagnosticSeverity::Warning);
+
+        let problems = parse_hook_output("gofmt", "gofmt_list", "main.go\n", "", 0, &workdir, &cpath);
+        assert_eq!(problems[0].file_name, "/proj/main.go");
+    }
+
+    #[test]
+    fn test_plain_output_and_fallback() {
+        let workdir = PathBuf::from("/proj");
+        let cpath = PathBuf::from("/proj/a.py");
+        assert!(parse_hook_output("mypy", "", "Success\n", "", 0, &workdir, &cpath).is_empty());
+        let problems = parse_hook_output("mypy", "", "a.py:3: error: bad\n", "", 1, &workdir, &cpath);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.contains("a.py:3: error: bad"));
+        // unparsable structured output with a failed exit code falls back to plain text
+        let problems = parse_hook_output("ruff", "ruff_json", "ruff: command not found", "", 127, &workdir, &cpath);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.starts_with("exit code 127"));
+    }
+
+    #[test]
+    fn test_only_in_patched_files() {
+        let problem = |file_name: &str| HookProblem {
+            hook_name: "cargo".to_string(),
+            file_name: file_name.to_string(),
+            line1: 1,
+            col1: 1,
+            severity: AstDiagnosticSeverity::Error,
+            message: "bad".to_string(),
+        };
+        let problems = vec![problem("/proj/src/main.rs"), problem("/proj/src/other.rs"), problem("")];
+        let (kept, dropped) = only_in_patched_files(problems, &vec![PathBuf::from("/proj/src/main.rs")]);
+        assert_eq!(kept.iter().map(|p| p.file_name.as_str()).collect::<Vec<_>>(), vec!["/proj/src/main.rs", ""]);
+        assert_eq!(dropped, 1);
+    }
+}
@@ -12,6 +12,7 @@ use crate::call_validation::ChatMessage;
 use crate::files_correction::get_project_dirs;
 use crate::global_context::GlobalContext;
 use crate::tools::tool_patch_aux::postprocessing_utils::does_doc_have_symbol;
+use crate::tools::tool_patch_aux::unified_diff::parse_unified_diff;
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub(crate) enum PatchAction {
@@ -19,6 +20,7 @@ pub(crate) enum PatchAction {
     #[default]
     PartialEdit,
     RewriteWholeFile,
+    UnifiedDiff,
     Other,
 }
 
@@ -46,6 +48,7 @@ impl PatchAction {
             "📍REWRITE_ONE_SYMBOL" => Ok(PatchAction::RewriteSymbol),
             "📍REWRITE_WHOLE_FILE" => Ok(PatchAction::RewriteWholeFile),
             "📍PARTIAL_EDIT" => Ok(PatchAction::PartialEdit),
+            "📍UNIFIED_DIFF" => Ok(PatchAction::UnifiedDiff),
             "📍OTHER" => Ok(PatchAction::Other),
             _ => Err(format!("invalid action: {}", action)),
         }
@@ -130,6 +133,22 @@ pub async fn correct_and_validate_active_ticket(gcx: Arc<ARwLock<GlobalContext>>
                 }
             }
         }
+        PatchAction::UnifiedDiff => {
+            ticket.filename_before = match resolve_path(gcx.clone(), &ticket.filename_before).await {
+                Ok(filename) => filename,
+                Err(e) => {
+                    // `--- /dev/null` creates a new file
+                    let creates_file = parse_unified_diff(&ticket.code).map(|x| x.creates_file).unwrap_or(false);
+                    if !creates_file {
+                        return Err(_error_text(&format!("failed to resolve filename_before: '{}'. Error:\n{}", ticket.filename_before, e), ticket));
+                    }
+                    if path_before.is_relative() {
+                        return Err(_error_text(&format!("filename_before: '{}' must be absolute.", ticket.filename_before), ticket));
+                    }
+                    crate::files_correction::to_pathbuf_normalize(&ticket.filename_before).to_string_lossy().to_string()
+                }
+            }
+        }
         PatchAction::Other => {}
     }
     Ok(())
@@ -333,9 +352,9 @@ pub async fn get_and_correct_active_tickets(
             &ticket_ids, Some("split the tickets into multiple patch calls".to_string()),
         ));
     }
-    if active_tickets.len() > 1 && !active_tickets.iter().all(|s| PatchAction::PartialEdit == s.action) {
+    if active_tickets.len() > 1 && !active_tickets.iter().all(|s| PatchAction::PartialEdit == s.action || PatchAction::UnifiedDiff == s.action) {
         return Err(good_error_text(
-            "multiple tickets is allowed only for action==PARTIAL_EDIT.",
+            "multiple tickets is allowed only for action==PARTIAL_EDIT or action==UNIFIED_DIFF.",
             &ticket_ids, Some("split the tickets into multiple patch calls".to_string()),
         ));
     }
@@ -0,0 +1,272 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use itertools::Itertools;
+use tokio::sync::RwLock as ARwLock;
+
+use crate::call_validation::DiffChunk;
+use crate::global_context::GlobalContext;
+use crate::tools::tool_patch_aux::diff_structs::chunks_from_diffs;
+use crate::tools::tool_patch_aux::fs_utils::read_file;
+use crate::tools::tool_patch_aux::tickets_parsing::TicketToApply;
+
+// like `patch --fuzz`, this many context lines can be dropped from each end of a hunk that doesn't match
+const MAX_CONTEXT_FUZZ: usize = 2;
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, Clone)]
+struct Hunk {
+    // where the hunk starts according to the @@ header, 0-based, models often get it wrong
+    hint0: Option<usize>,
+    lines: Vec<HunkLine>,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedUnifiedDiff {
+    pub creates_file: bool,
+    pub deletes_file: bool,
+    hunks: Vec<Hunk>,
+}
+
+fn parse_hunk_header(line: &str) -> Option<usize> {
+    // @@ -12,5 +12,6 @@ optional text
+    let old_range = line.trim_start_matches('@').trim().split(' ').next()?.strip_prefix('-')?;
+    let mut parts = old_range.split(',');
+    let start = parts.next()?.parse::<usize>().ok()?;
+    let count = parts.next().map(|c| c.parse::<usize>().unwrap_or(1)).unwrap_or(1);
+    // an empty old range points to the line after which the new lines go
+    Some(if count == 0 { start } else { start.saturating_sub(1) })
+}
+
+pub fn parse_unified_diff(text: &str) -> Result<ParsedUnifiedDiff, String> {
+    let mut result = ParsedUnifiedDiff::default();
+    let mut current: Option<Hunk> = None;
+    let mut files_seen = 0;
+    let lines = text.lines().collect::<Vec<_>>();
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx];
+        idx += 1;
+        // a removed line can start with "--" too, so the header is only a header if +++ follows
+        if line.starts_with("--- ") && lines.get(idx).map_or(false, |l| l.starts_with("+++ ")) {
+            files_seen += 1;
+            if files_seen > 1 {
+                return Err("the diff changes more than one file, use a separate ticket for each file".to_string());
+            }
+            result.creates_file = line[4..].trim() == "/dev/null";
+            result.deletes_file = lines[idx][4..].trim() == "/dev/null";
+            result.hunks.extend(current.take());
+            idx += 1;
+            continue;
+        }
+        if line.starts_with("@@") {
+            result.hunks.extend(current.take());
+            current = Some(Hunk { hint0: parse_hunk_header(line), lines: vec![] });
+            continue;
+        }
+        let hunk = match current.as_mut() {
+            Some(h) => h,
+            None => continue,  // "diff --git", "index ..." and any text before the first hunk
+        };
+        if let Some(rest) = line.strip_prefix('+') {
+            hunk.lines.push(HunkLine::Add(rest.to_string()));
+        } else if let Some(rest) = line.strip_prefix('-') {
+            hunk.lines.push(HunkLine::Remove(rest.to_string()));
+        } else if let Some(rest) = line.strip_prefix(' ') {
+            hunk.lines.push(HunkLine::Context(rest.to_string()));
+        } else if line.starts_with('\\') {
+            // \ No newline at end of file
+        } else if line.starts_with("diff --git") {
+            result.hunks.extend(current.take());
+        } else {
+            // models often lose the leading space of context lines
+            hunk.lines.push(HunkLine::Context(line.to_string()));
+        }
+    }
+    result.hunks.extend(current.take());
+    result.hunks.retain(|h| h.lines.iter().any(|l| !matches!(l, HunkLine::Context(_))));
+    if result.hunks.is_empty() && !result.deletes_file {
+        return Err("no changes found in the diff, expected `@@ ... @@` hunks with `+` and `-` lines".to_string());
+    }
+    Ok(result)
+}
+
+fn find_nearest_match(file_lines: &[String], before: &[&str], search_from: usize, hint0: Option<usize>, ignore_whitespace: bool) -> Option<usize> {
+    if file_lines.len() < before.len() + search_from {
+        return None;
+    }
+    let lines_equal = |a: &str, b: &str| if ignore_whitespace { a.trim() == b.trim() } else { a == b };
+    (search_from..=file_lines.len() - before.len())
+        .filter(|&pos| before.iter().enumerate().all(|(i, b)| lines_equal(&file_lines[pos + i], b)))
+        .min_by_key(|&pos| (pos as i64 - hint0.unwrap_or(search_from) as i64).abs())
+}
+
+fn locate_hunk(file_lines: &[String], hunk: &Hunk, search_from: usize) -> Option<(usize, Vec<HunkLine>)> {
+    for fuzz in 0..=MAX_CONTEXT_FUZZ {
+        let lead = hunk.lines.iter().take_while(|l| matches!(l, HunkLine::Context(_))).count().min(fuzz);
+        let trail = hunk.lines.iter().rev().take_while(|l| matches!(l, HunkLine::Context(_))).count().min(fuzz);
+        if fuzz > 0 && lead == 0 && trail == 0 {
+            break;
+        }
+        let lines = hunk.lines[lead..hunk.lines.len() - trail].to_vec();
+        let before = lines.iter().filter_map(|l| match l {
+            HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_str()),
+            HunkLine::Add(_) => None,
+        }).collect::<Vec<_>>();
+        let hint0 = hunk.hint0.map(|h| h + lead);
+        if before.is_empty() {
+            if fuzz > 0 {
+                // fuzz took away all the context of an insertion, it could go anywhere now
+                break;
+            }
+            // pure insertion written without context, the header is the only thing we have
+            return hint0.filter(|h| *h >= search_from && *h <= file_lines.len()).map(|h| (h, lines));
+        }
+        for ignore_whitespace in [false, true] {
+            if let Some(pos) = find_nearest_match(file_lines, &before, search_from, hint0, ignore_whitespace) {
+                return Some((pos, lines));
+            }
+        }
+    }
+    None
+}
+
+pub fn apply_unified_diff_to_text(file_text: &str, parsed: &ParsedUnifiedDiff) -> Result<String, String> {
+    let line_ending = if file_text.contains("\r\n") { "\r\n" } else { "\n" };
+    let file_lines = file_text.split(line_ending).map(|s| s.to_string()).collect::<Vec<_>>();
+    let mut result: Vec<String> = vec![];
+    let mut cursor = 0;
+    for (hunk_n, hunk) in parsed.hunks.iter().enumerate() {
+        let (pos, lines) = locate_hunk(&file_lines, hunk, cursor).ok_or_else(|| {
+            let first_lines = hunk.lines.iter().filter_map(|l| match l {
+                HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_str()),
+                HunkLine::Add(_) => None,
+            }).take(3).join("\n");
+            format!("hunk #{} not found in the file, it should contain these lines (in order, after the previous hunk):\n{}", hunk_n + 1, first_lines)
+        })?;
+        result.extend(file_lines[cursor..pos].iter().cloned());
+        let mut file_idx = pos;
+        for line in lines.iter() {
+            match line {
+                // context lines keep the original text, they might differ in whitespace
+                HunkLine::Context(_) => { result.push(file_lines[file_idx].clone()); file_idx += 1; }
+                HunkLine::Remove(_) => { file_idx += 1; }
+                HunkLine::Add(s) => { result.push(s.clone()); }
+            }
+        }
+        cursor = file_idx;
+    }
+    result.extend(file_lines[cursor..].iter().cloned());
+    Ok(result.join(line_ending))
+}
+
+pub async fn unified_diff_to_chunks(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    tickets: &Vec<TicketToApply>,
+) -> Result<Vec<DiffChunk>, String> {
+    let filename = tickets.first().ok_or("no tickets")?.filename_before.clone();
+    let parsed_diffs = tickets.iter()
+        .map(|t| parse_unified_diff(&t.code).map_err(|e| format!("ticket {}: {}", t.id, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match read_file(gcx.clone(), filename.clone()).await {
+        Ok(context_file) => {
+            if parsed_diffs.iter().any(|p| p.deletes_file) {
+                return Ok(vec![DiffChunk {
+                    file_name: context_file.file_name.clone(),
+                    file_action: "remove".to_string(),
+                    line1: 1,
+                    line2: 1,
+                    ..Default::default()
+                }]);
+            }
+            let mut new_text = context_file.file_content.clone();
+            for (ticket, parsed) in tickets.iter().zip(parsed_diffs.iter()) {
+                new_text = apply_unified_diff_to_text(&new_text, parsed).map_err(|e| format!("ticket {}: {}", ticket.id, e))?;
+            }
+            if new_text == context_file.file_content {
+                return Err("the diff doesn't change the file".to_string());
+            }
+            let diffs = diff::lines(&context_file.file_content, &new_text);
+            chunks_from_diffs(PathBuf::from(&context_file.file_name), diffs)
+        }
+        Err(e) => {
+            if !parsed_diffs.iter().all(|p| p.creates_file) {
+                return Err(format!("cannot read file to modify: {}.\nError: {e}", filename));
+            }
+            let new_text = apply_unified_diff_to_text("", &ParsedUnifiedDiff {
+                hunks: parsed_diffs.into_iter().flat_map(|p| p.hunks).collect(),
+                ..Default::default()
+            })?;
+            Ok(vec![DiffChunk {
+                file_name: filename,
+                file_name_rename: None,
+                file_action: "add".to_string(),
+                line1: 1,
+                line2: 1,
+                lines_remove: "".to_string(),
+                lines_add: new_text,
+                ..Default::default()
+            }])
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "import os\n\ndef f(x):\n    y = x + 1\n    return y\n\ndef g():\n    pass\n";
+
+    fn apply(diff: &str) -> Result<String, String> {
+        apply_unified_diff_to_text(FILE, &parse_unified_diff(diff)?)
+    }
+
+    #[test]
+    fn test_wrong_line_numbers_are_relocated() {
+        let diff = "--- a/x.py\n+++ b/x.py\n@@ -40,3 +40,3 @@ def f(x):\n def f(x):\n-    y = x + 1\n+    y = x + 2\n     return y\n";
+        assert_eq!(apply(diff).unwrap(), FILE.replace("x + 1", "x + 2"));
+        let no_numbers = "@@ ... @@\n def g():\n-    pass\n+    return 42\n";
+        assert_eq!(apply(no_numbers).unwrap(), FILE.replace("    pass", "    return 42"));
+    }
+
+    #[test]
+    fn test_whitespace_and_fuzz() {
+        // context with broken indent and a context line that isn't in the file at all
+        let diff = "@@ -3,4 +3,4 @@\n def f(x):\n  y = x + 1\n-    return y\n+    return y * 2\n # not in the file\n";
+        assert_eq!(apply(diff).unwrap(), FILE.replace("return y\n", "return y * 2\n"));
+    }
+
+    #[test]
+    fn test_insertion_and_several_hunks() {
+        let diff = "@@ -1,0 +2,1 @@\n+import sys\n@@ -7,2 +8,3 @@\n def g():\n     pass\n+\n";
+        assert_eq!(apply(diff).unwrap(), FILE.replacen("import os\n", "import os\nimport sys\n", 1).replace("    pass\n", "    pass\n\n"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(apply("@@ -1,1 +1,1 @@\n-nonexistent line\n+x\n").unwrap_err().contains("hunk #1 not found"));
+        assert!(parse_unified_diff("just text").is_err());
+        assert!(parse_unified_diff("--- a\n+++ b\n@@ -1 +1 @@\n-a\n+b\n--- c\n+++ d\n@@ -1 +1 @@\n-a\n+b\n").is_err());
+        // an insertion whose context isn't in the file is not put at the header line
+        assert!(apply("@@ -3,2 +3,3 @@\n def h(x):\n+    # comment\n     z = 0\n").unwrap_err().contains("hunk #1 not found"));
+        // hunks must be in file order
+        assert!(apply("@@ -7 +7 @@\n-    pass\n+    return\n@@ -1 +1 @@\n-import os\n+import sys\n").is_err());
+    }
+
+    #[test]
+    fn test_new_and_deleted_files() {
+        let created = parse_unified_diff("--- /dev/null\n+++ b/new.py\n@@ -0,0 +1,2 @@\n+a = 1\n+b = 2\n").unwrap();
+        assert!(created.creates_file);
+        assert_eq!(apply_unified_diff_to_text("", &created).unwrap(), "a = 1\nb = 2\n");
+        let deleted = parse_unified_diff("--- a/old.py\n+++ /dev/null\n").unwrap();
+        assert!(deleted.deletes_file);
+    }
+}
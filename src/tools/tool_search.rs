@@ -1,5 +1,6 @@
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tracing::info;
 
@@ -8,62 +9,41 @@ use itertools::Itertools;
 use tokio::sync::Mutex as AMutex;
 
 use crate::at_commands::at_commands::{vec_context_file_to_context_tools, AtCommandsContext};
-use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
 use crate::at_commands::at_search::execute_at_search;
-use crate::files_correction::{correct_to_nearest_dir_path, get_project_dirs};
 use crate::tools::tools_description::Tool;
 use crate::call_validation::{ChatMessage, ChatContent, ContextEnum, ContextFile};
+use crate::doc_splitter::{is_doc_file, HEADING_PATH_SEPARATOR};
+use crate::search_filter::SearchFilter;
 
 
 pub struct ToolSearch;
 
-async fn execute_att_search(
-    ccx: Arc<AMutex<AtCommandsContext>>,
-    query: &String,
-    scope: &String,
-) -> Result<Vec<ContextFile>, String> {
-    let gcx = ccx.lock().await.global_context.clone();
-    if scope == "workspace" {
-        return execute_at_search(ccx.clone(), &query, None).await
+fn parse_list_arg(args: &HashMap<String, Value>, name: &str) -> Result<Vec<String>, String> {
+    match args.get(name) {
+        Some(Value::String(s)) => Ok(s.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect()),
+        Some(Value::Array(a)) => a.iter().map(|x| x.as_str().map(|x| x.trim().to_string())
+            .ok_or(format!("argument `{}` should contain strings: {:?}", name, x))).collect(),
+        Some(Value::Null) | None => Ok(vec![]),
+        Some(v) => Err(format!("argument `{}` is not a string: {:?}", name, v)),
     }
-    let scope_is_dir = scope.ends_with('/') || scope.ends_with('\\');
-
-    let filter = if scope_is_dir {
-        return_one_candidate_or_a_good_error(
-            gcx.clone(),
-            scope,
-            &correct_to_nearest_dir_path(gcx.clone(), scope, false, 10).await,
-            &get_project_dirs(gcx.clone()).await,
-            true,
-        ).await.map(|dir| format!("(scope LIKE '{}%')", dir))?
-    } else {
-        match return_one_candidate_or_a_good_error(
-            gcx.clone(),
-            scope,
-            &file_repair_candidates(gcx.clone(), scope, 10, false).await,
-            &get_project_dirs(gcx.clone()).await,
-            false,
-        ).await {
-            Ok(file) => format!("(scope = \"{}\")", file),
-            Err(file_err) => {
-                return_one_candidate_or_a_good_error(
-                    gcx.clone(),
-                    scope,
-                    &correct_to_nearest_dir_path(gcx.clone(), scope, false, 10).await,
-                    &get_project_dirs(gcx.clone()).await,
-                    true,
-                ).await.map(|dir| format!("(scope LIKE '{}%')", dir)).map_err(|_| file_err)?
-            },
-        }
-    };
+}
 
-    info!("att-search: filter: {:?}", filter);
-    execute_at_search(ccx.clone(), &query, Some(filter)).await
+fn parse_bool_arg(args: &HashMap<String, Value>, name: &str) -> Result<bool, String> {
+    match args.get(name) {
+        Some(Value::Bool(b)) => Ok(*b),
+        Some(Value::String(s)) if s == "true" || s == "false" => Ok(s == "true"),
+        Some(Value::Null) | None => Ok(false),
+        Some(v) => Err(format!("argument `{}` is not a bool: {:?}", name, v)),
+    }
 }
 
 #[async_trait]
 impl Tool for ToolSearch {
     fn as_any(&self) -> &dyn std::any::Any { self }
+
+    fn parallel_safe_copy(&self) -> Option<Box<dyn Tool + Send>> {
+        Some(Box::new(ToolSearch))
+    }
     
     async fn tool_execute(
         &mut self,
@@ -82,7 +62,19 @@ impl Tool for ToolSearch {
             None => return Err("Missing argument `scope` in the search() call.".to_string())
         };
 
-        let vector_of_context_file = execute_att_search(ccx.clone(), &query, &scope).await?;
+        let filter = SearchFilter {
+            scope,
+            languages: parse_list_arg(args, "languages")?,
+            include: parse_list_arg(args, "include")?,
+            exclude: parse_list_arg(args, "exclude")?,
+            symbol_types: parse_list_arg(args, "symbol_types")?,
+            git_changed: parse_bool_arg(args, "git_changed")?,
+            open_files: parse_bool_arg(args, "open_files")?,
+        };
+        let gcx = ccx.lock().await.global_context.clone();
+        let resolved_filter = filter.resolve(gcx.clone()).await?;
+        info!("att-search: filter {:?} => {:?}", filter, resolved_filter.pushdown);
+        let vector_of_context_file = execute_at_search(ccx.clone(), &query, Some(&resolved_filter)).await?;
         info!("att-search: vector_of_context_file={:?}", vector_of_context_file);
 
         if vector_of_context_file.is_empty() {
@@ -100,7 +92,13 @@ impl Tool for ToolSearch {
                 content.push_str(&format!("{}:\n", rec.file_name.clone()));
                 let file_recs = file_results_to_reqs.get(&rec.file_name).unwrap();
                 for file_req in file_recs.iter().sorted_by(|rec1, rec2| rec2.usefulness.total_cmp(&rec1.usefulness)) {
-                    content.push_str(&format!("    lines {}-{} score {:.1}%\n", file_req.line1, file_req.line2, file_req.usefulness));
+                    match file_req.symbols.first() {
+                        // documents cite the heading path, "docs/architecture.md › Storage › Sharding"
+                        Some(heading_path) if is_doc_file(&PathBuf::from(&rec.file_name)) => content.push_str(&format!("    {}{}{} lines {}-{} score {:.1}%\n", rec.file_name, HEADING_PATH_SEPARATOR, heading_path, file_req.line1, file_req.line2, file_req.usefulness)),
+                        // windows that cover one definition, the symbol is a jump target for definition() and references()
+                        Some(symbol) => content.push_str(&format!("    {} lines {}-{} score {:.1}%\n", symbol, file_req.line1, file_req.line2, file_req.usefulness)),
+                        None => content.push_str(&format!("    lines {}-{} score {:.1}%\n", file_req.line1, file_req.line2, file_req.usefulness)),
+                    }
                 }
                 used_files.insert(rec.file_name.clone());
             }
@@ -118,6 +116,6 @@ impl Tool for ToolSearch {
     }
 
     fn tool_depends_on(&self) -> Vec<String> {
-        vec!["vecdb".to_string()]
+        vec!["vecdb_or_bm25".to_string()]
     }
 }
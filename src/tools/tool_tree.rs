@@ -24,6 +24,10 @@ fn preformat_path(path: &String) -> String {
 impl Tool for ToolTree {
     fn as_any(&self) -> &dyn std::any::Any { self }
 
+    fn parallel_safe_copy(&self) -> Option<Box<dyn Tool + Send>> {
+        Some(Box::new(ToolTree))
+    }
+
     async fn tool_execute(
         &mut self,
         ccx: Arc<AMutex<AtCommandsContext>>,
@@ -16,6 +16,10 @@ pub struct ToolWeb;
 impl Tool for ToolWeb {
     fn as_any(&self) -> &dyn std::any::Any { self }
 
+    fn parallel_safe_copy(&self) -> Option<Box<dyn Tool + Send>> {
+        Some(Box::new(ToolWeb))
+    }
+
     async fn tool_execute(
         &mut self,
         _ccx: Arc<AMutex<AtCommandsContext>>,
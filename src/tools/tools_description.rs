@@ -96,7 +96,10 @@ pub trait Tool: Send + Sync {
         return None;
     }
 
-    fn tool_depends_on(&self) -> Vec<String> { vec![] }   // "ast", "vecdb"
+    fn tool_depends_on(&self) -> Vec<String> { vec![] }   // "ast", "vecdb", "vecdb_or_bm25"
+
+    // Read-only tools return a fresh instance, then several calls from one assistant message run concurrently
+    fn parallel_safe_copy(&self) -> Option<Box<dyn Tool + Send>> { None }
 
     fn usage(&mut self) -> &mut Option<ChatUsage> {
         static mut DEFAULT_USAGE: Option<ChatUsage> = None;
@@ -117,13 +120,13 @@ pub async fn tools_merged_and_filtered(
     gcx: Arc<ARwLock<GlobalContext>>,
     _supports_clicks: bool,  // XXX
 ) -> Result<IndexMap<String, Box<dyn Tool + Send>>, String> {
-    let (ast_on, vecdb_on, allow_experimental) = {
+    let (ast_on, vecdb_on, bm25_on, allow_experimental) = {
         let gcx_locked = gcx.read().await;
         #[cfg(feature="vecdb")]
         let vecdb_on = gcx_locked.vec_db.lock().await.is_some();
         #[cfg(not(feature="vecdb"))]
         let vecdb_on = false;
-        (gcx_locked.ast_service.is_some(), vecdb_on, gcx_locked.cmdline.experimental)
+        (gcx_locked.ast_service.is_some(), vecdb_on, gcx_locked.bm25_service.is_some(), gcx_locked.cmdline.experimental)
     };
 
     let mut tools_all = IndexMap::from([
@@ -136,7 +139,6 @@ pub async fn tools_merged_and_filtered(
         ("think".to_string(), Box::new(crate::tools::tool_deep_thinking::ToolDeepThinking{}) as Box<dyn Tool + Send>),
         // ("locate".to_string(), Box::new(crate::tools::tool_locate::ToolLocate{}) as Box<dyn Tool + Send>))),
         // ("locate".to_string(), Box::new(crate::tools::tool_relevant_files::ToolRelevantFiles{}) as Box<dyn Tool + Send>))),
-        #[cfg(feature="vecdb")]
         ("search".to_string(), Box::new(crate::tools::tool_search::ToolSearch{}) as Box<dyn Tool + Send>),
         #[cfg(feature="vecdb")]
         ("locate".to_string(), Box::new(crate::tools::tool_locate_search::ToolLocateSearch{}) as Box<dyn Tool + Send>),
@@ -160,6 +162,9 @@ pub async fn tools_merged_and_filtered(
         if dependencies.contains(&"vecdb".to_string()) && !vecdb_on {
             continue;
         }
+        if dependencies.contains(&"vecdb_or_bm25".to_string()) && !vecdb_on && !bm25_on {
+            continue;
+        }
         filtered_tools.insert(tool_name, tool);
     }
 
@@ -169,7 +174,7 @@ pub async fn tools_merged_and_filtered(
 const BUILT_IN_TOOLS: &str = r####"
 tools:
   - name: "search"
-    description: "Find similar pieces of code or text using vector database"
+    description: "Find similar pieces of code or text using vector database and exact keyword matching, works well for identifiers, error messages and config keys"
     parameters:
       - name: "query"
         type: "string"
@@ -177,6 +182,24 @@ tools:
       - name: "scope"
         type: "string"
         description: "'workspace' to search all files in workspace, 'dir/subdir/' to search in files within a directory, 'dir/file.ext' to search in a single file."
+      - name: "languages"
+        type: "string"
+        description: "Optional, comma separated languages or file extensions, for example 'python,rust' or 'md'."
+      - name: "include"
+        type: "string"
+        description: "Optional, comma separated globs relative to the project, for example 'src/**/*.ts'. A glob without a slash matches file names."
+      - name: "exclude"
+        type: "string"
+        description: "Optional, comma separated globs to skip, for example '*_test.go,vendor/**'."
+      - name: "symbol_types"
+        type: "string"
+        description: "Optional, comma separated: function, class, type, variable. Only pieces of code that contain such definitions."
+      - name: "git_changed"
+        type: "boolean"
+        description: "Optional, search only in files changed since the last commit."
+      - name: "open_files"
+        type: "boolean"
+        description: "Optional, search only in files open in the IDE."
     parameters_required:
       - "query"
       - "scope"
@@ -267,7 +290,7 @@ tools:
     description: |
       The function to apply changes from the existing 📍-notation edit blocks.
       Do not call the function unless you have a generated 📍-notation edit blocks, you need an existing 📍-notation edit block ticket number!
-      Multiple tickets is allowed only for 📍PARTIAL_EDIT, otherwise only one ticket must be provided.
+      Multiple tickets is allowed only for 📍PARTIAL_EDIT and 📍UNIFIED_DIFF, otherwise only one ticket must be provided.
     parameters:
       - name: "path"
         type: "string"
@@ -402,6 +425,8 @@ pub struct ToolParam {
     #[serde(rename = "type", default = "default_param_type")]
     pub param_type: String,
     pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,  // full JSON schema of the parameter, for tools that come with one (MCP)
 }
 
 fn default_param_type() -> String {
@@ -416,13 +441,18 @@ pub fn make_openai_tool_value(
     parameters: Vec<ToolParam>,
 ) -> Value {
     let params_properties = parameters.iter().map(|param| {
-        (
-            param.name.clone(),
-            json!({
-                "type": param.param_type,
-                "description": param.description
-            })
-        )
+        let mut property = json!({
+            "type": param.param_type,
+            "description": param.description
+        });
+        if let Some(Value::Object(schema)) = &param.schema {
+            let mut schema = schema.clone();
+            if !param.description.is_empty() {
+                schema.entry("description").or_insert(json!(param.description));
+            }
+            property = Value::Object(schema);
+        }
+        (param.name.clone(), property)
     }).collect::<serde_json::Map<_, _>>();
 
     let function_json = json!({
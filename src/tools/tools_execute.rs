@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use futures::StreamExt;
 use glob::Pattern;
 use indexmap::IndexMap;
 use tokio::sync::Mutex as AMutex;
@@ -9,7 +10,7 @@ use tracing::{info, warn};
 
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::at_commands::execute_at::MIN_RAG_CONTEXT_LIMIT;
-use crate::call_validation::{ChatMessage, ChatContent, ContextEnum, ContextFile, SubchatParameters};
+use crate::call_validation::{ChatMessage, ChatContent, ChatToolCall, ChatUsage, ContextEnum, ContextFile, SubchatParameters};
 use crate::http::http_post_json;
 use crate::integrations::docker::docker_container_manager::docker_container_get_host_lsp_port_to_connect;
 use crate::postprocessing::pp_context_files::postprocess_context_files;
@@ -21,6 +22,8 @@ use crate::caps::get_model_record;
 use crate::http::routers::v1::at_tools::{ToolExecuteResponse, ToolsExecutePost};
 
 
+const PARALLEL_TOOL_CALLS_LIMIT: usize = 4;
+
 pub async fn unwrap_subchat_params(ccx: Arc<AMutex<AtCommandsContext>>, tool_name: &str) -> Result<SubchatParameters, String> {
     let (gcx, params_mb) = {
         let ccx_locked = ccx.lock().await;
@@ -158,20 +161,18 @@ pub async fn run_tools(
         return Ok((vec![], false));
     }
 
-    let mut context_files_for_pp = vec![];
-    let mut generated_tool = vec![];  // tool results must go first
-    let mut generated_other = vec![];
-    let mut any_corrections = false;
-
-    for t_call in last_msg_tool_calls {
-        let cmd = match tools.get_mut(&t_call.function.name) {
+    // Unknown tools, bad arguments and denied commands get their answer right away, the rest is ready to run
+    let mut outcomes: Vec<Option<Result<(bool, Vec<ContextEnum>), ChatMessage>>> = last_msg_tool_calls.iter().map(|_| None).collect();
+    let mut ready = vec![];
+    for (i, t_call) in last_msg_tool_calls.iter().enumerate() {
+        let cmd = match tools.get(&t_call.function.name) {
             Some(cmd) => cmd,
             None => {
                 let tool_failed_message = tool_answer(
                     format!("tool use: function {:?} not found", &t_call.function.name), t_call.id.to_string()
                 );
                 warn!("{}", tool_failed_message.content.content_text_only());
-                generated_tool.push(tool_failed_message.clone());
+                outcomes[i] = Some(Err(tool_failed_message));
                 continue;
             }
         };
@@ -182,7 +183,7 @@ pub async fn run_tools(
                 let tool_failed_message = tool_answer(
                     format!("Tool use: couldn't parse arguments: {}. Error:\n{}", t_call.function.arguments, e), t_call.id.to_string()
                 );
-                generated_tool.push(tool_failed_message);
+                outcomes[i] = Some(Err(tool_failed_message));
                 continue;
             }
         };
@@ -195,38 +196,78 @@ pub async fn run_tools(
                         let command_to_match = cmd
                             .command_to_match_against_confirm_deny(&args)
                             .unwrap_or("<error_command>".to_string());
-                        generated_tool.push(tool_answer(format!("tool use: command '{command_to_match}' is denied"), t_call.id.to_string()));
+                        outcomes[i] = Some(Err(tool_answer(format!("tool use: command '{command_to_match}' is denied"), t_call.id.to_string())));
                         continue;
                     }
                     MatchConfirmDenyResult::CONFIRMATION if !tools_confirmation => {
                         let command_to_match = cmd
                             .command_to_match_against_confirm_deny(&args)
                             .unwrap_or("<error_command>".to_string());
-                        generated_tool.push(tool_answer(format!("tool use: command '{command_to_match}' has been denied by the user"), t_call.id.to_string()));
+                        outcomes[i] = Some(Err(tool_answer(format!("tool use: command '{command_to_match}' has been denied by the user"), t_call.id.to_string())));
                         continue;
                     }
                     _ => {}
                 }
             }
             Err(err) => {
-                generated_tool.push(tool_answer(format!("tool use: {}", err), t_call.id.to_string()));
+                outcomes[i] = Some(Err(tool_answer(format!("tool use: {}", err), t_call.id.to_string())));
                 continue;
             }
         };
+        ready.push((i, args));
+    }
 
-        let (corrections, tool_execute_results) = {
-            match cmd.tool_execute(ccx.clone(), &t_call.id.to_string(), &args).await {
-                Ok(msg_and_maybe_more) => msg_and_maybe_more,
-                Err(e) => {
-                    warn!("tool use {}({:?}) FAILED: {}", &t_call.function.name, &args, e);
-                    let mut tool_failed_message = tool_answer(e, t_call.id.to_string());
+    // Consecutive parallel-safe calls run concurrently, any other tool waits for everything before it and blocks everything after
+    let mut ready = ready.into_iter().peekable();
+    while let Some((i, args)) = ready.next() {
+        let t_call = &last_msg_tool_calls[i];
+        let cmd = tools.get_mut(&t_call.function.name).unwrap();
+        let Some(first_copy) = cmd.parallel_safe_copy() else {
+            outcomes[i] = Some(execute_tool_call(cmd, ccx.clone(), t_call, &args).await);
+            continue;
+        };
+        let mut batch = vec![(i, args, first_copy)];
+        while let Some((j, _)) = ready.peek() {
+            let Some(copy) = tools.get(&last_msg_tool_calls[*j].function.name).and_then(|t| t.parallel_safe_copy()) else {
+                break;
+            };
+            let (j, args_j) = ready.next().unwrap();
+            batch.push((j, args_j, copy));
+        }
+        if batch.len() > 1 {
+            info!("run_tools: {} tool calls in parallel, limit {}", batch.len(), PARALLEL_TOOL_CALLS_LIMIT);
+        }
+        let mut batch_futures = vec![];
+        for (j, args_j, mut copy) in batch {
+            let ccx = ccx.clone();
+            let t_call = last_msg_tool_calls[j].clone();
+            batch_futures.push(async move {
+                let outcome = execute_tool_call(&mut copy, ccx, &t_call, &args_j).await;
+                (j, outcome, copy)
+            });
+        }
+        let batch_outcomes = futures::stream::iter(batch_futures).buffered(PARALLEL_TOOL_CALLS_LIMIT).collect::<Vec<_>>().await;
+        for (j, outcome, mut copy) in batch_outcomes {
+            // the copy is dropped, its usage goes to the tool it was copied from
+            if let Some(usage) = copy.usage().take() {
+                let cmd = tools.get_mut(&last_msg_tool_calls[j].function.name).unwrap();
+                add_usage(cmd.usage(), &usage);
+            }
+            outcomes[j] = Some(outcome);
+        }
+    }
 
-                    tool_failed_message.usage = cmd.usage().clone();
-                    *cmd.usage() = None;
+    let mut context_files_for_pp = vec![];
+    let mut generated_tool = vec![];  // tool results must go first
+    let mut generated_other = vec![];
+    let mut any_corrections = false;
 
-                    generated_tool.push(tool_failed_message.clone());
-                    continue;
-                }
+    for (t_call, outcome) in last_msg_tool_calls.iter().zip(outcomes.into_iter()) {
+        let (corrections, tool_execute_results) = match outcome.unwrap() {
+            Ok(msg_and_maybe_more) => msg_and_maybe_more,
+            Err(tool_failed_message) => {
+                generated_tool.push(tool_failed_message);
+                continue;
             }
         };
 
@@ -272,6 +313,31 @@ pub async fn run_tools(
     Ok((new_messages, true))
 }
 
+fn add_usage(total: &mut Option<ChatUsage>, usage: &ChatUsage) {
+    let total = total.get_or_insert_with(ChatUsage::default);
+    total.prompt_tokens += usage.prompt_tokens;
+    total.completion_tokens += usage.completion_tokens;
+    total.total_tokens += usage.total_tokens;
+}
+
+async fn execute_tool_call(
+    cmd: &mut Box<dyn Tool + Send>,
+    ccx: Arc<AMutex<AtCommandsContext>>,
+    t_call: &ChatToolCall,
+    args: &HashMap<String, Value>,
+) -> Result<(bool, Vec<ContextEnum>), ChatMessage> {
+    match cmd.tool_execute(ccx, &t_call.id.to_string(), args).await {
+        Ok(msg_and_maybe_more) => Ok(msg_and_maybe_more),
+        Err(e) => {
+            warn!("tool use {}({:?}) FAILED: {}", &t_call.function.name, args, e);
+            let mut tool_failed_message = tool_answer(e, t_call.id.to_string());
+            tool_failed_message.usage = cmd.usage().clone();
+            *cmd.usage() = None;
+            Err(tool_failed_message)
+        }
+    }
+}
+
 async fn pp_run_tools(
     ccx: Arc<AMutex<AtCommandsContext>>,
     original_messages: &Vec<ChatMessage>,
@@ -424,3 +490,84 @@ pub fn command_should_be_denied(
 
     (false, "".to_string())
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::sync::Mutex as StdMutex;
+    use std::time::{Duration, Instant};
+    use async_trait::async_trait;
+    use structopt::StructOpt;
+    use crate::call_validation::ChatToolFunction;
+
+    #[derive(Clone)]
+    struct SlowTool {
+        runs: Arc<StdMutex<Vec<(Instant, Instant)>>>,
+        usage: Option<ChatUsage>,
+    }
+
+    #[async_trait]
+    impl Tool for SlowTool {
+        fn as_any(&self) -> &dyn std::any::Any { self }
+
+        async fn tool_execute(
+            &mut self,
+            _ccx: Arc<AMutex<AtCommandsContext>>,
+            tool_call_id: &String,
+            args: &HashMap<String, Value>,
+        ) -> Result<(bool, Vec<ContextEnum>), String> {
+            let t0 = Instant::now();
+            tokio::time::sleep(Duration::from_millis(args["ms"].as_u64().unwrap())).await;
+            self.runs.lock().unwrap().push((t0, Instant::now()));
+            self.usage = Some(ChatUsage { prompt_tokens: 1, completion_tokens: 1, total_tokens: 2 });
+            Ok((false, vec![ContextEnum::ChatMessage(tool_answer(format!("done {}", tool_call_id), tool_call_id.clone()))]))
+        }
+
+        fn parallel_safe_copy(&self) -> Option<Box<dyn Tool + Send>> {
+            Some(Box::new(SlowTool { runs: self.runs.clone(), usage: None }))
+        }
+
+        fn usage(&mut self) -> &mut Option<ChatUsage> {
+            &mut self.usage
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_tools_in_parallel_keeps_order() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cmdline = crate::global_context::CommandLine::from_iter(["refact-lsp"]);
+        let (gcx, _ask_shutdown_receiver) = crate::global_context::global_context_from_cmdline(cmdline, tmp.path().to_path_buf(), tmp.path().to_path_buf()).await;
+        let ccx = Arc::new(AMutex::new(AtCommandsContext::new(gcx, 16000, 5, false, vec![], "".to_string(), false).await));
+        let tokenizer = Arc::new(RwLock::new(Tokenizer::from_str(include_str!("../ast/dummy_tokenizer.json")).unwrap()));
+
+        let runs = Arc::new(StdMutex::new(vec![]));
+        let mut tools: IndexMap<String, Box<dyn Tool + Send>> = IndexMap::new();
+        tools.insert("slow".to_string(), Box::new(SlowTool { runs: runs.clone(), usage: None }));
+        let tool_calls = [300, 100, 200].iter().enumerate().map(|(i, ms)| ChatToolCall {
+            id: format!("call_{}", i),
+            function: ChatToolFunction { arguments: json!({"ms": ms}).to_string(), name: "slow".to_string() },
+            tool_type: "function".to_string(),
+        }).collect::<Vec<_>>();
+        let messages = vec![ChatMessage { role: "assistant".to_string(), tool_calls: Some(tool_calls), ..Default::default() }];
+
+        let t0 = Instant::now();
+        let (new_messages, tools_ran) = run_tools(ccx, &mut tools, tokenizer, 1000, &messages, &None, false).await.unwrap();
+        assert!(tools_ran);
+        assert!(t0.elapsed() < Duration::from_millis(550), "took {:?}", t0.elapsed());
+        let runs = runs.lock().unwrap().clone();
+        assert_eq!(runs.len(), 3);
+        let latest_start = runs.iter().map(|r| r.0).max().unwrap();
+        let earliest_end = runs.iter().map(|r| r.1).min().unwrap();
+        assert!(latest_start < earliest_end, "the calls didn't overlap");
+
+        let answers: Vec<(String, String)> = new_messages.iter().map(|m| (m.tool_call_id.clone(), m.content.content_text_only())).collect();
+        assert_eq!(answers, vec![
+            ("call_0".to_string(), "done call_0".to_string()),
+            ("call_1".to_string(), "done call_1".to_string()),
+            ("call_2".to_string(), "done call_2".to_string()),
+        ]);
+        assert_eq!(tools.get_mut("slow").unwrap().usage().as_ref().unwrap().total_tokens, 6);
+    }
+}
@@ -1,15 +1,28 @@
+use crate::call_validation::ChatMessage;
 use crate::global_context::GlobalContext;
-use crate::vecdb::vdb_highlev::{memories_add, memories_block_until_vectorized, memories_erase, memories_select_all, VecDb};
+use crate::knowledge::{memo_content_hash, memories_fill_scope};
+use crate::vecdb::vdb_highlev::{memories_active_scope, memories_add, memories_block_until_vectorized, memories_erase, memories_import_records, memories_import_scope, memories_select_all, VecDb};
+use crate::vecdb::vdb_structs::MemoExportRecord;
 use serde_json::Value;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{RwLock as ARwLock, Mutex as AMutex};
-use tracing::info;
+use tracing::{info, warn};
 use chrono::{NaiveDateTime, Utc};
 
 static URL: &str = "https://www.smallcloud.ai/v1/trajectory-get-all";
 static TRAJECTORIES_STATUS_FILENAME: &str = "trajectories_last_update";
 static TRAJECTORIES_UPDATE_EACH_N_DAYS: i64 = 7;
 
+const LOCAL_TRAJECTORY_ORIGIN_PREFIX: &str = "local-trajectory:";  // followed by chat_id
+const TRAJECTORY_TYPE: &str = "trajectory";
+const TRAJECTORY_PACK_ORIGIN_PREFIX: &str = "trajectory-pack:";
+const TRAJECTORY_MIN_TOOL_CALLS: usize = 2;
+const TRAJECTORY_GOAL_MAX_CHARS: usize = 300;
+const TRAJECTORY_ARGS_MAX_CHARS: usize = 120;
+const TRAJECTORY_OUTCOME_MAX_CHARS: usize = 600;
+
 
 async fn save_last_download_time(gcx: Arc<ARwLock<GlobalContext>>) -> Result<(), String> {
     let cache_dir = gcx.read().await.cache_dir.clone();
@@ -48,17 +61,21 @@ async fn remove_legacy_trajectories(vecdb: Arc<AMutex<Option<VecDb>>>) -> Result
 }
 
 pub async fn try_to_download_trajectories(gcx: Arc<ARwLock<GlobalContext>>) -> Result<(), String> {
-    if !is_time_to_download_trajectories(gcx.clone()).await? {
-        return Ok(());
-    }
-    
-    let (vec_db, api_key) = {
+    let (vec_db, api_key, address_url) = {
         let gcx_locked = gcx.read().await;
         (
             gcx_locked.vec_db.clone(),
             gcx_locked.cmdline.api_key.clone(),
+            gcx_locked.cmdline.address_url.clone(),
         )
     };
+    // self-hosted and bring-your-own-key setups use local trajectories and trajectory_packs instead
+    if address_url.to_lowercase() != "refact" {
+        return Ok(());
+    }
+    if !is_time_to_download_trajectories(gcx.clone()).await? {
+        return Ok(());
+    }
     if vec_db.lock().await.is_none() {
         return Err("vecdb is not initialized".to_string());        
     }
@@ -96,6 +113,7 @@ pub async fn try_to_download_trajectories(gcx: Arc<ARwLock<GlobalContext>>) -> R
             m_project,
             m_payload,
             m_origin,
+            crate::knowledge::MEMORIES_GLOBAL_SCOPE,
         ).await {
             Ok(memid) => info!("memory added with ID: {}", memid),
             Err(err) => info!("failed to add memory: {}", err),
@@ -113,3 +131,276 @@ pub async fn try_to_download_trajectories(gcx: Arc<ARwLock<GlobalContext>>) -> R
     save_last_download_time(gcx.clone()).await?;
     Ok(())
 }
+
+fn tool_answer_looks_failed(text: &str) -> bool {
+    let t = text.trim_start().to_lowercase();
+    t.starts_with("error") || t.starts_with("tool use:") || t.starts_with("failed")
+}
+
+// Successful means the agent finished on its own: the last message is a complete answer without
+// tool calls, there was real work done with tools, and the last tool it ran did not fail.
+// Returns (goal, payload) where payload is the goal, the tool-call sequence and the outcome.
+pub fn compress_trajectory(messages: &Vec<ChatMessage>) -> Option<(String, String)> {
+    let last = messages.last()?;
+    if last.role != "assistant" || last.tool_calls.as_ref().map(|x| !x.is_empty()).unwrap_or(false) || last.finish_reason == Some("length".to_string()) {
+        return None;
+    }
+    let goal_full = messages.iter().find(|m| m.role == "user")?.content.content_text_only();
+    let goal: String = goal_full.trim().chars().take(TRAJECTORY_GOAL_MAX_CHARS).collect();
+    if goal.is_empty() {
+        return None;
+    }
+
+    let mut steps: Vec<String> = vec![];
+    let mut last_failed = false;
+    for m in messages.iter().filter(|m| m.role == "assistant") {
+        for call in m.tool_calls.iter().flatten() {
+            // the knowledge tool is how trajectories are consumed, it's not a part of the solution
+            if call.function.name == "knowledge" {
+                continue;
+            }
+            let failed = messages.iter()
+                .find(|x| x.role == "tool" && x.tool_call_id == call.id)
+                .map(|x| tool_answer_looks_failed(&x.content.content_text_only()))
+                .unwrap_or(false);
+            let args = serde_json::from_str::<Value>(&call.function.arguments)
+                .map(|x| x.to_string())
+                .unwrap_or(call.function.arguments.clone());
+            let step = format!(
+                "{}({}){}",
+                call.function.name,
+                crate::nicer_logs::first_n_chars(&args, TRAJECTORY_ARGS_MAX_CHARS),
+                if failed { " => failed" } else { "" },
+            );
+            if steps.last() != Some(&step) {
+                steps.push(step);
+            }
+            last_failed = failed;
+        }
+    }
+    if steps.len() < TRAJECTORY_MIN_TOOL_CALLS || last_failed {
+        return None;
+    }
+
+    let outcome: String = last.content.content_text_only().trim().chars().take(TRAJECTORY_OUTCOME_MAX_CHARS).collect();
+    let mut payload = format!("Goal: {}\nTool calls:\n", goal);
+    for (i, step) in steps.iter().enumerate() {
+        payload.push_str(&format!("{}. {}\n", i + 1, step));
+    }
+    payload.push_str(&format!("Outcome: {}\n", outcome));
+    Some((goal, payload))
+}
+
+// Called each time an agent chat turn is over, a continued chat replaces the trajectory it recorded before
+pub async fn try_to_record_local_trajectory(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    chat_id: &str,
+    messages: &Vec<ChatMessage>,
+) -> Result<Option<String>, String> {
+    let (goal, payload) = match compress_trajectory(messages) {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let vec_db = gcx.read().await.vec_db.clone();
+    if vec_db.lock().await.is_none() {
+        return Ok(None);
+    }
+    let scope = memories_active_scope(gcx.clone()).await;
+    let origin = format!("{}{}", LOCAL_TRAJECTORY_ORIGIN_PREFIX, chat_id);
+    for memo in memories_select_all(vec_db.clone()).await?.iter().filter(|x| x.m_origin == origin) {
+        if memo.m_payload == payload && memo.m_scope == scope {
+            return Ok(None);
+        }
+        memories_erase(vec_db.clone(), &memo.memid).await?;
+    }
+    let memid = memories_add(vec_db.clone(), TRAJECTORY_TYPE, &goal, &scope, &payload, &origin, &scope).await?;
+    info!("recorded local trajectory {}: {}", memid, crate::nicer_logs::first_n_chars(&goal, 100));
+    Ok(Some(memid))
+}
+
+fn trajectory_pack_record(item: &Value, origin: &str) -> Result<MemoExportRecord, String> {
+    let field = |names: &[&str], default: &str| -> String {
+        names.iter().find_map(|n| item.get(*n).and_then(|x| x.as_str())).unwrap_or(default).to_string()
+    };
+    let m_payload = field(&["payload"], "");
+    if m_payload.is_empty() {
+        return Err("empty payload".to_string());
+    }
+    Ok(MemoExportRecord {
+        m_type: field(&["type", "kind"], TRAJECTORY_TYPE),
+        m_goal: field(&["goal"], ""),
+        m_project: field(&["project", "framework"], ""),
+        m_payload,
+        m_origin: origin.to_string(),
+        m_scope: field(&["scope"], ""),
+        ..Default::default()
+    })
+}
+
+// Accepts the memories export JSONL, a JSON list, or the {"data": [...]} trajectory-get-all response,
+// records use either the export field names or the downloaded ones (kind, framework)
+pub fn trajectory_pack_parse(text: &str, origin: &str) -> Result<Vec<MemoExportRecord>, String> {
+    let items: Vec<(usize, Value)> = match serde_json::from_str::<Value>(text) {
+        Ok(Value::Array(a)) => a.into_iter().enumerate().collect(),
+        Ok(Value::Object(o)) if o.get("data").map(|x| x.is_array()).unwrap_or(false) => {
+            o["data"].as_array().unwrap().iter().cloned().enumerate().collect()
+        }
+        _ => {
+            let mut items = vec![];
+            for (line_n, line) in text.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let item = serde_json::from_str::<Value>(line).map_err(|e| format!("line {}: {}", line_n + 1, e))?;
+                items.push((line_n, item));
+            }
+            items
+        }
+    };
+    items.iter()
+        .map(|(n, item)| trajectory_pack_record(item, origin).map_err(|e| format!("record {}: {}", n + 1, e)))
+        .collect()
+}
+
+async fn trajectory_pack_read(gcx: Arc<ARwLock<GlobalContext>>, name: &String, pack: &crate::yaml_configs::customization_loader::TrajectoryPack) -> Result<Vec<MemoExportRecord>, String> {
+    let origin = format!("{}{}", TRAJECTORY_PACK_ORIGIN_PREFIX, name);
+    let mut records = vec![];
+    if !pack.dir.is_empty() {
+        let config_dir = gcx.read().await.config_dir.clone();
+        let dir = config_dir.join(PathBuf::from(&pack.dir));  // an absolute dir replaces config_dir
+        let mut files = vec![];
+        let mut entries = tokio::fs::read_dir(&dir).await.map_err(|e| format!("cannot read {}: {}", dir.display(), e))?;
+        while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+            let path = entry.path();
+            if path.extension().map(|x| x == "jsonl" || x == "json").unwrap_or(false) {
+                files.push(path);
+            }
+        }
+        files.sort();
+        for path in files {
+            let text = tokio::fs::read_to_string(&path).await.map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+            records.extend(trajectory_pack_parse(&text, &origin).map_err(|e| format!("{}: {}", path.display(), e))?);
+        }
+    }
+    if !pack.url.is_empty() {
+        let http_client = gcx.read().await.http_client.clone();
+        let response = http_client.get(&pack.url).send().await.map_err(|e| format!("{}: {}", pack.url, e))?;
+        if !response.status().is_success() {
+            return Err(format!("{}: bad status {}", pack.url, response.status()));
+        }
+        let text = response.text().await.map_err(|e| format!("{}: {}", pack.url, e))?;
+        records.extend(trajectory_pack_parse(&text, &origin).map_err(|e| format!("{}: {}", pack.url, e))?);
+    }
+    Ok(records)
+}
+
+// Packs are the source of truth for their records: new ones are imported, the ones removed from a pack are erased.
+// A pack that fails to load keeps what it had before, so an unreachable url doesn't wipe it.
+pub async fn load_trajectory_packs(gcx: Arc<ARwLock<GlobalContext>>) -> Result<(), String> {
+    let vec_db = gcx.read().await.vec_db.clone();
+    if vec_db.lock().await.is_none() {
+        return Err("vecdb is not initialized".to_string());
+    }
+    let mut error_log = vec![];
+    let packs = crate::yaml_configs::customization_loader::load_customization(gcx.clone(), true, &mut error_log).await.trajectory_packs;
+    let default_scope = memories_import_scope(gcx.clone()).await;
+    for (name, pack) in packs.iter() {
+        let mut records = match trajectory_pack_read(gcx.clone(), name, pack).await {
+            Ok(records) => records,
+            Err(e) => {
+                warn!("trajectory pack {:?} failed to load: {}", name, e);
+                continue;
+            }
+        };
+        memories_fill_scope(&mut records, &default_scope);
+        let origin = format!("{}{}", TRAJECTORY_PACK_ORIGIN_PREFIX, name);
+        let hashes: HashSet<String> = records.iter()
+            .map(|r| memo_content_hash(&r.m_type, &r.m_goal, &r.m_project, &r.m_payload, &r.m_scope))
+            .collect();
+        let mut removed_cnt = 0;
+        for memo in memories_select_all(vec_db.clone()).await?.iter()
+            .filter(|x| x.m_origin == origin)
+            .filter(|x| !hashes.contains(&memo_content_hash(&x.m_type, &x.m_goal, &x.m_project, &x.m_payload, &x.m_scope))) {
+            memories_erase(vec_db.clone(), &memo.memid).await?;
+            removed_cnt += 1;
+        }
+        let (imported, _) = memories_import_records(vec_db.clone(), records).await?;
+        info!("trajectory pack {:?}: {} total, {} new, {} removed", name, hashes.len(), imported, removed_cnt);
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::call_validation::{ChatContent, ChatToolCall, ChatToolFunction};
+
+    fn msg(role: &str, content: &str) -> ChatMessage {
+        ChatMessage { role: role.to_string(), content: ChatContent::SimpleText(content.to_string()), ..Default::default() }
+    }
+
+    fn call(id: &str, name: &str, arguments: &str) -> ChatMessage {
+        ChatMessage {
+            role: "assistant".to_string(),
+            tool_calls: Some(vec![ChatToolCall {
+                id: id.to_string(),
+                function: ChatToolFunction { name: name.to_string(), arguments: arguments.to_string() },
+                tool_type: "function".to_string(),
+            }]),
+            ..Default::default()
+        }
+    }
+
+    fn answer(id: &str, content: &str) -> ChatMessage {
+        ChatMessage { tool_call_id: id.to_string(), ..msg("tool", content) }
+    }
+
+    #[test]
+    fn test_compress_trajectory() {
+        let mut messages = vec![
+            msg("system", "You are an agent"),
+            msg("user", "Fix the failing test in parser.rs"),
+            call("c0", "knowledge", r#"{"goal": "fix test"}"#),
+            answer("c0", "nothing"),
+            call("c1", "cat", r#"{"paths": "parser.rs"}"#),
+            answer("c1", "fn parse() {}"),
+            call("c2", "patch", r#"{"tickets": "001"}"#),
+            answer("c2", "Error: ticket 001 not found"),
+            call("c3", "patch", r#"{"tickets": "002"}"#),
+            answer("c3", "applied"),
+        ];
+        // still running
+        assert!(compress_trajectory(&messages).is_none());
+        messages.push(msg("assistant", "The test passes now."));
+        let (goal, payload) = compress_trajectory(&messages).unwrap();
+        assert_eq!(goal, "Fix the failing test in parser.rs");
+        assert!(!payload.contains("knowledge("));
+        assert!(payload.contains("1. cat({\"paths\":\"parser.rs\"})\n"));
+        assert!(payload.contains("2. patch({\"tickets\":\"001\"}) => failed\n"));
+        assert!(payload.ends_with("Outcome: The test passes now.\n"));
+
+        // gave up after a failure
+        let mut failed = messages[..8].to_vec();
+        failed.push(msg("assistant", "I could not fix it."));
+        assert!(compress_trajectory(&failed).is_none());
+    }
+
+    #[test]
+    fn test_trajectory_pack_parse() {
+        let jsonl = "{\"type\": \"trajectory\", \"goal\": \"g1\", \"project\": \"p\", \"payload\": \"x\"}\n\n{\"kind\": \"k\", \"goal\": \"g2\", \"framework\": \"django\", \"payload\": \"y\", \"scope\": \"myproj\"}\n";
+        let records = trajectory_pack_parse(jsonl, "trajectory-pack:team").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].m_type, "k");
+        assert_eq!(records[1].m_project, "django");
+        assert_eq!(records[1].m_scope, "myproj");
+        assert_eq!(records[0].m_scope, "");
+        assert!(records.iter().all(|r| r.m_origin == "trajectory-pack:team"));
+
+        let downloaded = r#"{"retcode": "OK", "data": [{"kind": "k", "goal": "g", "framework": "f", "payload": "z"}]}"#;
+        assert_eq!(trajectory_pack_parse(downloaded, "o").unwrap()[0].m_payload, "z");
+        assert_eq!(trajectory_pack_parse(r#"[{"goal": "g", "payload": "z"}]"#, "o").unwrap()[0].m_type, TRAJECTORY_TYPE);
+        assert!(trajectory_pack_parse("{\"goal\": \"g\"}\n", "o").is_err());
+        assert!(trajectory_pack_parse("not json\n", "o").is_err());
+    }
+}
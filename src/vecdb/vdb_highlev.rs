@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{Mutex as AMutex, RwLock as ARwLock};
@@ -6,15 +7,17 @@ use async_trait::async_trait;
 use tracing::{error, info};
 
 use crate::background_tasks::BackgroundTasksHolder;
-use crate::caps::get_custom_embedding_api_key;
+use crate::caps::{get_custom_embedding_api_key, get_custom_vecdb_remote_api_key};
 use crate::fetch_embedding;
 use crate::global_context::{CommandLine, GlobalContext};
-use crate::knowledge::{lance_search, MemoriesDatabase};
-use crate::trajectories::try_to_download_trajectories;
+use crate::search_filter::ScopeFilter;
+use crate::knowledge::{lance_search, memo_retention_score, memo_retention_victims, memo_to_export_record, memo_scope_from_remote_url, memories_fill_scope, memories_from_jsonl, memories_to_jsonl, MemoRetentionPolicy, MemoriesDatabase, MEMORIES_GLOBAL_SCOPE, MEMORIES_LEGACY_SCOPE};
+use crate::trajectories::{load_trajectory_packs, try_to_download_trajectories};
 use crate::vecdb::vdb_cache::VecDBCache;
 use crate::vecdb::vdb_lance::VecDBHandler;
-use crate::vecdb::vdb_structs::{MemoRecord, MemoSearchResult, SearchResult, VecDbStatus, VecdbConstants, VecdbSearch};
-use crate::vecdb::vdb_thread::{vecdb_start_background_tasks, vectorizer_enqueue_dirty_memory, vectorizer_enqueue_files, FileVectorizerService};
+use crate::vecdb::vdb_remote::VecDbRemote;
+use crate::vecdb::vdb_structs::{MemoExportRecord, MemoRecord, MemoSearchResult, SearchResult, VecDbStatus, VecdbConstants, VecdbSearch};
+use crate::vecdb::vdb_thread::{vecdb_start_background_tasks, vectorizer_enqueue_dirty_memory, vectorizer_enqueue_files, vectorizer_forget_files, FileVectorizerService};
 
 
 fn model_to_rejection_threshold(embedding_model: &str) -> f32 {
@@ -31,6 +34,8 @@ pub struct VecDb {
     vecdb_emb_client: Arc<AMutex<reqwest::Client>>,
     vecdb_handler: Arc<AMutex<VecDBHandler>>,
     pub vectorizer_service: Arc<AMutex<FileVectorizerService>>,
+    // code search goes to the remote index instead of the local table, memories stay local
+    remote: Option<VecDbRemote>,
     // cmdline: CommandLine,  // TODO: take from command line what's needed, don't store a copy
     constants: VecdbConstants,
 }
@@ -72,12 +77,31 @@ async fn _create_vecdb(
         "" => (cache_dir, config_dir),
         path => (PathBuf::from(path), PathBuf::from(path)),
     };
+    let remote = if constants.vecdb_remote_endpoint.is_empty() {
+        None
+    } else {
+        let remote_api_key = get_custom_vecdb_remote_api_key(gcx.clone()).await.map_err(|e| e.message)?;
+        let (http_client, workspace_folders) = {
+            let gcx_locked = gcx.read().await;
+            let folders = gcx_locked.documents_state.workspace_folders.lock().unwrap().clone();
+            (gcx_locked.http_client.clone(), folders)
+        };
+        info!("vecdb: code search will use the remote index {}", constants.vecdb_remote_endpoint);
+        Some(VecDbRemote::new(
+            http_client,
+            constants.vecdb_remote_endpoint.clone(),
+            remote_api_key,
+            constants.vecdb_remote_page_size,
+            workspace_folders,
+        ))
+    };
     let vec_db_mb = match VecDb::init(
         &base_dir_cache,
         &base_dir_config,
         cmdline.clone(),
         constants,
-        &api_key
+        &api_key,
+        remote,
     ).await {
         Ok(res) => Some(res),
         Err(err) => {
@@ -129,7 +153,10 @@ async fn do_i_need_to_reload_vecdb(
         }
     };
 
-    let vecdb_max_files = gcx.read().await.cmdline.vecdb_max_files;
+    let (vecdb_max_files, vecdb_symbols) = {
+        let cmdline = &gcx.read().await.cmdline;
+        (cmdline.vecdb_max_files, cmdline.vecdb_symbols)
+    };
     let mut consts = {
         let caps_locked = caps.read().unwrap();
         let mut b = caps_locked.embedding_batch;
@@ -150,8 +177,13 @@ async fn do_i_need_to_reload_vecdb(
             endpoint_embeddings_style: caps_locked.endpoint_embeddings_style.clone(),
             splitter_window_size: caps_locked.embedding_n_ctx / 2,
             vecdb_max_files: vecdb_max_files,
+            workspace_key: "".to_string(),
+            vecdb_remote_endpoint: caps_locked.vecdb_remote_endpoint.clone(),
+            vecdb_remote_page_size: caps_locked.vecdb_remote_page_size,
+            vecdb_symbols,
         }
     };
+    consts.workspace_key = crate::files_in_workspace::workspace_key(gcx.clone()).await;  // a different set of folders means a different table
 
     let vec_db = gcx.write().await.vec_db.clone();
     match *vec_db.lock().await {
@@ -163,7 +195,11 @@ async fn do_i_need_to_reload_vecdb(
                 db.constants.endpoint_embeddings_style == consts.endpoint_embeddings_style &&
                 db.constants.splitter_window_size == consts.splitter_window_size &&
                 db.constants.embedding_batch == consts.embedding_batch &&
-                db.constants.embedding_size == consts.embedding_size
+                db.constants.embedding_size == consts.embedding_size &&
+                db.constants.workspace_key == consts.workspace_key &&
+                db.constants.vecdb_remote_endpoint == consts.vecdb_remote_endpoint &&
+                db.constants.vecdb_remote_page_size == consts.vecdb_remote_page_size &&
+                db.constants.vecdb_symbols == consts.vecdb_symbols
             {
                 return (false, None);
             }
@@ -195,6 +231,7 @@ pub async fn vecdb_background_reload(
     }
 
     let mut trajectories_updated_once: bool = false;
+    let mut memories_import_export_done: bool = false;
     let mut background_tasks = BackgroundTasksHolder::new(vec![]);
     loop {
         let (need_reload, consts) = do_i_need_to_reload_vecdb(gcx.clone()).await;
@@ -218,13 +255,23 @@ pub async fn vecdb_background_reload(
                 }
             }
         }
-        if !trajectories_updated_once {
+        let vec_db = gcx.read().await.vec_db.clone();
+        if !memories_import_export_done && vec_db.lock().await.is_some() {
+            if let Err(err) = memories_cmdline_import_export(gcx.clone()).await {
+                error!("memories import/export failed: {}", err);
+            }
+            memories_import_export_done = true;
+        }
+        if !trajectories_updated_once && vec_db.lock().await.is_some() {
             match try_to_download_trajectories(gcx.clone()).await {
                 Ok(_) => { }
                 Err(err) => {
                     error!("trajectories download failed: {}", err);
                 }
             };
+            if let Err(err) = load_trajectory_packs(gcx.clone()).await {
+                error!("trajectory packs failed: {}", err);
+            }
             trajectories_updated_once = true;
         }
         tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
@@ -237,13 +284,29 @@ impl VecDb {
         config_dir: &PathBuf,
         cmdline: CommandLine,
         constants: VecdbConstants,
-        api_key: &String
+        api_key: &String,
+        remote: Option<VecDbRemote>,
     ) -> Result<VecDb, String> {
-        let handler = VecDBHandler::init(constants.embedding_size).await?;
+        let table_dir = cache_dir.join("refact_vecdb_cache").join("tables").join(format!(
+            "model_{}_esize_{}_ws_{}{}",
+            constants.embedding_model.replace("/", "_"),
+            constants.embedding_size,
+            constants.workspace_key,
+            if constants.vecdb_symbols { "_symbols" } else { "" },
+        ));
+        let handler = VecDBHandler::init(&table_dir, constants.embedding_size).await?;
+        // Files removed while we were not running are only known to the fingerprints, check them all, unchanged ones are quick
+        let known_cpaths = handler.fingerprint_cpaths_all().await;
         let cache = VecDBCache::init(cache_dir, &constants.embedding_model, constants.embedding_size).await?;
         let vecdb_handler = Arc::new(AMutex::new(handler));
         let vecdb_cache = Arc::new(AMutex::new(cache));
-        let memdb = Arc::new(AMutex::new(MemoriesDatabase::init(config_dir, &constants, cmdline.reset_memory).await?));
+        let retention = MemoRetentionPolicy {
+            max_per_scope: cmdline.memories_max_per_project,
+            decay_per_use: cmdline.memories_decay_per_use,
+            prune_incorrect: cmdline.memories_prune_incorrect,
+        };
+        let memdb = Arc::new(AMutex::new(MemoriesDatabase::init(config_dir, &constants, cmdline.reset_memory, retention).await?));
+        memories_enforce_retention(memdb.clone()).await?;
 
         let vectorizer_service = Arc::new(AMutex::new(FileVectorizerService::new(
             vecdb_handler.clone(),
@@ -252,11 +315,16 @@ impl VecDb {
             api_key.clone(),
             memdb.clone(),
         ).await));
+        if !known_cpaths.is_empty() && remote.is_none() {
+            info!("vecdb table has {} files, will check them for changes", known_cpaths.len());
+            vectorizer_enqueue_files(vectorizer_service.clone(), &known_cpaths, false).await;
+        }
         Ok(VecDb {
             memdb: memdb.clone(),
             vecdb_emb_client: Arc::new(AMutex::new(reqwest::Client::new())),
             vecdb_handler,
             vectorizer_service,
+            remote,
             constants: constants.clone(),
         })
     }
@@ -271,6 +339,9 @@ impl VecDb {
     }
 
     pub async fn vectorizer_enqueue_files(&self, documents: &Vec<String>, process_immediately: bool) {
+        if self.remote.is_some() {
+            return;
+        }
         vectorizer_enqueue_files(self.vectorizer_service.clone(), documents, process_immediately).await;
     }
 
@@ -279,6 +350,17 @@ impl VecDb {
         let file_path_str = file_path.to_string_lossy().to_string();
         handler_locked.vecdb_records_remove(vec![file_path_str]).await;
     }
+
+    pub async fn vectorizer_forget_files(&self, documents: &Vec<String>) {
+        if self.remote.is_some() {
+            return;
+        }
+        vectorizer_forget_files(self.vectorizer_service.clone(), documents).await;
+    }
+
+    pub async fn indexed_files(&self) -> Vec<String> {
+        self.vecdb_handler.lock().await.fingerprint_cpaths_all().await
+    }
 }
 
 pub async fn memories_add(
@@ -287,7 +369,8 @@ pub async fn memories_add(
     m_goal: &str,
     m_project: &str,
     m_payload: &str,    // TODO: upgrade to serde_json::Value
-    m_origin: &str
+    m_origin: &str,
+    m_scope: &str,
 ) -> Result<String, String> {
     let (memdb, vectorizer_service) = {
         let vec_db_guard = vec_db.lock().await;
@@ -297,14 +380,111 @@ pub async fn memories_add(
 
     let memid = {
         let mut memdb_locked = memdb.lock().await;
-        let x = memdb_locked.permdb_add(m_type, m_goal, m_project, m_payload, m_origin)?;
+        let x = memdb_locked.permdb_add(m_type, m_goal, m_project, m_payload, m_origin, m_scope)?;
         memdb_locked.dirty_memids.push(x.clone());
         x
     };
+    memories_enforce_retention(memdb).await?;
     vectorizer_enqueue_dirty_memory(vectorizer_service).await;  // sets queue_additions inside
     Ok(memid)
 }
 
+// The origin remote of the VCS root of the active file, so teammates share the memories of a repo wherever they cloned it.
+// Without a remote it's the full path of the root or the workspace folder, two checkouts named the same don't share memories.
+pub async fn memories_active_scope(gcx: Arc<ARwLock<GlobalContext>>) -> String {
+    let project_path = match crate::files_correction::get_active_project_path(gcx).await {
+        Some(x) => x,
+        None => return MEMORIES_GLOBAL_SCOPE.to_string(),
+    };
+    let remote_url = git2::Repository::open(&project_path).ok()
+        .and_then(|repo| repo.find_remote("origin").ok().and_then(|r| r.url().map(|u| u.to_string())));
+    match remote_url {
+        Some(url) => memo_scope_from_remote_url(&url),
+        None => crate::files_correction::canonical_path(&project_path.to_string_lossy()).to_string_lossy().to_string(),
+    }
+}
+
+// Imports without a scope go to the active project, or to legacy when there's no project, never to global
+pub async fn memories_import_scope(gcx: Arc<ARwLock<GlobalContext>>) -> String {
+    let scope = memories_active_scope(gcx).await;
+    if scope == MEMORIES_GLOBAL_SCOPE { MEMORIES_LEGACY_SCOPE.to_string() } else { scope }
+}
+
+pub async fn memories_enforce_retention(memdb: Arc<AMutex<MemoriesDatabase>>) -> Result<usize, String> {
+    let mut memdb_locked = memdb.lock().await;
+    let all = memdb_locked.permdb_select_all(None).await?;
+    let victims = memo_retention_victims(&all, &memdb_locked.retention);
+    for memid in victims.iter() {
+        memdb_locked.permdb_erase(memid).await?;
+    }
+    if !victims.is_empty() {
+        info!("memories retention: erased {} of {}", victims.len(), all.len());
+    }
+    Ok(victims.len())
+}
+
+pub async fn memories_export(
+    vec_db: Arc<AMutex<Option<VecDb>>>,
+) -> Result<String, String> {
+    let memories = memories_select_all(vec_db).await?;
+    let records: Vec<MemoExportRecord> = memories.iter().map(memo_to_export_record).collect();
+    Ok(memories_to_jsonl(&records))
+}
+
+// Returns (imported, duplicates), vectors are made later by the vectorizer through dirty_memids
+pub async fn memories_import(
+    vec_db: Arc<AMutex<Option<VecDb>>>,
+    jsonl: &str,
+    default_scope: &str,
+) -> Result<(usize, usize), String> {
+    let mut records = memories_from_jsonl(jsonl)?;
+    memories_fill_scope(&mut records, default_scope);
+    memories_import_records(vec_db, records).await
+}
+
+pub async fn memories_import_records(
+    vec_db: Arc<AMutex<Option<VecDb>>>,
+    records: Vec<MemoExportRecord>,
+) -> Result<(usize, usize), String> {
+    let (memdb, vectorizer_service) = {
+        let vec_db_guard = vec_db.lock().await;
+        let vec_db = vec_db_guard.as_ref().ok_or("VecDb is not initialized")?;
+        (vec_db.memdb.clone(), vec_db.vectorizer_service.clone())
+    };
+    let (memids, duplicates_cnt) = {
+        let mut memdb_locked = memdb.lock().await;
+        let (memids, duplicates_cnt) = memdb_locked.permdb_import(records)?;
+        memdb_locked.dirty_memids.extend(memids.iter().cloned());
+        (memids, duplicates_cnt)
+    };
+    if !memids.is_empty() {
+        memories_enforce_retention(memdb).await?;
+        vectorizer_enqueue_dirty_memory(vectorizer_service).await;
+    }
+    info!("memories import: {} new, {} duplicates skipped", memids.len(), duplicates_cnt);
+    Ok((memids.len(), duplicates_cnt))
+}
+
+// --memories-import and --memories-export, run once when vecdb is up for the first time
+async fn memories_cmdline_import_export(gcx: Arc<ARwLock<GlobalContext>>) -> Result<(), String> {
+    let (vec_db, cmdline) = {
+        let gcx_locked = gcx.read().await;
+        (gcx_locked.vec_db.clone(), gcx_locked.cmdline.clone())
+    };
+    if !cmdline.memories_import.is_empty() {
+        let jsonl = tokio::fs::read_to_string(&cmdline.memories_import).await
+            .map_err(|e| format!("cannot read {}: {}", cmdline.memories_import, e))?;
+        memories_import(vec_db.clone(), &jsonl, &memories_import_scope(gcx.clone()).await).await?;
+    }
+    if !cmdline.memories_export.is_empty() {
+        let jsonl = memories_export(vec_db.clone()).await?;
+        tokio::fs::write(&cmdline.memories_export, jsonl).await
+            .map_err(|e| format!("cannot write {}: {}", cmdline.memories_export, e))?;
+        info!("memories exported to {}", cmdline.memories_export);
+    }
+    Ok(())
+}
+
 pub async fn memories_block_until_vectorized_from_vectorizer(
     vectorizer_service: Arc<AMutex<FileVectorizerService>>,
     max_blocking_time_ms: usize
@@ -382,6 +562,28 @@ pub async fn get_status(vec_db: Arc<AMutex<Option<VecDb>>>) -> Result<Option<Vec
     return Ok(Some(vstatus_copy));
 }
 
+// For /v1/mem-list: each memory with its retention score, and whether the policy keeps it
+pub async fn memories_select_all_with_retention(
+    vec_db: Arc<AMutex<Option<VecDb>>>,
+) -> Result<(Vec<(MemoRecord, f64, bool)>, MemoRetentionPolicy), String> {
+    let memdb = {
+        let vec_db_guard = vec_db.lock().await;
+        let vec_db = vec_db_guard.as_ref().ok_or("VecDb is not initialized")?;
+        vec_db.memdb.clone()
+    };
+
+    let memdb_locked = memdb.lock().await;
+    let all = memdb_locked.permdb_select_all(None).await?;
+    let policy = memdb_locked.retention.clone();
+    let victims: HashSet<String> = memo_retention_victims(&all, &policy).into_iter().collect();
+    let results = all.into_iter().map(|m| {
+        let score = memo_retention_score(&m, &policy);
+        let keep = !victims.contains(&m.memid);
+        (m, score, keep)
+    }).collect();
+    Ok((results, policy))
+}
+
 pub async fn memories_select_all(
     vec_db: Arc<AMutex<Option<VecDb>>>,
 ) -> Result<Vec<MemoRecord>, String> {
@@ -423,21 +625,25 @@ pub async fn memories_update(
         vec_db.memdb.clone()
     };
 
-    let memdb_locked = memdb.lock().await;
-    let updated_cnt = memdb_locked.permdb_update_used(memid, mstat_correct, mstat_relevant)?;
+    let updated_cnt = memdb.lock().await.permdb_update_used(memid, mstat_correct, mstat_relevant)?;
+    if mstat_correct < 0 {
+        memories_enforce_retention(memdb).await?;
+    }
     Ok(updated_cnt)
 }
 
+// Searches the memories of the scope (the active project if None) and the global ones
 pub async fn memories_search(
     gcx: Arc<ARwLock<GlobalContext>>,
     query: &String,
     top_n: usize,
+    scope_mb: Option<String>,
 ) -> Result<MemoSearchResult, String> {
     let vec_db = gcx.read().await.vec_db.clone();
-    fn calculate_score(distance: f32, _times_used: i32) -> f32 {
-        distance
-        // distance - (times_used as f32) * 0.01
-    }
+    let scope = match scope_mb {
+        Some(x) if !x.is_empty() => x,
+        _ => memories_active_scope(gcx.clone()).await,
+    };
 
     let t0 = std::time::Instant::now();
     let (memdb, vecdb_emb_client, constants) = {
@@ -469,16 +675,15 @@ pub async fn memories_search(
     }
     info!("search query {:?}, it took {:.3}s to vectorize the query", query, t0.elapsed().as_secs_f64());
 
-    let lance_results = match lance_search(memdb.clone(), &embedding[0], top_n).await {
+    // fetch more than needed, the reranker decides what makes it into top_n
+    let scopes = vec![scope, MEMORIES_GLOBAL_SCOPE.to_string()];
+    let lance_results = match lance_search(memdb.clone(), &embedding[0], top_n * 4, &scopes).await {
         Ok(res) => res,
         Err(err) => { return Err(err.to_string()) }
     };
-    let mut results: Vec<MemoRecord> = memdb.lock().await.permdb_fillout_records(lance_results).await?;
-    results.sort_by(|a, b| {
-        let score_a = calculate_score(a.distance, a.mstat_times_used);
-        let score_b = calculate_score(b.distance, b.mstat_times_used);
-        score_a.partial_cmp(&score_b).unwrap_or(std::cmp::Ordering::Equal)
-    });
+    let results: Vec<MemoRecord> = memdb.lock().await.permdb_fillout_records(lance_results).await?;
+    let mut results = crate::rerank::rerank_highlev::rerank_memories(gcx.clone(), query, results).await;
+    results.truncate(top_n);
     Ok(MemoSearchResult { query_text: query.clone(), results })
 }
 
@@ -546,9 +751,12 @@ impl VecdbSearch for VecDb {
         &self,
         query: String,
         top_n: usize,
-        vecdb_scope_filter_mb: Option<String>,
+        vecdb_scope_filter_mb: Option<ScopeFilter>,
         api_key: &String,
     ) -> Result<SearchResult, String> {
+        if let Some(remote) = &self.remote {
+            return remote.vecdb_search(query, top_n, vecdb_scope_filter_mb, api_key).await;
+        }
         // TODO: move out of struct, replace self with Arc
         let t0 = std::time::Instant::now();
         let embedding_mb = fetch_embedding::get_embedding_with_retry(
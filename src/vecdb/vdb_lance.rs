@@ -12,10 +12,14 @@ use arrow_array::types::{Float32Type, UInt64Type};
 use arrow_schema::{DataType, Field, Schema, SchemaRef};
 use futures_util::TryStreamExt;
 use lance::dataset::{WriteMode, WriteParams};
-use tempfile::{tempdir, TempDir};
+use rusqlite::{params, OpenFlags, OptionalExtension};
+use tokio::fs;
+use tokio_rusqlite::Connection;
+use tracing::info;
 use vectordb::database::Database;
 use vectordb::table::Table;
 
+use crate::search_filter::ScopeFilter;
 use crate::vecdb::vdb_structs::VecdbRecord;
 
 
@@ -26,8 +30,8 @@ impl Debug for VecDBHandler {
 }
 
 pub struct VecDBHandler {
-    _data_database_temp_dir: TempDir,
     data_table: Table,
+    fingerprints: Connection,
     schema: SchemaRef,
     // data_table_hashes: HashSet<String>,
     embedding_size: i32,
@@ -45,20 +49,34 @@ pub fn cosine_distance(vec1: &Vec<f32>, vec2: &Vec<f32>) -> f32 {
 }
 
 
+async fn fingerprints_table_init(db: &Connection) -> Result<(), String> {
+    db.call(move |conn| {
+        let _ = conn.execute_batch("PRAGMA journal_mode=WAL;");
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS file_fingerprints (
+                scope TEXT PRIMARY KEY,
+                text_md5 TEXT NOT NULL
+            )", [],
+        )?;
+        Ok(())
+    }).await.map_err(|e| format!("{:?}", e))
+}
+
 impl VecDBHandler {
-    pub async fn init(embedding_size: i32) -> Result<VecDBHandler, String> {
-        let data_database_temp_dir = match tempdir() {
-            Ok(dir) => dir,
-            Err(_) => return Err(format!("{:?}", "Error creating temp dir")),
-        };
-        let data_database_temp_dir_str = match data_database_temp_dir.path().to_str() {
-            Some(path) => path,
-            None => return Err(format!("{:?}", "Temp directory is not a valid path")),
-        };
-        let temp_database = match Database::connect(data_database_temp_dir_str).await {
+    // The table lives in `table_dir` and survives restarts, files with unchanged fingerprints are not processed again
+    pub async fn init(table_dir: &PathBuf, embedding_size: i32) -> Result<VecDBHandler, String> {
+        fs::create_dir_all(table_dir).await.map_err(|e| format!("Cannot create {:?}: {:?}", table_dir, e))?;
+        let table_dir_str = table_dir.to_str().ok_or(format!("{:?} is not a valid path", table_dir))?;
+        let database = match Database::connect(table_dir_str).await {
             Ok(db) => db,
             Err(err) => return Err(format!("{:?}", err))
         };
+        let fingerprints = Connection::open_with_flags(
+            table_dir.join("fingerprints.sqlite"), OpenFlags::SQLITE_OPEN_READ_WRITE
+                | OpenFlags::SQLITE_OPEN_CREATE
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                | OpenFlags::SQLITE_OPEN_URI).await.map_err(|e| format!("{:?}", e))?;
+        fingerprints_table_init(&fingerprints).await?;
 
         let vec_trait = Arc::new(Field::new("item", DataType::Float32, true));
         let schema = Arc::new(Schema::new(vec![
@@ -66,23 +84,82 @@ impl VecDBHandler {
             Field::new("scope", DataType::Utf8, true),
             Field::new("start_line", DataType::UInt64, true),
             Field::new("end_line", DataType::UInt64, true),
+            Field::new("symbol_path", DataType::Utf8, true),
         ]));
 
-        let batches_iter = RecordBatchIterator::new(vec![].into_iter().map(Ok), schema.clone());
-        let data_table = match temp_database.create_table("data", batches_iter, Option::from(WriteParams::default())).await {
-            Ok(table) => table,
-            Err(err) => return Err(format!("{:?}", err))
+        let existing_table = match database.table_names().await {
+            Ok(names) if names.contains(&"data".to_string()) => {
+                match database.open_table("data").await {
+                    Ok(table) if table.schema() == schema => Some(table),
+                    Ok(_) => {
+                        info!("vecdb table in {:?} has a different schema, recreating", table_dir);
+                        None
+                    }
+                    Err(err) => {
+                        info!("vecdb table in {:?} cannot be opened, recreating: {:?}", table_dir, err);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let data_table = match existing_table {
+            Some(table) => table,
+            None => {
+                let _ = database.drop_table("data").await;
+                fingerprints.call(|conn| {
+                    conn.execute("DELETE FROM file_fingerprints", [])?;
+                    Ok(())
+                }).await.map_err(|e| format!("{:?}", e))?;
+                let batches_iter = RecordBatchIterator::new(vec![].into_iter().map(Ok), schema.clone());
+                match database.create_table("data", batches_iter, Option::from(WriteParams::default())).await {
+                    Ok(table) => table,
+                    Err(err) => return Err(format!("{:?}", err))
+                }
+            }
         };
+        info!("vecdb table {:?} has {} records", table_dir, data_table.count_rows().await.unwrap_or(0));
 
         Ok(VecDBHandler {
-            _data_database_temp_dir: data_database_temp_dir,
             schema,
             data_table,
+            fingerprints,
             // data_table_hashes: HashSet::new(),
             embedding_size,
         })
     }
 
+    pub async fn fingerprint_get(&self, cpath: &String) -> Option<String> {
+        let cpath = cpath.clone();
+        self.fingerprints.call(move |conn| {
+            Ok(conn.query_row("SELECT text_md5 FROM file_fingerprints WHERE scope = ?1", params![cpath], |row| row.get(0)).optional()?)
+        }).await.unwrap_or(None)
+    }
+
+    pub async fn fingerprints_set(&self, fingerprints: Vec<(String, String)>) {
+        if fingerprints.is_empty() {
+            return;
+        }
+        if let Err(err) = self.fingerprints.call(move |conn| {
+            let tx = conn.transaction()?;
+            for (cpath, text_md5) in fingerprints.iter() {
+                tx.execute("INSERT OR REPLACE INTO file_fingerprints (scope, text_md5) VALUES (?1, ?2)", params![cpath, text_md5])?;
+            }
+            tx.commit()?;
+            Ok(())
+        }).await {
+            tracing::error!("cannot save vecdb fingerprints: {:?}", err);
+        }
+    }
+
+    pub async fn fingerprint_cpaths_all(&self) -> Vec<String> {
+        self.fingerprints.call(move |conn| {
+            let mut stmt = conn.prepare("SELECT scope FROM file_fingerprints")?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            Ok(rows.filter_map(|x| x.ok()).collect::<Vec<_>>())
+        }).await.unwrap_or_default()
+    }
+
     pub async fn size(&self) -> Result<usize, String> {
         match self.data_table.count_rows().await {
             Ok(size) => Ok(size),
@@ -133,6 +210,7 @@ impl VecDBHandler {
         let scopes: Vec<String> = records.iter().map(|x| x.file_path.to_str().unwrap_or("No filename").to_string()).collect();
         let start_lines: Vec<u64> = records.iter().map(|x| x.start_line).collect();
         let end_lines: Vec<u64> = records.iter().map(|x| x.end_line).collect();
+        let symbol_paths: Vec<String> = records.iter().map(|x| x.symbol_path.clone()).collect();
         let data_batches_iter = RecordBatchIterator::new(
             vec![RecordBatch::try_new(
                 self.schema.clone(),
@@ -141,6 +219,7 @@ impl VecDBHandler {
                     Arc::new(StringArray::from(scopes.clone())),
                     Arc::new(UInt64Array::from(start_lines.clone())),
                     Arc::new(UInt64Array::from(end_lines.clone())),
+                    Arc::new(StringArray::from(symbol_paths)),
                 ],
             )],
             self.schema.clone(),
@@ -164,13 +243,20 @@ impl VecDBHandler {
         let mut delete_queries = Vec::new();
 
         for chunk in &scopes_to_remove.iter().chunks(100) {
-            let paths_to_remove: Vec<&String> = chunk.collect();
-            let formatted_scopes: String = paths_to_remove
-                .iter()
-                .map(|scope| format!("'{}'", scope.replace("'", "''")))
-                .join(", ");
-            let delete_query = format!("scope IN ({})", formatted_scopes);
-            delete_queries.push(delete_query);
+            let files: Vec<String> = chunk.cloned().collect();
+            delete_queries.push(ScopeFilter { files, dirs: vec![] }.to_lance_filter());
+        }
+
+        let scopes_to_forget = scopes_to_remove.clone();
+        if let Err(err) = self.fingerprints.call(move |conn| {
+            let tx = conn.transaction()?;
+            for scope in scopes_to_forget.iter() {
+                tx.execute("DELETE FROM file_fingerprints WHERE scope = ?1", params![scope])?;
+            }
+            tx.commit()?;
+            Ok(())
+        }).await {
+            tracing::error!("cannot remove vecdb fingerprints: {:?}", err);
         }
 
         for delete_query in delete_queries {
@@ -243,6 +329,10 @@ impl VecDBHandler {
                     .value(idx),
                 distance,
                 usefulness: 0.0,
+                symbol_path: as_string_array(record_batch.column_by_name("symbol_path")
+                    .expect("Missing column 'symbol_path'"))
+                    .value(idx)
+                    .to_string(),
             })
         }).collect()
     }
@@ -251,7 +341,7 @@ impl VecDBHandler {
         &mut self,
         embedding: &Vec<f32>,
         top_n: usize,
-        vecdb_scope_filter_mb: Option<String>,
+        vecdb_scope_filter_mb: Option<ScopeFilter>,
     ) -> vectordb::error::Result<Vec<VecdbRecord>> {
         let use_prefilter = vecdb_scope_filter_mb.is_some();
         let query = self
@@ -259,7 +349,7 @@ impl VecDBHandler {
             .clone()
             .search(Some(Float32Array::from(embedding.clone())))
             .prefilter(use_prefilter)
-            .filter(vecdb_scope_filter_mb)
+            .filter(vecdb_scope_filter_mb.map(|x| x.to_lance_filter()))
             .limit(top_n)
             .use_index(true)
             .execute()
@@ -284,3 +374,56 @@ impl VecDBHandler {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(file: &str, start_line: u64) -> VecdbRecord {
+        VecdbRecord {
+            vector: Some(vec![1.0, start_line as f32, 0.5]),
+            file_path: PathBuf::from(file),
+            start_line,
+            end_line: start_line + 10,
+            distance: -1.0,
+            usefulness: 0.0,
+            symbol_path: "".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_table_survives_restart() {
+        let tmp = tempfile::tempdir().unwrap();
+        let table_dir = tmp.path().join("model_x_esize_3_ws_abc");
+        {
+            let mut handler = VecDBHandler::init(&table_dir, 3).await.unwrap();
+            handler.vecdb_records_add(&vec![record("/a.py", 0), record("/a.py", 10), record("/b.py", 0)]).await;
+            handler.fingerprints_set(vec![("/a.py".to_string(), "md5a".to_string()), ("/b.py".to_string(), "md5b".to_string())]).await;
+            handler.vecdb_records_remove(vec!["/b.py".to_string()]).await;
+        }
+        let handler = VecDBHandler::init(&table_dir, 3).await.unwrap();
+        assert_eq!(handler.size().await.unwrap(), 2);
+        assert_eq!(handler.fingerprint_get(&"/a.py".to_string()).await, Some("md5a".to_string()));
+        assert_eq!(handler.fingerprint_get(&"/b.py".to_string()).await, None);
+        assert_eq!(handler.fingerprint_cpaths_all().await, vec!["/a.py".to_string()]);
+
+        // another embedding size means another schema, old vectors and fingerprints are useless
+        let handler = VecDBHandler::init(&table_dir, 4).await.unwrap();
+        assert_eq!(handler.size().await.unwrap(), 0);
+        assert!(handler.fingerprint_cpaths_all().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_with_scope_filter() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut handler = VecDBHandler::init(&tmp.path().join("model_x_esize_3_ws_abc"), 3).await.unwrap();
+        handler.vecdb_records_add(&vec![record("/lib/it's.py", 0), record("/lib0.py", 0), record("/a.py", 0)]).await;
+        let paths = |records: Vec<VecdbRecord>| records.into_iter().map(|r| r.file_path.to_string_lossy().to_string()).sorted().collect::<Vec<_>>();
+
+        let under_lib = ScopeFilter { files: vec![], dirs: vec!["/lib/".to_string()] };
+        assert_eq!(paths(handler.vecdb_search(&vec![1.0, 0.0, 0.5], 10, Some(under_lib)).await.unwrap()), vec!["/lib/it's.py"]);
+        let two_files = ScopeFilter { files: vec!["/lib/it's.py".to_string(), "/a.py".to_string()], dirs: vec![] };
+        assert_eq!(paths(handler.vecdb_search(&vec![1.0, 0.0, 0.5], 10, Some(two_files)).await.unwrap()), vec!["/a.py", "/lib/it's.py"]);
+    }
+}
@@ -1,14 +1,133 @@
+use std::path::PathBuf;
 use async_trait::async_trait;
-use reqwest::header::CONTENT_TYPE;
-use reqwest::header::HeaderMap;
-use reqwest::header::HeaderValue;
+use serde::Deserialize;
 use serde_json::json;
+use tracing::info;
 
-use crate::vecdb::vdb_structs::{SearchResult, VecdbSearch};
+use crate::search_filter::ScopeFilter;
+use crate::vecdb::vdb_structs::{SearchResult, VecdbRecord, VecdbSearch};
 
 
+const REMOTE_PAGE_SIZE_DEFAULT: usize = 50;
+const REMOTE_MAX_PAGES: usize = 20;
+
+
+// A centrally built index knows paths relative to the repository root, these are mapped onto
+// the local workspace folders, and the local absolute paths in scope filters are mapped back.
 #[derive(Debug)]
-pub struct VecDbRemote {}
+pub struct VecDbRemote {
+    pub http_client: reqwest::Client,
+    pub endpoint: String,
+    pub api_key: String,
+    pub page_size: usize,
+    pub workspace_folders: Vec<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct RemoteRecord {
+    file_path: String,
+    start_line: u64,
+    end_line: u64,
+    #[serde(default)]
+    distance: f32,
+    #[serde(default)]
+    usefulness: f32,
+    #[serde(default)]
+    symbol_path: String,
+}
+
+#[derive(Deserialize)]
+struct RemoteLegacyResult {
+    results: Vec<RemoteRecord>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RemotePage {
+    Page {
+        results: Vec<RemoteRecord>,
+        #[serde(default)]
+        next_cursor: Option<String>,
+    },
+    // the old server format, a list of SearchResult without pagination
+    Legacy(Vec<RemoteLegacyResult>),
+}
+
+impl VecDbRemote {
+    pub fn new(http_client: reqwest::Client, endpoint: String, api_key: String, page_size: usize, workspace_folders: Vec<PathBuf>) -> Self {
+        VecDbRemote {
+            http_client,
+            endpoint,
+            api_key,
+            page_size: if page_size == 0 { REMOTE_PAGE_SIZE_DEFAULT } else { page_size },
+            workspace_folders,
+        }
+    }
+
+    fn path_to_remote(&self, path: &String) -> String {
+        for folder in self.workspace_folders.iter() {
+            let prefix = format!("{}{}", folder.to_string_lossy(), std::path::MAIN_SEPARATOR);
+            if let Some(relative) = path.strip_prefix(&prefix) {
+                return relative.to_string();
+            }
+        }
+        path.clone()
+    }
+
+    fn filter_to_remote(&self, filter: &ScopeFilter) -> ScopeFilter {
+        ScopeFilter {
+            files: filter.files.iter().map(|x| self.path_to_remote(x)).collect(),
+            dirs: filter.dirs.iter().map(|x| self.path_to_remote(x)).collect(),
+        }
+    }
+
+    fn path_from_remote(&self, remote_path: &String) -> PathBuf {
+        let path = PathBuf::from(remote_path);
+        if path.is_absolute() {
+            return path;
+        }
+        for folder in self.workspace_folders.iter() {
+            let candidate = folder.join(&path);
+            if candidate.exists() {
+                return candidate;
+            }
+        }
+        match self.workspace_folders.first() {
+            Some(folder) => folder.join(&path),
+            None => path,
+        }
+    }
+
+    async fn fetch_page(
+        &self,
+        query: &String,
+        page_size: usize,
+        filter_mb: &Option<ScopeFilter>,
+        cursor_mb: &Option<String>,
+        api_key: &String,
+    ) -> Result<(Vec<RemoteRecord>, Option<String>), String> {
+        let body = json!({
+            "text": query,
+            "top_n": page_size,
+            "filter": filter_mb.as_ref().map(|f| self.filter_to_remote(f)),
+            "cursor": cursor_mb,
+        });
+        let mut request = self.http_client.post(&self.endpoint).json(&body);
+        if !api_key.is_empty() {
+            request = request.bearer_auth(api_key);
+        }
+        let res = request.send().await.map_err(|e| format!("Vecdb search HTTP error (1): {}", e))?;
+        if !res.status().is_success() {
+            return Err(format!("Vecdb search HTTP error (2): bad status {}", res.status()));
+        }
+        let text = res.text().await.map_err(|e| format!("Vecdb search HTTP error (3): {}", e))?;
+        let page: RemotePage = serde_json::from_str(&text).map_err(|e| format!("vecdb JSON problem: {}", e))?;
+        Ok(match page {
+            RemotePage::Page { results, next_cursor } => (results, next_cursor),
+            RemotePage::Legacy(results) => (results.into_iter().flat_map(|x| x.results).collect(), None),
+        })
+    }
+}
 
 #[async_trait]
 impl VecdbSearch for VecDbRemote {
@@ -16,34 +135,78 @@ impl VecdbSearch for VecDbRemote {
         &self,
         query: String,
         top_n: usize,
-        _vecdb_scope_filter_mb: Option<String>,
-        _api_key: &String,
+        vecdb_scope_filter_mb: Option<ScopeFilter>,
+        api_key: &String,
     ) -> Result<SearchResult, String> {
-        let url = "http://127.0.0.1:8008/v1/vdb-search".to_string();
-        let mut headers = HeaderMap::new();
-        // headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", self.token)).unwrap());
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let body = json!({
-            "text": query,
-            "top_n": top_n
-        });
-        let res = reqwest::Client::new()
-            .post(&url)
-            .headers(headers)
-            .body(body.to_string())
-            .send()
-            .await.map_err(|e| format!("Vecdb search HTTP error (1): {}", e))?;
-
-        let body = res.text().await.map_err(|e| format!("Vecdb search HTTP error (2): {}", e))?;
-        // info!("Vecdb search result: {:?}", &body);
-        let result: Vec<SearchResult> = serde_json::from_str(&body).map_err(|e| {
-            format!("vecdb JSON problem: {}", e)
-        })?;
-        if result.len() == 0 {
-            return Err("Vecdb search result is empty".to_string());
-        }
-        let result0 = result[0].clone();
-        // info!("Vecdb search result: {:?}", &result0);
-        Ok(result0)
+        if top_n == 0 {
+            return Ok(SearchResult { query_text: query, results: vec![] });
+        }
+        // the remote token goes first, the embedding key is for the servers that share it
+        let api_key = if self.api_key.is_empty() { api_key.clone() } else { self.api_key.clone() };
+        let t0 = std::time::Instant::now();
+        let mut records: Vec<RemoteRecord> = vec![];
+        let mut cursor_mb: Option<String> = None;
+        for _ in 0..REMOTE_MAX_PAGES {
+            let page_size = self.page_size.min(top_n - records.len());
+            let (page, next_cursor) = self.fetch_page(&query, page_size, &vecdb_scope_filter_mb, &cursor_mb, &api_key).await?;
+            let page_was_empty = page.is_empty();
+            records.extend(page);
+            if records.len() >= top_n || page_was_empty || next_cursor.is_none() {
+                break;
+            }
+            cursor_mb = next_cursor;
+        }
+        records.truncate(top_n);
+        info!("remote vecdb search {:?} found {} records, {:.3}s", query, records.len(), t0.elapsed().as_secs_f64());
+
+        // servers that don't compute usefulness get the same formula as the local vecdb
+        let dist0 = records.first().map(|r| r.distance.abs()).unwrap_or(0.0);
+        let results = records.into_iter().map(|r| VecdbRecord {
+            vector: None,
+            file_path: self.path_from_remote(&r.file_path),
+            start_line: r.start_line,
+            end_line: r.end_line,
+            distance: r.distance,
+            usefulness: if r.usefulness > 0.0 {
+                r.usefulness
+            } else {
+                100.0 - 75.0 * ((r.distance.abs() - dist0) / (dist0 + 0.01)).max(0.0).min(1.0)
+            },
+            symbol_path: r.symbol_path,
+        }).collect();
+        Ok(SearchResult { query_text: query, results })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_paths_and_pages() {
+        let remote = VecDbRemote::new(
+            reqwest::Client::new(),
+            "http://localhost/v1/vdb-search".to_string(),
+            "".to_string(),
+            0,
+            vec![PathBuf::from("/home/user/monorepo")],
+        );
+        assert_eq!(remote.page_size, REMOTE_PAGE_SIZE_DEFAULT);
+        let sep = std::path::MAIN_SEPARATOR;
+        let filter = ScopeFilter {
+            files: vec![format!("/home/user/monorepo{}a.rs", sep), "/elsewhere/b.rs".to_string()],
+            dirs: vec![format!("/home/user/monorepo{}lib{}", sep, sep)],
+        };
+        let remote_filter = remote.filter_to_remote(&filter);
+        assert_eq!(remote_filter.files, vec!["a.rs".to_string(), "/elsewhere/b.rs".to_string()]);
+        assert_eq!(remote_filter.dirs, vec![format!("lib{}", sep)]);
+        assert_eq!(remote.path_from_remote(&"lib/a.rs".to_string()), PathBuf::from("/home/user/monorepo").join("lib/a.rs"));
+        assert_eq!(remote.path_from_remote(&"/abs/b.rs".to_string()), PathBuf::from("/abs/b.rs"));
+
+        let page: RemotePage = serde_json::from_str(r#"{"results": [{"file_path": "a.rs", "start_line": 1, "end_line": 5, "distance": 0.2}], "next_cursor": "xyz"}"#).unwrap();
+        assert!(matches!(page, RemotePage::Page { ref results, next_cursor: Some(ref c) } if results.len() == 1 && c == "xyz"));
+        let legacy: RemotePage = serde_json::from_str(r#"[{"query_text": "q", "results": [{"file_path": "a.rs", "start_line": 1, "end_line": 5, "distance": 0.2, "usefulness": 90.0, "vector": null}]}]"#).unwrap();
+        assert!(matches!(legacy, RemotePage::Legacy(ref x) if x[0].results[0].usefulness == 90.0));
     }
 }
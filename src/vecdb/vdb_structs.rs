@@ -7,6 +7,8 @@ use indexmap::IndexMap;
 use tokenizers::Tokenizer;
 use async_trait::async_trait;
 
+use crate::search_filter::ScopeFilter;
+
 
 #[async_trait]
 pub trait VecdbSearch: Send {
@@ -14,7 +16,7 @@ pub trait VecdbSearch: Send {
         &self,
         query: String,
         top_n: usize,
-        filter_mb: Option<String>,
+        filter_mb: Option<ScopeFilter>,
         api_key: &String,
     ) -> Result<SearchResult, String>;
 }
@@ -31,6 +33,10 @@ pub struct VecdbConstants {
     pub endpoint_embeddings_style: String,
     pub splitter_window_size: usize,
     pub vecdb_max_files: usize,
+    pub workspace_key: String,
+    pub vecdb_remote_endpoint: String,
+    pub vecdb_remote_page_size: usize,
+    pub vecdb_symbols: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -56,6 +62,8 @@ pub struct VecdbRecord {
     pub end_line: u64,
     pub distance: f32,
     pub usefulness: f32,
+    #[serde(default)]
+    pub symbol_path: String,  // empty for line windows
 }
 
 #[derive(Debug, Clone)]
@@ -91,11 +99,39 @@ pub struct MemoRecord {
     pub m_project: String,
     pub m_payload: String,
     pub m_origin: String,
+    pub m_scope: String,
+    pub mstat_correct: f64,
+    pub mstat_relevant: f64,
+    pub mstat_times_used: i32,
+}
+
+// One line of the memories JSONL, memid is local to a machine and is not exported, the content hash is what identifies a memory
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MemoExportRecord {
+    #[serde(rename = "type")]
+    pub m_type: String,
+    #[serde(rename = "goal")]
+    pub m_goal: String,
+    #[serde(rename = "project")]
+    pub m_project: String,
+    #[serde(rename = "payload")]
+    pub m_payload: String,
+    #[serde(rename = "origin", default = "default_memo_import_origin")]
+    pub m_origin: String,
+    #[serde(rename = "scope", default)]  // empty until the import puts it into the active project
+    pub m_scope: String,
+    #[serde(rename = "correct", default)]
     pub mstat_correct: f64,
+    #[serde(rename = "relevant", default)]
     pub mstat_relevant: f64,
+    #[serde(rename = "times_used", default)]
     pub mstat_times_used: i32,
 }
 
+fn default_memo_import_origin() -> String {
+    "imported".to_string()
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MemoSearchResult {
     pub query_text: String,
@@ -4,12 +4,14 @@ use std::collections::{HashMap, VecDeque};
 use std::io::Write;
 use std::ops::Div;
 use std::option::Option;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::SystemTime;
 use tokio::sync::{Mutex as AMutex, Notify as ANotify, RwLock as ARwLock};
 use tokio::task::JoinHandle;
 use tracing::{info, warn};
 
+use crate::ast::chunk_utils::official_text_hashing_function;
 use crate::ast::file_splitter::AstBasedFileSplitter;
 use crate::fetch_embedding::get_embedding_with_retry;
 use crate::files_in_workspace::{is_path_to_enqueue_valid, Document};
@@ -27,6 +29,7 @@ enum MessageToVecdbThread {
     RegularDocument(String),
     ImmediatelyRegularDocument(String),
     MemoriesSomethingDirty(),
+    ForgetDocument(String),  // remove from the index even if the file is still there
 }
 
 pub struct FileVectorizerService {
@@ -95,6 +98,7 @@ async fn vectorize_batch_from_q(
                 end_line: data_res.end_line,
                 distance: -1.0,
                 usefulness: 0.0,
+                symbol_path: data_res.symbol_path.clone(),
             }
         );
         send_to_cache.push(
@@ -147,6 +151,7 @@ async fn from_splits_to_vecdb_records_applying_cache(
                     end_line: split.end_line,
                     distance: -1.0,
                     usefulness: 0.0,
+                    symbol_path: split.symbol_path.clone(),
                 });
             }
         } else if let Err(err) = vectors_maybe {
@@ -189,10 +194,13 @@ async fn vectorize_thread(
     };
 
     let mut last_updated: HashMap<String, SystemTime> = HashMap::new();
+    // fingerprints are saved only after the vectors of the file have landed in the table
+    let mut pending_fingerprints: Vec<(String, String)> = vec![];
     loop {
         let mut work_on_one: Option<MessageToVecdbThread> = None;
         let current_time = SystemTime::now();
         let mut vstatus_changed = false;
+        let mut forget: Vec<String> = vec![];
         {
             let mut vecdb_todo_locked = vecdb_todo.lock().await;
             while let Some(msg) = vecdb_todo_locked.pop_front() {
@@ -200,6 +208,10 @@ async fn vectorize_thread(
                     MessageToVecdbThread::RegularDocument(cpath) => {
                         last_updated.insert(cpath, current_time);
                     }
+                    MessageToVecdbThread::ForgetDocument(cpath) => {
+                        last_updated.remove(&cpath);
+                        forget.push(cpath);
+                    }
                     MessageToVecdbThread::ImmediatelyRegularDocument(_) | MessageToVecdbThread::MemoriesSomethingDirty() => {
                         work_on_one = Some(msg);
                         break;
@@ -237,6 +249,15 @@ async fn vectorize_thread(
         if vstatus_changed {
             vstatus_notify.notify_waiters();
         }
+        if !forget.is_empty() {
+            // what's already split or vectorized for these files must not land in the table after the removal
+            let forget_paths: HashSet<PathBuf> = forget.iter().map(PathBuf::from).collect();
+            run_actual_model_on_these.retain(|x| !forget_paths.contains(&x.file_path));
+            ready_to_vecdb.retain(|x| !forget_paths.contains(&x.file_path));
+            pending_fingerprints.retain(|(cpath, _)| !forget_paths.contains(&PathBuf::from(cpath)));
+            info!("forgetting {} files", forget.len());
+            vecdb_handler_arc.lock().await.vecdb_records_remove(forget).await;
+        }
 
         let flush = ready_to_vecdb.len() > 100 || files_unprocessed == 0 || work_on_one.is_none();
         loop {
@@ -255,6 +276,7 @@ async fn vectorize_thread(
                     constants.embedding_batch,
                 ).await {
                     tracing::error!("{}", err);
+                    pending_fingerprints.clear();  // some vectors are lost, process these files again next time
                     continue;
                 }
             } else {
@@ -266,6 +288,7 @@ async fn vectorize_thread(
             assert!(run_actual_model_on_these.len() == 0);
             // This function assumes it can delete records with the filenames mentioned, therefore assert above
             _send_to_vecdb(vecdb_handler_arc.clone(), &mut ready_to_vecdb).await;
+            vecdb_handler_arc.lock().await.fingerprints_set(std::mem::take(&mut pending_fingerprints)).await;
         }
 
         if (files_unprocessed + 99).div(100) != (reported_unprocessed + 99).div(100) {
@@ -339,8 +362,8 @@ async fn vectorize_thread(
         let last_30_chars = crate::nicer_logs::last_n_chars(&cpath, 30);
 
         // Not from memory, vecdb works on files from disk, because they change less
-        let mut doc: Document = Document { doc_path: cpath.clone().into(), doc_text: None };
-        if let Err(_) = doc.update_text_from_disk(gcx.clone()).await {
+        let mut doc: Document = Document { doc_path: cpath.clone().into(), doc_text: None, doc_version: None };
+        if let Err(_) = doc.update_text_from_disk_for_indexing(gcx.clone()).await {
             info!("{} cannot read, deleting from index", last_30_chars);  // don't care what the error is, trivial (or privacy)
             vecdb_handler_arc.lock().await.vecdb_records_remove(vec![doc.doc_path.to_string_lossy().to_string()]).await;
             continue;
@@ -351,7 +374,13 @@ async fn vectorize_thread(
             continue;
         }
 
-        let file_splitter = AstBasedFileSplitter::new(constants.splitter_window_size);
+        let text_md5 = official_text_hashing_function(&doc.text_as_string().unwrap_or_default());
+        if vecdb_handler_arc.lock().await.fingerprint_get(&cpath).await.as_ref() == Some(&text_md5) {
+            continue;
+        }
+        pending_fingerprints.push((cpath.clone(), text_md5));
+
+        let file_splitter = AstBasedFileSplitter::new(constants.splitter_window_size, constants.vecdb_symbols);
         let mut splits = file_splitter.vectorization_split(&doc, None, gcx.clone(), constants.vectorizer_n_ctx).await.unwrap_or_else(|err| {
             info!("{}", err);
             vec![]
@@ -362,7 +391,7 @@ async fn vectorize_thread(
             splits.push(crate::vecdb::vdb_structs::SplitResult {
                 file_path: doc.doc_path.clone(),
                 window_text: filename.clone(),
-                window_text_hash: crate::ast::chunk_utils::official_text_hashing_function(&filename),
+                window_text_hash: official_text_hashing_function(&filename),
                 start_line: 0,
                 end_line: if let Some(text) = doc.doc_text { text.lines().count() as u64 - 1 } else { 0 },
                 symbol_path: "".to_string(),
@@ -544,3 +573,20 @@ pub async fn vectorizer_enqueue_files(
         }
     }
 }
+
+pub async fn vectorizer_forget_files(
+    vservice: Arc<AMutex<FileVectorizerService>>,
+    documents: &Vec<String>,
+) {
+    let (vecdb_todo, vstatus_notify) = {
+        let service = vservice.lock().await;
+        (service.vecdb_todo.clone(), service.vstatus_notify.clone())
+    };
+    {
+        let mut vecdb_todo_locked = vecdb_todo.lock().await;
+        for doc in documents.iter() {
+            vecdb_todo_locked.push_back(MessageToVecdbThread::ForgetDocument(doc.clone()));
+        }
+    }
+    vstatus_notify.notify_waiters();
+}
@@ -20,6 +20,10 @@ pub struct CustomizationYaml {
     pub toolbox_commands: IndexMap<String, ToolboxCommand>,
     #[serde(default)]
     pub code_lens: IndexMap<String, CodeLensCommand>,
+    #[serde(default)]
+    pub post_patch_hooks: IndexMap<String, PostPatchHook>,
+    #[serde(default)]
+    pub trajectory_packs: IndexMap<String, TrajectoryPack>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -57,6 +61,28 @@ pub struct CodeLensCommand {
     pub messages: Vec<ChatMessage>,
 }
 
+fn default_post_patch_hook_timeout() -> u64 {
+    30
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostPatchHook {
+    pub file_extensions: Vec<String>,
+    pub command: String,  // %FILE% and %PROJECT_DIR% are replaced
+    #[serde(default)]
+    pub output_format: String,  // "ruff_json" "cargo_json" "eslint_json" "gofmt_list", anything else is plain text
+    #[serde(default = "default_post_patch_hook_timeout")]
+    pub timeout: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrajectoryPack {
+    #[serde(default)]
+    pub dir: String,  // *.jsonl and *.json files, relative to the config dir unless absolute
+    #[serde(default)]
+    pub url: String,
+}
+
 fn _extract_mapping_values(mapping: &Option<&serde_yaml::Mapping>, variables: &mut HashMap<String, String>) {
     if let Some(mapping) = mapping {
         for (k, v) in mapping.iter() {
@@ -170,10 +196,14 @@ pub fn load_and_mix_with_users_config(
     work_config.system_prompts.extend(caps_config.system_prompts.iter().map(|(k, v)| (k.clone(), v.clone())));
     work_config.toolbox_commands.extend(caps_config.toolbox_commands.iter().map(|(k, v)| (k.clone(), v.clone())));
     work_config.code_lens.extend(caps_config.code_lens.iter().map(|(k, v)| (k.clone(), v.clone())));
+    work_config.post_patch_hooks.extend(caps_config.post_patch_hooks.iter().map(|(k, v)| (k.clone(), v.clone())));
+    work_config.trajectory_packs.extend(caps_config.trajectory_packs.iter().map(|(k, v)| (k.clone(), v.clone())));
 
     work_config.system_prompts.extend(user_config.system_prompts.iter().map(|(k, v)| (k.clone(), v.clone())));
     work_config.toolbox_commands.extend(user_config.toolbox_commands.iter().map(|(k, v)| (k.clone(), v.clone())));
     work_config.code_lens.extend(user_config.code_lens.iter().map(|(k, v)| (k.clone(), v.clone())));
+    work_config.post_patch_hooks.extend(user_config.post_patch_hooks.iter().map(|(k, v)| (k.clone(), v.clone())));
+    work_config.trajectory_packs.extend(user_config.trajectory_packs.iter().map(|(k, v)| (k.clone(), v.clone())));
 
     let filtered_system_prompts = work_config.system_prompts
         .iter()